@@ -0,0 +1,212 @@
+//! Home of TalcAccess, through which `Talck` and `Talcell` share their reallocation logic.
+
+use crate::{talc::Talc, OomHandler};
+
+use core::{
+    alloc::Layout,
+    ops::DerefMut,
+    ptr::{null_mut, NonNull},
+};
+
+#[cfg(feature = "allocator")]
+use core::alloc::AllocError;
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator")))]
+use allocator_api2::alloc::AllocError;
+
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+use crate::ptr_utils::{dangling_for, is_aligned_to, nonnull_slice_from_raw_parts};
+
+/// How a wrapper of [`Talc`] gets at it, such as by locking a mutex or borrowing a cell.
+pub(crate) trait TalcAccess {
+    type Oom: OomHandler;
+
+    /// Grants exclusive access to the `Talc` until dropped.
+    type Guard<'a>: DerefMut<Target = Talc<Self::Oom>>
+    where
+        Self: 'a;
+
+    /// Copies of more bytes than this are made without holding the guard,
+    /// so as not to hold up other threads while moving large allocations.
+    const RELEASE_TO_COPY_ABOVE: usize;
+
+    /// Get exclusive access to the `Talc`, or `None` if it can't be had,
+    /// such as while it's already in use by the caller.
+    fn access(&self) -> Option<Self::Guard<'_>>;
+
+    /// Free `ptr`, possibly later.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated given `layout`.
+    unsafe fn free(&self, ptr: NonNull<u8>, layout: Layout);
+}
+
+/// The body of `GlobalAlloc::realloc`.
+///
+/// # Safety
+/// As for [`GlobalAlloc::realloc`](core::alloc::GlobalAlloc::realloc).
+pub(crate) unsafe fn realloc<A: TalcAccess>(
+    access: &A,
+    ptr: *mut u8,
+    old_layout: Layout,
+    new_size: usize,
+) -> *mut u8 {
+    if new_size == old_layout.size() {
+        return ptr;
+    }
+
+    let Some(mut talc) = access.access() else { return null_mut() };
+    let nn_ptr = NonNull::new_unchecked(ptr);
+
+    if new_size < old_layout.size() {
+        talc.shrink(nn_ptr, old_layout, new_size);
+        return ptr;
+    }
+
+    // first try to grow in-place before manually re-allocating
+    if let Ok(nn) = talc.grow_in_place(nn_ptr, old_layout, new_size) {
+        return nn.as_ptr();
+    }
+
+    // grow in-place failed, reallocate manually
+    let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
+    relocate(access, talc, nn_ptr, old_layout, new_layout, false)
+        .map_or(null_mut(), NonNull::as_ptr)
+}
+
+/// The body of `Allocator::allocate`, or `Allocator::allocate_zeroed` if `zeroed`.
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+pub(crate) fn allocate<A: TalcAccess>(
+    access: &A,
+    layout: Layout,
+    zeroed: bool,
+) -> Result<NonNull<[u8]>, AllocError> {
+    if layout.size() == 0 {
+        return Ok(nonnull_slice_from_raw_parts(dangling_for(layout), 0));
+    }
+
+    let mut talc = access.access().ok_or(AllocError)?;
+    let allocation =
+        if zeroed { unsafe { talc.malloc_zeroed(layout) } } else { unsafe { talc.malloc(layout) } };
+
+    allocation.map(|nn| nonnull_slice_from_raw_parts(nn, layout.size())).map_err(|_| AllocError)
+}
+
+/// The body of `Allocator::grow`, or `Allocator::grow_zeroed` if `zeroed`.
+///
+/// # Safety
+/// As for [`Allocator::grow`](core::alloc::Allocator::grow).
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+pub(crate) unsafe fn grow<A: TalcAccess>(
+    access: &A,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+    zeroed: bool,
+) -> Result<NonNull<[u8]>, AllocError> {
+    debug_assert!(new_layout.size() >= old_layout.size());
+
+    if old_layout.size() == 0 {
+        return allocate(access, new_layout, zeroed);
+    }
+
+    let mut talc = access.access().ok_or(AllocError)?;
+
+    if is_aligned_to(ptr.as_ptr(), new_layout.align()) {
+        // alignment is fine, try to allocate in-place
+        let grown = if zeroed {
+            talc.grow_in_place_zeroed(ptr, old_layout, new_layout.size())
+        } else {
+            talc.grow_in_place(ptr, old_layout, new_layout.size())
+        };
+
+        if let Ok(nn) = grown {
+            return Ok(nonnull_slice_from_raw_parts(nn, new_layout.size()));
+        }
+    }
+
+    // can't grow in place, reallocate manually, only zeroing above the old contents
+    relocate(access, talc, ptr, old_layout, new_layout, zeroed)
+        .map(|nn| nonnull_slice_from_raw_parts(nn, new_layout.size()))
+        .ok_or(AllocError)
+}
+
+/// The body of `Allocator::shrink`.
+///
+/// # Safety
+/// As for [`Allocator::shrink`](core::alloc::Allocator::shrink).
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+pub(crate) unsafe fn shrink<A: TalcAccess>(
+    access: &A,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+) -> Result<NonNull<[u8]>, AllocError> {
+    debug_assert!(new_layout.size() <= old_layout.size());
+
+    if new_layout.size() == 0 {
+        if old_layout.size() > 0 {
+            access.free(ptr, old_layout);
+        }
+
+        return Ok(nonnull_slice_from_raw_parts(dangling_for(new_layout), 0));
+    }
+
+    let mut talc = access.access().ok_or(AllocError)?;
+
+    if !is_aligned_to(ptr.as_ptr(), new_layout.align()) {
+        return relocate(access, talc, ptr, old_layout, new_layout, false)
+            .map(|nn| nonnull_slice_from_raw_parts(nn, new_layout.size()))
+            .ok_or(AllocError);
+    }
+
+    talc.shrink(ptr, old_layout, new_layout.size());
+
+    Ok(nonnull_slice_from_raw_parts(ptr, new_layout.size()))
+}
+
+/// Move the allocation at `ptr` to a new allocation for `new_layout`, unless it's pinned.
+///
+/// If `zeroed`, the new allocation is zeroed above the contents copied over.
+///
+/// # Safety
+/// `ptr` must have been previously allocated given `old_layout`.
+unsafe fn relocate<'a, A: TalcAccess>(
+    access: &'a A,
+    mut talc: A::Guard<'a>,
+    ptr: NonNull<u8>,
+    old_layout: Layout,
+    new_layout: Layout,
+    zeroed: bool,
+) -> Option<NonNull<u8>> {
+    if !talc.is_movable(ptr, old_layout.size()) {
+        return None;
+    }
+
+    let copy_size = old_layout.size().min(new_layout.size());
+    let allocation = if zeroed {
+        talc.malloc_zeroed_from(new_layout, copy_size)
+    } else {
+        talc.malloc(new_layout)
+    };
+    let allocation = allocation.ok()?;
+
+    if copy_size > A::RELEASE_TO_COPY_ABOVE {
+        drop(talc);
+        allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), copy_size);
+
+        talc = match access.access() {
+            Some(talc) => talc,
+            // the old allocation can't be freed, so it's leaked
+            None => return Some(allocation),
+        };
+    } else {
+        allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), copy_size);
+    }
+
+    talc.inherit_owner(ptr, old_layout.size(), allocation, new_layout.size());
+    talc.free(ptr, old_layout);
+    talc.account_moved_realloc(old_layout.size(), new_layout.size());
+
+    Some(allocation)
+}
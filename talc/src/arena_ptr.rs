@@ -0,0 +1,142 @@
+//! Home of ArenaPtr, a compact pointer relative to a Talc's arena.
+
+use core::{fmt, marker::PhantomData, num::NonZeroU32};
+
+use crate::{OomHandler, Talc};
+
+/// A 4-byte pointer to a `T` in memory managed by a [`Talc`], stored as an offset
+/// from the allocator's [`arena_base`](Talc::arena_base).
+///
+/// Half the size of a pointer on 64-bit targets, this suits compact data structures,
+/// such as intrusive lists and trees, and data structures in memory that's mapped at
+/// different addresses over time, such as shared memory, as offsets are unaffected.
+///
+/// Memory up to 4 GiB above the arena base can be pointed to, but not below it. The
+/// offset is never zero, so `Option<ArenaPtr<T>>` is 4 bytes too.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::Layout;
+/// let mut arena = [0u8; 10000];
+/// let mut talc = Talc::new(ErrOnOom);
+/// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+///
+/// let node = unsafe { talc.malloc(Layout::new::<u64>()).unwrap().cast::<u64>() };
+/// let compact = ArenaPtr::new(&talc, node.as_ptr()).unwrap();
+/// assert_eq!(core::mem::size_of::<Option<ArenaPtr<u64>>>(), 4);
+/// assert_eq!(compact.get(&talc), node.as_ptr());
+/// ```
+#[repr(transparent)]
+pub struct ArenaPtr<T> {
+    offset: NonZeroU32,
+    _phantom: PhantomData<fn() -> *mut T>,
+}
+
+impl<T> ArenaPtr<T> {
+    /// Encode `ptr` relative to `talc`'s arena base.
+    ///
+    /// Returns `None` if `talc` has no arena yet, or if `ptr` isn't within 4 GiB above
+    /// the arena base.
+    pub fn new<O: OomHandler>(talc: &Talc<O>, ptr: *mut T) -> Option<Self> {
+        let base = talc.arena_base()?;
+        let offset = (ptr as usize).checked_sub(base.as_ptr() as usize)?;
+
+        Some(Self::from_offset(NonZeroU32::new(u32::try_from(offset).ok()?)?))
+    }
+
+    /// Create an `ArenaPtr` from an offset previously obtained with [`ArenaPtr::offset`].
+    pub const fn from_offset(offset: NonZeroU32) -> Self {
+        Self { offset, _phantom: PhantomData }
+    }
+
+    /// Returns the offset from the arena base.
+    pub const fn offset(self) -> NonZeroU32 {
+        self.offset
+    }
+
+    /// Decode the pointer relative to `talc`'s arena base.
+    ///
+    /// # Panics
+    /// Panics if `talc` has no arena. This can't be the case if this was created by
+    /// [`ArenaPtr::new`] with the same allocator.
+    pub fn get<O: OomHandler>(self, talc: &Talc<O>) -> *mut T {
+        let base = talc.arena_base().expect("the allocator has no arena");
+        base.as_ptr().wrapping_add(self.offset.get() as usize).cast()
+    }
+
+    /// Cast to an `ArenaPtr` of another type.
+    pub const fn cast<U>(self) -> ArenaPtr<U> {
+        ArenaPtr::from_offset(self.offset)
+    }
+}
+
+impl<T> Clone for ArenaPtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for ArenaPtr<T> {}
+
+impl<T> PartialEq for ArenaPtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.offset == other.offset
+    }
+}
+
+impl<T> Eq for ArenaPtr<T> {}
+
+impl<T> core::hash::Hash for ArenaPtr<T> {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.offset.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for ArenaPtr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ArenaPtr(+{:#x})", self.offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, mem::size_of, num::NonZeroU32};
+
+    use crate::*;
+
+    #[test]
+    fn arena_ptr_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        let mut value = 0u32;
+        assert!(ArenaPtr::new(&talc, &mut value).is_none());
+
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        assert!(size_of::<Option<ArenaPtr<u32>>>() == 4);
+
+        unsafe {
+            let a = talc.malloc(Layout::new::<u32>()).unwrap().cast::<u32>();
+            let b = talc.malloc(Layout::new::<u32>()).unwrap().cast::<u32>();
+
+            let a_ptr = ArenaPtr::new(&talc, a.as_ptr()).unwrap();
+            let b_ptr = ArenaPtr::new(&talc, b.as_ptr()).unwrap();
+            assert_ne!(a_ptr, b_ptr);
+            assert_eq!(a_ptr.get(&talc), a.as_ptr());
+
+            // round trip through the raw offset
+            let b_ptr = ArenaPtr::<u8>::from_offset(b_ptr.offset()).cast::<u32>();
+            b_ptr.get(&talc).write(7);
+            assert_eq!(b.as_ptr().read(), 7);
+
+            // the arena base itself, and memory below it, can't be pointed to
+            let base = talc.arena_base().unwrap().as_ptr();
+            assert!(ArenaPtr::new(&talc, base).is_none());
+            assert!(ArenaPtr::new(&talc, base.wrapping_sub(1)).is_none());
+            assert_eq!(
+                ArenaPtr::<u8>::from_offset(NonZeroU32::new(1).unwrap()).get(&talc),
+                base.add(1)
+            );
+        }
+    }
+}
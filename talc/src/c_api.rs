@@ -0,0 +1,345 @@
+//! Export the allocator to C code sharing the heap, see [`export_c_api!`](crate::export_c_api).
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+
+use crate::{ptr_utils::WORD_SIZE, OomHandler, Talc};
+
+/// The alignment of `talc_malloc`, `talc_calloc` and `talc_realloc`, i.e. that of `max_align_t`.
+///
+/// This matches GCC and Clang on common 32 and 64-bit targets.
+pub const MALLOC_ALIGN: usize = 2 * WORD_SIZE;
+
+/// C's `free` doesn't provide the layout, so the size and alignment of the underlying
+/// allocation are stored in the two words preceding the allocation, which is offset by this much.
+fn header_size(align: usize) -> usize {
+    align.max(2 * WORD_SIZE)
+}
+
+/// Returns the layout of the underlying allocation of `size` bytes aligned to `align`.
+fn outer_layout(size: usize, align: usize) -> Option<Layout> {
+    let header_size = header_size(align);
+    let outer_size = size.max(1).checked_add(header_size)?;
+    Layout::from_size_align(outer_size, header_size).ok()
+}
+
+/// Returns the layout of the underlying allocation of `ptr`.
+unsafe fn read_header(ptr: *mut u8) -> Layout {
+    let header = ptr.cast::<usize>().sub(2);
+    Layout::from_size_align_unchecked(header.add(1).read(), header.read())
+}
+
+/// Stores the layout of the underlying allocation, returning the pointer to hand out.
+unsafe fn write_header(outer: *mut u8, outer_layout: Layout) -> *mut u8 {
+    let ptr = outer.add(outer_layout.align());
+    let header = ptr.cast::<usize>().sub(2);
+    header.write(outer_layout.align());
+    header.add(1).write(outer_layout.size());
+    ptr
+}
+
+/// Allocate `size` bytes aligned to `align`, zeroed if `zeroed`, returning null on failure.
+///
+/// Zero-size requests yield a unique pointer.
+///
+/// # Safety
+/// `align` must be a power of two.
+#[doc(hidden)]
+pub unsafe fn malloc<A: GlobalAlloc>(
+    alloc: &A,
+    size: usize,
+    align: usize,
+    zeroed: bool,
+) -> *mut u8 {
+    let outer_layout = match outer_layout(size, align) {
+        Some(outer_layout) => outer_layout,
+        None => return null_mut(),
+    };
+
+    let outer = if zeroed { alloc.alloc_zeroed(outer_layout) } else { alloc.alloc(outer_layout) };
+    if outer.is_null() {
+        return null_mut();
+    }
+
+    write_header(outer, outer_layout)
+}
+
+/// Resize an allocation returned by [`malloc`] to `size` bytes, like C's `realloc`.
+///
+/// A null `ptr` is allocated, and a `size` of zero frees `ptr` and returns null. On
+/// failure, null is returned and `ptr` is left as it was.
+///
+/// # Safety
+/// `ptr` must be null or have been returned by [`malloc`] or `realloc` given `alloc`.
+#[doc(hidden)]
+pub unsafe fn realloc<A: GlobalAlloc>(alloc: &A, ptr: *mut u8, size: usize) -> *mut u8 {
+    if ptr.is_null() {
+        return malloc(alloc, size, MALLOC_ALIGN, false);
+    }
+    if size == 0 {
+        free(alloc, ptr);
+        return null_mut();
+    }
+
+    let old_layout = read_header(ptr);
+    let new_layout = match outer_layout(size, old_layout.align()) {
+        Some(new_layout) => new_layout,
+        None => return null_mut(),
+    };
+
+    let outer = alloc.realloc(ptr.sub(old_layout.align()), old_layout, new_layout.size());
+    if outer.is_null() {
+        return null_mut();
+    }
+
+    write_header(outer, new_layout)
+}
+
+/// Free an allocation returned by [`malloc`] or [`realloc`]. Null is ignored.
+///
+/// # Safety
+/// `ptr` must be null or have been returned by [`malloc`] or [`realloc`] given `alloc`.
+#[doc(hidden)]
+pub unsafe fn free<A: GlobalAlloc>(alloc: &A, ptr: *mut u8) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let outer_layout = read_header(ptr);
+    alloc.dealloc(ptr.sub(outer_layout.align()), outer_layout);
+}
+
+/// Heap statistics in the layout of glibc's `struct mallinfo2`, as returned by `talc_mallinfo`.
+///
+/// Fields that have no equivalent in Talc are zero, as are the arena and allocation totals
+/// unless the `counters` feature is enabled.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Mallinfo {
+    /// Sum of bytes actively claimed.
+    pub arena: usize,
+    /// Number of free chunks.
+    pub ordblks: usize,
+    /// Always zero.
+    pub smblks: usize,
+    /// Always zero.
+    pub hblks: usize,
+    /// Always zero.
+    pub hblkhd: usize,
+    /// Always zero.
+    pub usmblks: usize,
+    /// Always zero.
+    pub fsmblks: usize,
+    /// Sum of active allocations' layouts' size.
+    pub uordblks: usize,
+    /// Sum of the sizes of all free chunks.
+    pub fordblks: usize,
+    /// Always zero, as Talc doesn't release memory on its own.
+    pub keepcost: usize,
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the heap statistics in the layout of glibc's `struct mallinfo2`.
+    ///
+    /// This walks every free chunk, like [`Talc::report`].
+    pub fn mallinfo(&self) -> Mallinfo {
+        let report = self.report();
+
+        Mallinfo {
+            #[cfg(feature = "counters")]
+            arena: report.claimed_bytes,
+            ordblks: report.free_chunk_count,
+            #[cfg(feature = "counters")]
+            uordblks: report.allocated_bytes,
+            fordblks: report.free_bytes,
+            ..Default::default()
+        }
+    }
+
+    /// Make cached free memory available, returning whether there was any, like glibc's
+    /// `malloc_trim`.
+    ///
+    /// Talc never returns memory to the system on its own, so this only flushes the
+    /// caches of the `fastbins` feature and the `quarantine` feature's quarantine.
+    /// Use [`Talc::trim`] to release memory.
+    pub fn malloc_trim(&mut self) -> bool {
+        #[cfg_attr(not(any(feature = "fastbins", feature = "quarantine")), allow(unused_mut))]
+        let mut flushed = false;
+
+        #[cfg(feature = "quarantine")]
+        {
+            flushed |= self.flush_quarantine();
+        }
+        #[cfg(feature = "fastbins")]
+        {
+            flushed |= self.flush_fastbins();
+        }
+
+        flushed
+    }
+}
+
+/// Export C functions operating on the [`Talck`](crate::Talck) in the `static` named `$talck`,
+/// for C code sharing the heap with Rust.
+///
+/// The following are exported:
+/// - `void *talc_malloc(size_t size)`
+/// - `void *talc_calloc(size_t count, size_t size)`
+/// - `void *talc_realloc(void *ptr, size_t size)`, which frees `ptr` and returns null if
+///   `size` is zero.
+/// - `void talc_free(void *ptr)`
+/// - `void *talc_aligned_alloc(size_t align, size_t size)`
+/// - `int talc_posix_memalign(void **memptr, size_t align, size_t size)`
+/// - `struct mallinfo2 talc_mallinfo(void)`, see [`Talc::mallinfo`].
+/// - `int talc_malloc_trim(size_t pad)`, see [`Talc::malloc_trim`]. `pad` is ignored.
+///
+/// These behave like their C standard library counterparts, and their allocations can
+/// be passed to any of them. Allocations are aligned to at least [`MALLOC_ALIGN`], and
+/// the size and alignment of each are stored in the two words preceding it, as `talc_free`
+/// isn't given them. The symbols are prefixed to avoid conflicting with the C library's;
+/// to replace its allocator, alias them with the linker, e.g. `--defsym=malloc=talc_malloc`.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static mut ARENA: [u8; 10000] = [0; 10000];
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
+///     Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA))) })
+///         .lock();
+///
+/// talc::export_c_api!(ALLOCATOR);
+/// ```
+#[macro_export]
+macro_rules! export_c_api {
+    ($talck:path) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_malloc(size: usize) -> *mut u8 {
+            $crate::c_api::malloc(&$talck, size, $crate::c_api::MALLOC_ALIGN, false)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_calloc(count: usize, size: usize) -> *mut u8 {
+            match count.checked_mul(size) {
+                Some(size) => {
+                    $crate::c_api::malloc(&$talck, size, $crate::c_api::MALLOC_ALIGN, true)
+                }
+                None => core::ptr::null_mut(),
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_realloc(ptr: *mut u8, size: usize) -> *mut u8 {
+            $crate::c_api::realloc(&$talck, ptr, size)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_free(ptr: *mut u8) {
+            $crate::c_api::free(&$talck, ptr)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_aligned_alloc(align: usize, size: usize) -> *mut u8 {
+            if !align.is_power_of_two() {
+                return core::ptr::null_mut();
+            }
+            $crate::c_api::malloc(&$talck, size, align, false)
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_posix_memalign(
+            memptr: *mut *mut u8,
+            align: usize,
+            size: usize,
+        ) -> core::ffi::c_int {
+            /// `EINVAL` and `ENOMEM`, as defined by newlib, picolibc and glibc.
+            const EINVAL: core::ffi::c_int = 22;
+            const ENOMEM: core::ffi::c_int = 12;
+
+            if !align.is_power_of_two() || align % core::mem::size_of::<usize>() != 0 {
+                return EINVAL;
+            }
+
+            let ptr = $crate::c_api::malloc(&$talck, size, align, false);
+            if ptr.is_null() {
+                return ENOMEM;
+            }
+
+            memptr.write(ptr);
+            0
+        }
+
+        #[no_mangle]
+        pub extern "C" fn talc_mallinfo() -> $crate::Mallinfo {
+            $talck.lock().mallinfo()
+        }
+
+        #[no_mangle]
+        pub extern "C" fn talc_malloc_trim(_pad: usize) -> core::ffi::c_int {
+            $talck.lock().malloc_trim() as core::ffi::c_int
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use crate::*;
+
+    static mut ARENA: [u8; 10000] = [0; 10000];
+    static TALCK: Talck<spin::Mutex<()>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+
+    export_c_api!(TALCK);
+
+    #[test]
+    fn mallinfo_test() {
+        let heap = unsafe { TALCK.lock().claim(Span::from_array(core::ptr::addr_of_mut!(ARENA))) };
+        let heap = heap.unwrap();
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let ptr = unsafe { TALCK.alloc(layout) };
+        let info = talc_mallinfo();
+
+        assert_eq!(info.ordblks, 1);
+        assert!(info.fordblks > 0 && info.fordblks < heap.size() - layout.size());
+        #[cfg(feature = "counters")]
+        assert_eq!((info.arena, info.uordblks), (heap.size(), layout.size()));
+
+        unsafe { TALCK.dealloc(ptr, layout) };
+        talc_malloc_trim(0);
+        assert!(talc_mallinfo().fordblks > info.fordblks);
+    }
+
+    #[test]
+    fn c_malloc_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+        let initial = talck.lock().report();
+
+        unsafe {
+            let a = c_api::malloc(&talck, 100, c_api::MALLOC_ALIGN, false);
+            assert_eq!(a as usize % c_api::MALLOC_ALIGN, 0);
+            a.write_bytes(0xAB, 100);
+
+            let b = c_api::malloc(&talck, 50, 256, true);
+            assert_eq!(b as usize % 256, 0);
+            assert!((0..50).all(|i| *b.add(i) == 0));
+
+            // the contents follow the allocation, and its alignment is kept
+            let a = c_api::realloc(&talck, a, 1000);
+            assert!((0..100).all(|i| *a.add(i) == 0xAB));
+            let b = c_api::realloc(&talck, b, 500);
+            assert_eq!(b as usize % 256, 0);
+
+            assert!(c_api::realloc(&talck, a, 0).is_null());
+            c_api::free(&talck, b);
+            c_api::free(&talck, core::ptr::null_mut());
+        }
+
+        talck.lock().malloc_trim();
+        assert_eq!(talck.lock().report(), initial);
+    }
+}
@@ -0,0 +1,274 @@
+//! Home of CodeHeap, which allocates memory for machine code, keeping pages writable or executable.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use crate::{ErrOnOom, Span, Talc};
+
+/// Changes the protection of pages, on behalf of a [`CodeHeap`].
+///
+/// Implement this using the platform's facility, such as `mprotect` or the MPU.
+pub trait PageProtect {
+    /// Make `pages` readable and executable, but not writable.
+    ///
+    /// # Safety
+    /// `pages` is page-aligned, a multiple of the page size, and within a heap claimed by the [`CodeHeap`].
+    unsafe fn make_executable(&mut self, pages: Span) -> Result<(), ()>;
+
+    /// Make `pages` readable and writable, but not executable.
+    ///
+    /// # Safety
+    /// `pages` is page-aligned, a multiple of the page size, and within a heap claimed by the [`CodeHeap`].
+    unsafe fn make_writable(&mut self, pages: Span) -> Result<(), ()>;
+}
+
+/// Memory allocated by a [`CodeHeap`], consisting of whole pages.
+///
+/// It's writable until [finalized](CodeHeap::finalize), after which it's executable.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CodeRegion {
+    ptr: NonNull<u8>,
+    size: usize,
+    executable: bool,
+}
+
+impl CodeRegion {
+    /// Returns a pointer to the base of the region.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.ptr.as_ptr()
+    }
+
+    /// Returns the size of the region, which is a multiple of the page size.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Returns the region as a [`Span`].
+    pub fn span(&self) -> Span {
+        Span::from_base_size(self.ptr.as_ptr(), self.size)
+    }
+
+    /// Returns whether the region is executable, rather than writable.
+    pub fn is_executable(&self) -> bool {
+        self.executable
+    }
+
+    fn layout(&self, page_size: usize) -> Layout {
+        // SAFETY: the layout was valid when the region was allocated
+        unsafe { Layout::from_size_align_unchecked(self.size, page_size) }
+    }
+}
+
+/// An allocator for JIT-compiled code, which never makes memory writable and executable at once.
+///
+/// Regions are allocated writable, to emit code into, then [finalized](CodeHeap::finalize)
+/// to make them executable, and [made writable](CodeHeap::make_writable) again before being
+/// freed. Protection changes are delegated to the [`PageProtect`] implementation.
+///
+/// Regions are rounded up to whole pages, and the allocator's metadata is kept out of them,
+/// so that no page is shared between regions or with the allocator. As the metadata
+/// directly follows each region, regions are spaced at least a page apart.
+///
+/// Claimed memory must be writable, as the allocator keeps its metadata in it.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// struct NoProtect;
+/// impl PageProtect for NoProtect {
+///     unsafe fn make_executable(&mut self, pages: Span) -> Result<(), ()> { Ok(()) }
+///     unsafe fn make_writable(&mut self, pages: Span) -> Result<(), ()> { Ok(()) }
+/// }
+///
+/// let mut arena = vec![0u8; 1 << 16];
+/// let mut code_heap = CodeHeap::new(NoProtect, 4096);
+/// unsafe { code_heap.claim(arena.as_mut_slice().into()).unwrap(); }
+///
+/// let code = [0xC3]; // ret
+/// let mut region = code_heap.allocate(code.len()).unwrap();
+/// unsafe {
+///     region.as_ptr().copy_from_nonoverlapping(code.as_ptr(), code.len());
+///     code_heap.finalize(&mut region).unwrap();
+///     // ... call into the region ...
+///     code_heap.free(region).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct CodeHeap<P: PageProtect> {
+    talc: Talc<ErrOnOom>,
+    protect: P,
+    page_size: usize,
+}
+
+impl<P: PageProtect> CodeHeap<P> {
+    /// Create a new code heap that protects pages of `page_size` bytes using `protect`.
+    ///
+    /// # Panics
+    /// Panics if `page_size` isn't a power of two.
+    pub const fn new(protect: P, page_size: usize) -> Self {
+        assert!(page_size.is_power_of_two());
+
+        Self { talc: Talc::new(ErrOnOom), protect, page_size }
+    }
+
+    /// Returns the page size the code heap was created with.
+    pub fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    /// Returns the underlying allocator, e.g. to get a [`HeapReport`](crate::HeapReport).
+    pub fn talc(&self) -> &Talc<ErrOnOom> {
+        &self.talc
+    }
+
+    /// Claim memory for allocating code regions from. See [`Talc::claim`].
+    ///
+    /// # Safety
+    /// See [`Talc::claim`]. The memory must be writable.
+    pub unsafe fn claim(&mut self, memory: Span) -> Result<Span, ()> {
+        self.talc.claim(memory)
+    }
+
+    /// Allocate a writable region of at least `size` bytes, rounded up to whole pages.
+    ///
+    /// Returns `Err` if `size` is zero or there isn't enough memory.
+    pub fn allocate(&mut self, size: usize) -> Result<CodeRegion, ()> {
+        if size == 0 || size > isize::MAX as usize - (self.page_size - 1) {
+            return Err(());
+        }
+
+        let size = (size + (self.page_size - 1)) & !(self.page_size - 1);
+        let layout = Layout::from_size_align(size, self.page_size).map_err(|_| ())?;
+
+        // SAFETY: the size is nonzero
+        let ptr = unsafe { self.talc.malloc(layout)? };
+
+        Ok(CodeRegion { ptr, size, executable: false })
+    }
+
+    /// Make a region executable, after which it can't be written to.
+    ///
+    /// Does nothing if the region is already executable.
+    ///
+    /// # Safety
+    /// `region` must have been allocated by this code heap.
+    pub unsafe fn finalize(&mut self, region: &mut CodeRegion) -> Result<(), ()> {
+        if !region.executable {
+            self.protect.make_executable(region.span())?;
+            region.executable = true;
+        }
+
+        Ok(())
+    }
+
+    /// Make a region writable again, e.g. for patching, after which it can't be executed.
+    ///
+    /// Does nothing if the region is already writable.
+    ///
+    /// # Safety
+    /// `region` must have been allocated by this code heap.
+    pub unsafe fn make_writable(&mut self, region: &mut CodeRegion) -> Result<(), ()> {
+        if region.executable {
+            self.protect.make_writable(region.span())?;
+            region.executable = false;
+        }
+
+        Ok(())
+    }
+
+    /// Free a region, making it writable first if necessary.
+    ///
+    /// If the region can't be made writable, it's returned in `Err` and not freed.
+    ///
+    /// # Safety
+    /// `region` must have been allocated by this code heap.
+    pub unsafe fn free(&mut self, mut region: CodeRegion) -> Result<(), CodeRegion> {
+        if self.make_writable(&mut region).is_err() {
+            return Err(region);
+        }
+
+        self.talc.free(region.ptr, region.layout(self.page_size));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Records which pages are executable, and checks that transitions are sound.
+    struct MockProtect {
+        page_size: usize,
+        executable: std::vec::Vec<Span>,
+        fail: bool,
+    }
+
+    impl PageProtect for MockProtect {
+        unsafe fn make_executable(&mut self, pages: Span) -> Result<(), ()> {
+            let (base, acme) = pages.get_base_acme().unwrap();
+            assert!(base as usize % self.page_size == 0 && acme as usize % self.page_size == 0);
+            assert!(self.executable.iter().all(|span| !span.overlaps(pages)));
+
+            self.executable.push(pages);
+            Ok(())
+        }
+
+        unsafe fn make_writable(&mut self, pages: Span) -> Result<(), ()> {
+            if self.fail {
+                return Err(());
+            }
+
+            let index = self.executable.iter().position(|&span| span == pages).unwrap();
+            self.executable.swap_remove(index);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn code_heap_test() {
+        const PAGE_SIZE: usize = 256;
+
+        let mut arena = std::vec![0u8; 1 << 16];
+        let protect = MockProtect { page_size: PAGE_SIZE, executable: std::vec![], fail: false };
+        let mut code_heap = CodeHeap::new(protect, PAGE_SIZE);
+        unsafe { code_heap.claim(arena.as_mut_slice().into()).unwrap() };
+
+        let initial = code_heap.talc().report();
+        assert!(code_heap.allocate(0).is_err());
+        assert!(code_heap.allocate(usize::MAX).is_err());
+
+        let mut regions = std::vec![];
+        for size in [1, PAGE_SIZE, PAGE_SIZE + 1, 1000] {
+            let mut region = code_heap.allocate(size).unwrap();
+            assert!(region.size() >= size && region.size() % PAGE_SIZE == 0);
+            assert!(region.as_ptr() as usize % PAGE_SIZE == 0);
+
+            unsafe {
+                region.as_ptr().write_bytes(0xC3, region.size());
+                code_heap.finalize(&mut region).unwrap();
+                code_heap.finalize(&mut region).unwrap();
+            }
+            assert!(region.is_executable());
+            regions.push(region);
+        }
+
+        // allocating and freeing around executable regions doesn't touch their pages
+        let scratch = code_heap.allocate(10).unwrap();
+        unsafe { code_heap.free(scratch).unwrap() };
+        for region in regions.iter() {
+            assert!(unsafe { (0..region.size()).all(|i| *region.as_ptr().add(i) == 0xC3) });
+        }
+
+        // a region that can't be made writable isn't freed
+        code_heap.protect.fail = true;
+        let region = unsafe { code_heap.free(regions.pop().unwrap()).unwrap_err() };
+        assert!(region.is_executable());
+        code_heap.protect.fail = false;
+        regions.push(region);
+
+        for region in regions {
+            unsafe { code_heap.free(region).unwrap() };
+        }
+        assert!(code_heap.protect.executable.is_empty());
+        assert_eq!(code_heap.talc().report(), initial);
+    }
+}
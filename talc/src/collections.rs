@@ -0,0 +1,86 @@
+//! Allocate collections in an [`Allocator`], returning an error instead of aborting on OOM.
+//!
+//! The infallible constructors of `Vec` and `Box` call `handle_alloc_error` when allocation
+//! fails, which aborts. These helpers return the error instead, such that allocation failure
+//! can be handled like any other error, e.g. by firmware without a way to recover from an abort.
+
+use alloc::{boxed::Box, collections::TryReserveError, vec::Vec};
+use core::alloc::{AllocError, Allocator};
+
+/// Create an empty `Vec` in `alloc` with room for at least `capacity` elements,
+/// or return an error if the allocation fails.
+///
+/// # Example
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # use talc::*;
+/// let mut arena = [0u8; 10000];
+/// let mut talc = Talc::new(ErrOnOom);
+/// unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+/// let talck = talc.lock::<spin::Mutex<()>>();
+///
+/// let mut samples = try_new_vec_in::<u32, _>(100, &talck).unwrap();
+/// samples.push(1);
+///
+/// assert!(try_new_vec_in::<u32, _>(100000, &talck).is_err());
+/// ```
+pub fn try_new_vec_in<T, A: Allocator>(
+    capacity: usize,
+    alloc: A,
+) -> Result<Vec<T, A>, TryReserveError> {
+    let mut vec = Vec::new_in(alloc);
+    vec.try_reserve_exact(capacity)?;
+    Ok(vec)
+}
+
+/// Move `value` into a `Box` in `alloc`, or return an error if the allocation fails.
+///
+/// `value` is dropped if the allocation fails.
+///
+/// # Example
+/// ```rust
+/// # #![feature(allocator_api)]
+/// # use talc::*;
+/// let mut arena = [0u8; 10000];
+/// let mut talc = Talc::new(ErrOnOom);
+/// unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+/// let talck = talc.lock::<spin::Mutex<()>>();
+///
+/// let config = try_box_in([0u64; 16], &talck).unwrap();
+///
+/// assert!(try_box_in([0u64; 2000], &talck).is_err());
+/// ```
+pub fn try_box_in<T, A: Allocator>(value: T, alloc: A) -> Result<Box<T, A>, AllocError> {
+    Box::try_new_in(value, alloc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn try_collections_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let mut vec = try_new_vec_in::<u64, _>(100, &talck).unwrap();
+        assert!(vec.capacity() >= 100);
+        vec.extend(0..100);
+
+        // failure is reported instead of aborting, leaving the allocator usable
+        assert!(try_new_vec_in::<u64, _>(10000, &talck).is_err());
+        assert!(try_new_vec_in::<u64, _>(usize::MAX, &talck).is_err());
+        assert!(try_box_in([0u64; 2000], &talck).is_err());
+
+        let boxed = try_box_in(vec, &talck).unwrap();
+        assert_eq!(boxed.iter().sum::<u64>(), 4950);
+        drop(boxed);
+
+        // zero-sized values and empty vectors don't allocate
+        assert!(try_box_in((), &talck).is_ok());
+        assert_eq!(try_new_vec_in::<u64, _>(0, &talck).unwrap().capacity(), 0);
+    }
+}
@@ -0,0 +1,323 @@
+//! Export the allocator as C++'s `operator new` and `operator delete`,
+//! see [`export_cpp_new_delete!`](crate::export_cpp_new_delete).
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::null_mut;
+
+use crate::ptr_utils::WORD_SIZE;
+
+/// The alignment of the non-aligned `operator new` overloads, i.e. `__STDCPP_DEFAULT_NEW_ALIGNMENT__`.
+///
+/// This matches GCC and Clang on common 32 and 64-bit targets.
+pub const DEFAULT_NEW_ALIGN: usize = 2 * WORD_SIZE;
+
+/// C++'s unsized `operator delete` doesn't provide the size, so it's stored
+/// in the word preceding the allocation. Allocations are offset by this much.
+fn header_size(align: usize) -> usize {
+    align.max(WORD_SIZE)
+}
+
+/// Allocate `size` bytes aligned to `align`, returning null on failure.
+///
+/// Zero-size requests yield a unique pointer, as C++ requires.
+///
+/// # Safety
+/// `align` must be a power of two.
+#[doc(hidden)]
+pub unsafe fn new<A: GlobalAlloc>(alloc: &A, size: usize, align: usize) -> *mut u8 {
+    let header_size = header_size(align);
+    let outer_layout = match size
+        .max(1)
+        .checked_add(header_size)
+        .and_then(|outer_size| Layout::from_size_align(outer_size, header_size).ok())
+    {
+        Some(outer_layout) => outer_layout,
+        None => return null_mut(),
+    };
+
+    let outer = alloc.alloc(outer_layout);
+    if outer.is_null() {
+        return null_mut();
+    }
+
+    let ptr = outer.add(header_size);
+    ptr.cast::<usize>().sub(1).write(outer_layout.size());
+    ptr
+}
+
+/// Free an allocation returned by [`new`] given the same `align`. Null is ignored.
+///
+/// # Safety
+/// `ptr` must be null or have been returned by [`new`] given `alloc` and `align`.
+#[doc(hidden)]
+pub unsafe fn delete<A: GlobalAlloc>(alloc: &A, ptr: *mut u8, align: usize) {
+    if ptr.is_null() {
+        return;
+    }
+
+    let header_size = header_size(align);
+    let outer_size = ptr.cast::<usize>().sub(1).read();
+    alloc.dealloc(ptr.sub(header_size), Layout::from_size_align_unchecked(outer_size, header_size));
+}
+
+/// Handle the failure of a throwing `operator new`.
+///
+/// Rust can't throw C++ exceptions, so this panics, which aborts as it
+/// can't unwind out of the `extern "C"` function.
+#[doc(hidden)]
+#[cold]
+pub fn new_failed(size: usize) -> ! {
+    panic!("talc: operator new failed to allocate {} bytes", size);
+}
+
+/// Export C++'s replaceable allocation functions, i.e. `operator new` and `operator delete`,
+/// operating on the [`GlobalAlloc`] in the `static` named `$alloc`, usually the global
+/// [`Talck`](crate::Talck). This gives C++ code the same heap as Rust code, instead of
+/// the C library's.
+///
+/// All the overloads of the Itanium C++ ABI (as used by GCC and Clang, including on Arm)
+/// are exported, i.e. the scalar and array forms of:
+/// - `operator new(size_t)` and its `std::nothrow_t` variant.
+/// - `operator new(size_t, std::align_val_t)` and its `std::nothrow_t` variant.
+/// - `operator delete(void*)`, and its sized, `std::align_val_t`, and `std::nothrow_t` variants.
+///
+/// As Rust can't throw `std::bad_alloc`, the throwing `operator new` overloads panic on
+/// failure instead, which aborts. Use the `std::nothrow` overloads to handle failure.
+///
+/// The symbols conflict with those of the C++ standard library's, so link it such that
+/// these take precedence, as they normally would as replacements.
+///
+/// # Example
+/// ```rust,no_run
+/// # use talc::*;
+/// static mut ARENA: [u8; 10000] = [0; 10000];
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
+///     Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA))) })
+///         .lock();
+///
+/// talc::export_cpp_new_delete!(ALLOCATOR);
+/// ```
+#[macro_export]
+macro_rules! export_cpp_new_delete {
+    ($alloc:path) => {
+        /// `operator new(size_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_Znwm")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_Znwj")]
+        pub unsafe extern "C" fn talc_operator_new(size: usize) -> *mut u8 {
+            let ptr = $crate::cpp::new(&$alloc, size, $crate::cpp::DEFAULT_NEW_ALIGN);
+            if ptr.is_null() {
+                $crate::cpp::new_failed(size);
+            }
+            ptr
+        }
+
+        /// `operator new[](size_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_Znam")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_Znaj")]
+        pub unsafe extern "C" fn talc_operator_new_array(size: usize) -> *mut u8 {
+            talc_operator_new(size)
+        }
+
+        /// `operator new(size_t, const std::nothrow_t&)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnwmRKSt9nothrow_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnwjRKSt9nothrow_t")]
+        pub unsafe extern "C" fn talc_operator_new_nothrow(size: usize, _: *const u8) -> *mut u8 {
+            $crate::cpp::new(&$alloc, size, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator new[](size_t, const std::nothrow_t&)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnamRKSt9nothrow_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnajRKSt9nothrow_t")]
+        pub unsafe extern "C" fn talc_operator_new_array_nothrow(
+            size: usize,
+            _: *const u8,
+        ) -> *mut u8 {
+            $crate::cpp::new(&$alloc, size, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator new(size_t, std::align_val_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnwmSt11align_val_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnwjSt11align_val_t")]
+        pub unsafe extern "C" fn talc_operator_new_aligned(size: usize, align: usize) -> *mut u8 {
+            let ptr = $crate::cpp::new(&$alloc, size, align);
+            if ptr.is_null() {
+                $crate::cpp::new_failed(size);
+            }
+            ptr
+        }
+
+        /// `operator new[](size_t, std::align_val_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnamSt11align_val_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnajSt11align_val_t")]
+        pub unsafe extern "C" fn talc_operator_new_array_aligned(
+            size: usize,
+            align: usize,
+        ) -> *mut u8 {
+            talc_operator_new_aligned(size, align)
+        }
+
+        /// `operator new(size_t, std::align_val_t, const std::nothrow_t&)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnwmSt11align_val_tRKSt9nothrow_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnwjSt11align_val_tRKSt9nothrow_t")]
+        pub unsafe extern "C" fn talc_operator_new_aligned_nothrow(
+            size: usize,
+            align: usize,
+            _: *const u8,
+        ) -> *mut u8 {
+            $crate::cpp::new(&$alloc, size, align)
+        }
+
+        /// `operator new[](size_t, std::align_val_t, const std::nothrow_t&)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZnamSt11align_val_tRKSt9nothrow_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZnajSt11align_val_tRKSt9nothrow_t")]
+        pub unsafe extern "C" fn talc_operator_new_array_aligned_nothrow(
+            size: usize,
+            align: usize,
+            _: *const u8,
+        ) -> *mut u8 {
+            $crate::cpp::new(&$alloc, size, align)
+        }
+
+        /// `operator delete(void*)`
+        #[export_name = "_ZdlPv"]
+        pub unsafe extern "C" fn talc_operator_delete(ptr: *mut u8) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete[](void*)`
+        #[export_name = "_ZdaPv"]
+        pub unsafe extern "C" fn talc_operator_delete_array(ptr: *mut u8) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete(void*, size_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZdlPvm")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZdlPvj")]
+        pub unsafe extern "C" fn talc_operator_delete_sized(ptr: *mut u8, _: usize) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete[](void*, size_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZdaPvm")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZdaPvj")]
+        pub unsafe extern "C" fn talc_operator_delete_array_sized(ptr: *mut u8, _: usize) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete(void*, const std::nothrow_t&)`
+        #[export_name = "_ZdlPvRKSt9nothrow_t"]
+        pub unsafe extern "C" fn talc_operator_delete_nothrow(ptr: *mut u8, _: *const u8) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete[](void*, const std::nothrow_t&)`
+        #[export_name = "_ZdaPvRKSt9nothrow_t"]
+        pub unsafe extern "C" fn talc_operator_delete_array_nothrow(ptr: *mut u8, _: *const u8) {
+            $crate::cpp::delete(&$alloc, ptr, $crate::cpp::DEFAULT_NEW_ALIGN)
+        }
+
+        /// `operator delete(void*, std::align_val_t)`
+        #[export_name = "_ZdlPvSt11align_val_t"]
+        pub unsafe extern "C" fn talc_operator_delete_aligned(ptr: *mut u8, align: usize) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+
+        /// `operator delete[](void*, std::align_val_t)`
+        #[export_name = "_ZdaPvSt11align_val_t"]
+        pub unsafe extern "C" fn talc_operator_delete_array_aligned(ptr: *mut u8, align: usize) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+
+        /// `operator delete(void*, size_t, std::align_val_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZdlPvmSt11align_val_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZdlPvjSt11align_val_t")]
+        pub unsafe extern "C" fn talc_operator_delete_sized_aligned(
+            ptr: *mut u8,
+            _: usize,
+            align: usize,
+        ) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+
+        /// `operator delete[](void*, size_t, std::align_val_t)`
+        #[cfg_attr(target_pointer_width = "64", export_name = "_ZdaPvmSt11align_val_t")]
+        #[cfg_attr(target_pointer_width = "32", export_name = "_ZdaPvjSt11align_val_t")]
+        pub unsafe extern "C" fn talc_operator_delete_array_sized_aligned(
+            ptr: *mut u8,
+            _: usize,
+            align: usize,
+        ) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+
+        /// `operator delete(void*, std::align_val_t, const std::nothrow_t&)`
+        #[export_name = "_ZdlPvSt11align_val_tRKSt9nothrow_t"]
+        pub unsafe extern "C" fn talc_operator_delete_aligned_nothrow(
+            ptr: *mut u8,
+            align: usize,
+            _: *const u8,
+        ) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+
+        /// `operator delete[](void*, std::align_val_t, const std::nothrow_t&)`
+        #[export_name = "_ZdaPvSt11align_val_tRKSt9nothrow_t"]
+        pub unsafe extern "C" fn talc_operator_delete_array_aligned_nothrow(
+            ptr: *mut u8,
+            align: usize,
+            _: *const u8,
+        ) {
+            $crate::cpp::delete(&$alloc, ptr, align)
+        }
+    };
+}
+
+#[cfg(all(test, feature = "lock_api"))]
+mod tests {
+    use core::ptr::null;
+
+    use crate::*;
+
+    static mut ARENA: [u8; 10000] = [0; 10000];
+    static TALCK: Talck<spin::Mutex<()>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+
+    mod exports {
+        use super::TALCK;
+
+        crate::export_cpp_new_delete!(TALCK);
+    }
+
+    #[test]
+    fn cpp_new_delete_test() {
+        use exports::*;
+
+        unsafe {
+            TALCK.lock().claim(Span::from_array(core::ptr::addr_of_mut!(ARENA))).unwrap();
+
+            let a = talc_operator_new(100);
+            let b = talc_operator_new_array(0);
+            assert!(!a.is_null() && !b.is_null() && a != b);
+            assert_eq!(a as usize % cpp::DEFAULT_NEW_ALIGN, 0);
+            a.write_bytes(0xAB, 100);
+
+            let c = talc_operator_new_aligned(64, 256);
+            assert_eq!(c as usize % 256, 0);
+
+            // nothrow variants return null instead of failing
+            assert!(talc_operator_new_nothrow(1 << 20, null()).is_null());
+            assert!(talc_operator_new_aligned_nothrow(1 << 20, 64, null()).is_null());
+
+            talc_operator_delete(a);
+            talc_operator_delete_array_sized(b, 0);
+            talc_operator_delete_aligned(c, 256);
+            talc_operator_delete(core::ptr::null_mut());
+
+            // everything was freed, so a large allocation succeeds
+            let d = talc_operator_new_nothrow(8000, null());
+            assert!(!d.is_null());
+            talc_operator_delete_nothrow(d, null());
+        }
+    }
+}
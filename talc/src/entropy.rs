@@ -0,0 +1,138 @@
+//! Sources of randomness for hardening the heap against exploitation.
+
+/// A source of random numbers, from which security features draw their randomness,
+/// such that each doesn't need its own way of obtaining it.
+///
+/// Which implementation is appropriate depends on the threat model:
+/// - `GetRandom`, with the `getrandom` feature, uses the operating system's
+///   cryptographically secure generator.
+/// - [`CallbackEntropy`] calls a function, e.g. to read a hardware TRNG on a microcontroller.
+/// - [`XorShift`] is fast and deterministic, but predictable, so is only for
+///   non-adversarial uses, such as testing and fuzzing.
+pub trait EntropySource {
+    /// Returns the next random number.
+    fn next_u64(&mut self) -> u64;
+
+    /// Returns a random number in `0..bound`, or zero if `bound` is zero.
+    ///
+    /// The result is slightly biased towards smaller numbers,
+    /// negligibly so when `bound` is small relative to `u64::MAX`.
+    fn next_below(&mut self, bound: u64) -> u64 {
+        // multiply-shift rather than modulo, which is costly on small cores
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+impl<E: EntropySource + ?Sized> EntropySource for &mut E {
+    fn next_u64(&mut self) -> u64 {
+        (**self).next_u64()
+    }
+}
+
+/// Obtains randomness from the operating system using the `getrandom` crate.
+///
+/// # Panics
+/// Panics if the operating system fails to provide randomness, which isn't
+/// expected to happen once the system is booted.
+#[cfg(feature = "getrandom")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct GetRandom;
+
+#[cfg(feature = "getrandom")]
+impl EntropySource for GetRandom {
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0; 8];
+        getrandom::getrandom(&mut bytes).expect("talc: the OS failed to provide randomness");
+        u64::from_ne_bytes(bytes)
+    }
+}
+
+/// Obtains randomness by calling a function, e.g. one that reads a hardware random
+/// number generator.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// fn read_trng() -> u64 {
+///     // e.g. read the RNG peripheral's data register twice
+///     # 4
+/// }
+///
+/// let mut entropy = CallbackEntropy(read_trng);
+/// assert_eq!(entropy.next_u64(), 4);
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct CallbackEntropy(pub fn() -> u64);
+
+impl EntropySource for CallbackEntropy {
+    fn next_u64(&mut self) -> u64 {
+        (self.0)()
+    }
+}
+
+/// A xorshift64* pseudorandom number generator.
+///
+/// This is **not** suitable for security, as its output is predictable
+/// from a few observed values. Use it for testing and fuzzing.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    /// Create a generator from `seed`. Equal seeds produce equal sequences.
+    pub const fn new(seed: u64) -> Self {
+        // the state must be nonzero, else it stays zero
+        Self { state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed } }
+    }
+}
+
+impl EntropySource for XorShift {
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn xorshift_test() {
+        let mut a = XorShift::new(1);
+        let mut b = XorShift::new(1);
+        let values = (0..100).map(|_| a.next_u64()).collect::<std::vec::Vec<_>>();
+
+        assert!((0..100).all(|i| b.next_u64() == values[i]));
+        assert!(values.windows(2).all(|pair| pair[0] != pair[1]));
+
+        // a zero seed doesn't get stuck at zero
+        assert_ne!(XorShift::new(0).next_u64(), 0);
+
+        // bounds are respected, and most of the range is hit
+        let mut seen = [false; 10];
+        for _ in 0..1000 {
+            let value = a.next_below(10);
+            seen[value as usize] = true;
+        }
+        assert!(seen.iter().all(|&seen| seen));
+        assert_eq!(a.next_below(0), 0);
+
+        // usable through a reference
+        fn draw(mut entropy: impl EntropySource) -> u64 {
+            entropy.next_u64()
+        }
+        let expected = b.clone().next_u64();
+        assert_eq!(draw(&mut b), expected);
+    }
+
+    #[cfg(feature = "getrandom")]
+    #[test]
+    fn getrandom_test() {
+        let values = (0..8).map(|_| GetRandom.next_u64()).collect::<std::vec::Vec<_>>();
+        assert!(values.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
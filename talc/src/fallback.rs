@@ -0,0 +1,273 @@
+//! Home of ContentionFallback, which diverts allocations elsewhere while a Talck is contended.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cmp::Ordering,
+    ptr::{null_mut, NonNull},
+};
+
+use crate::{ptr_utils::WORD_SIZE, talc::Talc, OomHandler, Talck};
+
+/// Marks an allocation as belonging to the [`Talck`].
+const OWNER_TALCK: usize = 0;
+/// Marks an allocation as belonging to the fallback allocator.
+const OWNER_FALLBACK: usize = 1;
+
+/// A [`GlobalAlloc`] that allocates from a [`Talck`], but falls back to a secondary
+/// allocator if the lock remains contended after a bounded number of attempts.
+///
+/// This caps the time spent waiting on the heap lock when allocating. Deallocations
+/// of memory belonging to the `Talck` are deferred if the lock is contended with the
+/// `deferred_frees` feature, see [`Talck`].
+///
+/// Each allocation is prefixed by a header recording which allocator owns it, so that
+/// deallocation and reallocation can be routed correctly. The header is the larger
+/// of the allocation's alignment and `usize`.
+///
+/// With the `deferred_frees` feature, reallocating memory belonging to the `Talck`
+/// while the lock is contended moves the allocation into the fallback allocator.
+/// Otherwise, the old allocation can't be freed without the lock, so reallocation waits on it.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static mut ARENA: [u8; 10000] = [0; 10000];
+///
+/// #[global_allocator]
+/// static ALLOCATOR: ContentionFallback<spin::Mutex<()>, ClaimOnOom, std::alloc::System> =
+///     ContentionFallback::new(
+///         Talc::new(unsafe { ClaimOnOom::new(Span::from_const_array(core::ptr::addr_of!(ARENA))) })
+///             .lock(),
+///         std::alloc::System,
+///         100,
+///     );
+///
+/// fn main() {
+///     let _vec = vec![0u8; 100];
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ContentionFallback<R: lock_api::RawMutex, O: OomHandler, F: GlobalAlloc> {
+    talck: Talck<R, O>,
+    fallback: F,
+    spins: usize,
+}
+
+impl<R: lock_api::RawMutex, O: OomHandler, F: GlobalAlloc> ContentionFallback<R, O, F> {
+    /// Create a new `ContentionFallback`.
+    ///
+    /// Acquiring the lock of `talck` is attempted `spins + 1` times before
+    /// allocating from `fallback` instead.
+    pub const fn new(talck: Talck<R, O>, fallback: F, spins: usize) -> Self {
+        Self { talck, fallback, spins }
+    }
+
+    /// Access the inner `Talck`.
+    pub fn talck(&self) -> &Talck<R, O> {
+        &self.talck
+    }
+
+    /// Access the fallback allocator.
+    pub fn fallback(&self) -> &F {
+        &self.fallback
+    }
+
+    /// Retrieve the inner `Talck` and fallback allocator.
+    pub fn into_inner(self) -> (Talck<R, O>, F) {
+        (self.talck, self.fallback)
+    }
+
+    /// Try to acquire the lock, giving up after `self.spins` failed retries.
+    fn try_lock_bounded(&self) -> Option<lock_api::MutexGuard<'_, R, Talc<O>>> {
+        for _ in 0..self.spins {
+            if let Some(talc) = self.talck.try_lock() {
+                return Some(talc);
+            }
+
+            core::hint::spin_loop();
+        }
+
+        self.talck.try_lock()
+    }
+
+    /// Returns the size of the header preceding allocations with the given alignment.
+    #[inline]
+    fn header_size(align: usize) -> usize {
+        align.max(WORD_SIZE)
+    }
+
+    /// Returns the layout of the allocation including the header, if valid.
+    #[inline]
+    fn outer_layout(size: usize, align: usize) -> Option<Layout> {
+        Layout::from_size_align(size.checked_add(Self::header_size(align))?, align).ok()
+    }
+
+    /// Write the owner into the header and return the pointer to the payload.
+    #[inline]
+    unsafe fn finish(outer: *mut u8, align: usize, owner: usize) -> *mut u8 {
+        let ptr = outer.add(Self::header_size(align));
+        ptr.cast::<usize>().sub(1).write(owner);
+        ptr
+    }
+
+    /// Returns the pointer to the allocation including the header, and its owner.
+    #[inline]
+    unsafe fn outer_and_owner(ptr: *mut u8, align: usize) -> (*mut u8, usize) {
+        (ptr.sub(Self::header_size(align)), ptr.cast::<usize>().sub(1).read())
+    }
+}
+
+unsafe impl<R: lock_api::RawMutex, O: OomHandler, F: GlobalAlloc> GlobalAlloc
+    for ContentionFallback<R, O, F>
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let outer_layout = match Self::outer_layout(layout.size(), layout.align()) {
+            Some(outer_layout) => outer_layout,
+            None => return null_mut(),
+        };
+
+        if let Some(mut talc) = self.try_lock_bounded() {
+            return talc
+                .malloc(outer_layout)
+                .map_or(null_mut(), |nn| Self::finish(nn.as_ptr(), layout.align(), OWNER_TALCK));
+        }
+
+        let outer = self.fallback.alloc(outer_layout);
+
+        if outer.is_null() {
+            null_mut()
+        } else {
+            Self::finish(outer, layout.align(), OWNER_FALLBACK)
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (outer, owner) = Self::outer_and_owner(ptr, layout.align());
+        let outer_layout = Self::outer_layout(layout.size(), layout.align()).unwrap_unchecked();
+
+        if owner == OWNER_TALCK {
+            self.talck.dealloc(outer, outer_layout);
+        } else {
+            debug_assert!(owner == OWNER_FALLBACK);
+            self.fallback.dealloc(outer, outer_layout);
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> *mut u8 {
+        let (outer, owner) = Self::outer_and_owner(ptr, old_layout.align());
+        let old_outer_layout =
+            Self::outer_layout(old_layout.size(), old_layout.align()).unwrap_unchecked();
+        let new_outer_layout = match Self::outer_layout(new_size, old_layout.align()) {
+            Some(new_outer_layout) => new_outer_layout,
+            None => return null_mut(),
+        };
+
+        if owner == OWNER_FALLBACK {
+            let new_outer = self.fallback.realloc(outer, old_outer_layout, new_outer_layout.size());
+            // the header is copied along with the rest of the allocation
+            return if new_outer.is_null() {
+                null_mut()
+            } else {
+                new_outer.add(Self::header_size(old_layout.align()))
+            };
+        }
+
+        debug_assert!(owner == OWNER_TALCK);
+
+        #[cfg(feature = "deferred_frees")]
+        let talc = self.try_lock_bounded();
+        #[cfg(not(feature = "deferred_frees"))]
+        let talc = Some(self.talck.lock());
+
+        if let Some(mut talc) = talc {
+            let nn_outer = NonNull::new_unchecked(outer);
+
+            return match new_outer_layout.size().cmp(&old_outer_layout.size()) {
+                Ordering::Greater => talc
+                    .grow(nn_outer, old_outer_layout, new_outer_layout.size())
+                    .map_or(null_mut(), |nn| {
+                        nn.as_ptr().add(Self::header_size(old_layout.align()))
+                    }),
+                Ordering::Less => {
+                    talc.shrink(nn_outer, old_outer_layout, new_outer_layout.size());
+                    ptr
+                }
+                Ordering::Equal => ptr,
+            };
+        }
+
+        // the lock is contended, move the allocation into the fallback allocator
+        let new_outer = self.fallback.alloc(new_outer_layout);
+        if new_outer.is_null() {
+            return null_mut();
+        }
+
+        let new_ptr = Self::finish(new_outer, old_layout.align(), OWNER_FALLBACK);
+        new_ptr.copy_from_nonoverlapping(ptr, old_layout.size().min(new_size));
+
+        self.talck.dealloc(outer, old_outer_layout);
+
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::alloc::System;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn contention_fallback_test() {
+        let mut arena = [0u8; 10000];
+        let arena_span: Span = arena.as_mut().into();
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena_span).unwrap() };
+
+        let alloc = ContentionFallback::new(talc.lock::<spin::Mutex<()>>(), System, 10);
+        let layout = Layout::from_size_align(100, 32).unwrap();
+
+        unsafe {
+            let a = alloc.alloc(layout);
+            assert!(arena_span.contains(a));
+            assert!(a as usize % 32 == 0);
+            a.write_bytes(0xAB, layout.size());
+
+            // while the lock is held, allocations are diverted
+            let guard = alloc.talck().lock();
+            let b = alloc.alloc(layout);
+            assert!(!b.is_null() && !arena_span.contains(b));
+            assert!(b as usize % 32 == 0);
+            b.write_bytes(0xCD, layout.size());
+
+            // and talck-owned reallocations move into the fallback
+            #[cfg(feature = "deferred_frees")]
+            let a = alloc.realloc(a, layout, 200);
+            #[cfg(feature = "deferred_frees")]
+            assert!(!a.is_null() && !arena_span.contains(a));
+            drop(guard);
+
+            #[cfg(not(feature = "deferred_frees"))]
+            let a = alloc.realloc(a, layout, 200);
+            assert!((0..100).all(|i| *a.add(i) == 0xAB));
+
+            let b = alloc.realloc(b, layout, 1000);
+            assert!((0..100).all(|i| *b.add(i) == 0xCD));
+
+            let c = alloc.alloc(layout);
+            assert!(arena_span.contains(c));
+            let c = alloc.realloc(c, layout, 500);
+            assert!(arena_span.contains(c));
+            let c = alloc.realloc(c, Layout::from_size_align(500, 32).unwrap(), 50);
+
+            alloc.dealloc(a, Layout::from_size_align(200, 32).unwrap());
+            alloc.dealloc(b, Layout::from_size_align(1000, 32).unwrap());
+            alloc.dealloc(c, Layout::from_size_align(50, 32).unwrap());
+        }
+
+        let (talck, _) = alloc.into_inner();
+        let report = talck.into_inner().report();
+        assert_eq!(report.free_chunk_count, 1);
+    }
+}
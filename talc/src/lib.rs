@@ -12,25 +12,101 @@
 #![cfg_attr(feature = "allocator", feature(allocator_api))]
 #![cfg_attr(feature = "nightly_api", feature(slice_ptr_len))]
 #![cfg_attr(feature = "nightly_api", feature(const_slice_ptr_len))]
+#![cfg_attr(feature = "sanitize", feature(cfg_sanitize))]
 
+#[cfg(feature = "std")]
+extern crate std;
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+mod access;
+mod arena_ptr;
+#[cfg(feature = "c_api")]
+#[doc(hidden)]
+pub mod c_api;
+mod code_heap;
+#[cfg(feature = "alloc")]
+mod collections;
+#[cfg(feature = "cpp_new_delete")]
+#[doc(hidden)]
+pub mod cpp;
+mod entropy;
+#[cfg(feature = "lock_api")]
+mod fallback;
 mod oom_handler;
 mod ptr_utils;
+#[cfg(feature = "redzones")]
+mod redzones;
 mod span;
 mod talc;
+mod talcell;
 
 #[cfg(feature = "lock_api")]
 pub mod locking;
 #[cfg(feature = "lock_api")]
 mod talck;
+#[cfg(feature = "std")]
+mod tracker;
+#[cfg(feature = "trustzone")]
+pub mod trustzone;
 
+#[cfg(feature = "c_api")]
+pub use c_api::{Mallinfo, MALLOC_ALIGN};
+#[cfg(feature = "debug_api")]
+pub use talc::debug;
+#[cfg(feature = "alloc")]
+pub use collections::{try_box_in, try_new_vec_in};
+pub use arena_ptr::ArenaPtr;
+pub use code_heap::{CodeHeap, CodeRegion, PageProtect};
+#[cfg(feature = "getrandom")]
+pub use entropy::GetRandom;
+pub use entropy::{CallbackEntropy, EntropySource, XorShift};
 pub use oom_handler::{ClaimOnOom, ErrOnOom, OomHandler};
+#[cfg(feature = "redzones")]
+pub use redzones::{Redzones, REDZONE_BYTE};
 pub use span::Span;
-pub use talc::Talc;
+pub use talc::{
+    sizing::{
+        bin_of_size, min_additional_arena_size, min_arena_size, required_chunk_size,
+        round_up_to_allocation_size,
+    },
+    BinOccupancy, HeapAdvice, HeapError, HeapReport, LifetimeHint, MallocError, RegionReport, Talc,
+    TalcBuilder, ADVISED_ALIGN_COUNT, BIN_COUNT,
+};
+
+#[cfg(feature = "bin_scan_limit")]
+pub use talc::REALTIME_BIN_SCAN_LIMIT;
 
+#[cfg(feature = "counters")]
+pub use talc::counters::{LiveStats, Stats};
+#[cfg(feature = "event_log")]
+pub use talc::{HeapEvent, HeapOp, EVENT_LOG_CAPACITY};
+#[cfg(feature = "owner_stats")]
+pub use talc::{OwnerStats, TRACKED_OWNER_COUNT};
+#[cfg(feature = "poison")]
+pub use talc::POISON_BYTE;
+#[cfg(all(feature = "timing", target_arch = "arm", target_os = "none"))]
+pub use talc::dwt_cycle_count;
+#[cfg(feature = "timing")]
+pub use talc::OpTiming;
+#[cfg(feature = "trace")]
+pub use talc::TraceEntry;
+#[cfg(all(feature = "trace", feature = "std"))]
+pub use talc::{ReplayReport, Replayer};
+pub use talcell::Talcell;
+
+#[cfg(feature = "lock_api")]
+pub use fallback::ContentionFallback;
 #[cfg(feature = "lock_api")]
-pub use talck::Talck;
+pub use talck::{ChildHeap, ExtendGuard, Talck};
+#[cfg(all(feature = "lock_api", feature = "counters"))]
+pub use talck::Region;
+#[cfg(feature = "std")]
+pub use tracker::{BacktraceTracker, CallsiteLeaks, LeakReport};
 #[cfg(all(target_family = "wasm", feature = "lock_api"))]
 pub use talck::TalckWasm;
+#[cfg(all(target_family = "wasm", target_feature = "atomics", feature = "lock_api"))]
+pub use talck::TalckWasmShared;
 
 #[cfg(all(target_family = "wasm", feature = "lock_api"))]
 pub use oom_handler::WasmHandler;
@@ -1,5 +1,20 @@
-//! Note this only contains [`AssumeUnlockable`] which is not generally recommended.
+//! Note this mostly contains [`AssumeUnlockable`] which is not generally recommended.
 //! Use of the `spin` crate's mutex with [`Talck`](crate::Talc) is a good default.
+//!
+//! [`TicketMutex`] is a fair spin lock, which bounds the wait time of each thread
+//! under heavy contention, at the cost of some uncontended performance.
+//!
+//! [`LazyRtosMutex`] adapts a mutex provided by an RTOS, such as a priority-inheritance
+//! mutex, that has to be created at runtime. RTOS mutexes that can be statically
+//! initialized can instead implement [`RawMutex`](lock_api::RawMutex) directly.
+//!
+//! With the `"std"` feature, [`StdMutex`] blocks waiting threads using the operating
+//! system instead of spinning, which suits hosted targets with more threads than cores.
+//!
+//! Platform-specific locks are available behind feature flags:
+//! - `"basepri"`: `BasePriMutex` masks interrupts up to a priority ceiling on ARMv7-M/ARMv8-M Mainline.
+//! - `"riscv_machine_mode"`: `MachineModeMutex` disables interrupts and spins on RISC-V in M-mode.
+//! - `"critical_section"`: `CriticalSectionMutex` holds a critical section from the `critical-section` crate.
 
 /// #### WARNING: [`AssumeUnlockable`] may cause undefined behaviour without `unsafe` code!
 ///
@@ -31,3 +46,782 @@ unsafe impl lock_api::RawMutex for AssumeUnlockable {
 
     unsafe fn unlock(&self) {}
 }
+
+/// A fair spin lock [`RawMutex`](lock_api::RawMutex) implementation.
+///
+/// Threads acquire the lock in the order they started waiting for it, so no thread
+/// is starved while others repeatedly reacquire the lock.
+///
+/// ```
+/// # use talc::{*, locking::TicketMutex};
+/// static ALLOCATOR: Talck<TicketMutex, ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+#[cfg(target_has_atomic = "ptr")]
+pub struct TicketMutex {
+    next_ticket: core::sync::atomic::AtomicUsize,
+    now_serving: core::sync::atomic::AtomicUsize,
+}
+
+#[cfg(target_has_atomic = "ptr")]
+unsafe impl lock_api::RawMutex for TicketMutex {
+    const INIT: Self = Self {
+        next_ticket: core::sync::atomic::AtomicUsize::new(0),
+        now_serving: core::sync::atomic::AtomicUsize::new(0),
+    };
+
+    // A spinlock guard can be sent to another thread and unlocked there
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock(&self) {
+        use core::sync::atomic::Ordering;
+
+        let ticket = self.next_ticket.fetch_add(1, Ordering::Relaxed);
+
+        while self.now_serving.load(Ordering::Acquire) != ticket {
+            core::hint::spin_loop();
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        use core::sync::atomic::Ordering;
+
+        let ticket = self.now_serving.load(Ordering::Relaxed);
+
+        self.next_ticket
+            .compare_exchange(ticket, ticket.wrapping_add(1), Ordering::Acquire, Ordering::Relaxed)
+            .is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        use core::sync::atomic::Ordering;
+
+        // only the holder of the lock modifies `now_serving`
+        let ticket = self.now_serving.load(Ordering::Relaxed);
+        self.now_serving.store(ticket.wrapping_add(1), Ordering::Release);
+    }
+
+    fn is_locked(&self) -> bool {
+        use core::sync::atomic::Ordering;
+
+        self.next_ticket.load(Ordering::Relaxed) != self.now_serving.load(Ordering::Relaxed)
+    }
+}
+
+/// A [`RawMutex`](lock_api::RawMutex) implementation that blocks waiting threads
+/// using the operating system's primitives, rather than spinning.
+///
+/// Spinning on an oversubscribed host wastes CPU time, and inflates tail latencies when
+/// the thread holding the lock is descheduled. The uncontended path is a single atomic
+/// operation, as with a spin lock. See [`StdTalck`] for the ready-made alias.
+///
+/// On platforms where the standard library's `Mutex` or `Condvar` allocate memory,
+/// this mustn't guard the global allocator.
+///
+/// ```
+/// # use talc::{*, locking::StdMutex};
+/// static ALLOCATOR: Talck<StdMutex, ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+#[cfg(feature = "std")]
+pub struct StdMutex {
+    locked: core::sync::atomic::AtomicBool,
+    /// The number of threads blocked, or about to block, in `lock`.
+    waiters: core::sync::atomic::AtomicUsize,
+    mutex: std::sync::Mutex<()>,
+    condvar: std::sync::Condvar,
+}
+
+#[cfg(feature = "std")]
+unsafe impl lock_api::RawMutex for StdMutex {
+    const INIT: Self = Self {
+        locked: core::sync::atomic::AtomicBool::new(false),
+        waiters: core::sync::atomic::AtomicUsize::new(0),
+        mutex: std::sync::Mutex::new(()),
+        condvar: std::sync::Condvar::new(),
+    };
+
+    // The lock isn't tied to the thread that acquired it
+    type GuardMarker = lock_api::GuardSend;
+
+    fn lock(&self) {
+        use core::sync::atomic::Ordering;
+
+        if self.try_lock() {
+            return;
+        }
+
+        // the lock is only ever held briefly, so poisoning is of no concern
+        let mut guard = self.mutex.lock().unwrap_or_else(|e| e.into_inner());
+        self.waiters.fetch_add(1, Ordering::SeqCst);
+
+        // `unlock` notifies while holding `mutex` if it sees any waiters, so checking the
+        // lock and waiting while holding `mutex` can't miss its release
+        while !self.try_lock() {
+            guard = self.condvar.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+
+        self.waiters.fetch_sub(1, Ordering::SeqCst);
+    }
+
+    fn try_lock(&self) -> bool {
+        use core::sync::atomic::Ordering;
+
+        self.locked.compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed).is_ok()
+    }
+
+    unsafe fn unlock(&self) {
+        use core::sync::atomic::Ordering;
+
+        self.locked.store(false, Ordering::SeqCst);
+
+        if self.waiters.load(Ordering::SeqCst) != 0 {
+            let _guard = self.mutex.lock().unwrap_or_else(|e| e.into_inner());
+            self.condvar.notify_one();
+        }
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(core::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// A [`Talck`](crate::Talck) that blocks waiting threads using the operating system,
+/// see [`StdMutex`].
+///
+/// ```
+/// # use talc::{*, locking::StdTalck};
+/// static ALLOCATOR: StdTalck<ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+#[cfg(feature = "std")]
+pub type StdTalck<O> = crate::Talck<StdMutex, O>;
+
+/// A mutex provided by an operating system, identified by a handle.
+///
+/// Implement this to use an RTOS's mutexes with [`LazyRtosMutex`]. Using a
+/// priority-inheritance mutex prevents a low-priority task holding the heap lock
+/// from blocking a high-priority task that allocates indefinitely.
+///
+/// # Safety
+/// - `create` must return a non-null handle to a new, unlocked mutex, or panic.
+/// - `lock` must block until the mutex is acquired by the calling task, and `try_lock`
+///   must acquire it if possible without blocking, returning whether it did.
+/// - `unlock` must release the mutex.
+/// - The mutex must provide mutual exclusion between all tasks that use the allocator.
+///
+/// None of these may allocate memory using the allocator the mutex is guarding.
+///
+/// # Example
+/// ```ignore
+/// struct FreeRtosMutex;
+///
+/// unsafe impl RtosMutex for FreeRtosMutex {
+///     fn create() -> *mut core::ffi::c_void {
+///         // FreeRTOS mutexes use priority inheritance
+///         unsafe { xSemaphoreCreateMutex() }
+///     }
+///     unsafe fn destroy(handle: *mut core::ffi::c_void) {
+///         vSemaphoreDelete(handle)
+///     }
+///     unsafe fn lock(handle: *mut core::ffi::c_void) {
+///         xSemaphoreTake(handle, portMAX_DELAY);
+///     }
+///     unsafe fn try_lock(handle: *mut core::ffi::c_void) -> bool {
+///         xSemaphoreTake(handle, 0) == pdTRUE
+///     }
+///     unsafe fn unlock(handle: *mut core::ffi::c_void) {
+///         xSemaphoreGive(handle);
+///     }
+/// }
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<LazyRtosMutex<FreeRtosMutex>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+pub unsafe trait RtosMutex {
+    /// Create a new mutex, returning its handle.
+    fn create() -> *mut core::ffi::c_void;
+
+    /// Destroy a mutex that was never locked.
+    ///
+    /// # Safety
+    /// `handle` must have been returned by `create`, and must not be used afterwards.
+    unsafe fn destroy(handle: *mut core::ffi::c_void);
+
+    /// Acquire the mutex, blocking until it is available.
+    ///
+    /// # Safety
+    /// `handle` must have been returned by `create`.
+    unsafe fn lock(handle: *mut core::ffi::c_void);
+
+    /// Attempt to acquire the mutex without blocking, returning whether it was acquired.
+    ///
+    /// # Safety
+    /// `handle` must have been returned by `create`.
+    unsafe fn try_lock(handle: *mut core::ffi::c_void) -> bool;
+
+    /// Release the mutex.
+    ///
+    /// # Safety
+    /// `handle` must have been returned by `create`, and the mutex must be held by the caller.
+    unsafe fn unlock(handle: *mut core::ffi::c_void);
+}
+
+/// A [`RawMutex`](lock_api::RawMutex) that wraps an [`RtosMutex`], creating it when it's first locked.
+///
+/// This allows runtime-created mutexes, such as an RTOS's priority-inheritance mutexes,
+/// to be used in a `static` [`Talck`](crate::Talck).
+///
+/// If multiple tasks race to create the mutex, all but one of the created mutexes are destroyed.
+/// Once created, the mutex is never destroyed.
+#[cfg(target_has_atomic = "ptr")]
+pub struct LazyRtosMutex<M: RtosMutex> {
+    handle: core::sync::atomic::AtomicPtr<core::ffi::c_void>,
+    _phantom: core::marker::PhantomData<M>,
+}
+
+#[cfg(target_has_atomic = "ptr")]
+impl<M: RtosMutex> LazyRtosMutex<M> {
+    /// Returns the handle of the mutex, creating it if necessary.
+    fn handle(&self) -> *mut core::ffi::c_void {
+        use core::sync::atomic::Ordering;
+
+        let handle = self.handle.load(Ordering::Acquire);
+        if !handle.is_null() {
+            return handle;
+        }
+
+        let new_handle = M::create();
+        assert!(!new_handle.is_null(), "RtosMutex::create returned a null handle!");
+
+        match self.handle.compare_exchange(
+            core::ptr::null_mut(),
+            new_handle,
+            Ordering::AcqRel,
+            Ordering::Acquire,
+        ) {
+            Ok(_) => new_handle,
+            Err(handle) => {
+                // another task won the race
+                unsafe { M::destroy(new_handle) };
+                handle
+            }
+        }
+    }
+}
+
+#[cfg(target_has_atomic = "ptr")]
+unsafe impl<M: RtosMutex> lock_api::RawMutex for LazyRtosMutex<M> {
+    const INIT: Self = Self {
+        handle: core::sync::atomic::AtomicPtr::new(core::ptr::null_mut()),
+        _phantom: core::marker::PhantomData,
+    };
+
+    // RTOS mutexes are generally owned by the task that locked them
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock(&self) {
+        unsafe { M::lock(self.handle()) }
+    }
+
+    fn try_lock(&self) -> bool {
+        unsafe { M::try_lock(self.handle()) }
+    }
+
+    unsafe fn unlock(&self) {
+        // the mutex must have been created in order to be locked
+        M::unlock(self.handle.load(core::sync::atomic::Ordering::Relaxed))
+    }
+}
+
+/// Stop at a lock that can never be acquired, such as one already held by the caller,
+/// without unwinding, as unwinding out of the allocator may deallocate and lock it again.
+#[cfg(any(
+    feature = "critical_section",
+    all(
+        feature = "basepri",
+        target_arch = "arm",
+        target_feature = "mclass",
+        target_feature = "v7"
+    )
+))]
+#[cold]
+fn lock_failed(message: &str) -> ! {
+    #[cfg(feature = "std")]
+    {
+        std::eprintln!("{}", message);
+        std::process::abort()
+    }
+
+    #[cfg(not(feature = "std"))]
+    {
+        let _ = message;
+
+        // SAFETY: undefined instructions raise an exception, which the platform treats
+        // as fatal, e.g. a HardFault on Cortex-M
+        #[cfg(target_arch = "arm")]
+        unsafe {
+            core::arch::asm!("udf #0", options(noreturn, nomem, nostack))
+        };
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
+        unsafe {
+            core::arch::asm!("unimp", options(noreturn, nomem, nostack))
+        };
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        unsafe {
+            core::arch::asm!("ud2", options(noreturn, nomem, nostack))
+        };
+        #[cfg(target_arch = "wasm32")]
+        core::arch::wasm32::unreachable();
+
+        #[allow(unreachable_code)]
+        loop {
+            core::hint::spin_loop();
+        }
+    }
+}
+
+/// A [`RawMutex`](lock_api::RawMutex) for single-core Cortex-M (ARMv7-M/ARMv8-M Mainline) that
+/// masks interrupts using `BASEPRI` instead of disabling them globally.
+///
+/// Locking raises `BASEPRI` to `CEILING` (only ever increasing the masking level, via `BASEPRI_MAX`)
+/// and unlocking restores the previous value. Interrupts with a higher priority than `CEILING`
+/// (a numerically lower priority value) are never delayed by heap activity.
+///
+/// `CEILING` is the raw value written to `BASEPRI`, i.e. the priority already shifted into the
+/// implemented priority bits. It must be non-zero, as writing zero disables `BASEPRI` masking.
+///
+/// # Usage
+/// Every execution context that allocates must run at a priority of `CEILING` or lower
+/// (a priority value numerically greater than or equal to `CEILING`). Interrupt handlers
+/// above the ceiling must never allocate, deallocate, or otherwise lock the allocator. This
+/// is checked: locking from above the ceiling, or re-entrantly, faults with an undefined
+/// instruction instead of deadlocking.
+///
+/// This lock does not provide mutual exclusion between cores.
+///
+/// ```ignore
+/// use talc::{*, locking::BasePriMutex};
+///
+/// // allow interrupts of priority 0x00..0x40 to preempt the allocator (with 4 priority bits)
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<BasePriMutex<0x40>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+#[cfg(all(
+    feature = "basepri",
+    target_arch = "arm",
+    target_feature = "mclass",
+    target_feature = "v7"
+))]
+pub struct BasePriMutex<const CEILING: u8> {
+    locked: core::cell::UnsafeCell<bool>,
+    prev_basepri: core::cell::UnsafeCell<u32>,
+}
+
+// SAFETY: the state is only accessed while BASEPRI masks all the contexts that may lock,
+// and the lock is only intended for single-core systems.
+#[cfg(all(
+    feature = "basepri",
+    target_arch = "arm",
+    target_feature = "mclass",
+    target_feature = "v7"
+))]
+unsafe impl<const CEILING: u8> Sync for BasePriMutex<CEILING> {}
+
+#[cfg(all(
+    feature = "basepri",
+    target_arch = "arm",
+    target_feature = "mclass",
+    target_feature = "v7"
+))]
+impl<const CEILING: u8> BasePriMutex<CEILING> {
+    /// Raise `BASEPRI` to `CEILING`, returning the previous value.
+    #[inline]
+    fn raise_basepri() -> u32 {
+        let prev: u32;
+        // SAFETY: reading and raising BASEPRI has no memory safety implications.
+        // Not using `nomem` ensures memory accesses aren't reordered across these.
+        unsafe {
+            core::arch::asm!("mrs {}, BASEPRI", out(reg) prev, options(nostack, preserves_flags));
+            core::arch::asm!(
+                "msr BASEPRI_MAX, {}",
+                in(reg) CEILING as u32,
+                options(nostack, preserves_flags)
+            );
+        }
+        prev
+    }
+
+    #[inline]
+    fn restore_basepri(prev: u32) {
+        // SAFETY: restores the BASEPRI value that was read by `raise_basepri`.
+        unsafe {
+            core::arch::asm!("msr BASEPRI, {}", in(reg) prev, options(nostack, preserves_flags));
+        }
+    }
+}
+
+#[cfg(all(
+    feature = "basepri",
+    target_arch = "arm",
+    target_feature = "mclass",
+    target_feature = "v7"
+))]
+unsafe impl<const CEILING: u8> lock_api::RawMutex for BasePriMutex<CEILING> {
+    const INIT: Self = {
+        assert!(CEILING != 0, "a BASEPRI ceiling of zero does not mask any interrupts");
+
+        Self {
+            locked: core::cell::UnsafeCell::new(false),
+            prev_basepri: core::cell::UnsafeCell::new(0),
+        }
+    };
+
+    // BASEPRI must be restored by the context that raised it
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock(&self) {
+        if !self.try_lock() {
+            lock_failed("BasePriMutex was locked re-entrantly or from above its priority ceiling");
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        let prev = Self::raise_basepri();
+
+        // SAFETY: all contexts that may lock are now masked
+        unsafe {
+            if *self.locked.get() {
+                Self::restore_basepri(prev);
+                return false;
+            }
+
+            *self.locked.get() = true;
+            *self.prev_basepri.get() = prev;
+        }
+
+        true
+    }
+
+    unsafe fn unlock(&self) {
+        *self.locked.get() = false;
+        Self::restore_basepri(*self.prev_basepri.get());
+    }
+
+    fn is_locked(&self) -> bool {
+        let prev = Self::raise_basepri();
+        // SAFETY: all contexts that may lock are now masked
+        let locked = unsafe { *self.locked.get() };
+        Self::restore_basepri(prev);
+        locked
+    }
+}
+
+/// A [`RawMutex`](lock_api::RawMutex) for RISC-V firmware and kernels running in machine mode.
+///
+/// Locking saves and clears `mstatus.MIE`, then acquires a spin lock using atomic memory
+/// operations, allowing use from interrupt handlers as well as across harts. Unlocking releases
+/// the spin lock and restores `mstatus.MIE`. Interrupts are re-enabled while waiting
+/// for another hart to release the lock.
+///
+/// Requires the `A` extension and must be used from M-mode, as `mstatus` is not accessible otherwise.
+#[cfg(all(feature = "riscv_machine_mode", any(target_arch = "riscv32", target_arch = "riscv64")))]
+pub struct MachineModeMutex {
+    locked: core::sync::atomic::AtomicBool,
+    prev_mie: core::cell::UnsafeCell<bool>,
+}
+
+// SAFETY: `prev_mie` is only accessed by the holder of the lock
+#[cfg(all(feature = "riscv_machine_mode", any(target_arch = "riscv32", target_arch = "riscv64")))]
+unsafe impl Sync for MachineModeMutex {}
+
+#[cfg(all(feature = "riscv_machine_mode", any(target_arch = "riscv32", target_arch = "riscv64")))]
+impl MachineModeMutex {
+    const MSTATUS_MIE: usize = 1 << 3;
+
+    /// Clear `mstatus.MIE`, returning whether it was set.
+    #[inline]
+    fn disable_interrupts() -> bool {
+        let mstatus: usize;
+        // SAFETY: clearing MIE has no memory safety implications.
+        // Not using `nomem` ensures memory accesses aren't reordered across this.
+        unsafe {
+            core::arch::asm!("csrrci {}, mstatus, 8", out(reg) mstatus, options(nostack, preserves_flags));
+        }
+        mstatus & Self::MSTATUS_MIE != 0
+    }
+
+    #[inline]
+    fn restore_interrupts(mie: bool) {
+        if mie {
+            // SAFETY: MIE was set prior to `disable_interrupts`
+            unsafe {
+                core::arch::asm!("csrsi mstatus, 8", options(nostack, preserves_flags));
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "riscv_machine_mode", any(target_arch = "riscv32", target_arch = "riscv64")))]
+unsafe impl lock_api::RawMutex for MachineModeMutex {
+    const INIT: Self = Self {
+        locked: core::sync::atomic::AtomicBool::new(false),
+        prev_mie: core::cell::UnsafeCell::new(false),
+    };
+
+    // mstatus.MIE must be restored on the hart that cleared it
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock(&self) {
+        while !self.try_lock() {
+            while self.is_locked() {
+                core::hint::spin_loop();
+            }
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        use core::sync::atomic::Ordering;
+
+        let mie = Self::disable_interrupts();
+
+        if self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok() {
+            // SAFETY: the lock is held
+            unsafe { *self.prev_mie.get() = mie };
+            true
+        } else {
+            Self::restore_interrupts(mie);
+            false
+        }
+    }
+
+    unsafe fn unlock(&self) {
+        let mie = *self.prev_mie.get();
+        self.locked.store(false, core::sync::atomic::Ordering::Release);
+        Self::restore_interrupts(mie);
+    }
+
+    fn is_locked(&self) -> bool {
+        self.locked.load(core::sync::atomic::Ordering::Relaxed)
+    }
+}
+
+/// A [`RawMutex`](lock_api::RawMutex) built on the `critical-section` crate.
+///
+/// Locking enters a critical section, which is held until unlocking, so the allocator can
+/// be used from both thread and interrupt context. On single-core MCUs, this typically
+/// disables interrupts. The platform must provide a `critical-section` implementation.
+///
+/// Locking re-entrantly, such as from an OOM handler that allocates, aborts with `std`,
+/// or otherwise faults with an undefined instruction, instead of deadlocking.
+///
+/// ```ignore
+/// use talc::{*, locking::CriticalSectionMutex};
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<CriticalSectionMutex, ErrOnOom> = Talc::new(ErrOnOom).lock();
+/// ```
+#[cfg(feature = "critical_section")]
+pub struct CriticalSectionMutex {
+    locked: core::cell::UnsafeCell<bool>,
+    restore_state: core::cell::UnsafeCell<critical_section::RestoreState>,
+}
+
+// SAFETY: the state is only accessed within a critical section
+#[cfg(feature = "critical_section")]
+unsafe impl Sync for CriticalSectionMutex {}
+
+#[cfg(feature = "critical_section")]
+unsafe impl lock_api::RawMutex for CriticalSectionMutex {
+    const INIT: Self = Self {
+        locked: core::cell::UnsafeCell::new(false),
+        restore_state: core::cell::UnsafeCell::new(critical_section::RestoreState::invalid()),
+    };
+
+    // the critical section must be released by the context that acquired it
+    type GuardMarker = lock_api::GuardNoSend;
+
+    fn lock(&self) {
+        if !self.try_lock() {
+            lock_failed("CriticalSectionMutex was locked re-entrantly");
+        }
+    }
+
+    fn try_lock(&self) -> bool {
+        // SAFETY: released in `unlock`, or below if the lock is already held
+        let restore_state = unsafe { critical_section::acquire() };
+
+        // SAFETY: within the critical section
+        unsafe {
+            if *self.locked.get() {
+                critical_section::release(restore_state);
+                return false;
+            }
+
+            *self.locked.get() = true;
+            *self.restore_state.get() = restore_state;
+        }
+
+        true
+    }
+
+    unsafe fn unlock(&self) {
+        *self.locked.get() = false;
+        critical_section::release(*self.restore_state.get());
+    }
+
+    fn is_locked(&self) -> bool {
+        critical_section::with(|_| {
+            // SAFETY: within the critical section
+            unsafe { *self.locked.get() }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lock_api::RawMutex;
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn std_mutex_test() {
+        let mutex = lock_api::Mutex::<StdMutex, usize>::new(0);
+
+        assert!(!mutex.is_locked());
+        let guard = mutex.lock();
+        assert!(mutex.is_locked());
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+
+        // more threads than most machines have cores, which block rather than spin
+        std::thread::scope(|scope| {
+            for _ in 0..16 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        let mut guard = mutex.lock();
+                        *guard += 1;
+                        if *guard % 100 == 0 {
+                            std::thread::yield_now();
+                        }
+                    }
+                });
+            }
+        });
+
+        assert_eq!(mutex.into_inner(), 16000);
+    }
+
+    #[cfg(feature = "critical_section")]
+    #[test]
+    fn critical_section_mutex_test() {
+        let mutex = lock_api::Mutex::<CriticalSectionMutex, usize>::new(0);
+
+        assert!(!mutex.is_locked());
+        let guard = mutex.lock();
+        assert!(mutex.is_locked());
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        *mutex.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(*mutex.lock(), 4000);
+    }
+
+    #[test]
+    fn ticket_mutex_test() {
+        let mutex = lock_api::Mutex::<TicketMutex, usize>::new(0);
+
+        assert!(!mutex.is_locked());
+        let guard = mutex.lock();
+        assert!(mutex.is_locked());
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+        assert!(mutex.try_lock().is_some());
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                // ticket locks hand over slowly when threads outnumber the cores, keep this short
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        *mutex.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        assert_eq!(mutex.into_inner(), 4000);
+
+        let raw = <TicketMutex as RawMutex>::INIT;
+        assert!(raw.try_lock());
+        assert!(!raw.try_lock());
+        unsafe { raw.unlock() };
+        assert!(!raw.is_locked());
+    }
+
+    static CREATED: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+    static DESTROYED: core::sync::atomic::AtomicUsize = core::sync::atomic::AtomicUsize::new(0);
+
+    /// Stands in for an RTOS mutex, using a spin lock allocated by the system allocator.
+    ///
+    /// This isn't a ticket lock, as those crawl when the test threads outnumber the cores.
+    struct MockRtosMutex;
+
+    unsafe impl RtosMutex for MockRtosMutex {
+        fn create() -> *mut core::ffi::c_void {
+            CREATED.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            std::boxed::Box::into_raw(std::boxed::Box::new(<spin::Mutex<()> as RawMutex>::INIT))
+                .cast()
+        }
+
+        unsafe fn destroy(handle: *mut core::ffi::c_void) {
+            DESTROYED.fetch_add(1, core::sync::atomic::Ordering::Relaxed);
+            drop(std::boxed::Box::from_raw(handle.cast::<spin::Mutex<()>>()));
+        }
+
+        unsafe fn lock(handle: *mut core::ffi::c_void) {
+            RawMutex::lock(&*handle.cast::<spin::Mutex<()>>())
+        }
+
+        unsafe fn try_lock(handle: *mut core::ffi::c_void) -> bool {
+            RawMutex::try_lock(&*handle.cast::<spin::Mutex<()>>())
+        }
+
+        unsafe fn unlock(handle: *mut core::ffi::c_void) {
+            RawMutex::unlock(&*handle.cast::<spin::Mutex<()>>())
+        }
+    }
+
+    #[test]
+    fn lazy_rtos_mutex_test() {
+        let mutex = lock_api::Mutex::<LazyRtosMutex<MockRtosMutex>, usize>::new(0);
+        assert_eq!(CREATED.load(core::sync::atomic::Ordering::Relaxed), 0);
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..10000 {
+                        *mutex.lock() += 1;
+                    }
+                });
+            }
+        });
+
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+
+        // exactly one mutex survives any creation races
+        let created = CREATED.load(core::sync::atomic::Ordering::Relaxed);
+        assert_eq!(created - DESTROYED.load(core::sync::atomic::Ordering::Relaxed), 1);
+
+        assert_eq!(mutex.into_inner(), 40000);
+    }
+}
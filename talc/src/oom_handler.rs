@@ -2,14 +2,62 @@ use core::alloc::Layout;
 
 use crate::{Span, Talc};
 
+/// Handles out-of-memory conditions, e.g. by claiming more memory.
+///
+/// Handlers can hold state, accessible through [`Talc::oom_handler`]. Give them
+/// a `const fn` constructor, as the bundled handlers have, such that a
+/// `#[global_allocator]` using them can be initialized in a `static`.
+///
+/// # Example
+/// A handler that claims reserve regions one at a time, counting how often it ran.
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::Layout;
+/// struct ReserveOnOom {
+///     reserves: [Span; 2],
+///     handled: usize,
+/// }
+///
+/// impl OomHandler for ReserveOnOom {
+///     fn handle_oom(talc: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+///         talc.oom_handler.handled += 1;
+///
+///         let reserves = &mut talc.oom_handler.reserves;
+///         let reserve = reserves.iter_mut().find(|s| !s.is_empty()).ok_or(())?;
+///         let memory = core::mem::replace(reserve, Span::empty());
+///         unsafe { talc.claim(memory).map(|_| ()) }
+///     }
+/// }
+///
+/// let mut first = [0u8; 10000];
+/// let mut second = [0u8; 10000];
+/// let mut talc = Talc::new(ReserveOnOom {
+///     reserves: [first.as_mut().into(), second.as_mut().into()],
+///     handled: 0,
+/// });
+///
+/// let layout = Layout::new::<[u8; 5000]>();
+/// unsafe {
+///     talc.malloc(layout).unwrap();
+///     talc.malloc(layout).unwrap();
+/// }
+/// assert_eq!(talc.oom_handler.handled, 2);
+/// ```
 pub trait OomHandler: Sized {
     /// Given the allocator and the `layout` of the allocation that caused
     /// OOM, resize or claim and return `Ok(())` or fail by returning `Err(())`.
     ///
-    /// This function is called repeatedly if the allocator is still out of memory.
-    /// Therefore an infinite loop will occur if `Ok(())` is repeatedly returned
-    /// without extending or claiming new memory.
+    /// This function is called repeatedly if the allocator is still out of memory,
+    /// up to [`RETRY_LIMIT`](OomHandler::RETRY_LIMIT) times per allocation,
+    /// after which the allocation fails.
     fn handle_oom(talc: &mut Talc<Self>, layout: Layout) -> Result<(), ()>;
+
+    /// The maximum number of times `handle_oom` is called for a single allocation.
+    ///
+    /// This prevents a handler that returns `Ok(())` without making enough memory
+    /// available from hanging the allocator. Handlers that make progress in small
+    /// steps, such as by repeatedly extending a heap, may need to raise it.
+    const RETRY_LIMIT: usize = 64;
 }
 
 /// Doesn't handle out-of-memory conditions, immediate allocation error occurs.
@@ -22,38 +70,77 @@ impl OomHandler for ErrOnOom {
 }
 
 /// An out-of-memory handler that attempts to claim the
-/// memory within the given [`Span`] upon OOM.
+/// memory within the given [`Span`]s upon OOM.
 ///
-/// The contained span is then overwritten with an empty span.
+/// Each span is claimed the first time allocation fails after the spans before it were
+/// claimed, and is then overwritten with an empty span. This defers touching the memory,
+/// such as that of a `static` array, until it's needed.
 ///
-/// If the span is empty or `claim` fails, allocation failure occurs.
-pub struct ClaimOnOom(Span);
+/// If all the spans are empty or `claim` fails on all of them, allocation failure occurs.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::{GlobalAlloc, Layout};
+/// static mut FIRST: [u8; 10000] = [0; 10000];
+/// static mut FALLBACK: [u8; 10000] = [0; 10000];
+///
+/// static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom<2>> = Talc::new(unsafe {
+///     ClaimOnOom::new_many([
+///         Span::from_array(core::ptr::addr_of_mut!(FIRST)),
+///         Span::from_array(core::ptr::addr_of_mut!(FALLBACK)),
+///     ])
+/// })
+/// .lock();
+///
+/// // the second allocation doesn't fit in the first arena, so the fallback is claimed
+/// let layout = Layout::new::<[u8; 6000]>();
+/// unsafe {
+///     assert!(!ALLOCATOR.alloc(layout).is_null());
+///     assert!(!ALLOCATOR.alloc(layout).is_null());
+/// }
+/// ```
+pub struct ClaimOnOom<const N: usize = 1>([Span; N]);
 
 impl ClaimOnOom {
     /// # Safety
     /// The memory within the given [`Span`] must conform to
     /// the requirements laid out by [`claim`](Talc::claim).
     pub const unsafe fn new(span: Span) -> Self {
-        ClaimOnOom(span)
+        ClaimOnOom([span])
     }
 }
 
-impl OomHandler for ClaimOnOom {
-    fn handle_oom(talc: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
-        if !talc.oom_handler.0.is_empty() {
-            unsafe {
-                talc.claim(talc.oom_handler.0)?;
-            }
+impl<const N: usize> ClaimOnOom<N> {
+    /// Create a handler that claims each of `spans` in turn, one per OOM.
+    ///
+    /// # Safety
+    /// The memory within each of the given [`Span`]s must conform to
+    /// the requirements laid out by [`claim`](Talc::claim).
+    pub const unsafe fn new_many(spans: [Span; N]) -> Self {
+        ClaimOnOom(spans)
+    }
+}
 
-            talc.oom_handler.0 = Span::empty();
+impl<const N: usize> OomHandler for ClaimOnOom<N> {
+    fn handle_oom(talc: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+        for i in 0..N {
+            let span = core::mem::take(&mut talc.oom_handler.0[i]);
 
-            Ok(())
-        } else {
-            Err(())
+            if !span.is_empty() && unsafe { talc.claim(span) }.is_ok() {
+                return Ok(());
+            }
         }
+
+        Err(())
     }
 }
 
+/// An out-of-memory handler that grows WASM memory with `memory.grow`, then
+/// extends the heap over the new pages, or claims them if they don't border it.
+///
+/// This is available on WASM targets without any feature flags. Use it
+/// through [`TalckWasm`](crate::TalckWasm) or [`TalckWasmShared`](crate::TalckWasmShared).
 #[cfg(all(target_family = "wasm", feature = "lock_api"))]
 pub struct WasmHandler {
     prev_heap: Span,
@@ -66,8 +153,9 @@ unsafe impl Send for WasmHandler {}
 impl WasmHandler {
     /// Create a new WASM handler.
     /// # Safety
-    /// [`WasmHandler`] expects to have full control over WASM memory
-    /// and be running in a single-threaded environment.
+    /// [`WasmHandler`] expects to have full control over WASM memory.
+    /// It must be locked by a mutex that synchronizes between threads if
+    /// multiple threads share the memory, such as with `TalckWasmShared`.
     pub const unsafe fn new() -> Self {
         Self { prev_heap: Span::empty() }
     }
@@ -80,8 +168,8 @@ impl OomHandler for WasmHandler {
         const PAGE_SIZE: usize = 1024 * 64;
 
         // growth strategy: just try to grow enough to avoid OOM again on this allocation
-        let required = (layout.size() + 8).max(layout.align() * 2);
-        let mut delta_pages = (required + (PAGE_SIZE - 1)) / PAGE_SIZE;
+        let required = layout.size().saturating_add(8).max(layout.align().saturating_mul(2));
+        let mut delta_pages = required / PAGE_SIZE + (required % PAGE_SIZE != 0) as usize;
 
         let prev = 'prev: {
             // This performs a scan, trying to find a smaller possible
@@ -109,6 +197,8 @@ impl OomHandler for WasmHandler {
             return Err(());
         };
 
+        // memory.grow atomically returns the previous size, so the new pages are
+        // ours to claim, even if other threads are growing shared memory concurrently
         let prev_heap_acme = (prev * PAGE_SIZE) as *mut u8;
         let new_heap_acme = prev_heap_acme.wrapping_add(delta_pages * PAGE_SIZE);
 
@@ -134,3 +224,36 @@ impl OomHandler for WasmHandler {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn claim_on_oom_many_test() {
+        let mut first = [0u8; 10000];
+        let mut tiny = [0u8; 8];
+        let mut second = [0u8; 10000];
+
+        let mut talc = Talc::new(unsafe {
+            ClaimOnOom::new_many([
+                first.as_mut().into(),
+                tiny.as_mut().into(),
+                second.as_mut().into(),
+            ])
+        });
+        let first = Span::from(&mut first);
+        let second = Span::from(&mut second);
+
+        let layout = Layout::new::<[u8; 6000]>();
+        let a = unsafe { talc.malloc(layout).unwrap() };
+        assert!(first.contains(a.as_ptr()));
+
+        // the span that's too small to claim is skipped
+        let b = unsafe { talc.malloc(layout).unwrap() };
+        assert!(second.contains(b.as_ptr()));
+        assert!(talc.oom_handler.0.iter().all(|span| span.is_empty()));
+
+        assert!(unsafe { talc.malloc(layout) }.is_err());
+    }
+}
@@ -7,13 +7,17 @@ pub const ALIGN: usize = core::mem::align_of::<usize>();
 /// Aligns `ptr` up to the next `align_mask + 1`.
 ///
 /// `align_mask` must be a power of two minus one.
+///
+/// If this overflows, the result wraps around the address space.
 #[inline]
 pub fn align_up_by(ptr: *mut u8, align_mask: usize) -> *mut u8 {
     debug_assert!((align_mask + 1).is_power_of_two());
 
     // this incantation maintains provenance of ptr
     // while allowing the compiler to see through the wrapping_add and optimize it
-    ptr.wrapping_add(((ptr as usize + align_mask) & !align_mask) - ptr as usize)
+    ptr.wrapping_add(
+        ((ptr as usize).wrapping_add(align_mask) & !align_mask).wrapping_sub(ptr as usize),
+    )
     // equivalent to the following:
     // ((ptr as usize + align_mask) & !align_mask) as *mut u8
     // i.e. just align up to the next align_mask + 1
@@ -32,6 +36,27 @@ pub fn align_up(ptr: *mut u8) -> *mut u8 {
     offset_ptr.wrapping_sub(offset_ptr as usize % ALIGN)
 }
 
+pub fn is_aligned_to(ptr: *mut u8, align: usize) -> bool {
+    (ptr as usize).trailing_zeros() >= align.trailing_zeros()
+}
+
+/// Returns a dangling pointer aligned for `layout`, for zero-sized allocations.
+pub fn dangling_for(layout: core::alloc::Layout) -> core::ptr::NonNull<u8> {
+    // SAFETY: alignments are nonzero
+    unsafe { core::ptr::NonNull::new_unchecked(layout.align() as *mut u8) }
+}
+
+/// Convert a nonnull and length to a nonnull slice.
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+pub fn nonnull_slice_from_raw_parts(
+    ptr: core::ptr::NonNull<u8>,
+    len: usize,
+) -> core::ptr::NonNull<[u8]> {
+    unsafe {
+        core::ptr::NonNull::new_unchecked(core::ptr::slice_from_raw_parts_mut(ptr.as_ptr(), len))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use core::ptr::null_mut;
@@ -0,0 +1,199 @@
+//! Home of Redzones, a [`GlobalAlloc`] wrapper that guards allocations against overruns.
+
+use core::alloc::{GlobalAlloc, Layout};
+
+/// The byte that redzones are filled with.
+pub const REDZONE_BYTE: u8 = 0xCA;
+
+/// Wraps a [`GlobalAlloc`], such as a [`Talck`](crate::Talck), surrounding each allocation
+/// with at least `GUARD` bytes of [`REDZONE_BYTE`] on either side.
+///
+/// The redzones are checked when the allocation is freed or reallocated, panicking with
+/// the allocation's address and where the redzone was overwritten. This turns buffer
+/// overruns and underruns into immediate diagnostics on targets without memory protection.
+/// Writes that skip over the redzones entirely aren't caught.
+///
+/// The redzone below an allocation is rounded up to its alignment, so over-aligned
+/// allocations cost more.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static mut ARENA: [u8; 10000] = [0; 10000];
+///
+/// static ALLOCATOR: Redzones<Talck<spin::Mutex<()>, ClaimOnOom>, 16> = Redzones::new(
+///     Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA))) })
+///         .lock(),
+/// );
+/// ```
+#[derive(Debug)]
+pub struct Redzones<A: GlobalAlloc, const GUARD: usize> {
+    inner: A,
+}
+
+impl<A: GlobalAlloc, const GUARD: usize> Redzones<A, GUARD> {
+    /// Wrap `inner`, guarding each allocation made through this.
+    pub const fn new(inner: A) -> Self {
+        Self { inner }
+    }
+
+    /// Access the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the size of the redzone below an allocation of `layout`.
+    fn lower_size(layout: Layout) -> usize {
+        (GUARD + layout.align() - 1) & !(layout.align() - 1)
+    }
+
+    /// Returns the layout of the guarded allocation, and the size of the lower redzone.
+    fn guarded_layout(layout: Layout) -> Option<(Layout, usize)> {
+        let lower = Self::lower_size(layout);
+        let size = lower.checked_add(layout.size())?.checked_add(GUARD)?;
+
+        Some((Layout::from_size_align(size, layout.align()).ok()?, lower))
+    }
+
+    /// Fill the redzones around the allocation of `size` bytes at `ptr`.
+    unsafe fn fill(ptr: *mut u8, lower: usize, size: usize) {
+        ptr.sub(lower).write_bytes(REDZONE_BYTE, lower);
+        ptr.add(size).write_bytes(REDZONE_BYTE, GUARD);
+    }
+
+    /// Check the redzones around the allocation of `layout` at `ptr`.
+    ///
+    /// # Panics
+    /// Panics with the allocation's address and the offset of the overwritten byte from it.
+    unsafe fn check(ptr: *mut u8, layout: Layout) {
+        // check outwards from the allocation, as overruns are most likely to hit there
+        let below = (1..=Self::lower_size(layout)).find(|&i| *ptr.sub(i) != REDZONE_BYTE);
+        let above = (0..GUARD).find(|&i| *ptr.add(layout.size() + i) != REDZONE_BYTE);
+
+        let offset = match (below, above) {
+            (None, None) => return,
+            (Some(i), _) => -(i as isize),
+            (None, Some(i)) => (layout.size() + i) as isize,
+        };
+
+        panic!(
+            "redzone overwritten at offset {} of the allocation at {:p} of {} bytes",
+            offset,
+            ptr,
+            layout.size()
+        );
+    }
+}
+
+unsafe impl<A: GlobalAlloc, const GUARD: usize> GlobalAlloc for Redzones<A, GUARD> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let Some((guarded, lower)) = Self::guarded_layout(layout) else {
+            return core::ptr::null_mut();
+        };
+
+        let base = self.inner.alloc(guarded);
+        if base.is_null() {
+            return base;
+        }
+
+        let ptr = base.add(lower);
+        Self::fill(ptr, lower, layout.size());
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let Some((guarded, lower)) = Self::guarded_layout(layout) else {
+            return core::ptr::null_mut();
+        };
+
+        let base = self.inner.alloc_zeroed(guarded);
+        if base.is_null() {
+            return base;
+        }
+
+        let ptr = base.add(lower);
+        Self::fill(ptr, lower, layout.size());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        Self::check(ptr, layout);
+
+        // SAFETY: this succeeded when the allocation was made
+        let (guarded, lower) = Self::guarded_layout(layout).unwrap();
+        self.inner.dealloc(ptr.sub(lower), guarded);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::check(ptr, layout);
+
+        let (guarded, lower) = Self::guarded_layout(layout).unwrap();
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let Some((new_guarded, _)) = Self::guarded_layout(new_layout) else {
+            return core::ptr::null_mut();
+        };
+
+        let base = self.inner.realloc(ptr.sub(lower), guarded, new_guarded.size());
+        if base.is_null() {
+            return base;
+        }
+
+        // the lower redzone moved along with the allocation, if it moved
+        let ptr = base.add(lower);
+        ptr.add(new_size).write_bytes(REDZONE_BYTE, GUARD);
+        ptr
+    }
+}
+
+#[cfg(all(test, feature = "lock_api"))]
+mod tests {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn redzones_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+        let redzones = Redzones::<_, 16>::new(talck);
+
+        let layout = Layout::from_size_align(100, 64).unwrap();
+        unsafe {
+            let ptr = redzones.alloc(layout);
+            assert_eq!(ptr as usize % 64, 0);
+            ptr.write_bytes(0xAB, layout.size());
+            assert_eq!(*ptr.sub(1), REDZONE_BYTE);
+            assert_eq!(*ptr.add(layout.size() + 15), REDZONE_BYTE);
+
+            // the redzones follow the allocation
+            let ptr = redzones.realloc(ptr, layout, 1000);
+            assert!((0..layout.size()).all(|i| *ptr.add(i) == 0xAB));
+            let layout = Layout::from_size_align(1000, 64).unwrap();
+            assert_eq!(*ptr.add(layout.size()), REDZONE_BYTE);
+
+            redzones.dealloc(ptr, layout);
+        }
+
+        #[cfg(feature = "fastbins")]
+        redzones.inner().lock().flush_fastbins();
+        assert_eq!(redzones.inner().lock().report().free_chunk_count, 1);
+    }
+
+    #[test]
+    #[should_panic = "redzone overwritten at offset 24"]
+    fn redzones_overrun_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+        let redzones = Redzones::<_, 8>::new(talck);
+
+        let layout = Layout::new::<[u8; 24]>();
+        unsafe {
+            let ptr = redzones.alloc(layout);
+            ptr.add(layout.size()).write(0);
+            redzones.dealloc(ptr, layout);
+        }
+    }
+}
@@ -247,6 +247,9 @@ impl Span {
         }
     }
     /// Aligns `base` downward and `acme` upward by `align_of::<usize>()`.
+    ///
+    /// # Panics
+    /// Panics if aligning `acme` upward would wrap around the address space.
     #[inline]
     pub fn word_align_outward(self) -> Self {
         if ALIGN > usize::MAX - self.acme as usize {
@@ -266,10 +269,22 @@ impl Span {
     pub fn below(self, max: *mut u8) -> Self {
         Self { base: self.base, acme: if max < self.acme { max } else { self.acme } }
     }
+    /// Raises `base` past the first word of the address space if `self` contains the null address.
+    ///
+    /// This is useful where memory starts at address zero, as [`Talc`](crate::Talc)
+    /// refuses to manage memory containing the null address.
+    #[inline]
+    pub fn exclude_null(self) -> Self {
+        if self.contains(core::ptr::null_mut()) {
+            Self { base: self.base.wrapping_add(ALIGN), acme: self.acme }
+        } else {
+            self
+        }
+    }
 
     /// Returns the [`Span`]s of `self` below and above the `exclude` span, respectively.
     /// Alternatively worded, the set difference `self`\\`exclude`.
-    /// 
+    ///
     /// If `exclude` is empty, `self` and an empty `Span` are returned.
     #[inline]
     pub fn except(self, exclude: Span) -> (Self, Self) {
@@ -417,5 +432,29 @@ mod test {
         assert!(span.extend(1234, 1010) == Span::new(ptr(0), ptr(5678 + 1010)));
         assert!(span.truncate(1234, 1010) == Span::new(ptr(1234 + 1234), ptr(5678 - 1010)));
         assert!(span.truncate(235623, 45235772).is_empty());
+
+        assert_eq!(span.exclude_null(), span);
+        assert_eq!(Span::new(ptr(0), aptr).exclude_null(), Span::new(ptr(ALIGN), aptr));
+        assert!(Span::new(ptr(0), ptr(ALIGN - 1)).exclude_null().is_empty());
+        assert!(Span::empty().exclude_null().is_empty());
+    }
+
+    #[test]
+    fn test_span_address_space_edges() {
+        let top = ptr(usize::MAX);
+        let span = Span::new(ptr(usize::MAX - 1000), top);
+
+        // the top of the address space isn't word-aligned, so it's trimmed off
+        let aligned = span.word_align_inward();
+        assert!(aligned.is_sized());
+        assert_eq!(aligned.get_base_acme().unwrap().1, ptr(usize::MAX - (ALIGN - 1)));
+        assert!(aligned.size() % ALIGN == 0);
+
+        // spans too close to the top to contain an aligned word are empty
+        assert!(Span::new(ptr(usize::MAX - (ALIGN - 2)), top).word_align_inward().is_empty());
+        assert!(Span::from_base_size(ptr(usize::MAX - 10), 20).is_empty());
+
+        assert!(span.truncate(0, usize::MAX).is_empty());
+        assert!(span.truncate(usize::MAX, 0).is_empty());
     }
 }
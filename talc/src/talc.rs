@@ -1,8 +1,59 @@
+mod adopt;
+mod advice;
+mod builder;
+mod child;
+mod housekeeping;
 mod llist;
+mod placement;
+mod prefault;
+mod raw_parts;
+mod realign;
+mod realloc;
+mod report;
+mod scan;
 mod tag;
+mod trim;
+mod untouched;
 
+pub mod sizing;
+
+mod budget;
 #[cfg(feature = "counters")]
 pub mod counters;
+#[cfg(feature = "debug_api")]
+pub mod debug;
+#[cfg(feature = "emergency_reserve")]
+mod emergency;
+#[cfg(feature = "event_log")]
+mod event_log;
+#[cfg(feature = "fastbins")]
+mod fastbins;
+#[cfg(feature = "generations")]
+mod generations;
+#[cfg(all(feature = "owners", feature = "generations"))]
+mod layout_free;
+#[cfg(feature = "owner_stats")]
+mod owner_stats;
+#[cfg(feature = "owners")]
+mod owners;
+#[cfg(feature = "pinning")]
+mod pinning;
+#[cfg(feature = "poison")]
+mod poison;
+#[cfg(feature = "quarantine")]
+mod quarantine;
+#[cfg(feature = "rtt")]
+mod rtt;
+#[cfg(feature = "sanitize")]
+mod sanitize;
+#[cfg(all(feature = "semihosting", target_arch = "arm"))]
+mod semihosting;
+#[cfg(feature = "timing")]
+mod timing;
+#[cfg(feature = "trace")]
+mod trace;
+#[cfg(feature = "user_data")]
+mod user_data;
 
 use crate::{ptr_utils::*, OomHandler, Span};
 use core::{
@@ -12,21 +63,89 @@ use core::{
 use llist::LlistNode;
 use tag::Tag;
 
+pub use advice::{HeapAdvice, ADVISED_ALIGN_COUNT};
+pub use builder::TalcBuilder;
+pub use budget::MallocError;
+#[cfg(feature = "event_log")]
+pub use event_log::{HeapEvent, HeapOp, EVENT_LOG_CAPACITY};
+#[cfg(feature = "owner_stats")]
+pub use owner_stats::{OwnerStats, TRACKED_OWNER_COUNT};
+#[cfg(feature = "poison")]
+pub use poison::POISON_BYTE;
+pub use report::{BinOccupancy, HeapReport, RegionReport};
+pub use scan::HeapError;
+#[cfg(all(feature = "timing", target_arch = "arm", target_os = "none"))]
+pub use timing::dwt_cycle_count;
+#[cfg(feature = "timing")]
+pub use timing::OpTiming;
+#[cfg(feature = "trace")]
+pub use trace::TraceEntry;
+#[cfg(all(feature = "trace", feature = "std"))]
+pub use trace::{ReplayReport, Replayer};
+
 const NODE_SIZE: usize = core::mem::size_of::<LlistNode>();
 const TAG_SIZE: usize = core::mem::size_of::<Tag>();
 
+// a free chunk holds its free list links at its base, and its size or tag at its top,
+// so no chunk can be smaller, regardless of how small the allocation it holds is
 const MIN_TAG_OFFSET: usize = NODE_SIZE;
 const MIN_CHUNK_SIZE: usize = MIN_TAG_OFFSET + TAG_SIZE;
 const MIN_HEAP_SIZE: usize = MIN_CHUNK_SIZE + TAG_SIZE;
 
-const BIN_COUNT: usize = usize::BITS as usize * 2;
+/// The number of bins free chunks are sorted into by size, see [`Talc::bin_histogram`].
+///
+/// The `profile_tiny` feature halves this, along with the bin array at the base of the heap.
+pub const BIN_COUNT: usize = usize::BITS as usize * if TINY_BINS { 1 } else { 2 };
+/// Whether the bins are halved to minimize metadata, with coarser size classes.
+const TINY_BINS: bool = cfg!(feature = "profile_tiny");
+
+/// Alignments from which allocations first look for a chunk that fits regardless of alignment,
+/// rather than checking the smallest sufficiently-sized chunks one by one.
+const LARGE_ALIGN: usize = 1 << 12;
+const BIN_ARRAY_SIZE: usize = core::mem::size_of::<Bin>() * BIN_COUNT;
+
+/// Per-allocation metadata, stored at the base of each allocated chunk.
+///
+/// This is empty unless features that track allocations are enabled.
+#[derive(Debug, Clone, Copy)]
+#[repr(C)]
+struct Header {
+    /// The owner of the allocation, see [`Talc::free_all_owned_by`].
+    #[cfg(feature = "owners")]
+    owner: usize,
+    /// The size of the allocation's layout.
+    #[cfg(feature = "owners")]
+    size: usize,
+    /// Distinguishes this allocation from others at the same address, see [`Talc::is_live`].
+    ///
+    /// This is always odd while allocated, and zeroed when freed.
+    #[cfg(feature = "generations")]
+    generation: usize,
+    /// Whether the allocation mustn't be moved, see [`Talc::set_pinned`].
+    #[cfg(feature = "pinning")]
+    pinned: bool,
+    /// A word for the caller's use, see [`Talc::set_user_data`].
+    #[cfg(feature = "user_data")]
+    user_data: usize,
+}
+
+/// The space reserved below each allocation.
+///
+/// With the `generations` feature, the word immediately below each allocation
+/// holds the chunk base pointer, so that the header can be found without the tag.
+const HEADER_SIZE: usize = ((core::mem::size_of::<Header>() + (ALIGN - 1)) & !(ALIGN - 1))
+    + if cfg!(feature = "generations") { WORD_SIZE } else { 0 };
 
 type Bin = Option<NonNull<LlistNode>>;
 
+/// The default bin scan limit with the `profile_realtime` feature, see [`Talc::set_bin_scan_limit`].
+#[cfg(feature = "bin_scan_limit")]
+pub const REALTIME_BIN_SCAN_LIMIT: usize = 8;
+
 // Free chunk (3x ptr size minimum):
 //   ?? | NODE: LlistNode (2 * ptr), SIZE: usize, ..???.., SIZE: usize | ??
-// Reserved chunk (1x ptr size of overhead):
-//   ?? |       ???????         , TAG: Tag (ptr) | ??
+// Reserved chunk (1x ptr size of overhead, plus the header if any):
+//   ?? | HEADER: Header, ???????, TAG: Tag (ptr) | ??
 
 // TAG contains a pointer to the bottom of the reserved chunk,
 // a is_allocated (set) bit flag differentiating itself from a free chunk
@@ -87,6 +206,25 @@ unsafe fn is_gap_above_heap_base(heap_base: *mut u8) -> bool {
     heap_base.cast::<Tag>().read().is_above_free()
 }
 
+/// Returns a pointer to the header of an allocated chunk.
+#[cfg(any(
+    feature = "owners",
+    feature = "generations",
+    feature = "pinning",
+    feature = "user_data"
+))]
+#[inline]
+unsafe fn header_from_chunk_base(chunk_base: *mut u8) -> *mut Header {
+    chunk_base.cast()
+}
+
+/// Returns a pointer to the header of an allocation, given its pointer and size.
+#[cfg(any(feature = "owners", feature = "pinning", feature = "user_data"))]
+#[inline]
+unsafe fn header_from_alloc_ptr(ptr: *mut u8, size: usize) -> *mut Header {
+    header_from_chunk_base(tag_from_alloc_ptr(ptr, size).1.chunk_base())
+}
+
 /// Determines the tag pointer and retrieves the tag, given the allocated pointer.
 #[inline]
 unsafe fn tag_from_alloc_ptr(ptr: *mut u8, size: usize) -> (*mut u8, Tag) {
@@ -127,22 +265,31 @@ unsafe fn bin_of_size(size: usize) -> usize {
     // note to anyone adding support for another word size: use buckets.py to figure it out
     const ERRMSG: &str = "Unsupported system word size, open an issue/create a PR!";
 
+    // with half the bins, the linear ranges shrink and the divisions coarsen,
+    // such that the last bin still only holds chunks of hundreds of MiB
+
     /// up to what size do we use a bin for every multiple of a word
-    const WORD_BIN_LIMIT: usize = match WORD_SIZE {
-        8 => 256,
-        4 => 64,
+    const WORD_BIN_LIMIT: usize = match (WORD_SIZE, TINY_BINS) {
+        (8, false) => 256,
+        (8, true) => 128,
+        (4, false) => 64,
+        (4, true) => 32,
         _ => panic!("{}", ERRMSG),
     };
     /// up to what size beyond that do we use a bin for every multiple of a doubleword
-    const DOUBLE_BIN_LIMIT: usize = match WORD_SIZE {
-        8 => 512,
-        4 => 128,
+    const DOUBLE_BIN_LIMIT: usize = match (WORD_SIZE, TINY_BINS) {
+        (8, false) => 512,
+        (8, true) => 256,
+        (4, false) => 128,
+        (4, true) => 64,
         _ => panic!("{}", ERRMSG),
     };
     /// how many buckets are linearly spaced among each power of two magnitude (how many divisions)
-    const DIVS_PER_POW2: usize = match WORD_SIZE {
-        8 => 4,
-        4 => 2,
+    const DIVS_PER_POW2: usize = match (WORD_SIZE, TINY_BINS) {
+        (8, false) => 4,
+        (8, true) => 2,
+        (4, false) => 2,
+        (4, true) => 1,
         _ => panic!("{}", ERRMSG),
     };
     /// how many bits are used to determine the division
@@ -191,6 +338,15 @@ unsafe fn bin_of_size(size: usize) -> usize {
     }
 }
 
+/// How long an allocation is expected to live, for [`Talc::malloc_hinted`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LifetimeHint {
+    /// Freed soon, such as per-request buffers. This is the default.
+    ShortLived,
+    /// Kept around for a while, such as per-connection state.
+    LongLived,
+}
+
 /// The Talc Allocator!
 ///
 /// One way to get started:
@@ -200,6 +356,12 @@ unsafe fn bin_of_size(size: usize) -> usize {
 /// [`GlobalAlloc`](core::alloc::GlobalAlloc) and [`Allocator`](core::alloc::Allocator) traits.
 ///
 /// Check out the associated functions `new`, `claim`, `lock`, `extend`, and `truncate`.
+///
+/// # Unwinding
+/// The only user code the allocator calls is the OOM handler and, with the `owners`
+/// feature, the owner source. Both are called before the heap is modified, so if either
+/// panics, the operation is abandoned and the allocator remains consistent and usable.
+/// [`Talck`] is unlocked as the panic unwinds.
 pub struct Talc<O: OomHandler> {
     /// The low bits of the availability flags.
     availability_low: usize,
@@ -213,9 +375,64 @@ pub struct Talc<O: OomHandler> {
     /// Its state is entirely maintained by the user.
     pub oom_handler: O,
 
+    /// Memory set aside for emergency mode, as its pointer and size.
+    #[cfg(feature = "emergency_reserve")]
+    emergency_reserve: Option<(NonNull<u8>, usize)>,
+    /// Whether allocations can fall back on the emergency reserve.
+    #[cfg(feature = "emergency_reserve")]
+    emergency_mode: bool,
+    /// Where [`Talc::scan_step`] resumes, as a bin and an index into its list.
+    #[cfg(feature = "incremental_scan")]
+    scan_position: (usize, usize),
+    /// The most free chunks inspected per bin when allocating, see [`Talc::set_bin_scan_limit`].
+    #[cfg(feature = "bin_scan_limit")]
+    bin_scan_limit: usize,
+    /// Zeroed memory that hasn't been allocated since it was claimed, see [`Talc::claim_zeroed`].
+    #[cfg(feature = "claim_zeroed")]
+    untouched: (*mut u8, *mut u8),
+
     #[cfg(feature = "counters")]
     /// Allocation stats.
     counters: counters::Counters,
+    #[cfg(feature = "counters")]
+    /// Where a subset of the counters are published for lock-free reading.
+    live_stats: Option<&'static counters::LiveStats>,
+    #[cfg(feature = "counters")]
+    /// The maximum sum of the sizes of live allocations.
+    budget: Option<usize>,
+    #[cfg(all(feature = "rtt", feature = "counters"))]
+    /// The allocated bytes above which an RTT event is printed.
+    rtt_threshold: Option<usize>,
+    #[cfg(all(feature = "rtt", feature = "counters"))]
+    /// Whether the allocated bytes were above the threshold when last checked.
+    rtt_threshold_exceeded: bool,
+    #[cfg(feature = "timing")]
+    /// Timestamps the start and end of timed operations.
+    timestamp_source: Option<fn() -> u32>,
+    #[cfg(feature = "event_log")]
+    /// The most recent heap operations.
+    event_log: event_log::EventLog,
+    #[cfg(feature = "trace")]
+    /// Receives a record of each call to the allocator.
+    trace_sink: Option<fn(trace::TraceEntry)>,
+
+    #[cfg(feature = "owners")]
+    /// Identifies the owner of new allocations.
+    owner_source: fn() -> usize,
+    #[cfg(feature = "owner_stats")]
+    /// Allocation stats of the tracked owners.
+    owner_stats: [owner_stats::OwnerStats; owner_stats::TRACKED_OWNER_COUNT],
+
+    #[cfg(feature = "generations")]
+    /// The generation of the next allocation.
+    next_generation: usize,
+
+    #[cfg(feature = "fastbins")]
+    /// Stacks of recently freed small chunks, by size.
+    fastbins: [fastbins::FastBin; fastbins::FASTBIN_COUNT],
+    #[cfg(feature = "quarantine")]
+    /// Recently freed chunks held back from reuse.
+    quarantine: quarantine::Quarantine,
 }
 
 unsafe impl<O: Send + OomHandler> Send for Talc<O> {}
@@ -231,15 +448,6 @@ impl<O: OomHandler> core::fmt::Debug for Talc<O> {
 }
 
 impl<O: OomHandler> Talc<O> {
-    #[inline]
-    const fn required_chunk_size(size: usize) -> usize {
-        if size <= MIN_CHUNK_SIZE - TAG_SIZE {
-            MIN_CHUNK_SIZE
-        } else {
-            (size + TAG_SIZE + (ALIGN - 1)) & !(ALIGN - 1)
-        }
-    }
-
     /// Get the pointer to the `bin`th bin.
     /// # Safety
     /// `bin` must be smaller than `BIN_COUNT`.
@@ -282,6 +490,67 @@ impl<O: OomHandler> Talc<O> {
         }
     }
 
+    /// Accounts for a reallocation performed by allocating, copying, and freeing,
+    /// such that it's counted as a single reallocation instead.
+    #[inline]
+    pub(crate) fn account_moved_realloc(&mut self, old_size: usize, new_size: usize) {
+        #[cfg(feature = "counters")]
+        self.counters.account_moved_realloc(old_size, new_size);
+        #[cfg(not(feature = "counters"))]
+        let _ = (old_size, new_size);
+    }
+
+    /// Gives an allocation moved by a reallocation the owner and user data
+    /// of the original allocation.
+    ///
+    /// # Safety
+    /// Both allocations must be valid given their sizes.
+    #[inline]
+    pub(crate) unsafe fn inherit_owner(
+        &mut self,
+        old_ptr: NonNull<u8>,
+        old_size: usize,
+        new_ptr: NonNull<u8>,
+        new_size: usize,
+    ) {
+        #[cfg(feature = "owners")]
+        {
+            let new_header = header_from_alloc_ptr(new_ptr.as_ptr(), new_size);
+            let owner = (*header_from_alloc_ptr(old_ptr.as_ptr(), old_size)).owner;
+
+            #[cfg(feature = "owner_stats")]
+            {
+                self.account_owner_realloc((*new_header).owner, new_size, 0);
+                self.account_owner_realloc(owner, 0, new_size);
+            }
+
+            (*new_header).owner = owner;
+        }
+        #[cfg(feature = "user_data")]
+        {
+            (*header_from_alloc_ptr(new_ptr.as_ptr(), new_size)).user_data =
+                (*header_from_alloc_ptr(old_ptr.as_ptr(), old_size)).user_data;
+        }
+        #[cfg(not(any(feature = "owners", feature = "user_data")))]
+        let _ = (old_ptr, old_size, new_ptr, new_size);
+    }
+
+    /// Returns whether the allocation may be moved to reallocate it, i.e. isn't pinned.
+    ///
+    /// # Safety
+    /// The allocation must be valid given its size.
+    #[inline]
+    pub(crate) unsafe fn is_movable(&self, ptr: NonNull<u8>, size: usize) -> bool {
+        #[cfg(feature = "pinning")]
+        return !(*header_from_alloc_ptr(ptr.as_ptr(), size)).pinned;
+
+        #[cfg(not(feature = "pinning"))]
+        {
+            let _ = (ptr, size);
+            true
+        }
+    }
+
     /// Registers a gap in memory which is allocatable.
     #[inline]
     unsafe fn register_gap(&mut self, base: *mut u8, acme: *mut u8) {
@@ -296,13 +565,30 @@ impl<O: OomHandler> Talc<O> {
             self.set_avails(bin);
         }
 
+        #[cfg(not(feature = "address_ordered"))]
         LlistNode::insert(gap_base_to_node(base), bin_ptr, *bin_ptr);
 
+        // keep the list sorted by address, such that allocation prefers lower addresses
+        #[cfg(feature = "address_ordered")]
+        {
+            let mut next_of_prev = bin_ptr;
+            while let Some(next) = *next_of_prev {
+                if next.as_ptr() > gap_base_to_node(base) {
+                    break;
+                }
+                next_of_prev = LlistNode::next_ptr(next.as_ptr());
+            }
+            LlistNode::insert(gap_base_to_node(base), next_of_prev, *next_of_prev);
+        }
+
         debug_assert!((*bin_ptr).is_some());
 
         gap_base_to_size(base).write(size);
         gap_acme_to_size(acme).write(size);
 
+        #[cfg(feature = "sanitize")]
+        sanitize::poison_gap(base, acme);
+
         #[cfg(feature = "counters")]
         self.counters.account_register_gap(size);
     }
@@ -313,6 +599,8 @@ impl<O: OomHandler> Talc<O> {
         debug_assert!((*self.get_bin_ptr(bin)).is_some());
         #[cfg(feature = "counters")]
         self.counters.account_deregister_gap(gap_base_to_size(base).read());
+        #[cfg(feature = "sanitize")]
+        sanitize::unpoison(base, gap_base_to_acme(base));
 
         LlistNode::remove(gap_base_to_node(base));
 
@@ -322,23 +610,214 @@ impl<O: OomHandler> Talc<O> {
     }
 
     /// Allocate a contiguous region of memory according to `layout`, if possible.
+    ///
+    /// Returns `Err` if the OOM handler fails to make enough memory available,
+    /// including when `layout` is larger than any heap could possibly hold.
+    /// With the `counters` feature, also returns `Err` if the allocation would exceed
+    /// the budget set by `set_budget`.
+    ///
+    /// Zero-sized allocations don't use the heap: a dangling pointer aligned to
+    /// `layout.align()` is returned, which [`free`](Talc::free) ignores.
+    ///
     /// # Safety
-    /// `layout.size()` must be nonzero.
+    /// The returned pointer is only valid for `layout.size()` bytes, so it's dangling if that's zero.
+    #[inline]
     pub unsafe fn malloc(&mut self, layout: Layout) -> Result<NonNull<u8>, ()> {
-        debug_assert!(layout.size() != 0);
+        self.malloc_hinted(layout, LifetimeHint::ShortLived)
+    }
+
+    /// Like [`Talc::malloc`], but places the allocation according to how long it's expected to live.
+    ///
+    /// Short-lived allocations are placed at the bottom of the free chunk they're allocated from,
+    /// like those made by [`Talc::malloc`], while long-lived allocations are placed at the top.
+    /// Keeping the two apart avoids long-lived allocations being interleaved with short-lived ones,
+    /// which would otherwise fragment the memory they leave behind once freed.
+    ///
+    /// # Safety
+    /// As for [`Talc::malloc`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// unsafe {
+    ///     let connection = talc.malloc_hinted(layout, LifetimeHint::LongLived).unwrap();
+    ///     let request = talc.malloc_hinted(layout, LifetimeHint::ShortLived).unwrap();
+    ///     assert!(request < connection);
+    /// }
+    /// ```
+    pub unsafe fn malloc_hinted(
+        &mut self,
+        layout: Layout,
+        hint: LifetimeHint,
+    ) -> Result<NonNull<u8>, ()> {
+        if layout.size() == 0 {
+            return Ok(dangling_for(layout));
+        }
+
+        #[cfg(feature = "timing")]
+        let start = self.timestamp();
+
+        let result = self.malloc_untimed(layout, hint);
+
+        #[cfg(feature = "timing")]
+        if result.is_ok() {
+            if let Some(cycles) = self.cycles_since(start) {
+                self.counters.malloc_timing.record(cycles);
+            }
+        }
+
+        #[cfg(feature = "event_log")]
+        self.event_log.record(
+            event_log::HeapOp::Malloc,
+            result.is_ok(),
+            layout.size(),
+            result.map_or(0, |ptr| ptr.as_ptr() as usize),
+        );
+        #[cfg(feature = "trace")]
+        self.trace(trace::TraceEntry::Malloc {
+            layout,
+            hint,
+            ptr: result.map_or(0, |ptr| ptr.as_ptr() as usize),
+        });
+
+        result
+    }
+
+    /// Record an allocation that failed for `reason`, for diagnostics.
+    #[cold]
+    #[cfg_attr(
+        not(any(feature = "rtt", all(feature = "semihosting", target_arch = "arm"))),
+        allow(unused_variables)
+    )]
+    fn alloc_failed(&mut self, layout: Layout, reason: &str) {
+        #[cfg(feature = "counters")]
+        {
+            self.counters.total_failed_allocation_count += 1;
+            self.counters.last_failed_layout = Some(layout);
+        }
+
+        #[cfg(feature = "rtt")]
+        self.report_alloc_failure(layout, reason);
+        #[cfg(all(feature = "semihosting", target_arch = "arm"))]
+        semihosting::report_alloc_failure(layout, reason);
+    }
+
+    #[inline]
+    unsafe fn malloc_untimed(
+        &mut self,
+        layout: Layout,
+        hint: LifetimeHint,
+    ) -> Result<NonNull<u8>, ()> {
         self.scan_for_errors();
+        let header = self.admit(layout)?;
+
+        #[cfg(feature = "fastbins")]
+        if let Some(chunk_base) = self.pop_fastbin(layout) {
+            return Ok(self.finish_malloc(
+                chunk_base,
+                chunk_base.add(HEADER_SIZE),
+                header,
+                layout.size(),
+            ));
+        }
 
-        let (mut free_base, free_acme, alloc_base) = loop {
+        let mut oom_retries = 0;
+        let (free_base, free_acme, mut alloc_base) = loop {
             // this returns None if there are no heaps or allocatable memory
             match self.get_sufficient_chunk(layout) {
                 Some(payload) => break payload,
-                None => _ = O::handle_oom(self, layout)?,
+                None => {
+                    #[cfg(feature = "quarantine")]
+                    if self.flush_quarantine() {
+                        continue;
+                    }
+
+                    // cached chunks might be enough if they're coalesced
+                    #[cfg(feature = "fastbins")]
+                    if self.flush_fastbins() {
+                        continue;
+                    }
+
+                    // give up on handlers that don't make enough memory available
+                    if oom_retries == O::RETRY_LIMIT || O::handle_oom(self, layout).is_err() {
+                        // the reserve is the last resort
+                        #[cfg(feature = "emergency_reserve")]
+                        if self.emergency_mode && self.release_emergency_reserve() {
+                            continue;
+                        }
+
+                        self.alloc_failed(layout, "out of memory");
+
+                        return Err(());
+                    }
+
+                    oom_retries += 1;
+                }
             }
         };
 
+        if let LifetimeHint::LongLived = hint {
+            // move the allocation as high as it can go, leaving the tag at the top of the chunk
+            let align_mask = layout.align().max(ALIGN) - 1;
+            let highest_alloc_base = free_acme.sub(TAG_SIZE + layout.size());
+            alloc_base = highest_alloc_base.wrapping_sub(highest_alloc_base as usize & align_mask);
+        }
+
+        Ok(self.carve(free_base, free_acme, alloc_base, header, layout.size()))
+    }
+
+    /// Checks that an allocation of `layout` is within the budget and its owner's cap,
+    /// and creates its header.
+    ///
+    /// This calls the owner source, which may unwind.
+    #[inline]
+    fn admit(&mut self, layout: Layout) -> Result<Header, ()> {
+        // the OOM handler can't help with this
+        #[cfg(feature = "counters")]
+        if !self.fits_budget(layout.size()) {
+            self.alloc_failed(layout, "over budget");
+
+            return Err(());
+        }
+
+        // this calls user code, so do it before the heap is modified, in case it unwinds
+        let header = self.new_header(layout.size());
+
+        #[cfg(feature = "owner_stats")]
+        if !self.fits_owner_cap(header.owner, layout.size()) {
+            self.alloc_failed(layout, "over the owner's cap");
+
+            return Err(());
+        }
+
+        Ok(header)
+    }
+
+    /// Allocates `size` bytes at `alloc_base` out of the free chunk `[free_base, free_acme)`,
+    /// which has been deregistered, freeing the memory around the allocated chunk.
+    ///
+    /// The allocation and its header must fit within the free chunk.
+    #[inline]
+    unsafe fn carve(
+        &mut self,
+        mut free_base: *mut u8,
+        free_acme: *mut u8,
+        alloc_base: *mut u8,
+        header: Header,
+        size: usize,
+    ) -> NonNull<u8> {
+        #[cfg(feature = "poison")]
+        poison::verify(free_base, free_acme, alloc_base, size);
+
         // determine the base of the allocated chunk
         // if the amount of memory below the chunk is too small, subsume it, else free it
-        let chunk_base_ceil = alloc_base.min(free_acme.sub(MIN_CHUNK_SIZE));
+        let chunk_base_ceil = alloc_base.sub(HEADER_SIZE).min(free_acme.sub(MIN_CHUNK_SIZE));
         if is_chunk_size(free_base, chunk_base_ceil) {
             self.register_gap(free_base, chunk_base_ceil);
             free_base = chunk_base_ceil;
@@ -347,7 +826,7 @@ impl<O: OomHandler> Talc<O> {
         }
 
         // the word immediately after the allocation
-        let post_alloc_ptr = align_up(alloc_base.add(layout.size()));
+        let post_alloc_ptr = align_up(alloc_base.add(size));
         // the tag position, accounting for the minimum size of a chunk
         let mut tag_ptr = free_base.add(MIN_TAG_OFFSET).max(post_alloc_ptr);
         // the pointer after the lowest possible tag pointer
@@ -361,16 +840,105 @@ impl<O: OomHandler> Talc<O> {
             tag_ptr = free_acme.sub(TAG_SIZE);
             Tag::write(tag_ptr.cast(), free_base, false);
         }
+        #[cfg(feature = "claim_zeroed")]
+        self.touch(free_base, tag_ptr.add(TAG_SIZE));
 
         if tag_ptr != post_alloc_ptr {
             // write the real tag ptr where the tag is expected to be
             post_alloc_ptr.cast::<*mut u8>().write(tag_ptr);
         }
 
+        self.finish_malloc(free_base, alloc_base, header, size)
+    }
+
+    /// Creates the header for a new allocation of `size` bytes.
+    ///
+    /// This calls the owner source, which may unwind.
+    #[inline]
+    fn new_header(&mut self, size: usize) -> Header {
+        #[cfg(not(feature = "owners"))]
+        let _ = size;
+
+        Header {
+            #[cfg(feature = "owners")]
+            owner: (self.owner_source)(),
+            #[cfg(feature = "owners")]
+            size,
+            #[cfg(feature = "generations")]
+            generation: self.take_generation(),
+            #[cfg(feature = "pinning")]
+            pinned: false,
+            #[cfg(feature = "user_data")]
+            user_data: 0,
+        }
+    }
+
+    /// Accounts for the allocation and writes its header.
+    #[inline]
+    unsafe fn finish_malloc(
+        &mut self,
+        chunk_base: *mut u8,
+        alloc_base: *mut u8,
+        header: Header,
+        size: usize,
+    ) -> NonNull<u8> {
+        #[cfg(feature = "counters")]
+        self.counters.account_alloc(size);
         #[cfg(feature = "counters")]
-        self.counters.account_alloc(layout.size());
+        self.publish_live_stats();
+        #[cfg(feature = "owner_stats")]
+        self.account_owner_realloc(header.owner, 0, size);
+
+        #[cfg(any(
+            feature = "owners",
+            feature = "generations",
+            feature = "pinning",
+            feature = "user_data"
+        ))]
+        header_from_chunk_base(chunk_base).write(header);
+        #[cfg(feature = "generations")]
+        alloc_base.cast::<*mut u8>().sub(1).write(chunk_base);
+
+        #[cfg(not(any(
+            feature = "owners",
+            feature = "generations",
+            feature = "pinning",
+            feature = "user_data"
+        )))]
+        let _ = (chunk_base, header);
+        #[cfg(not(feature = "counters"))]
+        let _ = size;
+
+        NonNull::new_unchecked(alloc_base)
+    }
 
-        Ok(NonNull::new_unchecked(alloc_base))
+    /// Limit how many free chunks allocation inspects in each bin before moving on to the next,
+    /// larger bin, or lift the limit. At least one chunk is always inspected.
+    ///
+    /// This bounds the worst-case time taken by allocation, which can otherwise walk a long
+    /// list of free chunks that are slightly too small, e.g. after a burst of frees. The cost
+    /// is that a larger chunk may be split when a better fit exists, and that allocation may
+    /// fail while a chunk that fits is beyond the limit in the last bin it inspects.
+    ///
+    /// Requires the `bin_scan_limit` feature, which `profile_realtime` implies. There's no
+    /// limit by default, unless the `profile_realtime` feature is enabled, in which case
+    /// it's [`REALTIME_BIN_SCAN_LIMIT`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// talc.set_bin_scan_limit(Some(8));
+    ///
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// unsafe { talc.malloc(layout).unwrap(); }
+    /// ```
+    #[cfg(feature = "bin_scan_limit")]
+    pub fn set_bin_scan_limit(&mut self, limit: Option<usize>) {
+        self.bin_scan_limit = limit.map_or(usize::MAX, |limit| limit.max(1));
     }
 
     /// Returns `(chunk_base, chunk_acme, alloc_base)`
@@ -378,7 +946,7 @@ impl<O: OomHandler> Talc<O> {
         &mut self,
         layout: Layout,
     ) -> Option<(*mut u8, *mut u8, *mut u8)> {
-        let required_chunk_size = Self::required_chunk_size(layout.size());
+        let required_chunk_size = sizing::required_chunk_size(layout.size());
 
         // if there are no valid heaps, availability is zero, and next_available_bin returns None
         let mut bin = self.next_available_bin(bin_of_size(required_chunk_size))?;
@@ -387,14 +955,18 @@ impl<O: OomHandler> Talc<O> {
             // the required alignment is most often the machine word size (or less)
             // a faster loop without alignment checking is used in this case
             loop {
-                for node_ptr in LlistNode::iter_mut(*self.get_bin_ptr(bin)) {
+                let nodes = LlistNode::iter_mut(*self.get_bin_ptr(bin));
+                #[cfg(feature = "bin_scan_limit")]
+                let nodes = nodes.take(self.bin_scan_limit);
+
+                for node_ptr in nodes {
                     let size = gap_node_to_size(node_ptr).read();
 
                     // if the chunk size is sufficient, remove from bookkeeping data structures and return
                     if size >= required_chunk_size {
                         let base = gap_node_to_base(node_ptr);
                         self.deregister_gap(base, bin);
-                        return Some((base, base.add(size), base));
+                        return Some((base, base.add(size), base.add(HEADER_SIZE)));
                     }
                 }
 
@@ -406,20 +978,47 @@ impl<O: OomHandler> Talc<O> {
             let align_mask = layout.align() - 1;
             let required_size = layout.size() + TAG_SIZE;
 
+            // with large alignments, few chunks that are large enough may actually fit,
+            // so first take a chunk from the bins above the size that always fits, if any
+            if layout.align() >= LARGE_ALIGN {
+                let fitting_size = (HEADER_SIZE + align_mask - (ALIGN - 1))
+                    .checked_add(required_size)
+                    .map(|size| size.max(required_chunk_size));
+
+                if let Some(fit_bin) =
+                    fitting_size.and_then(|size| self.next_available_bin(bin_of_size(size) + 1))
+                {
+                    let node_ptr = (*self.get_bin_ptr(fit_bin)).unwrap();
+                    let size = gap_node_to_size(node_ptr).read();
+                    let base = gap_node_to_base(node_ptr);
+                    let aligned_ptr = align_up_by(base.add(HEADER_SIZE), align_mask);
+                    debug_assert!(aligned_ptr as usize - base as usize <= size - required_size);
+
+                    self.deregister_gap(base, fit_bin);
+                    return Some((base, base.add(size), aligned_ptr));
+                }
+            }
+
             loop {
-                for node_ptr in LlistNode::iter_mut(*self.get_bin_ptr(bin)) {
+                let nodes = LlistNode::iter_mut(*self.get_bin_ptr(bin));
+                #[cfg(feature = "bin_scan_limit")]
+                let nodes = nodes.take(self.bin_scan_limit);
+
+                for node_ptr in nodes {
                     let size = gap_node_to_size(node_ptr).read();
 
                     if size >= required_chunk_size {
                         let base = gap_node_to_base(node_ptr);
-                        let acme = base.add(size);
-                        // calculate the lowest aligned pointer above the tag-offset free chunk pointer
-                        let aligned_ptr = align_up_by(base, align_mask);
+                        // calculate the lowest aligned pointer with room for the header below it
+                        let aligned_ptr = align_up_by(base.add(HEADER_SIZE), align_mask);
+                        // if aligning wrapped around the address space, this will be huge
+                        let align_offset = (aligned_ptr as usize).wrapping_sub(base as usize);
 
                         // if the remaining size is sufficient, remove the chunk from the books and return
-                        if aligned_ptr.add(required_size) <= acme {
+                        // (this is written to avoid overflow for extreme sizes and alignments)
+                        if align_offset <= size - required_size {
                             self.deregister_gap(base, bin);
-                            return Some((base, acme, aligned_ptr));
+                            return Some((base, base.add(size), aligned_ptr));
                         }
                     }
                 }
@@ -458,25 +1057,88 @@ impl<O: OomHandler> Talc<O> {
     }
 
     /// Free previously allocated/reallocated memory.
+    ///
+    /// This does nothing if `layout.size()` is zero, see [`malloc`](Talc::malloc).
+    ///
     /// # Safety
     /// `ptr` must have been previously allocated given `layout`.
     pub unsafe fn free(&mut self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() == 0 {
+            return;
+        }
+
+        #[cfg(feature = "timing")]
+        let start = self.timestamp();
+
+        self.free_untimed(ptr, layout);
+
+        #[cfg(feature = "timing")]
+        if let Some(cycles) = self.cycles_since(start) {
+            self.counters.free_timing.record(cycles);
+        }
+
+        #[cfg(feature = "event_log")]
+        self.event_log.record(event_log::HeapOp::Free, true, layout.size(), ptr.as_ptr() as usize);
+        #[cfg(feature = "trace")]
+        self.trace(trace::TraceEntry::Free { ptr: ptr.as_ptr() as usize, layout });
+    }
+
+    #[inline]
+    unsafe fn free_untimed(&mut self, ptr: NonNull<u8>, layout: Layout) {
         self.scan_for_errors();
         #[cfg(feature = "counters")]
         self.counters.account_dealloc(layout.size());
 
         let (tag_ptr, tag) = tag_from_alloc_ptr(ptr.as_ptr(), layout.size());
+
+        #[cfg(feature = "owner_stats")]
+        self.account_owner_realloc(
+            (*header_from_chunk_base(tag.chunk_base())).owner,
+            layout.size(),
+            0,
+        );
+
+        #[cfg(feature = "quarantine")]
+        if self.push_quarantine(tag_ptr, tag) {
+            return;
+        }
+
+        #[cfg(feature = "fastbins")]
+        if self.push_fastbin(tag_ptr, tag) {
+            return;
+        }
+
+        self.free_chunk(tag_ptr, tag);
+    }
+
+    /// Free the allocated chunk, given its tag.
+    #[inline]
+    unsafe fn free_chunk(&mut self, tag_ptr: *mut u8, tag: Tag) {
         let mut chunk_base = tag.chunk_base();
         let mut chunk_acme = tag_ptr.add(TAG_SIZE);
 
+        // the header might not be overwritten if the chunk is merged with the gap below
+        #[cfg(feature = "generations")]
+        {
+            (*header_from_chunk_base(chunk_base)).generation = 0;
+        }
+
         debug_assert!(tag.is_allocated());
         debug_assert!(is_chunk_size(chunk_base, chunk_acme));
 
+        // the freed chunk, as well as the metadata of the gaps it's merged with
+        #[cfg(feature = "poison")]
+        let (mut poison_base, mut poison_acme) = (chunk_base, chunk_acme);
+
         // try recombine below
         if is_gap_below(chunk_base) {
             let (below_base, below_size) = gap_acme_to_base_size(chunk_base);
             self.deregister_gap(below_base, bin_of_size(below_size));
 
+            #[cfg(feature = "poison")]
+            {
+                poison_base = chunk_base.sub(GAP_HIGH_SIZE_OFFSET);
+            }
             chunk_base = below_base;
         } else {
             Tag::set_above_free(chunk_base.sub(TAG_SIZE).cast());
@@ -487,14 +1149,29 @@ impl<O: OomHandler> Talc<O> {
             let above_size = gap_base_to_size(chunk_acme).read();
             self.deregister_gap(chunk_acme, bin_of_size(above_size));
 
+            #[cfg(feature = "poison")]
+            {
+                poison_acme = chunk_acme.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE);
+            }
             chunk_acme = chunk_acme.add(above_size);
         }
 
+        #[cfg(feature = "poison")]
+        poison::poison(poison_base, poison_acme);
+
         // add the full recombined free chunk back into the books
         self.register_gap(chunk_base, chunk_acme);
+
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
     }
 
     /// Grow a previously allocated/reallocated region of memory to `new_size`.
+    ///
+    /// Returns `Err` if the allocation could not be grown, including when
+    /// `new_size` is too large for a valid [`Layout`]. Zero-sized allocations
+    /// are grown by allocating anew.
+    ///
     /// # Safety
     /// `ptr` must have been previously allocated or reallocated given `layout`.
     /// `new_size` must be larger or equal to `layout.size()`.
@@ -504,13 +1181,52 @@ impl<O: OomHandler> Talc<O> {
         old_layout: Layout,
         new_size: usize,
     ) -> Result<NonNull<u8>, ()> {
+        // the calls made to reallocate aren't traced separately
+        #[cfg(feature = "trace")]
+        let trace_sink = self.trace_sink.take();
+
+        let result = self.grow_untraced(ptr, old_layout, new_size);
+
+        #[cfg(feature = "trace")]
+        {
+            self.trace_sink = trace_sink;
+            self.trace(trace::TraceEntry::Grow {
+                ptr: ptr.as_ptr() as usize,
+                layout: old_layout,
+                new_size,
+                new_ptr: result.map_or(0, |ptr| ptr.as_ptr() as usize),
+            });
+        }
+
+        result
+    }
+
+    #[inline]
+    unsafe fn grow_untraced(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        if old_layout.size() == 0 {
+            return self
+                .malloc(Layout::from_size_align(new_size, old_layout.align()).map_err(|_| ())?);
+        }
+
         match self.grow_in_place(ptr, old_layout, new_size) {
             Err(_) => {
+                if !self.is_movable(ptr, old_layout.size()) {
+                    return Err(());
+                }
+
                 // grow in-place failed; reallocate the slow way
-                let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
+                let new_layout =
+                    Layout::from_size_align(new_size, old_layout.align()).map_err(|_| ())?;
                 let allocation = self.malloc(new_layout)?;
                 allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), old_layout.size());
+                self.inherit_owner(ptr, old_layout.size(), allocation, new_size);
                 self.free(ptr, old_layout);
+                self.account_moved_realloc(old_layout.size(), new_size);
 
                 Ok(allocation)
             }
@@ -520,8 +1236,9 @@ impl<O: OomHandler> Talc<O> {
 
     /// Attempt to grow a previously allocated/reallocated region of memory to `new_size`.
     ///
-    /// Returns `Err` if reallocation could not occur in-place.
-    /// Ownership of the memory remains with the caller.
+    /// Returns `Err` if reallocation could not occur in-place, including when
+    /// `new_size` is too large for the allocation to possibly be grown in-place,
+    /// or the allocation is zero-sized. Ownership of the memory remains with the caller.
     /// # Safety
     /// `ptr` must have been previously allocated or reallocated given `layout`.
     /// `new_size` must be larger or equal to `layout.size()`.
@@ -530,12 +1247,61 @@ impl<O: OomHandler> Talc<O> {
         ptr: NonNull<u8>,
         old_layout: Layout,
         new_size: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        let result = self.grow_in_place_unlogged(ptr, old_layout, new_size);
+
+        #[cfg(feature = "event_log")]
+        self.event_log.record(
+            event_log::HeapOp::GrowInPlace,
+            result.is_ok(),
+            new_size,
+            ptr.as_ptr() as usize,
+        );
+        #[cfg(feature = "trace")]
+        self.trace(trace::TraceEntry::GrowInPlace {
+            ptr: ptr.as_ptr() as usize,
+            layout: old_layout,
+            new_size,
+            ok: result.is_ok(),
+        });
+
+        result
+    }
+
+    #[inline]
+    unsafe fn grow_in_place_unlogged(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
     ) -> Result<NonNull<u8>, ()> {
         debug_assert!(new_size >= old_layout.size());
         self.scan_for_errors();
 
+        // zero-sized allocations don't have a chunk to grow into
+        if old_layout.size() == 0 {
+            return Err(());
+        }
+
+        // reject sizes that would overflow the address space before doing any pointer arithmetic
+        if new_size > usize::MAX - (ALIGN - 1) - ptr.as_ptr() as usize {
+            return Err(());
+        }
+
+        #[cfg(feature = "counters")]
+        if !self.fits_budget(new_size - old_layout.size()) {
+            return Err(());
+        }
+
+        #[cfg(feature = "owner_stats")]
+        let owner = (*header_from_alloc_ptr(ptr.as_ptr(), old_layout.size())).owner;
+        #[cfg(feature = "owner_stats")]
+        if !self.fits_owner_cap(owner, new_size - old_layout.size()) {
+            return Err(());
+        }
+
         let old_post_alloc_ptr = align_up(ptr.as_ptr().add(old_layout.size()));
-        let new_post_alloc_ptr = align_up(ptr.as_ptr().add(new_size));
+        let new_post_alloc_ptr = align_up(ptr.as_ptr().wrapping_add(new_size));
 
         if old_post_alloc_ptr == new_post_alloc_ptr {
             // this handles a rare short-circuit, but more helpfully
@@ -545,6 +1311,14 @@ impl<O: OomHandler> Talc<O> {
 
             #[cfg(feature = "counters")]
             self.counters.account_grow_in_place(old_layout.size(), new_size);
+            #[cfg(feature = "counters")]
+            self.publish_live_stats();
+            #[cfg(feature = "owners")]
+            {
+                (*header_from_alloc_ptr(ptr.as_ptr(), new_size)).size = new_size;
+            }
+            #[cfg(feature = "owner_stats")]
+            self.account_owner_realloc(owner, old_layout.size(), new_size);
 
             return Ok(ptr);
         }
@@ -559,6 +1333,14 @@ impl<O: OomHandler> Talc<O> {
 
             #[cfg(feature = "counters")]
             self.counters.account_grow_in_place(old_layout.size(), new_size);
+            #[cfg(feature = "counters")]
+            self.publish_live_stats();
+            #[cfg(feature = "owners")]
+            {
+                (*header_from_alloc_ptr(ptr.as_ptr(), new_size)).size = new_size;
+            }
+            #[cfg(feature = "owner_stats")]
+            self.account_owner_realloc(owner, old_layout.size(), new_size);
 
             return Ok(ptr);
         }
@@ -583,10 +1365,21 @@ impl<O: OomHandler> Talc<O> {
                 // finally, determine if the remainder of the free block is big enough
                 // to be freed again, or if the entire region should be allocated
                 if is_chunk_size(new_tag_ptr, above_tag_ptr) {
+                    // the gap's old metadata might be left in the remainder
+                    #[cfg(feature = "poison")]
+                    poison::poison(
+                        new_tag_ptr.add(TAG_SIZE),
+                        acme.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE),
+                    );
+
                     self.register_gap(new_tag_ptr.add(TAG_SIZE), above_tag_ptr.add(TAG_SIZE));
                     Tag::write(new_tag_ptr.cast(), base, true);
+                    #[cfg(feature = "claim_zeroed")]
+                    self.touch(base, new_tag_ptr.add(TAG_SIZE));
                 } else {
                     Tag::write(above_tag_ptr.cast(), base, false);
+                    #[cfg(feature = "claim_zeroed")]
+                    self.touch(base, above_tag_ptr.add(TAG_SIZE));
 
                     if new_post_alloc_ptr != above_tag_ptr {
                         new_post_alloc_ptr.cast::<*mut u8>().write(above_tag_ptr);
@@ -595,6 +1388,14 @@ impl<O: OomHandler> Talc<O> {
 
                 #[cfg(feature = "counters")]
                 self.counters.account_grow_in_place(old_layout.size(), new_size);
+                #[cfg(feature = "counters")]
+                self.publish_live_stats();
+                #[cfg(feature = "owners")]
+                {
+                    (*header_from_chunk_base(base)).size = new_size;
+                }
+                #[cfg(feature = "owner_stats")]
+                self.account_owner_realloc(owner, old_layout.size(), new_size);
 
                 return Ok(ptr);
             }
@@ -603,6 +1404,59 @@ impl<O: OomHandler> Talc<O> {
         Err(())
     }
 
+    /// Grow a previously allocated/reallocated region of memory in-place as far as it goes,
+    /// absorbing the free chunk above it, if any, and return the allocation's new size.
+    ///
+    /// This suits ring buffers and arena writers, which can make use of whatever memory
+    /// is available, as long as they aren't moved. The free memory below the allocation
+    /// can't be absorbed without moving it, so it's left alone. Chunks cached by the
+    /// `fastbins` feature aren't considered free.
+    ///
+    /// If the allocation can't be grown, e.g. because the chunk above is allocated,
+    /// or growing would exceed the budget, `layout.size()` is returned. Zero-sized
+    /// allocations don't use the heap, so they're never grown.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given `layout`.
+    /// Afterwards, the allocation's layout has the returned size.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::from_size_align(100, 8).unwrap();
+    /// let ptr = unsafe { talc.malloc(layout).unwrap() };
+    /// let size = unsafe { talc.grow_to_fill(ptr, layout) };
+    /// assert!(size > 5000);
+    ///
+    /// unsafe { talc.free(ptr, Layout::from_size_align(size, 8).unwrap()); }
+    /// ```
+    pub unsafe fn grow_to_fill(&mut self, ptr: NonNull<u8>, layout: Layout) -> usize {
+        if layout.size() == 0 {
+            return layout.size();
+        }
+
+        let (tag_ptr, tag) = tag_from_alloc_ptr(ptr.as_ptr(), layout.size());
+
+        // the allocation can extend up to its tag, or the tag of the free chunk above
+        let max_tag_ptr = if tag.is_above_free() {
+            tag_ptr.add(gap_base_to_size(tag_ptr.add(TAG_SIZE)).read())
+        } else {
+            tag_ptr
+        };
+        let max_size = max_tag_ptr as usize - ptr.as_ptr() as usize;
+
+        if max_size > layout.size() && self.grow_in_place(ptr, layout, max_size).is_ok() {
+            max_size
+        } else {
+            layout.size()
+        }
+    }
+
     /// Shrink a previously allocated/reallocated region of memory to `new_size`.
     ///
     /// This function is infallible given valid inputs, and the reallocation will always be
@@ -611,12 +1465,17 @@ impl<O: OomHandler> Talc<O> {
     /// # Safety
     /// - `ptr` must have been previously allocated or reallocated given `layout`.
     /// - `new_size` must be smaller or equal to `layout.size()`.
-    /// - `new_size` should be nonzero.
+    /// - `new_size` must be nonzero, else the allocation is leaked when it's freed.
     pub unsafe fn shrink(&mut self, ptr: NonNull<u8>, layout: Layout, new_size: usize) {
         debug_assert!(new_size != 0);
         debug_assert!(new_size <= layout.size());
         self.scan_for_errors();
 
+        #[cfg(feature = "event_log")]
+        self.event_log.record(event_log::HeapOp::Shrink, true, new_size, ptr.as_ptr() as usize);
+        #[cfg(feature = "trace")]
+        self.trace(trace::TraceEntry::Shrink { ptr: ptr.as_ptr() as usize, layout, new_size });
+
         let (tag_ptr, tag) = tag_from_alloc_ptr(ptr.as_ptr(), layout.size());
         let chunk_base = tag.chunk_base();
 
@@ -634,13 +1493,23 @@ impl<O: OomHandler> Talc<O> {
             let mut acme = tag_ptr.add(TAG_SIZE);
             let new_acme = new_tag_ptr.add(TAG_SIZE);
 
+            #[cfg(feature = "poison")]
+            let mut poison_acme = acme;
+
             if tag.is_above_free() {
                 let above_size = gap_base_to_size(acme).read();
                 self.deregister_gap(acme, bin_of_size(above_size));
 
+                #[cfg(feature = "poison")]
+                {
+                    poison_acme = acme.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE);
+                }
                 acme = acme.add(above_size);
             }
 
+            #[cfg(feature = "poison")]
+            poison::poison(new_acme, poison_acme);
+
             self.register_gap(new_acme, acme);
             Tag::write(new_tag_ptr.cast(), chunk_base, true);
         } else {
@@ -651,8 +1520,28 @@ impl<O: OomHandler> Talc<O> {
             new_post_alloc_ptr.cast::<*mut u8>().write(new_tag_ptr);
         }
 
+        self.account_shrink(chunk_base, layout.size(), new_size);
+    }
+
+    /// Updates the books for an allocation in the chunk at `chunk_base` having shrunk in place.
+    unsafe fn account_shrink(&mut self, chunk_base: *mut u8, old_size: usize, new_size: usize) {
+        #[cfg(feature = "counters")]
+        self.counters.account_shrink_in_place(old_size, new_size);
         #[cfg(feature = "counters")]
-        self.counters.account_shrink_in_place(layout.size(), new_size);
+        self.publish_live_stats();
+        #[cfg(feature = "owners")]
+        {
+            (*header_from_chunk_base(chunk_base)).size = new_size;
+        }
+        #[cfg(feature = "owner_stats")]
+        self.account_owner_realloc((*header_from_chunk_base(chunk_base)).owner, old_size, new_size);
+
+        #[cfg(not(feature = "owners"))]
+        let _ = chunk_base;
+        #[cfg(not(any(feature = "counters", feature = "owner_stats")))]
+        let _ = old_size;
+        #[cfg(not(any(feature = "counters", feature = "owners")))]
+        let _ = new_size;
     }
 
     /// Returns an uninitialized [`Talc`].
@@ -667,11 +1556,65 @@ impl<O: OomHandler> Talc<O> {
             availability_high: 0,
             bins: null_mut(),
 
+            #[cfg(feature = "emergency_reserve")]
+            emergency_reserve: None,
+            #[cfg(feature = "emergency_reserve")]
+            emergency_mode: false,
+            #[cfg(feature = "incremental_scan")]
+            scan_position: (0, 0),
+            #[cfg(feature = "bin_scan_limit")]
+            bin_scan_limit: if cfg!(feature = "profile_realtime") {
+                REALTIME_BIN_SCAN_LIMIT
+            } else {
+                usize::MAX
+            },
+            #[cfg(feature = "claim_zeroed")]
+            untouched: (null_mut(), null_mut()),
+
             #[cfg(feature = "counters")]
             counters: counters::Counters::new(),
+            #[cfg(feature = "counters")]
+            live_stats: None,
+            #[cfg(feature = "counters")]
+            budget: None,
+            #[cfg(all(feature = "rtt", feature = "counters"))]
+            rtt_threshold: None,
+            #[cfg(all(feature = "rtt", feature = "counters"))]
+            rtt_threshold_exceeded: false,
+            #[cfg(feature = "timing")]
+            timestamp_source: None,
+            #[cfg(feature = "event_log")]
+            event_log: event_log::EventLog::new(),
+            #[cfg(feature = "trace")]
+            trace_sink: None,
+
+            #[cfg(feature = "owners")]
+            owner_source: owners::no_owner,
+            #[cfg(feature = "owner_stats")]
+            owner_stats: [owner_stats::OwnerStats {
+                allocated_bytes: 0,
+                peak_allocated_bytes: 0,
+                cap: None,
+            }; owner_stats::TRACKED_OWNER_COUNT],
+
+            #[cfg(feature = "generations")]
+            next_generation: 1,
+
+            #[cfg(feature = "fastbins")]
+            fastbins: [fastbins::FastBin::EMPTY; fastbins::FASTBIN_COUNT],
+            #[cfg(feature = "quarantine")]
+            quarantine: quarantine::Quarantine::OFF,
         }
     }
 
+    /// Returns the base of the allocator's arena, or `None` if no heap has been established.
+    ///
+    /// This is where the allocator's metadata is stored, within the first heap established,
+    /// and doesn't change thereafter. [`ArenaPtr`](crate::ArenaPtr)s are relative to it.
+    pub fn arena_base(&self) -> Option<NonNull<u8>> {
+        NonNull::new(self.bins.cast())
+    }
+
     /// Returns the minimum [`Span`] containing this heap's allocated memory.
     /// # Safety
     /// `heap` must be the return value of a heap manipulation function.
@@ -701,6 +1644,9 @@ impl<O: OomHandler> Talc<O> {
     /// * Each heap reserves a `usize` at the bottom as fixed overhead.
     /// * Metadata will be placed into the bottom of the first successfully established heap.
     /// It is currently ~1KiB on 64-bit systems (less on 32-bit). This is subject to change.
+    /// * Memory right up against the top of the address space is fine, but the top
+    ///   address itself can't be included, as `memory.acme` is exclusive. Any unaligned
+    ///   remainder at either end is trimmed off.
     ///
     /// # Return Values
     /// The resulting [`Span`] is the actual heap extent, and may
@@ -719,12 +1665,11 @@ impl<O: OomHandler> Talc<O> {
     /// - `memory` should not overlap with any other active heap.
     ///
     /// # Panics
-    /// Panics if `memory` contains the null address.
+    /// Panics if `memory` contains the null address. Use [`Span::exclude_null`]
+    /// to trim it off where memory begins at address zero.
     pub unsafe fn claim(&mut self, memory: Span) -> Result<Span, ()> {
         self.scan_for_errors();
 
-        const BIN_ARRAY_SIZE: usize = core::mem::size_of::<Bin>() * BIN_COUNT;
-
         // create a new heap
         // if bins is null, we will need to try put the metadata in this heap
         // this metadata is allocated 'by hand' to be isomorphic with other chunks
@@ -732,6 +1677,8 @@ impl<O: OomHandler> Talc<O> {
         assert!(!memory.contains(null_mut()), "heap covers the null address!");
 
         let aligned_heap = memory.word_align_inward();
+        #[cfg(feature = "sanitize")]
+        sanitize::unpoison_span(aligned_heap);
 
         // if this fails, there's no space to work with
         if let Some((base, acme)) = aligned_heap.get_base_acme() {
@@ -744,12 +1691,16 @@ impl<O: OomHandler> Talc<O> {
 
                     // register the free memory
                     let chunk_base = base.wrapping_add(TAG_SIZE);
+                    #[cfg(feature = "poison")]
+                    poison::poison(chunk_base, acme);
                     self.register_gap(chunk_base, acme);
 
                     self.scan_for_errors();
 
                     #[cfg(feature = "counters")]
                     self.counters.account_claim(aligned_heap.size());
+                    #[cfg(feature = "counters")]
+                    self.publish_live_stats();
 
                     return Ok(aligned_heap);
                 }
@@ -775,6 +1726,8 @@ impl<O: OomHandler> Talc<O> {
                     // add_chunk_to_record only depends on self.bins
                     let metadata_chunk_acme = post_metadata_ptr.add(TAG_SIZE);
                     if is_chunk_size(metadata_chunk_acme, acme) {
+                        #[cfg(feature = "poison")]
+                        poison::poison(metadata_chunk_acme, acme);
                         self.register_gap(metadata_chunk_acme, acme);
                         Tag::write(post_metadata_ptr.cast(), base, true);
                     } else {
@@ -790,6 +1743,8 @@ impl<O: OomHandler> Talc<O> {
 
                     #[cfg(feature = "counters")]
                     self.counters.account_claim(aligned_heap.size());
+                    #[cfg(feature = "counters")]
+                    self.publish_live_stats();
 
                     return Ok(aligned_heap);
                 }
@@ -814,7 +1769,7 @@ impl<O: OomHandler> Talc<O> {
     /// This function panics if:
     /// - `old_heap` is too small or heap metadata is not yet allocated
     /// - `req_heap` doesn't contain `old_heap`
-    /// - `req_heap` contains the null address
+    /// - `req_heap` contains the null address (see [`Span::exclude_null`])
     ///
     /// A recommended pattern for satisfying these criteria is:
     /// ```rust
@@ -840,6 +1795,11 @@ impl<O: OomHandler> Talc<O> {
 
         let (old_base, old_acme) = old_heap.word_align_inward().get_base_acme().unwrap();
         let (new_base, new_acme) = req_heap.word_align_inward().get_base_acme().unwrap();
+        #[cfg(feature = "sanitize")]
+        {
+            sanitize::unpoison(new_base, old_base);
+            sanitize::unpoison(old_acme, new_acme);
+        }
         let new_chunk_base = new_base.add(TAG_SIZE);
         let mut ret_base = new_base;
         let mut ret_acme = new_acme;
@@ -849,8 +1809,12 @@ impl<O: OomHandler> Talc<O> {
         if is_gap_below(old_acme) {
             let (top_base, top_size) = gap_acme_to_base_size(old_acme);
             self.deregister_gap(top_base, bin_of_size(top_size));
+            #[cfg(feature = "poison")]
+            poison::poison(old_acme.sub(GAP_HIGH_SIZE_OFFSET), new_acme);
             self.register_gap(top_base, new_acme);
         } else if is_chunk_size(old_acme, new_acme) {
+            #[cfg(feature = "poison")]
+            poison::poison(old_acme, new_acme);
             self.register_gap(old_acme, new_acme);
             Tag::set_above_free(old_acme.sub(TAG_SIZE).cast());
         } else {
@@ -862,9 +1826,13 @@ impl<O: OomHandler> Talc<O> {
             let bottom_base = old_base.add(TAG_SIZE);
             let bottom_size = gap_base_to_size(bottom_base).read();
             self.deregister_gap(bottom_base, bin_of_size(bottom_size));
+            #[cfg(feature = "poison")]
+            poison::poison(new_chunk_base, bottom_base.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE));
             self.register_gap(new_chunk_base, bottom_base.add(bottom_size));
             Tag::write(new_base.cast(), null_mut(), true);
         } else if is_chunk_size(new_base, old_base) {
+            #[cfg(feature = "poison")]
+            poison::poison(new_base.add(TAG_SIZE), old_base.add(TAG_SIZE));
             self.register_gap(new_base.add(TAG_SIZE), old_base.add(TAG_SIZE));
             Tag::write(new_base.cast(), null_mut(), true);
         } else {
@@ -875,6 +1843,8 @@ impl<O: OomHandler> Talc<O> {
 
         #[cfg(feature = "counters")]
         self.counters.account_extend(old_heap.size(), ret_heap.size());
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
 
         ret_heap
     }
@@ -927,6 +1897,8 @@ impl<O: OomHandler> Talc<O> {
         assert!(!self.bins.is_null(), "no heaps have been successfully established!");
 
         self.scan_for_errors();
+        #[cfg(feature = "claim_zeroed")]
+        self.forget_untouched_in(old_heap);
 
         let new_heap = req_heap.word_align_inward();
 
@@ -949,6 +1921,8 @@ impl<O: OomHandler> Talc<O> {
 
             #[cfg(feature = "counters")]
             self.counters.account_truncate(old_heap.size(), 0);
+            #[cfg(feature = "counters")]
+            self.publish_live_stats();
 
             return Span::empty();
         }
@@ -994,10 +1968,60 @@ impl<O: OomHandler> Talc<O> {
 
         #[cfg(feature = "counters")]
         self.counters.account_truncate(old_heap.size(), ret_heap.size());
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
 
         ret_heap
     }
 
+    /// Release `heap` entirely, the inverse of [`claim`](Talc::claim), if none of its
+    /// memory is allocated. Otherwise `Err` is returned and the heap is left intact.
+    ///
+    /// Unlike [`truncate`](Talc::truncate), this needn't be given the allocated span,
+    /// and can release any heap, not just one at the edge of some memory. The heap
+    /// holding the allocator's metadata is never empty, and can't be released.
+    ///
+    /// Once released, you may do what you like with the heap memory, such as handing
+    /// it back to the OS or to another subsystem.
+    ///
+    /// # Safety
+    /// `heap` must be the return value of a heap-manipulation function
+    /// of this allocator instance.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut first = [0u8; 4000];
+    /// let mut second = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(first.as_mut().into()).unwrap() };
+    /// let second = unsafe { talc.claim(second.as_mut().into()).unwrap() };
+    ///
+    /// // only the second heap has room for this
+    /// let layout = Layout::new::<[u8; 6000]>();
+    /// let ptr = unsafe { talc.malloc(layout).unwrap() };
+    /// assert!(second.contains(ptr.as_ptr()));
+    /// assert!(unsafe { talc.unclaim(second) }.is_err());
+    ///
+    /// unsafe { talc.free(ptr, layout) };
+    /// assert!(unsafe { talc.unclaim(second) }.is_ok());
+    /// ```
+    pub unsafe fn unclaim(&mut self, heap: Span) -> Result<(), ()> {
+        // cached and quarantined chunks look allocated
+        #[cfg(feature = "fastbins")]
+        self.flush_fastbins();
+        #[cfg(feature = "quarantine")]
+        self.flush_quarantine();
+
+        if !self.get_allocated_span(heap).is_empty() {
+            return Err(());
+        }
+
+        self.truncate(heap, Span::empty());
+        Ok(())
+    }
+
     #[cfg(not(debug_assertions))]
     fn scan_for_errors(&self) {}
 
@@ -1065,6 +2089,26 @@ mod tests {
         assert!(ALIGN == std::mem::align_of::<Bin>() && ALIGN == std::mem::size_of::<Bin>());
     }
 
+    #[test]
+    fn bin_of_size_test() {
+        let mut prev_bin = 0;
+        let mut size = MIN_CHUNK_SIZE;
+
+        // bins are contiguous and ordered by size
+        while prev_bin < BIN_COUNT - 1 {
+            let bin = unsafe { bin_of_size(size) };
+            assert!(bin == prev_bin || bin == prev_bin + 1, "size {} skipped a bin", size);
+            prev_bin = bin;
+            // smaller than the size difference between bins at every size
+            size += ((size / 32) & !(ALIGN - 1)).max(ALIGN);
+        }
+
+        assert_eq!(unsafe { bin_of_size(MIN_CHUNK_SIZE) }, 0);
+        // large chunks are still told apart, even on 32-bit targets
+        assert!(unsafe { bin_of_size(1 << 20) } < BIN_COUNT - 1);
+        assert_eq!(unsafe { bin_of_size(usize::MAX & !(ALIGN - 1)) }, BIN_COUNT - 1);
+    }
+
     #[test]
     fn alloc_dealloc_test() {
         const ARENA_SIZE: usize = 10000000;
@@ -1118,6 +2162,455 @@ mod tests {
         }
     }
 
+    #[test]
+    fn lifetime_hint_test() {
+        let arena = Box::leak(vec![0u8; 100000].into_boxed_slice()) as *mut [_];
+
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe {
+            talc.claim(arena.as_mut().unwrap().into()).unwrap();
+        }
+
+        let initial = talc.report();
+        let short = Layout::from_size_align(100, 8).unwrap();
+        let long =
+            [Layout::from_size_align(1, 1).unwrap(), Layout::from_size_align(50, 64).unwrap()];
+        let mut allocations = vec![];
+
+        for i in 0..40 {
+            unsafe {
+                let a = talc.malloc_hinted(short, LifetimeHint::ShortLived).unwrap();
+                let b = talc.malloc_hinted(long[i % 2], LifetimeHint::LongLived).unwrap();
+                assert!(a < b);
+                assert!(b.as_ptr() as usize % long[i % 2].align() == 0);
+                b.as_ptr().write_bytes(0xab, long[i % 2].size());
+                allocations.push((a, b, long[i % 2]));
+            }
+        }
+
+        // the short-lived allocations leave behind contiguous free memory
+        for &(a, _, _) in allocations.iter() {
+            unsafe { talc.free(a, short) };
+        }
+        let lowest_short = allocations.iter().map(|&(a, _, _)| a.as_ptr() as usize).min().unwrap();
+        let lowest_long = allocations.iter().map(|&(_, b, _)| b.as_ptr() as usize).min().unwrap();
+        let report = talc.report();
+        assert!(
+            report.largest_free_chunk >= lowest_long - lowest_short - HEADER_SIZE - MIN_CHUNK_SIZE
+        );
+
+        for &(_, b, layout) in allocations.iter() {
+            unsafe {
+                assert!((0..layout.size()).all(|i| *b.as_ptr().add(i) == 0xab));
+                talc.free(b, layout);
+            }
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+
+        unsafe {
+            drop(Box::from_raw(arena));
+        }
+    }
+
+    #[test]
+    fn large_align_test() {
+        const ALIGN_64K: usize = 1 << 16;
+        let mut arena = vec![0u8; ALIGN_64K * 8];
+
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+        let initial = talc.report();
+
+        // leave many free chunks that are large enough, but mostly can't be aligned
+        let small = Layout::from_size_align(3000, 8).unwrap();
+        let mut smalls = vec![];
+        while let Ok(ptr) = unsafe { talc.malloc(small) } {
+            smalls.push(ptr);
+            if smalls.len() == 100 {
+                break;
+            }
+        }
+        for &ptr in smalls.iter().step_by(2) {
+            unsafe { talc.free(ptr, small) };
+        }
+
+        let layouts = [
+            Layout::from_size_align(2000, ALIGN_64K).unwrap(),
+            Layout::from_size_align(ALIGN_64K, ALIGN_64K).unwrap(),
+            Layout::from_size_align(100, LARGE_ALIGN).unwrap(),
+        ];
+        let mut allocations = vec![];
+        for layout in layouts {
+            let ptr = unsafe { talc.malloc(layout).unwrap() };
+            assert_eq!(ptr.as_ptr() as usize % layout.align(), 0);
+            unsafe { ptr.as_ptr().write_bytes(0xab, layout.size()) };
+            allocations.push((ptr, layout));
+        }
+        assert_eq!(talc.validate(), Ok(()));
+
+        for (ptr, layout) in allocations {
+            unsafe { talc.free(ptr, layout) };
+        }
+        for &ptr in smalls.iter().skip(1).step_by(2) {
+            unsafe { talc.free(ptr, small) };
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+
+        // when no chunk is large enough to always fit, an aligned fit is still found
+        // (the heap's metadata takes up the memory below, which must be within the arena)
+        let aligned = (arena.as_ptr() as usize + 2048 + ALIGN_64K - 1) & !(ALIGN_64K - 1);
+        let snug_heap = Span::from_base_size((aligned - 2048) as *mut u8, 2048 + 4096 + 256);
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe { talc.claim(snug_heap).unwrap() };
+        let layout = Layout::from_size_align(4096, ALIGN_64K).unwrap();
+        assert_eq!(unsafe { talc.malloc(layout) }.unwrap().as_ptr() as usize, aligned);
+    }
+
+    #[test]
+    fn high_align_small_size_test() {
+        let mut arena = vec![0u8; 1 << 15];
+        let mut talc = Talc::new(crate::ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+        let initial = unsafe { talc.region_report(heap) };
+
+        for (size, align) in [(16, 4096), (1, 2048), (24, 256), (8, 64)] {
+            let layout = Layout::from_size_align(size, align).unwrap();
+            // the slack around each allocation is left free, rather than its alignment
+            let max_chunk_size =
+                HEADER_SIZE + (size + ALIGN - 1) / ALIGN * ALIGN + TAG_SIZE + 2 * MIN_CHUNK_SIZE;
+
+            for hint in [LifetimeHint::ShortLived, LifetimeHint::LongLived] {
+                let mut allocs = vec![];
+                let mut used_bytes = initial.used_bytes;
+                while let Ok(ptr) = unsafe { talc.malloc_hinted(layout, hint) } {
+                    assert_eq!(ptr.as_ptr() as usize % align, 0);
+                    allocs.push(ptr);
+
+                    let report = unsafe { talc.region_report(heap) };
+                    assert!(report.used_bytes - used_bytes <= max_chunk_size);
+                    used_bytes = report.used_bytes;
+                }
+
+                // nearly every alignment unit of the heap holds an allocation
+                assert!(allocs.len() >= heap.size() / align.max(max_chunk_size) * 9 / 10);
+
+                // and the slack between them is usable by other allocations
+                if align >= 2 * max_chunk_size {
+                    let small = Layout::from_size_align(size, ALIGN).unwrap();
+                    let filler = unsafe { talc.malloc(small).unwrap() };
+                    unsafe { talc.free(filler, small) };
+                }
+
+                for ptr in allocs {
+                    unsafe { talc.free(ptr, layout) };
+                }
+                assert_eq!(talc.validate(), Ok(()));
+
+                #[cfg(feature = "fastbins")]
+                talc.flush_fastbins();
+                assert_eq!(unsafe { talc.region_report(heap) }, initial);
+            }
+        }
+    }
+
+    #[test]
+    fn grow_to_fill_test() {
+        let mut arena = vec![0u8; 10000];
+        let mut talc = Talc::new(crate::ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            let b = talc.malloc(layout).unwrap();
+            let (low, high) = if a < b { (a, b) } else { (b, a) };
+
+            // only the slack within the allocation's chunk is available below another allocation
+            let low_size = talc.grow_to_fill(low, layout);
+            assert!(low_size >= layout.size() && low_size < layout.size() + MIN_CHUNK_SIZE);
+            assert_eq!(
+                talc.grow_to_fill(low, Layout::from_size_align(low_size, 8).unwrap()),
+                low_size
+            );
+
+            // the free chunk above is taken whole
+            let high_size = talc.grow_to_fill(high, layout);
+            let high_layout = Layout::from_size_align(high_size, 8).unwrap();
+            assert_eq!(
+                high.as_ptr().add(high_size) as usize,
+                heap.get_base_acme().unwrap().1 as usize - TAG_SIZE
+            );
+            high.as_ptr().write_bytes(0xab, high_size);
+            assert_eq!(talc.report().free_chunk_count, 0);
+            assert_eq!(talc.validate(), Ok(()));
+
+            // zero-sized allocations have no chunk to grow
+            let zst = Layout::new::<()>();
+            assert_eq!(talc.grow_to_fill(NonNull::<()>::dangling().cast(), zst), 0);
+
+            talc.free(low, Layout::from_size_align(low_size, 8).unwrap());
+            talc.shrink(high, high_layout, layout.size());
+            assert!(talc.report().free_chunk_count > 0);
+            talc.free(high, layout);
+        }
+    }
+
+    #[cfg(feature = "bin_scan_limit")]
+    #[test]
+    fn bin_scan_limit_test() {
+        let mut arena = vec![0u8; 100000];
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+
+        #[cfg(feature = "profile_realtime")]
+        assert_eq!(talc.bin_scan_limit, REALTIME_BIN_SCAN_LIMIT);
+
+        // a list of free chunks in the same bin, where only the last inspected one fits
+        // (it's both the first freed and the highest, so this holds if address-ordered)
+        let guard = Layout::from_size_align(100, 8).unwrap();
+        let fitting = Layout::from_size_align(4900, 8).unwrap();
+        let too_small = Layout::from_size_align(4200, 8).unwrap();
+        let mut chunks = vec![];
+        for layout in [too_small, too_small, too_small, too_small, fitting] {
+            let ptr = unsafe { talc.malloc(layout).unwrap() };
+            unsafe { talc.malloc(guard).unwrap() };
+            chunks.push((ptr, layout));
+        }
+        for &(ptr, layout) in chunks.iter().rev() {
+            unsafe { talc.free(ptr, layout) };
+        }
+
+        let layout = Layout::from_size_align(4800, 8).unwrap();
+        let fitting_ptr = chunks[4].0;
+
+        talc.set_bin_scan_limit(Some(2));
+        let ptr = unsafe { talc.malloc(layout).unwrap() };
+        assert!(ptr > fitting_ptr);
+        unsafe { talc.free(ptr, layout) };
+
+        talc.set_bin_scan_limit(None);
+        let ptr = unsafe { talc.malloc(layout).unwrap() };
+        assert_eq!(ptr, fitting_ptr);
+        unsafe { talc.free(ptr, layout) };
+    }
+
+    #[cfg(feature = "address_ordered")]
+    #[test]
+    fn address_ordered_test() {
+        let mut arena = vec![0u8; 100000];
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+
+        let guard = Layout::from_size_align(100, 8).unwrap();
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        let mut ptrs = vec![];
+        for _ in 0..6 {
+            ptrs.push(unsafe { talc.malloc(layout).unwrap() });
+            unsafe { talc.malloc(guard).unwrap() };
+        }
+
+        for i in [3, 0, 5, 1, 4, 2] {
+            unsafe { talc.free(ptrs[i], layout) };
+        }
+
+        // the free chunks are in address order, rather than the reverse order they were freed in
+        let bin = unsafe { bin_of_size(sizing::required_chunk_size(layout.size())) };
+        let nodes = unsafe { LlistNode::iter_mut(*talc.get_bin_ptr(bin)) }.collect::<Vec<_>>();
+        assert_eq!(nodes.len(), ptrs.len());
+        assert!(nodes.windows(2).all(|pair| pair[0] < pair[1]));
+
+        assert_eq!(unsafe { talc.malloc(layout).unwrap() }, ptrs[0]);
+        assert_eq!(talc.validate(), Ok(()));
+    }
+
+    #[test]
+    fn absurd_size_test() {
+        let arena = Box::leak(vec![0u8; 100000].into_boxed_slice()) as *mut [_];
+
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe {
+            talc.claim(arena.as_mut().unwrap().into()).unwrap();
+        }
+
+        let max_align = 1 << (usize::BITS - 2);
+        let layouts = [
+            Layout::from_size_align(isize::MAX as usize - (ALIGN - 1), ALIGN).unwrap(),
+            Layout::from_size_align(isize::MAX as usize - (max_align - 1), max_align).unwrap(),
+            Layout::from_size_align(1, max_align).unwrap(),
+            Layout::from_size_align(isize::MAX as usize / 2, 4096).unwrap(),
+        ];
+
+        for layout in layouts {
+            assert!(unsafe { talc.malloc(layout) }.is_err());
+        }
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let allocation = unsafe { talc.malloc(layout).unwrap() };
+
+        for new_size in [isize::MAX as usize, usize::MAX - 100, usize::MAX] {
+            assert!(unsafe { talc.grow_in_place(allocation, layout, new_size) }.is_err());
+            assert!(unsafe { talc.grow(allocation, layout, new_size) }.is_err());
+        }
+
+        // the allocator is still intact
+        let allocation = unsafe { talc.grow(allocation, layout, 1000).unwrap() };
+        unsafe {
+            talc.free(allocation, Layout::from_size_align(1000, 8).unwrap());
+        }
+
+        unsafe {
+            drop(Box::from_raw(arena));
+        }
+    }
+
+    #[test]
+    fn zero_sized_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(crate::ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let initial = talc.report();
+
+        unsafe {
+            // zero-sized allocations are dangling and aligned, and don't touch the heap
+            let zst = Layout::from_size_align(0, 64).unwrap();
+            let a = talc.malloc(zst).unwrap();
+            assert_eq!(a.as_ptr() as usize, 64);
+            talc.free(a, zst);
+
+            // growing allocates anew, while reallocating to zero frees
+            let layout = Layout::from_size_align(100, 64).unwrap();
+            let b = talc.grow(a, zst, layout.size()).unwrap();
+            assert_eq!(b.as_ptr() as usize % 64, 0);
+            let b = talc.realloc(b, layout, zst).unwrap();
+            assert_eq!(b, a);
+        }
+
+        #[cfg(feature = "quarantine")]
+        talc.flush_quarantine();
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+    }
+
+    #[test]
+    fn oom_retry_limit_test() {
+        /// Claims a small heap, then pretends to help without doing anything.
+        struct UselessOom {
+            arena: Span,
+            calls: usize,
+        }
+
+        impl OomHandler for UselessOom {
+            fn handle_oom(talc: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+                talc.oom_handler.calls += 1;
+
+                let arena = core::mem::take(&mut talc.oom_handler.arena);
+                if !arena.is_empty() {
+                    unsafe { talc.claim(arena)? };
+                }
+
+                Ok(())
+            }
+
+            const RETRY_LIMIT: usize = 5;
+        }
+
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(UselessOom { arena: arena.as_mut().into(), calls: 0 });
+
+        // the first call makes progress, so the allocation succeeds
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let allocation = unsafe { talc.malloc(layout).unwrap() };
+        assert_eq!(talc.oom_handler.calls, 1);
+
+        // but the allocation fails, rather than hanging, once the handler can't help
+        let huge = Layout::from_size_align(100000, 8).unwrap();
+        assert!(unsafe { talc.malloc(huge) }.is_err());
+        assert_eq!(talc.oom_handler.calls, 1 + UselessOom::RETRY_LIMIT);
+
+        unsafe { talc.free(allocation, layout) };
+    }
+
+    #[test]
+    fn oom_handler_unwind_test() {
+        struct PanicOnOom;
+
+        impl OomHandler for PanicOnOom {
+            fn handle_oom(_: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+                panic!("out of memory");
+            }
+        }
+
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(PanicOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let allocation = unsafe { talc.malloc(layout).unwrap() };
+        let report = talc.report();
+
+        let huge = Layout::from_size_align(100000, 8).unwrap();
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { talc.malloc(huge) }));
+        assert!(result.is_err());
+
+        // the allocator is untouched and still usable
+        assert_eq!(talc.report(), report);
+        unsafe { talc.free(allocation, layout) };
+        let allocation = unsafe { talc.malloc(layout).unwrap() };
+        unsafe { talc.free(allocation, layout) };
+    }
+
+    #[test]
+    fn disjoint_heaps_test() {
+        // e.g. scattered RAM banks, with unclaimed gaps between them
+        let mut memory = vec![0u8; 3 * 12000];
+        let mut talc = Talc::new(crate::ErrOnOom);
+
+        let heaps: Vec<Span> = memory
+            .chunks_mut(12000)
+            .map(|bank| unsafe { talc.claim((&mut bank[..10000]).into()).unwrap() })
+            .collect();
+        // the banks aren't contiguous, so each is a separate heap
+        assert!(
+            heaps
+                .windows(2)
+                .all(|w| w[0].get_base_acme().unwrap().1 < w[1].get_base_acme().unwrap().0)
+        );
+
+        let layout = Layout::from_size_align(256, 8).unwrap();
+        let mut allocs = Vec::new();
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            allocs.push(ptr);
+        }
+
+        // every bank is used
+        for heap in &heaps {
+            assert!(allocs.iter().any(|ptr| heap.contains(ptr.as_ptr())));
+        }
+
+        for ptr in allocs {
+            unsafe { talc.free(ptr, layout) };
+        }
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report().free_chunk_count, 3);
+
+        // the middle bank can be returned, but not the one holding the metadata
+        unsafe {
+            assert!(talc.unclaim(heaps[1]).is_ok());
+            assert!(talc.unclaim(heaps[0]).is_err());
+        }
+        assert_eq!(talc.report().free_chunk_count, 2);
+        assert!(unsafe { talc.malloc(Layout::new::<[u8; 9000]>()) }.is_ok());
+    }
+
     #[test]
     fn claim_truncate_extend_test() {
         // not big enough to fit the metadata
@@ -0,0 +1,299 @@
+//! Establish a heap over memory that already contains allocations.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{
+    is_chunk_size, tag::Tag, Bin, Talc, BIN_ARRAY_SIZE, HEADER_SIZE, MIN_HEAP_SIZE, MIN_TAG_OFFSET,
+    TAG_SIZE,
+};
+use crate::{
+    ptr_utils::{align_up, ALIGN},
+    OomHandler, Span,
+};
+
+impl<O: OomHandler> Talc<O> {
+    /// Establish a new heap over `memory`, which already contains the allocations in `live`,
+    /// e.g. ones made by a bootloader or by a previously used allocator.
+    ///
+    /// The allocations are left untouched, and become allocations of this allocator,
+    /// to be freed or reallocated as if they were allocated by [`Talc::malloc`] with the
+    /// given layouts. The memory around them becomes free memory.
+    ///
+    /// The allocator keeps metadata in the memory surrounding each allocation,
+    /// so each allocation must be:
+    /// - aligned to `usize`, and not zero-sized.
+    /// - preceded by room for a header that isn't part of another allocation. This is
+    ///   zero bytes, unless the `owners` or `generations` features are enabled.
+    /// - followed by a `usize` that isn't part of another allocation, after
+    ///   rounding its end up to the alignment of `usize`.
+    ///
+    /// If the allocator's metadata isn't yet established, it's placed at the base of
+    /// `memory`, which must then be free of allocations for the first
+    /// [`min_arena_size(0)`](crate::min_arena_size) bytes or so.
+    ///
+    /// Returns `Err` without modifying any memory if `memory` is too small, or any
+    /// of the allocations doesn't satisfy the above or is out of order.
+    ///
+    /// # Safety
+    /// - See [`Talc::claim`].
+    /// - `live` must yield the allocations within `memory` in ascending order of address,
+    ///   identically each time it's cloned.
+    /// - The memory not covered by `live` mustn't be in use.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::{alloc::Layout, ptr::NonNull};
+    /// let mut arena = [0u64; 1000];
+    /// let base = arena.as_mut_ptr().cast::<u8>();
+    ///
+    /// // e.g. a bootloader left a buffer at the end of the arena
+    /// let layout = Layout::new::<[u64; 16]>();
+    /// let buffer = unsafe { NonNull::new_unchecked(base.add(8000 - 1000)) };
+    ///
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let live = [(buffer, layout)];
+    /// unsafe {
+    ///     talc.adopt(Span::from_base_size(base, 8000), live.iter().copied()).unwrap();
+    ///     talc.free(buffer, layout);
+    /// }
+    /// ```
+    pub unsafe fn adopt<I>(&mut self, memory: Span, live: I) -> Result<Span, ()>
+    where
+        I: Iterator<Item = (NonNull<u8>, Layout)> + Clone,
+    {
+        self.scan_for_errors();
+
+        assert!(!memory.contains(core::ptr::null_mut()), "heap covers the null address!");
+
+        let aligned_heap = memory.word_align_inward();
+        let (base, acme) = aligned_heap.get_base_acme().ok_or(())?;
+        #[cfg(feature = "sanitize")]
+        super::sanitize::unpoison(base, acme);
+
+        // check everything before modifying any memory
+        self.adopt_pass(base, acme, live.clone(), false)?;
+        self.adopt_pass(base, acme, live, true)?;
+
+        #[cfg(feature = "counters")]
+        self.counters.account_claim(aligned_heap.size());
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
+
+        self.scan_for_errors();
+
+        Ok(aligned_heap)
+    }
+
+    /// Lays out the chunks of the heap from `base` to `acme` around the `live` allocations.
+    ///
+    /// Only writes to memory if `commit` is set, and never fails if it succeeded without.
+    unsafe fn adopt_pass(
+        &mut self,
+        base: *mut u8,
+        acme: *mut u8,
+        live: impl Iterator<Item = (NonNull<u8>, Layout)>,
+        commit: bool,
+    ) -> Result<(), ()> {
+        if (acme as usize - base as usize) < MIN_HEAP_SIZE {
+            return Err(());
+        }
+
+        // the chunk below the next one, as (chunk_base, post_alloc_ptr)
+        let mut prev: Option<(*mut u8, *mut u8)> = None;
+        // the base tag, or the tag of the chunk below the next one
+        let mut prev_tag_ptr = base;
+        // the acme of the chunk below the next one
+        let mut cursor = base.wrapping_add(TAG_SIZE);
+
+        if commit {
+            Tag::write(base.cast(), core::ptr::null_mut(), false);
+        }
+
+        if self.bins.is_null() {
+            // the metadata is allocated 'by hand' at the base of the heap, as in `claim`
+            let metadata_ptr = base.wrapping_add(TAG_SIZE);
+            let post_metadata_ptr = metadata_ptr.wrapping_add(BIN_ARRAY_SIZE);
+
+            if acme as usize - (metadata_ptr as usize) < BIN_ARRAY_SIZE + TAG_SIZE {
+                return Err(());
+            }
+
+            if commit {
+                for i in 0..super::BIN_COUNT {
+                    metadata_ptr.cast::<Bin>().add(i).write(None);
+                }
+                self.bins = metadata_ptr.cast::<Bin>();
+
+                Tag::write(post_metadata_ptr.cast(), base, false);
+            }
+
+            prev = Some((base, post_metadata_ptr));
+            prev_tag_ptr = post_metadata_ptr;
+            cursor = post_metadata_ptr.wrapping_add(TAG_SIZE);
+        }
+
+        for (ptr, layout) in live {
+            let ptr = ptr.as_ptr();
+
+            if ptr as usize % ALIGN != 0
+                || layout.size() == 0
+                || (ptr as usize) < cursor as usize + HEADER_SIZE
+                || ptr >= acme
+                || layout.size() > acme as usize - ptr as usize
+            {
+                return Err(());
+            }
+
+            // if the memory below the allocation is too small, subsume it, else free it
+            let mut chunk_base = ptr.sub(HEADER_SIZE);
+            if is_chunk_size(cursor, chunk_base) {
+                if commit {
+                    #[cfg(feature = "poison")]
+                    super::poison::poison(cursor, chunk_base);
+                    self.register_gap(cursor, chunk_base);
+                    Tag::set_above_free(prev_tag_ptr.cast());
+                }
+            } else {
+                chunk_base = cursor;
+            }
+
+            let post_alloc_ptr = align_up(ptr.add(layout.size()));
+            let tag_ptr = chunk_base.add(MIN_TAG_OFFSET).max(post_alloc_ptr);
+
+            if tag_ptr as usize >= acme as usize {
+                return Err(());
+            }
+
+            if commit {
+                let header = self.new_header(layout.size());
+
+                Tag::write(tag_ptr.cast(), chunk_base, false);
+                if tag_ptr != post_alloc_ptr {
+                    post_alloc_ptr.cast::<*mut u8>().write(tag_ptr);
+                }
+
+                self.finish_malloc(chunk_base, ptr, header, layout.size());
+            }
+
+            prev = Some((chunk_base, post_alloc_ptr));
+            prev_tag_ptr = tag_ptr;
+            cursor = tag_ptr.add(TAG_SIZE);
+        }
+
+        // handle the memory above the last chunk
+        if is_chunk_size(cursor, acme) {
+            if commit {
+                #[cfg(feature = "poison")]
+                super::poison::poison(cursor, acme);
+                self.register_gap(cursor, acme);
+                Tag::set_above_free(prev_tag_ptr.cast());
+            }
+        } else if let Some((chunk_base, post_alloc_ptr)) = prev {
+            if commit && cursor != acme {
+                // extend the chunk below to the top of the heap
+                let tag_ptr = acme.sub(TAG_SIZE);
+                Tag::write(tag_ptr.cast(), chunk_base, false);
+                if tag_ptr != post_alloc_ptr {
+                    post_alloc_ptr.cast::<*mut u8>().write(tag_ptr);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use crate::*;
+
+    #[test]
+    fn adopt_test() {
+        const SIZE: usize = 20000;
+        let mut arena = std::vec![0u64; SIZE / 8];
+        let base = arena.as_mut_ptr().cast::<u8>();
+
+        let ptr_at = |offset: usize| unsafe { NonNull::new_unchecked(base.add(offset)) };
+        let live = [
+            (ptr_at(2000), Layout::from_size_align(100, 8).unwrap()),
+            (ptr_at(2200), Layout::from_size_align(1, 1).unwrap()),
+            (ptr_at(2280), Layout::from_size_align(7, 8).unwrap()),
+            (ptr_at(4096), Layout::from_size_align(3000, 64).unwrap()),
+            (ptr_at(SIZE - 48), Layout::from_size_align(40, 8).unwrap()),
+        ];
+
+        for (ptr, layout) in live.iter() {
+            unsafe { ptr.as_ptr().write_bytes(0xCD, layout.size()) };
+        }
+
+        let mut talc = Talc::new(ErrOnOom);
+
+        // out of order, overlapping, or in the way of the metadata
+        let span = Span::from_base_size(base, SIZE);
+        unsafe {
+            assert!(talc.adopt(span, live.iter().rev().copied()).is_err());
+            assert!(talc.adopt(span, [live[0], live[0]].iter().copied()).is_err());
+            assert!(talc.adopt(span, [(ptr_at(16), live[0].1)].iter().copied()).is_err());
+        }
+
+        let heap = unsafe { talc.adopt(span, live.iter().copied()).unwrap() };
+        assert_eq!(heap, span);
+
+        #[cfg(feature = "counters")]
+        assert_eq!(talc.get_counters().allocation_count, live.len());
+
+        // the free memory around the allocations is usable, without touching them
+        let layout = Layout::from_size_align(8, 8).unwrap();
+        let mut allocations = std::vec![];
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            assert!(live.iter().all(|(live_ptr, live_layout)| {
+                ptr.as_ptr() as usize + 8 <= live_ptr.as_ptr() as usize
+                    || ptr.as_ptr() as usize >= live_ptr.as_ptr() as usize + live_layout.size()
+            }));
+            unsafe { ptr.as_ptr().write_bytes(0xAB, 8) };
+            allocations.push(ptr);
+        }
+
+        for (ptr, layout) in live.iter() {
+            unsafe {
+                assert!((0..layout.size()).all(|i| *ptr.as_ptr().add(i) == 0xCD));
+                talc.free(*ptr, *layout);
+            }
+        }
+        for ptr in allocations {
+            unsafe { talc.free(ptr, layout) };
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+
+        // everything coalesced back together, as if the arena was claimed
+        let mut other_arena = std::vec![0u64; SIZE / 8];
+        let mut other_talc = Talc::new(ErrOnOom);
+        unsafe { other_talc.claim(other_arena.as_mut_slice().into()).unwrap() };
+
+        assert_eq!(talc.report().free_chunk_count, 1);
+        assert_eq!(talc.report().free_bytes, other_talc.report().free_bytes);
+    }
+
+    #[test]
+    fn adopt_empty_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.adopt(arena.as_mut().into(), core::iter::empty()).unwrap() };
+
+        let mut other_arena = [0u8; 10000];
+        let mut other_talc = Talc::new(ErrOnOom);
+        let other_heap = unsafe { other_talc.claim(other_arena.as_mut().into()).unwrap() };
+
+        // adopting nothing is claiming
+        assert_eq!(talc.report(), other_talc.report());
+        assert_eq!(heap.size(), other_heap.size());
+
+        let mut tiny = [0u8; 4];
+        assert!(unsafe { talc.adopt(tiny.as_mut().into(), core::iter::empty()) }.is_err());
+    }
+}
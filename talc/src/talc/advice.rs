@@ -0,0 +1,181 @@
+//! Analyze fragmentation to inform memory pressure decisions.
+
+use super::{
+    gap_node_to_base, gap_node_to_size, llist::LlistNode, sizing::required_chunk_size, Talc,
+    BIN_COUNT, HEADER_SIZE, TAG_SIZE,
+};
+use crate::{
+    ptr_utils::{align_up_by, ALIGN},
+    OomHandler, Span,
+};
+
+/// The number of alignments [`HeapAdvice`] tracks the largest allocation for,
+/// being the powers of two from 1 up to 4096.
+pub const ADVISED_ALIGN_COUNT: usize = 13;
+
+/// Suggestions derived from the state of the allocator's free memory,
+/// as returned by [`Talc::advise`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeapAdvice {
+    /// Bytes that [`Talc::trim`] could release from the ends of the given heaps.
+    pub trimmable_bytes: usize,
+    /// Sum of the sizes of all free chunks within the given heaps.
+    pub free_bytes: usize,
+    /// The most free memory within any one of the given heaps, which is roughly how
+    /// large a chunk compacting that heap's allocations could recover.
+    pub compactable_bytes: usize,
+    /// The largest allocation that currently fits in the given heaps, indexed by the
+    /// base-2 logarithm of its alignment. See [`HeapAdvice::largest_allocation`].
+    pub largest_allocation_by_align: [usize; ADVISED_ALIGN_COUNT],
+}
+
+impl HeapAdvice {
+    /// Returns the size of the largest allocation with `align` that currently fits,
+    /// without the OOM handler having to make more memory available.
+    ///
+    /// Returns `None` if `align` isn't a power of two, or is larger than 4096.
+    pub fn largest_allocation(&self, align: usize) -> Option<usize> {
+        if !align.is_power_of_two() {
+            return None;
+        }
+
+        self.largest_allocation_by_align.get(align.trailing_zeros() as usize).copied()
+    }
+
+    /// Returns whether an allocation of `size` bytes, aligned to no more than `usize`,
+    /// doesn't fit now, but would likely fit after compacting the allocations of a heap.
+    pub fn compaction_recovers(&self, size: usize) -> bool {
+        size > self.largest_allocation_by_align[ALIGN.trailing_zeros() as usize]
+            && required_chunk_size(size) <= self.compactable_bytes
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Analyze the free memory within `heaps` and return actionable figures:
+    /// how much trimming would release, whether compaction would recover a block
+    /// of a given size, and the largest allocation that fits for each alignment.
+    ///
+    /// Free chunks outside of `heaps` are ignored. Chunks cached by the `fastbins`
+    /// feature aren't considered free. With the `bin_scan_limit` feature, the limit set by
+    /// [`Talc::set_bin_scan_limit`] may prevent allocations this reports as fitting from finding their chunk.
+    ///
+    /// This walks every free chunk for each heap, so it's intended for occasional
+    /// use, such as by a memory pressure manager, rather than on hot paths.
+    ///
+    /// # Safety
+    /// Each of `heaps` must be the return value of a heap-manipulation function
+    /// of this allocator instance.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+    ///
+    /// let advice = unsafe { talc.advise(&[heap]) };
+    /// assert!(advice.trimmable_bytes > 0);
+    /// assert!(advice.largest_allocation(64).unwrap() > 5000);
+    /// assert!(!advice.compaction_recovers(100));
+    /// ```
+    pub unsafe fn advise(&self, heaps: &[Span]) -> HeapAdvice {
+        let mut advice = HeapAdvice::default();
+
+        if self.bins.is_null() {
+            return advice;
+        }
+
+        for &heap in heaps {
+            advice.trimmable_bytes += match self.get_allocated_span(heap).get_base_acme() {
+                Some((base, acme)) => heap.size() - (acme as usize - base as usize),
+                None => heap.size(),
+            };
+
+            let mut heap_free_bytes = 0;
+
+            for b in 0..BIN_COUNT {
+                for node in LlistNode::iter_mut(*self.get_bin_ptr(b)) {
+                    let base = gap_node_to_base(node);
+                    if !heap.contains(base) {
+                        continue;
+                    }
+
+                    let size = gap_node_to_size(node).read();
+                    heap_free_bytes += size;
+
+                    for (i, largest) in advice.largest_allocation_by_align.iter_mut().enumerate() {
+                        // the lowest aligned pointer with room for the header below it
+                        let align_mask = (1 << i).max(ALIGN) - 1;
+                        let offset =
+                            align_up_by(base.add(HEADER_SIZE), align_mask) as usize - base as usize;
+
+                        *largest = (*largest).max(size.saturating_sub(offset + TAG_SIZE));
+                    }
+                }
+            }
+
+            advice.free_bytes += heap_free_bytes;
+            advice.compactable_bytes = advice.compactable_bytes.max(heap_free_bytes);
+        }
+
+        advice
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use crate::*;
+
+    #[test]
+    fn advise_test() {
+        let mut arena = [0u8; 20000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+
+        unsafe {
+            let fresh = talc.advise(&[heap]);
+            assert_eq!(fresh.compactable_bytes, fresh.free_bytes);
+            assert_eq!(fresh.trimmable_bytes, fresh.free_bytes);
+            assert!(fresh.largest_allocation(4096).unwrap() < fresh.largest_allocation(8).unwrap());
+            assert_eq!(fresh.largest_allocation(8192), None);
+            assert_eq!(fresh.largest_allocation(3), None);
+
+            // the largest allocation reported really fits
+            let largest = fresh.largest_allocation(64).unwrap();
+            let big = Layout::from_size_align(largest, 64).unwrap();
+            let ptr = talc.malloc(big).unwrap();
+            assert!(talc.malloc(Layout::from_size_align(largest + 1, 64).unwrap()).is_err());
+            talc.free(ptr, big);
+
+            // fill the heap, then free every other allocation
+            let mut allocs = [None::<NonNull<u8>>; 20];
+            for slot in allocs.iter_mut() {
+                *slot = talc.malloc(layout).ok();
+            }
+            for slot in allocs.iter_mut().step_by(2) {
+                if let Some(ptr) = slot.take() {
+                    talc.free(ptr, layout);
+                }
+            }
+
+            let fragmented = talc.advise(&[heap]);
+            assert!(fragmented.free_bytes > 3000);
+            assert!(fragmented.largest_allocation(8).unwrap() < 3000);
+            assert!(fragmented.compaction_recovers(3000));
+            assert!(!fragmented.compaction_recovers(fragmented.free_bytes));
+
+            // heaps not passed in are ignored
+            assert_eq!(talc.advise(&[]), HeapAdvice::default());
+
+            for ptr in allocs.iter().flatten() {
+                talc.free(*ptr, layout);
+            }
+            assert_eq!(talc.advise(&[heap]), fresh);
+        }
+    }
+}
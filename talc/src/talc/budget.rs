@@ -0,0 +1,174 @@
+//! Limit the sum of the sizes of live allocations, independently of the memory available,
+//! and tell why allocations fail.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{Talc, HEADER_SIZE, TAG_SIZE};
+use crate::OomHandler;
+
+/// The reason an allocation failed, as returned by [`Talc::try_malloc`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MallocError {
+    /// The OOM handler failed to make enough memory available.
+    OutOfMemory,
+    /// The allocation would exceed the budget set by `Talc::set_budget`,
+    /// which requires the `counters` feature.
+    OverBudget,
+    /// The allocation, with its alignment and the allocator's metadata, would be larger
+    /// than `isize::MAX` bytes, so no heap could hold it.
+    SizeOverflow,
+}
+
+impl core::fmt::Display for MallocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            MallocError::OutOfMemory => f.write_str("out of memory"),
+            MallocError::OverBudget => f.write_str("memory budget exceeded"),
+            MallocError::SizeOverflow => f.write_str("allocation size overflows"),
+        }
+    }
+}
+
+#[cfg(feature = "counters")]
+impl<O: OomHandler> Talc<O> {
+    /// Limit the sum of the sizes of live allocations to `budget` bytes, or lift the limit.
+    ///
+    /// Allocations and in-place growth that would exceed the budget fail without
+    /// calling the OOM handler. Use [`Talc::try_malloc`] to tell these failures
+    /// apart from running out of memory.
+    ///
+    /// Sizes are counted as requested, excluding the allocator's overhead, as in the
+    /// `allocated_bytes` of [`Talc::get_counters`].
+    /// While a reallocation moves the memory, both the old and new allocations count
+    /// against the budget. Lowering the budget below the currently allocated bytes
+    /// doesn't affect existing allocations.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// talc.set_budget(Some(1000));
+    /// unsafe {
+    ///     let ptr = talc.try_malloc(Layout::new::<[u8; 800]>()).unwrap();
+    ///     let err = talc.try_malloc(Layout::new::<[u8; 800]>()).unwrap_err();
+    ///     assert_eq!(err, MallocError::OverBudget);
+    ///     talc.free(ptr, Layout::new::<[u8; 800]>());
+    /// }
+    /// ```
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Returns the limit set by [`Talc::set_budget`], if any.
+    pub fn get_budget(&self) -> Option<usize> {
+        self.budget
+    }
+
+    /// Returns whether `additional` bytes can be allocated without exceeding the budget.
+    #[inline]
+    pub(super) fn fits_budget(&self, additional: usize) -> bool {
+        match self.budget {
+            Some(budget) => match self.counters.allocated_bytes.checked_add(additional) {
+                Some(allocated_bytes) => allocated_bytes <= budget,
+                None => false,
+            },
+            None => true,
+        }
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Like [`Talc::malloc`], but returns why the allocation failed.
+    ///
+    /// # Safety
+    /// As for [`Talc::malloc`].
+    pub unsafe fn try_malloc(&mut self, layout: Layout) -> Result<NonNull<u8>, MallocError> {
+        // zero-sized allocations don't use the heap, so any alignment will do
+        if layout.size() != 0 {
+            let max_size = (isize::MAX as usize)
+                .checked_sub(HEADER_SIZE + TAG_SIZE)
+                .and_then(|max_size| max_size.checked_sub(layout.align() - 1));
+
+            match max_size {
+                Some(max_size) if layout.size() <= max_size => (),
+                _ => return Err(MallocError::SizeOverflow),
+            }
+        }
+
+        #[cfg(feature = "counters")]
+        if !self.fits_budget(layout.size()) {
+            return Err(MallocError::OverBudget);
+        }
+
+        self.malloc(layout).map_err(|()| MallocError::OutOfMemory)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn try_malloc_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        unsafe {
+            let absurd = Layout::from_size_align(isize::MAX as usize - 7, 8).unwrap();
+            assert_eq!(talc.try_malloc(absurd), Err(MallocError::SizeOverflow));
+            let absurd = Layout::from_size_align(isize::MAX as usize / 2, 1 << (usize::BITS - 2));
+            assert_eq!(talc.try_malloc(absurd.unwrap()), Err(MallocError::SizeOverflow));
+
+            // zero-sized allocations succeed regardless of their alignment
+            let zst = Layout::from_size_align(0, 1 << (usize::BITS - 1)).unwrap();
+            let ptr = talc.try_malloc(zst).unwrap();
+            talc.free(ptr, zst);
+
+            let huge = Layout::from_size_align(20000, 8).unwrap();
+            assert_eq!(talc.try_malloc(huge), Err(MallocError::OutOfMemory));
+        }
+    }
+
+    #[cfg(feature = "counters")]
+    #[test]
+    fn budget_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(400, 8).unwrap();
+        talc.set_budget(Some(1000));
+        assert_eq!(talc.get_budget(), Some(1000));
+
+        unsafe {
+            let a = talc.try_malloc(layout).unwrap();
+            let b = talc.malloc(layout).unwrap();
+            assert_eq!(talc.try_malloc(layout), Err(MallocError::OverBudget));
+            assert!(talc.malloc(layout).is_err());
+
+            // in-place growth is limited too, but shrinking always succeeds
+            assert!(talc.grow_in_place(b, layout, 700).is_err());
+            let b = talc.grow_in_place(b, layout, 600).unwrap();
+            talc.shrink(b, Layout::from_size_align(600, 8).unwrap(), 100);
+
+            // running out of memory is told apart
+            talc.set_budget(None);
+            let huge = Layout::from_size_align(20000, 8).unwrap();
+            assert_eq!(talc.try_malloc(huge), Err(MallocError::OutOfMemory));
+
+            // lowering the budget leaves existing allocations be
+            talc.set_budget(Some(0));
+            talc.free(a, layout);
+            talc.free(b, Layout::from_size_align(100, 8).unwrap());
+            assert_eq!(talc.try_malloc(layout), Err(MallocError::OverBudget));
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Home of TalcBuilder, which assembles a configured Talc.
+
+use super::Talc;
+use crate::OomHandler;
+
+/// Assembles a [`Talc`] with its options set, as an alternative to calling
+/// [`Talc::new`] followed by setters.
+///
+/// All methods are `const`, so a configured allocator can be built for a `static`.
+/// Options that depend on a feature are only available while it's enabled.
+/// Options left unset have the same defaults as with [`Talc::new`].
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static TALC: Talck<spin::Mutex<()>, ClaimOnOom> = TalcBuilder::new()
+///     .build(unsafe { ClaimOnOom::new(Span::empty()) })
+///     .lock();
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TalcBuilder {
+    #[cfg(feature = "bin_scan_limit")]
+    bin_scan_limit: Option<usize>,
+    #[cfg(feature = "counters")]
+    budget: Option<usize>,
+    #[cfg(feature = "owners")]
+    owner_source: Option<fn() -> usize>,
+    #[cfg(feature = "timing")]
+    timestamp_source: Option<fn() -> u32>,
+    #[cfg(feature = "trace")]
+    trace_sink: Option<fn(super::trace::TraceEntry)>,
+}
+
+impl TalcBuilder {
+    /// Start building a `Talc` with the default options.
+    pub const fn new() -> Self {
+        Self {
+            #[cfg(feature = "bin_scan_limit")]
+            bin_scan_limit: None,
+            #[cfg(feature = "counters")]
+            budget: None,
+            #[cfg(feature = "owners")]
+            owner_source: None,
+            #[cfg(feature = "timing")]
+            timestamp_source: None,
+            #[cfg(feature = "trace")]
+            trace_sink: None,
+        }
+    }
+
+    /// See [`Talc::set_bin_scan_limit`].
+    #[cfg(feature = "bin_scan_limit")]
+    pub const fn bin_scan_limit(mut self, limit: usize) -> Self {
+        self.bin_scan_limit = Some(limit);
+        self
+    }
+
+    /// See [`Talc::set_budget`].
+    #[cfg(feature = "counters")]
+    pub const fn budget(mut self, budget: usize) -> Self {
+        self.budget = Some(budget);
+        self
+    }
+
+    /// See [`Talc::set_owner_source`].
+    #[cfg(feature = "owners")]
+    pub const fn owner_source(mut self, owner_source: fn() -> usize) -> Self {
+        self.owner_source = Some(owner_source);
+        self
+    }
+
+    /// See [`Talc::set_timestamp_source`].
+    #[cfg(feature = "timing")]
+    pub const fn timestamp_source(mut self, timestamp_source: fn() -> u32) -> Self {
+        self.timestamp_source = Some(timestamp_source);
+        self
+    }
+
+    /// See [`Talc::set_trace_sink`].
+    #[cfg(feature = "trace")]
+    pub const fn trace_sink(mut self, trace_sink: fn(super::trace::TraceEntry)) -> Self {
+        self.trace_sink = Some(trace_sink);
+        self
+    }
+
+    /// Build a `Talc` with `oom_handler` and the options set.
+    /// Claim memory for it to become useful, see [`Talc::claim`].
+    pub const fn build<O: OomHandler>(self, oom_handler: O) -> Talc<O> {
+        #[cfg_attr(
+            not(any(
+                feature = "bin_scan_limit",
+                feature = "counters",
+                feature = "owners",
+                feature = "trace"
+            )),
+            allow(unused_mut)
+        )]
+        let mut talc = Talc::new(oom_handler);
+
+        #[cfg(feature = "bin_scan_limit")]
+        if let Some(limit) = self.bin_scan_limit {
+            // at least one chunk is always inspected
+            talc.bin_scan_limit = if limit == 0 { 1 } else { limit };
+        }
+        #[cfg(feature = "counters")]
+        {
+            talc.budget = self.budget;
+        }
+        #[cfg(feature = "owners")]
+        if let Some(owner_source) = self.owner_source {
+            talc.owner_source = owner_source;
+        }
+        #[cfg(feature = "timing")]
+        {
+            talc.timestamp_source = self.timestamp_source;
+        }
+        #[cfg(feature = "trace")]
+        {
+            talc.trace_sink = self.trace_sink;
+        }
+
+        talc
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn builder_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc =
+            TalcBuilder::new().build(unsafe { ClaimOnOom::new(Span::from(arena.as_mut())) });
+
+        #[cfg(feature = "bin_scan_limit")]
+        {
+            let talc = TalcBuilder::new().bin_scan_limit(0).build(ErrOnOom);
+            assert_eq!(talc.bin_scan_limit, 1);
+            assert_eq!(
+                TalcBuilder::new().build(ErrOnOom).bin_scan_limit,
+                Talc::new(ErrOnOom).bin_scan_limit
+            );
+        }
+
+        #[cfg(feature = "counters")]
+        {
+            let talc = TalcBuilder::new().budget(100).build(ErrOnOom);
+            assert_eq!(talc.get_budget(), Some(100));
+        }
+
+        // the OOM handler claims the arena
+        let layout = Layout::new::<[u64; 4]>();
+        unsafe {
+            let ptr = talc.malloc(layout).unwrap();
+            talc.free(ptr, layout);
+        }
+    }
+}
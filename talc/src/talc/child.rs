@@ -0,0 +1,117 @@
+//! Carve independent child allocators out of a parent's heap.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::Talc;
+use crate::{ptr_utils::ALIGN, OomHandler, Span};
+
+impl<O: OomHandler> Talc<O> {
+    /// Allocate a region of at least `size` bytes and establish a new allocator over it,
+    /// returning the child allocator and the region.
+    ///
+    /// The child has its own bins and heap, so its fragmentation is isolated from this
+    /// allocator and its siblings. Wrap it in a lock with [`Talc::lock`] like any other.
+    /// Once done with the child, release the region in one go with [`Talc::free_child`],
+    /// regardless of whether the child has outstanding allocations.
+    ///
+    /// Returns `Err` if the region can't be allocated, or if it's too small to hold
+    /// the child's metadata, see [`min_arena_size`](crate::min_arena_size).
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut parent = Talc::new(ErrOnOom);
+    /// unsafe { parent.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let (mut child, region) = parent.carve_child(4000, ErrOnOom).unwrap();
+    /// let layout = Layout::new::<[u32; 8]>();
+    /// let ptr = unsafe { child.malloc(layout).unwrap() };
+    /// assert!(region.contains(ptr.as_ptr()));
+    ///
+    /// // tear down the child, along with its allocations
+    /// unsafe { parent.free_child(region); }
+    /// ```
+    pub fn carve_child<C: OomHandler>(
+        &mut self,
+        size: usize,
+        oom_handler: C,
+    ) -> Result<(Talc<C>, Span), ()> {
+        // round up, such that the child claims the whole region
+        let size = size.checked_add(ALIGN - 1).ok_or(())? & !(ALIGN - 1);
+        let layout = Layout::from_size_align(size.max(ALIGN), ALIGN).map_err(|_| ())?;
+
+        // SAFETY: the layout's size is nonzero
+        let region_base = unsafe { self.malloc(layout)? };
+        let region = Span::from_base_size(region_base.as_ptr(), layout.size());
+
+        let mut child = Talc::new(oom_handler);
+
+        // SAFETY: the region is allocated to the child until it's freed with `free_child`
+        match unsafe { child.claim(region) } {
+            Ok(_) => Ok((child, region)),
+            Err(()) => {
+                unsafe { self.free(region_base, layout) };
+                Err(())
+            }
+        }
+    }
+
+    /// Free the region of a child allocator made by [`Talc::carve_child`], returning it to
+    /// this allocator, along with all of the child's allocations.
+    ///
+    /// # Safety
+    /// `region` must have been returned by `carve_child` on this allocator, and not freed
+    /// since. The child allocator and its allocations must no longer be used.
+    pub unsafe fn free_child(&mut self, region: Span) {
+        let (base, acme) = region.get_base_acme().unwrap();
+        let layout = Layout::from_size_align_unchecked(acme as usize - base as usize, ALIGN);
+
+        self.free(NonNull::new_unchecked(base), layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn carve_child_test() {
+        let mut arena = [0u8; 20000];
+        let mut parent = Talc::new(ErrOnOom);
+        unsafe { parent.claim(arena.as_mut().into()).unwrap() };
+        let initial = parent.report();
+
+        assert!(parent.carve_child(100000, ErrOnOom).is_err());
+        assert!(parent.carve_child(16, ErrOnOom).is_err());
+        #[cfg(feature = "fastbins")]
+        parent.flush_fastbins();
+        assert_eq!(parent.report(), initial);
+
+        let (mut a, a_region) = parent.carve_child(5000, ErrOnOom).unwrap();
+        let (mut b, b_region) = parent.carve_child(5001, ErrOnOom).unwrap();
+        assert!(a_region.size() >= 5000 && b_region.size() >= 5001);
+        assert!(!a_region.overlaps(b_region));
+
+        // the children allocate independently, within their regions
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        unsafe {
+            let a_ptr = a.malloc(layout).unwrap();
+            let b_ptr = b.malloc(layout).unwrap();
+            assert!(a_region.contains(a_ptr.as_ptr()));
+            assert!(b_region.contains(b_ptr.as_ptr()));
+
+            // a child running out of memory doesn't take from the parent
+            assert!(a.malloc(Layout::from_size_align(5000, 8).unwrap()).is_err());
+
+            // tearing down the children returns their regions, allocations and all
+            parent.free_child(a_region);
+            parent.free_child(b_region);
+        }
+
+        assert_eq!(parent.report().free_bytes, initial.free_bytes);
+    }
+}
@@ -1,19 +1,31 @@
 //! Track allocation counters for Talc.
 
+use core::{
+    alloc::Layout,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
 pub struct Counters {
     /// Number of active allocations.
     pub allocation_count: usize,
     /// Total number of allocations.
+    ///
+    /// Reallocations are counted separately, even if the allocation moved.
     pub total_allocation_count: u64,
 
     /// Sum of active allocations' layouts' size.
     pub allocated_bytes: usize,
     /// Sum of all allocations' layouts' maximum size.
     ///
-    /// In-place reallocations's unchanged bytes are not recounted.
+    /// Reallocations's unchanged bytes are not recounted.
     pub total_allocated_bytes: u64,
 
+    /// Total number of reallocations, whether in-place or not.
+    pub total_reallocation_count: u64,
+    /// Net change in size due to all reallocations.
+    pub total_reallocated_bytes: i64,
+
     /// Number of bytes available for allocation.
     pub available_bytes: usize,
     /// Number of holes/gaps between allocations.
@@ -28,6 +40,18 @@ pub struct Counters {
     pub claimed_bytes: usize,
     /// Sum of bytes ever claimed. Reclaimed bytes included.
     pub total_claimed_bytes: u64,
+
+    /// Total number of allocations that failed.
+    pub total_failed_allocation_count: u64,
+    /// Layout of the most recent allocation that failed, if any.
+    pub last_failed_layout: Option<Layout>,
+
+    /// Cycles taken by successful allocations, see [`Talc::set_timestamp_source`](super::Talc::set_timestamp_source).
+    #[cfg(feature = "timing")]
+    pub malloc_timing: super::timing::OpTiming,
+    /// Cycles taken by frees, see [`Talc::set_timestamp_source`](super::Talc::set_timestamp_source).
+    #[cfg(feature = "timing")]
+    pub free_timing: super::timing::OpTiming,
 }
 
 impl Counters {
@@ -37,12 +61,20 @@ impl Counters {
             total_allocation_count: 0,
             allocated_bytes: 0,
             total_allocated_bytes: 0,
+            total_reallocation_count: 0,
+            total_reallocated_bytes: 0,
             available_bytes: 0,
             fragment_count: 0,
             heap_count: 0,
             total_heap_count: 0,
             claimed_bytes: 0,
             total_claimed_bytes: 0,
+            total_failed_allocation_count: 0,
+            last_failed_layout: None,
+            #[cfg(feature = "timing")]
+            malloc_timing: super::timing::OpTiming::new(),
+            #[cfg(feature = "timing")]
+            free_timing: super::timing::OpTiming::new(),
         }
     }
 
@@ -61,6 +93,24 @@ impl Counters {
         self.total_claimed_bytes - self.claimed_bytes as u64
     }
 
+    /// Returns the total number of allocations freed.
+    pub const fn total_deallocation_count(&self) -> u64 {
+        self.total_allocation_count - self.allocation_count as u64
+    }
+
+    /// Returns the allocation statistics in the shape used by the `stats_alloc` crate.
+    pub const fn stats(&self) -> Stats {
+        Stats {
+            allocations: self.total_allocation_count as usize,
+            deallocations: self.total_deallocation_count() as usize,
+            reallocations: self.total_reallocation_count as usize,
+            bytes_allocated: (self.total_allocated_bytes as i64 - self.total_reallocated_bytes)
+                as usize,
+            bytes_deallocated: self.total_freed_bytes() as usize,
+            bytes_reallocated: self.total_reallocated_bytes as isize,
+        }
+    }
+
     pub(crate) fn account_register_gap(&mut self, size: usize) {
         self.available_bytes += size;
         self.fragment_count += 1;
@@ -86,11 +136,27 @@ impl Counters {
     pub(crate) fn account_grow_in_place(&mut self, old_alloc_size: usize, new_alloc_size: usize) {
         self.allocated_bytes += new_alloc_size - old_alloc_size;
         self.total_allocated_bytes += (new_alloc_size - old_alloc_size) as u64;
+
+        self.total_reallocation_count += 1;
+        self.total_reallocated_bytes += (new_alloc_size - old_alloc_size) as i64;
     }
 
     pub(crate) fn account_shrink_in_place(&mut self, old_alloc_size: usize, new_alloc_size: usize) {
         self.allocated_bytes -= old_alloc_size - new_alloc_size;
         self.total_allocated_bytes -= (old_alloc_size - new_alloc_size) as u64;
+
+        self.total_reallocation_count += 1;
+        self.total_reallocated_bytes -= (old_alloc_size - new_alloc_size) as i64;
+    }
+
+    /// Converts the accounting of an allocation of `new_alloc_size` followed by
+    /// a deallocation of `old_alloc_size` into that of a single reallocation.
+    pub(crate) fn account_moved_realloc(&mut self, old_alloc_size: usize, new_alloc_size: usize) {
+        self.total_allocation_count -= 1;
+        self.total_allocated_bytes -= old_alloc_size as u64;
+
+        self.total_reallocation_count += 1;
+        self.total_reallocated_bytes += new_alloc_size as i64 - old_alloc_size as i64;
     }
 
     pub(crate) fn account_claim(&mut self, claimed_size: usize) {
@@ -122,35 +188,205 @@ impl core::fmt::Display for Counters {
 ---------------------|---------------------|--------------------
 # of Allocations     | {:>19} | {:>19}
 # of Allocated Bytes | {:>19} | {:>19}
+# of Reallocations   |                 N/A | {:>19}
 # of Available Bytes | {:>19} |                 N/A
 # of Claimed Bytes   | {:>19} | {:>19}
 # of Heaps           | {:>19} | {:>19}
-# of Fragments       | {:>19} |                 N/A"#,
+# of Fragments       | {:>19} |                 N/A
+# of Failed Allocs   |                 N/A | {:>19}"#,
             self.allocation_count,
             self.total_allocation_count,
             self.allocated_bytes,
             self.total_allocated_bytes,
+            self.total_reallocation_count,
             self.available_bytes,
             self.claimed_bytes,
             self.total_claimed_bytes,
             self.heap_count,
             self.total_heap_count,
-            self.fragment_count
+            self.fragment_count,
+            self.total_failed_allocation_count
         ))
     }
 }
 
+/// Allocation statistics with the same fields and semantics as the `stats_alloc` crate's
+/// `Stats`, such that assertions and harnesses written against it can be reused.
+///
+/// Obtain this using `talc.get_counters().stats()`, or measure a span of execution
+/// with a [`Region`](crate::Region).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Stats {
+    /// Number of allocations.
+    pub allocations: usize,
+    /// Number of deallocations.
+    pub deallocations: usize,
+    /// Number of reallocations.
+    pub reallocations: usize,
+    /// Sum of the sizes of all allocations.
+    pub bytes_allocated: usize,
+    /// Sum of the sizes of all deallocations.
+    pub bytes_deallocated: usize,
+    /// Net change in size due to reallocations.
+    pub bytes_reallocated: isize,
+}
+
+impl core::ops::Add for Stats {
+    type Output = Stats;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl core::ops::AddAssign for Stats {
+    fn add_assign(&mut self, rhs: Self) {
+        self.allocations += rhs.allocations;
+        self.deallocations += rhs.deallocations;
+        self.reallocations += rhs.reallocations;
+        self.bytes_allocated += rhs.bytes_allocated;
+        self.bytes_deallocated += rhs.bytes_deallocated;
+        self.bytes_reallocated += rhs.bytes_reallocated;
+    }
+}
+
+impl core::ops::Sub for Stats {
+    type Output = Stats;
+
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl core::ops::SubAssign for Stats {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.allocations -= rhs.allocations;
+        self.deallocations -= rhs.deallocations;
+        self.reallocations -= rhs.reallocations;
+        self.bytes_allocated -= rhs.bytes_allocated;
+        self.bytes_deallocated -= rhs.bytes_deallocated;
+        self.bytes_reallocated -= rhs.bytes_reallocated;
+    }
+}
+
+/// A subset of the [`Counters`], published using relaxed atomics such that
+/// they can be read without acquiring the allocator's lock, e.g. by a telemetry task.
+///
+/// Register one using [`Talc::set_live_stats`](super::Talc::set_live_stats).
+/// The values are updated at the end of each heap operation. Each value is
+/// up-to-date, but they aren't updated together, so they may be briefly inconsistent.
+#[derive(Debug, Default)]
+pub struct LiveStats {
+    allocation_count: AtomicUsize,
+    allocated_bytes: AtomicUsize,
+    peak_allocated_bytes: AtomicUsize,
+    available_bytes: AtomicUsize,
+    claimed_bytes: AtomicUsize,
+}
+
+impl LiveStats {
+    pub const fn new() -> Self {
+        Self {
+            allocation_count: AtomicUsize::new(0),
+            allocated_bytes: AtomicUsize::new(0),
+            peak_allocated_bytes: AtomicUsize::new(0),
+            available_bytes: AtomicUsize::new(0),
+            claimed_bytes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Number of active allocations.
+    pub fn allocation_count(&self) -> usize {
+        self.allocation_count.load(Ordering::Relaxed)
+    }
+
+    /// Sum of active allocations' layouts' size.
+    pub fn allocated_bytes(&self) -> usize {
+        self.allocated_bytes.load(Ordering::Relaxed)
+    }
+
+    /// The maximum of `allocated_bytes` since registration, or since [`reset_peak`](Self::reset_peak).
+    pub fn peak_allocated_bytes(&self) -> usize {
+        self.peak_allocated_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Number of bytes available for allocation.
+    pub fn available_bytes(&self) -> usize {
+        self.available_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Sum of bytes actively claimed.
+    pub fn claimed_bytes(&self) -> usize {
+        self.claimed_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Restart tracking the peak from the current number of allocated bytes.
+    pub fn reset_peak(&self) {
+        self.peak_allocated_bytes.store(self.allocated_bytes(), Ordering::Relaxed);
+    }
+
+    /// Only one thread may publish at a time, as the peak isn't updated atomically.
+    fn publish(&self, counters: &Counters) {
+        self.allocation_count.store(counters.allocation_count, Ordering::Relaxed);
+        self.allocated_bytes.store(counters.allocated_bytes, Ordering::Relaxed);
+        self.available_bytes.store(counters.available_bytes, Ordering::Relaxed);
+        self.claimed_bytes.store(counters.claimed_bytes, Ordering::Relaxed);
+
+        if counters.allocated_bytes > self.peak_allocated_bytes() {
+            self.peak_allocated_bytes.store(counters.allocated_bytes, Ordering::Relaxed);
+        }
+    }
+}
+
 impl<O: super::OomHandler> super::Talc<O> {
     pub fn get_counters(&self) -> &Counters {
         &self.counters
     }
+
+    /// Publish a subset of the counters to `live_stats` whenever they change, such that
+    /// they can be read without locking the allocator. Pass `None` to stop publishing.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// static LIVE_STATS: LiveStats = LiveStats::new();
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// talc.set_live_stats(Some(&LIVE_STATS));
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// unsafe { talc.malloc(Layout::new::<[u8; 100]>()).unwrap(); }
+    ///
+    /// // e.g. from a telemetry thread, without contending on the allocator's lock
+    /// assert_eq!(LIVE_STATS.allocated_bytes(), 100);
+    /// ```
+    pub fn set_live_stats(&mut self, live_stats: Option<&'static LiveStats>) {
+        self.live_stats = live_stats;
+        self.publish_live_stats();
+    }
+
+    /// Called whenever the counters change.
+    #[inline]
+    pub(crate) fn publish_live_stats(&mut self) {
+        if let Some(live_stats) = self.live_stats {
+            live_stats.publish(&self.counters);
+        }
+
+        #[cfg(feature = "rtt")]
+        self.check_rtt_threshold();
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use core::alloc::Layout;
 
-    use ptr_utils::{WORD_BITS, WORD_SIZE};
+    use super::LiveStats;
+
+    use ptr_utils::WORD_SIZE;
 
     use crate::{talc::TAG_SIZE, *};
 
@@ -172,8 +408,8 @@ mod tests {
 
         let pre_alloc_avl_bytes = talc.get_counters().available_bytes;
         dbg!(pre_alloc_avl_bytes);
-        assert!(talc.get_counters().available_bytes < high - low - WORD_SIZE * WORD_BITS * 2);
-        assert!(talc.get_counters().available_bytes >= high - low - WORD_SIZE * WORD_BITS * 2 - 64);
+        assert!(talc.get_counters().available_bytes < high - low - WORD_SIZE * BIN_COUNT);
+        assert!(talc.get_counters().available_bytes >= high - low - WORD_SIZE * BIN_COUNT - 64);
 
         assert!(talc.get_counters().allocated_bytes == 0);
         assert!(talc.get_counters().total_allocated_bytes == 0);
@@ -181,8 +417,8 @@ mod tests {
         assert!(talc.get_counters().allocation_count == 0);
         assert!(talc.get_counters().total_allocation_count == 0);
         assert!(talc.get_counters().fragment_count == 1);
-        assert!(talc.get_counters().overhead_bytes() >= TAG_SIZE + WORD_SIZE * WORD_BITS * 2);
-        assert!(talc.get_counters().overhead_bytes() <= TAG_SIZE + WORD_SIZE * WORD_BITS * 2 + 64);
+        assert!(talc.get_counters().overhead_bytes() >= TAG_SIZE + WORD_SIZE * BIN_COUNT);
+        assert!(talc.get_counters().overhead_bytes() <= TAG_SIZE + WORD_SIZE * BIN_COUNT + 64);
 
         let alloc_layout = Layout::new::<[u128; 3]>();
         let alloc = unsafe { talc.malloc(alloc_layout).unwrap() };
@@ -202,6 +438,8 @@ mod tests {
         unsafe {
             talc.free(alloc, alloc_layout);
         }
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
 
         assert!(talc.get_counters().claimed_bytes == pre_alloc_claimed_bytes);
         assert!(talc.get_counters().total_claimed_bytes == pre_alloc_claimed_bytes as _);
@@ -214,7 +452,7 @@ mod tests {
 
         let heap1 = unsafe { talc.truncate(heap1, talc.get_allocated_span(heap1)) };
 
-        assert!(heap1.size() <= TAG_SIZE + WORD_SIZE * WORD_BITS * 2 + 64);
+        assert!(heap1.size() <= TAG_SIZE + WORD_SIZE * BIN_COUNT + 64);
 
         assert!(talc.get_counters().claimed_bytes == heap1.size());
         assert!(talc.get_counters().overhead_bytes() == talc.get_counters().claimed_bytes);
@@ -225,5 +463,129 @@ mod tests {
         assert!(talc.get_counters().allocation_count == 0);
         assert!(talc.get_counters().total_allocation_count == 1);
         assert!(talc.get_counters().fragment_count == 0);
+
+        assert!(talc.get_counters().total_failed_allocation_count == 0);
+        assert!(unsafe { talc.malloc(alloc_layout) }.is_err());
+        assert!(talc.get_counters().total_failed_allocation_count == 1);
+        assert!(talc.get_counters().last_failed_layout == Some(alloc_layout));
+        assert!(talc.get_counters().total_allocation_count == 1);
+    }
+
+    #[test]
+    fn test_realloc_stats() {
+        let mut arena = [0u8; 100000];
+
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let a = unsafe { talc.malloc(layout).unwrap() };
+        let b = unsafe { talc.malloc(layout).unwrap() };
+
+        // grows in-place, then shrinks in-place
+        let b = unsafe { talc.grow(b, layout, 200).unwrap() };
+        unsafe { talc.shrink(b, Layout::from_size_align(200, 8).unwrap(), 150) };
+        // a is blocked by b, so this moves
+        let a2 = unsafe { talc.grow(a, layout, 1000).unwrap() };
+        assert!(a2 != a);
+
+        let stats = talc.get_counters().stats();
+        assert_eq!(stats.allocations, 2);
+        assert_eq!(stats.deallocations, 0);
+        assert_eq!(stats.reallocations, 3);
+        assert_eq!(stats.bytes_allocated, 200);
+        assert_eq!(stats.bytes_deallocated, 0);
+        assert_eq!(stats.bytes_reallocated, 100 - 50 + 900);
+        assert_eq!(talc.get_counters().allocated_bytes, 1150);
+
+        unsafe {
+            talc.free(a2, Layout::from_size_align(1000, 8).unwrap());
+            talc.free(b, Layout::from_size_align(150, 8).unwrap());
+        }
+
+        let stats = talc.get_counters().stats();
+        assert_eq!(stats.deallocations, 2);
+        assert_eq!(stats.bytes_deallocated, 1150);
+        assert_eq!(
+            stats.bytes_allocated as isize + stats.bytes_reallocated,
+            stats.bytes_deallocated as isize
+        );
+    }
+
+    #[cfg(feature = "lock_api")]
+    #[test]
+    fn test_region() {
+        use core::alloc::GlobalAlloc;
+
+        let mut arena = [0u8; 100000];
+
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let before = unsafe { talck.alloc(layout) };
+
+        let mut region = Region::new(&talck);
+        assert_eq!(region.change(), Stats::default());
+
+        unsafe {
+            let a = talck.alloc(layout);
+            let a = talck.realloc(a, layout, 640);
+            talck.dealloc(before, layout);
+
+            let change = region.change_and_reset();
+            assert_eq!(change.allocations, 1);
+            assert_eq!(change.deallocations, 1);
+            assert_eq!(change.reallocations, 1);
+            assert_eq!(change.bytes_allocated, 64);
+            assert_eq!(change.bytes_deallocated, 64);
+            assert_eq!(change.bytes_reallocated, 576);
+            assert_eq!(region.initial() + Stats::default(), talck.lock().get_counters().stats());
+
+            talck.dealloc(a, Layout::from_size_align(640, 8).unwrap());
+        }
+
+        assert_eq!(region.change().bytes_deallocated, 640);
+        region.reset();
+        assert_eq!(region.change(), Stats::default());
+    }
+
+    #[test]
+    fn test_live_stats() {
+        static LIVE_STATS: LiveStats = LiveStats::new();
+
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        talc.set_live_stats(Some(&LIVE_STATS));
+        assert_eq!(LIVE_STATS.claimed_bytes(), heap.size());
+
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        let a = unsafe { talc.malloc(layout).unwrap() };
+        let b = unsafe { talc.malloc(layout).unwrap() };
+        assert_eq!(LIVE_STATS.allocation_count(), 2);
+        assert_eq!(LIVE_STATS.allocated_bytes(), 2000);
+        assert_eq!(LIVE_STATS.available_bytes(), talc.get_counters().available_bytes);
+
+        unsafe { talc.free(a, layout) };
+        unsafe { talc.shrink(b, layout, 500) };
+        assert_eq!(LIVE_STATS.allocated_bytes(), 500);
+        assert_eq!(LIVE_STATS.peak_allocated_bytes(), 2000);
+
+        LIVE_STATS.reset_peak();
+        assert_eq!(LIVE_STATS.peak_allocated_bytes(), 500);
+
+        let b = unsafe { talc.grow(b, Layout::from_size_align(500, 8).unwrap(), 700).unwrap() };
+        assert_eq!(LIVE_STATS.peak_allocated_bytes(), 700);
+        assert_eq!(LIVE_STATS.available_bytes(), talc.get_counters().available_bytes);
+
+        unsafe { talc.free(b, Layout::from_size_align(700, 8).unwrap()) };
+        assert_eq!(LIVE_STATS.allocation_count(), 0);
+
+        talc.set_live_stats(None);
+        unsafe { talc.malloc(layout).unwrap() };
+        assert_eq!(LIVE_STATS.allocation_count(), 0);
     }
 }
@@ -0,0 +1,321 @@
+//! Inspect Talc's heaps from a debugger, see [`export_debug_api!`](crate::export_debug_api).
+//!
+//! # In-memory layout
+//! This describes version 1 of the layout, as recorded in [`TALC_DEBUG_LAYOUT`], such that
+//! scripts can walk a heap in a core dump without calling into the program. All pointers
+//! and sizes are target words, and all chunks are aligned to `align` bytes.
+//!
+//! A heap is a contiguous sequence of chunks, each either free or allocated, spanning
+//! from just above the tag at the heap's base up to the heap's acme.
+//! - The heap's base holds a tag, whose `above_free_flag` indicates whether the chunk
+//!   above it is free.
+//! - A free chunk holds a free list node (`next`, then `next_of_prev` pointers) at its base,
+//!   its size at `base + node_size`, and its size again in the last word of the chunk.
+//! - An allocated chunk holds its tag in its last word: a pointer to the chunk's base,
+//!   with `allocated_flag` set, and `above_free_flag` set if the chunk above is free.
+//!   If the allocation ends lower, the word following it points to the tag.
+//!
+//! As sizes are multiples of `align`, the word below any chunk boundary is a free chunk's
+//! size if `allocated_flag` is clear, else an allocated chunk's tag. A heap can thus be
+//! walked top-down from its acme, see [`Talc::debug_chunks`].
+//!
+//! The first heap begins with an allocated chunk holding the bins: `bin_count` free list
+//! heads, each pointing to the node of the first free chunk of that bin, or null.
+//!
+//! Chunks cached by the `fastbins` feature look allocated.
+//!
+//! Copies of heaps taken from outside of the program, such as from crashed devices,
+//! can be checked and walked with [`HeapImage`].
+
+mod image;
+
+use crate::{ptr_utils::ALIGN, OomHandler, Span};
+
+use super::{gap_acme_to_base, is_gap_below, tag::Tag, Talc, BIN_COUNT, NODE_SIZE, TAG_SIZE};
+
+pub use image::{HeapImage, ImageChunks};
+
+/// The version of the in-memory layout described by [`TALC_DEBUG_LAYOUT`].
+///
+/// This is incremented whenever the layout changes incompatibly.
+pub const DEBUG_LAYOUT_VERSION: u32 = 1;
+
+/// A description of the in-memory layout of Talc's heaps for debugger scripts.
+/// See the [module documentation](self) for the layout itself.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugLayout {
+    /// See [`DEBUG_LAYOUT_VERSION`].
+    pub version: u32,
+    /// The size of pointers and sizes.
+    pub word_size: u32,
+    /// The alignment of chunks.
+    pub align: u32,
+    /// The size of a free chunk's free list node.
+    pub node_size: u32,
+    /// The size of an allocated chunk's tag.
+    pub tag_size: u32,
+    /// The number of bins.
+    pub bin_count: u32,
+    /// Set in tags, distinguishing them from free chunk sizes.
+    pub allocated_flag: u32,
+    /// Set in tags if the chunk above is free.
+    pub above_free_flag: u32,
+}
+
+/// The layout of Talc's heaps, exported for debuggers to find by name.
+#[no_mangle]
+pub static TALC_DEBUG_LAYOUT: DebugLayout = DebugLayout {
+    version: DEBUG_LAYOUT_VERSION,
+    word_size: core::mem::size_of::<usize>() as u32,
+    align: ALIGN as u32,
+    node_size: NODE_SIZE as u32,
+    tag_size: TAG_SIZE as u32,
+    bin_count: BIN_COUNT as u32,
+    allocated_flag: Tag::ALLOCATED_FLAG as u32,
+    above_free_flag: Tag::IS_ABOVE_FREE_FLAG as u32,
+};
+
+/// A chunk of a heap, as yielded by [`Talc::debug_chunks`].
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DebugChunk {
+    /// The address of the base of the chunk.
+    pub base: usize,
+    /// The address of the acme of the chunk.
+    pub acme: usize,
+    /// Whether the chunk is allocated, including the bins and cached chunks, else free.
+    pub is_allocated: bool,
+}
+
+impl DebugChunk {
+    /// Returns whether `addr` is within the chunk.
+    pub fn contains(&self, addr: usize) -> bool {
+        self.base <= addr && addr < self.acme
+    }
+}
+
+/// Iterator over the chunks of a heap, top-down. See [`Talc::debug_chunks`].
+#[derive(Debug)]
+pub struct DebugChunks {
+    base: *mut u8,
+    cursor: *mut u8,
+}
+
+impl Iterator for DebugChunks {
+    type Item = DebugChunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // SAFETY: the heap is valid as guaranteed by the caller of `debug_chunks`
+        unsafe {
+            if self.cursor <= self.base.add(TAG_SIZE) {
+                return None;
+            }
+
+            let acme = self.cursor;
+            let (base, is_allocated) = if is_gap_below(acme) {
+                (gap_acme_to_base(acme), false)
+            } else {
+                (acme.sub(TAG_SIZE).cast::<Tag>().read().chunk_base(), true)
+            };
+
+            // the bins' chunk is the only chunk based at the heap's base
+            self.cursor = if base == self.base { self.base } else { base };
+
+            Some(DebugChunk { base: base as usize, acme: acme as usize, is_allocated })
+        }
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Iterate over the chunks of `heap`, top-down, for debugging.
+    ///
+    /// This doesn't modify the heap, and takes time proportional to the number of chunks.
+    ///
+    /// # Safety
+    /// `heap` must be the return value of a heap manipulation function, and the heap
+    /// mustn't be modified while iterating.
+    pub unsafe fn debug_chunks(&self, heap: Span) -> DebugChunks {
+        match heap.get_base_acme() {
+            Some((base, acme)) => DebugChunks { base, cursor: acme },
+            None => DebugChunks { base: core::ptr::null_mut(), cursor: core::ptr::null_mut() },
+        }
+    }
+
+    /// Returns the chunk of `heap` containing `addr`, if any.
+    ///
+    /// # Safety
+    /// See [`Talc::debug_chunks`].
+    pub unsafe fn debug_find_chunk(&self, heap: Span, addr: usize) -> Option<DebugChunk> {
+        if !heap.contains(addr as *mut u8) {
+            return None;
+        }
+
+        self.debug_chunks(heap).find(|chunk| chunk.contains(addr))
+    }
+
+    /// Returns the address of the bins' free list heads, or null if no heap is established.
+    pub fn debug_bins(&self) -> *const u8 {
+        self.bins.cast()
+    }
+}
+
+/// Export `#[no_mangle]` functions inspecting the [`Talck`](crate::Talck) in the `static`
+/// named `$talck`, for calling from a debugger, e.g. with GDB's `call`.
+///
+/// The heap to inspect is given by its base and acme, as returned by `claim` and co.
+/// If the allocator is locked, e.g. because the program stopped in the middle of an
+/// allocation, the functions return `-1` rather than deadlocking.
+///
+/// The following are exported:
+/// - `isize talc_debug_dump(void* base, void* acme, DebugChunk* out, size_t capacity)`
+///   writes up to `capacity` of the heap's chunks, top-down, to `out`,
+///   and returns the total number of chunks.
+/// - `isize talc_debug_find_chunk(void* base, void* acme, size_t addr, DebugChunk* out)`
+///   writes the chunk containing `addr` to `out` and returns `1`, or returns `0` if there is none.
+/// - `isize talc_debug_count_allocations(void* base, void* acme)` returns the number
+///   of allocated chunks, excluding the bins.
+///
+/// See the [`debug`](crate::debug) module for a description of the heap's layout
+/// for when the program can't be called into, such as when inspecting a core dump.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static mut ARENA: [u8; 10000] = [0; 10000];
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
+///     Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA))) })
+///         .lock();
+///
+/// talc::export_debug_api!(ALLOCATOR);
+/// ```
+#[macro_export]
+macro_rules! export_debug_api {
+    ($talck:path) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_debug_dump(
+            base: *mut u8,
+            acme: *mut u8,
+            out: *mut $crate::debug::DebugChunk,
+            capacity: usize,
+        ) -> isize {
+            let talc = match $talck.try_lock() {
+                Some(talc) => talc,
+                None => return -1,
+            };
+
+            let mut count = 0;
+            for chunk in talc.debug_chunks($crate::Span::new(base, acme)) {
+                if count < capacity {
+                    out.add(count).write(chunk);
+                }
+                count += 1;
+            }
+            count as isize
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_debug_find_chunk(
+            base: *mut u8,
+            acme: *mut u8,
+            addr: usize,
+            out: *mut $crate::debug::DebugChunk,
+        ) -> isize {
+            let talc = match $talck.try_lock() {
+                Some(talc) => talc,
+                None => return -1,
+            };
+
+            match talc.debug_find_chunk($crate::Span::new(base, acme), addr) {
+                Some(chunk) => {
+                    out.write(chunk);
+                    1
+                }
+                None => 0,
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn talc_debug_count_allocations(
+            base: *mut u8,
+            acme: *mut u8,
+        ) -> isize {
+            let talc = match $talck.try_lock() {
+                Some(talc) => talc,
+                None => return -1,
+            };
+
+            let bins = talc.debug_bins() as usize;
+            talc.debug_chunks($crate::Span::new(base, acme))
+                .filter(|chunk| chunk.is_allocated && !chunk.contains(bins))
+                .count() as isize
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::{GlobalAlloc, Layout},
+        ptr::null_mut,
+    };
+
+    use super::*;
+    use crate::*;
+
+    static mut ARENA: [u8; 10000] = [0; 10000];
+    static TALCK: Talck<spin::Mutex<()>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+
+    export_debug_api!(TALCK);
+
+    #[test]
+    fn debug_api_test() {
+        let heap = unsafe { TALCK.lock().claim(Span::from_array(core::ptr::addr_of_mut!(ARENA))) };
+        let (base, acme) = heap.unwrap().get_base_acme().unwrap();
+
+        let small = Layout::from_size_align(100, 8).unwrap();
+        let aligned = Layout::from_size_align(64, 256).unwrap();
+
+        unsafe {
+            let a = TALCK.alloc(small);
+            let b = TALCK.alloc(aligned);
+            let c = TALCK.alloc(small);
+            TALCK.dealloc(a, small);
+
+            assert_eq!(talc_debug_count_allocations(base, acme), 2);
+
+            let mut chunks = [DebugChunk { base: 0, acme: 0, is_allocated: false }; 16];
+            let count = talc_debug_dump(base, acme, chunks.as_mut_ptr(), chunks.len());
+            let chunks = &chunks[..count as usize];
+
+            // the chunks tile the heap, top-down
+            assert_eq!(chunks[0].acme, acme as usize);
+            assert!(chunks.windows(2).all(|pair| pair[0].base == pair[1].acme));
+            assert!(chunks.windows(2).all(|pair| pair[0].is_allocated || pair[1].is_allocated));
+            assert!(chunks.last().unwrap().base <= base as usize + TAG_SIZE);
+
+            // only the dumped chunk count is returned when out of capacity
+            assert_eq!(talc_debug_dump(base, acme, null_mut(), 0), count);
+
+            let mut found = DebugChunk { base: 0, acme: 0, is_allocated: false };
+            for (ptr, is_allocated) in [(a, false), (b, true), (c, true)] {
+                assert_eq!(talc_debug_find_chunk(base, acme, ptr as usize, &mut found), 1);
+                assert!(found.contains(ptr as usize));
+                assert_eq!(found.is_allocated, is_allocated);
+            }
+            assert_eq!(talc_debug_find_chunk(base, acme, acme as usize, &mut found), 0);
+
+            // a locked allocator isn't waited on
+            let guard = TALCK.lock();
+            assert_eq!(talc_debug_count_allocations(base, acme), -1);
+            drop(guard);
+
+            TALCK.dealloc(b, aligned);
+            TALCK.dealloc(c, small);
+        }
+
+        assert_eq!(TALC_DEBUG_LAYOUT.version, DEBUG_LAYOUT_VERSION);
+    }
+}
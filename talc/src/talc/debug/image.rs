@@ -0,0 +1,331 @@
+//! Verify captured copies of heaps offline, such as those pulled from crashed devices.
+
+use super::DebugChunk;
+use crate::{
+    ptr_utils::{ALIGN, WORD_SIZE},
+    talc::{bin_of_size, tag::Tag, BIN_COUNT, MIN_CHUNK_SIZE, NODE_SIZE, TAG_SIZE},
+    HeapError, RegionReport, Span,
+};
+
+/// A captured copy of a heap, for walking and checking it outside of the program.
+///
+/// The image holds the bytes of a heap from its base to its acme, as returned by
+/// [`Talc::claim`](crate::Talc::claim) and co., along with the address the heap was at.
+/// Pointers within the heap are translated into the image and every read is
+/// bounds-checked, so a corrupt image produces errors rather than faults.
+///
+/// The image must have been captured from a target with the same word size, endianness,
+/// and allocator features, see [`TALC_DEBUG_LAYOUT`](super::TALC_DEBUG_LAYOUT).
+///
+/// # Example
+/// ```rust
+/// # use talc::{*, debug::HeapImage};
+/// # use core::alloc::Layout;
+/// let mut arena = [0u8; 10000];
+/// let mut talc = Talc::new(ErrOnOom);
+/// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+/// let layout = Layout::new::<[u64; 4]>();
+/// let ptr = unsafe { talc.malloc(layout).unwrap() };
+///
+/// // e.g. read back from a crashed device's RAM
+/// let (base, _) = heap.get_base_acme().unwrap();
+/// let bytes = unsafe { core::slice::from_raw_parts(base, heap.size()) }.to_vec();
+///
+/// let image = HeapImage::new(&bytes, base as usize);
+/// let report = image.verify().unwrap();
+/// assert_eq!(report, unsafe { talc.region_report(heap) });
+/// # unsafe { talc.free(ptr, layout); }
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct HeapImage<'a> {
+    bytes: &'a [u8],
+    base: usize,
+    acme: usize,
+}
+
+impl<'a> HeapImage<'a> {
+    /// Create an image of the heap that was based at `base`, from a copy of its bytes.
+    ///
+    /// # Panics
+    /// Panics if the image would extend past the end of the address space.
+    pub fn new(bytes: &'a [u8], base: usize) -> Self {
+        let acme = base.checked_add(bytes.len()).expect("heap image overflows the address space");
+        Self { bytes, base, acme }
+    }
+
+    /// Returns the heap the image was captured from, at its original address.
+    pub fn heap(&self) -> Span {
+        Span::new(self.base as *mut u8, self.acme as *mut u8)
+    }
+
+    /// Iterate over the chunks of the heap, top-down, like
+    /// [`Talc::debug_chunks`](crate::Talc::debug_chunks).
+    ///
+    /// Each chunk boundary is checked as it's walked, and the free chunks' sizes, tags,
+    /// and list links, as far as they're within the image. Iteration stops after the
+    /// first error, as the rest of the heap can't be found.
+    pub fn chunks(&self) -> ImageChunks<'a> {
+        ImageChunks { image: *self, cursor: self.acme, above_free: false, done: false }
+    }
+
+    /// Check the integrity of the heap, and summarize it as
+    /// [`Talc::region_report`](crate::Talc::region_report) would have.
+    ///
+    /// On top of the checks done by [`HeapImage::chunks`], if the image holds the
+    /// allocator's bins, each free chunk is checked to be listed in the bin of its size.
+    /// Free list links to other heaps can't be followed, so these aren't checked.
+    pub fn verify(&self) -> Result<RegionReport, HeapError> {
+        let mut report = RegionReport { heap: self.heap(), ..Default::default() };
+        let mut has_bins = false;
+
+        for chunk in self.chunks() {
+            let chunk = chunk?;
+            let size = chunk.acme - chunk.base;
+
+            if chunk.is_allocated {
+                // the bins' chunk is the only chunk based at the heap's base
+                has_bins |= chunk.base == self.base;
+            } else {
+                report.free_bytes += size;
+                report.free_chunk_count += 1;
+                report.largest_free_chunk = report.largest_free_chunk.max(size);
+            }
+        }
+
+        report.used_bytes = self.bytes.len() - report.free_bytes;
+
+        if has_bins {
+            for chunk in self.chunks().flatten().filter(|chunk| !chunk.is_allocated) {
+                self.check_bin(chunk, report.free_chunk_count)?;
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Reads the word at `addr`, if it's within the image.
+    fn word(&self, addr: usize) -> Option<usize> {
+        let offset = addr.checked_sub(self.base)?;
+        let bytes = self.bytes.get(offset..offset.checked_add(WORD_SIZE)?)?;
+        Some(usize::from_ne_bytes(bytes.try_into().unwrap()))
+    }
+
+    /// Checks that the links of the free chunk at `chunk` point back at it,
+    /// where they're within the image.
+    fn check_links(&self, chunk: usize) -> Result<(), HeapError> {
+        // the free list node is at the chunk's base, `next` then `next_of_prev`
+        let next = self.word(chunk).ok_or(HeapError::Link { chunk })?;
+        let next_of_prev = self.word(chunk + WORD_SIZE).ok_or(HeapError::Link { chunk })?;
+
+        if next_of_prev == 0 || self.word(next_of_prev).map_or(false, |node| node != chunk) {
+            return Err(HeapError::Link { chunk });
+        }
+        if next != 0 && self.word(next + WORD_SIZE).map_or(false, |link| link != chunk) {
+            return Err(HeapError::Link { chunk });
+        }
+
+        Ok(())
+    }
+
+    /// Follows the links of the free `chunk` back to its bin, and checks that it's
+    /// the bin of its size. At most `free_chunk_count` links are followed.
+    fn check_bin(&self, chunk: DebugChunk, free_chunk_count: usize) -> Result<(), HeapError> {
+        let bins = self.base + TAG_SIZE;
+        let bins_acme = bins + BIN_COUNT * WORD_SIZE;
+        let mut node = chunk.base;
+
+        for _ in 0..free_chunk_count {
+            let next_of_prev = match self.word(node + WORD_SIZE) {
+                Some(next_of_prev) => next_of_prev,
+                None => return Err(HeapError::Link { chunk: chunk.base }),
+            };
+
+            if (bins..bins_acme).contains(&next_of_prev) {
+                let bin = (next_of_prev - bins) / WORD_SIZE;
+
+                // SAFETY: free chunks were checked to be at least MIN_CHUNK_SIZE
+                if unsafe { bin_of_size(chunk.acme - chunk.base) } != bin {
+                    return Err(HeapError::Bin { chunk: chunk.base, bin });
+                }
+
+                return Ok(());
+            }
+
+            if !(self.base..self.acme).contains(&next_of_prev) {
+                // listed after a chunk in another heap
+                return Ok(());
+            }
+
+            // `next` is the first field, so `next_of_prev` points at the previous node
+            node = next_of_prev;
+        }
+
+        Err(HeapError::Link { chunk: chunk.base })
+    }
+}
+
+/// Iterator over the chunks of a heap image, top-down. See [`HeapImage::chunks`].
+#[derive(Debug, Clone)]
+pub struct ImageChunks<'a> {
+    image: HeapImage<'a>,
+    cursor: usize,
+    /// Whether the chunk above the cursor is free.
+    above_free: bool,
+    done: bool,
+}
+
+impl<'a> ImageChunks<'a> {
+    fn step(&mut self) -> Result<Option<DebugChunk>, HeapError> {
+        let image = self.image;
+        let acme = self.cursor;
+
+        if acme <= image.base {
+            return Ok(None);
+        }
+
+        if acme == image.base + TAG_SIZE {
+            // the tag at the base of the heap
+            let base_tag = Tag(image.word(image.base).unwrap_or(0) as *mut u8);
+            if !base_tag.is_allocated() || base_tag.is_above_free() != self.above_free {
+                return Err(HeapError::Boundary { addr: acme });
+            }
+
+            self.cursor = image.base;
+            return Ok(None);
+        }
+
+        let below = match image.word(acme.wrapping_sub(WORD_SIZE)) {
+            Some(below) if acme > image.base + TAG_SIZE => below,
+            _ => return Err(HeapError::Boundary { addr: acme }),
+        };
+
+        if below & Tag::ALLOCATED_FLAG == 0 {
+            let size = below;
+            let chunk = acme.wrapping_sub(size);
+
+            if size < MIN_CHUNK_SIZE || size % ALIGN != 0 || size > acme - image.base - TAG_SIZE {
+                return Err(HeapError::Size { chunk });
+            }
+            if image.word(chunk + NODE_SIZE) != Some(size) {
+                return Err(HeapError::Size { chunk });
+            }
+
+            // two free chunks are never adjacent, so this also catches that
+            let lower_tag = Tag(image.word(chunk - TAG_SIZE).unwrap_or(0) as *mut u8);
+            if !lower_tag.is_allocated() || !lower_tag.is_above_free() {
+                return Err(HeapError::Tag { chunk });
+            }
+
+            image.check_links(chunk)?;
+
+            self.cursor = chunk;
+            self.above_free = true;
+            Ok(Some(DebugChunk { base: chunk, acme, is_allocated: false }))
+        } else {
+            let tag = Tag(below as *mut u8);
+            let chunk = tag.chunk_base() as usize;
+
+            // only the bins' chunk may be based at the heap's base, below its tag
+            let in_heap = chunk == image.base || chunk >= image.base + TAG_SIZE;
+            if !in_heap || chunk >= acme || tag.is_above_free() != self.above_free {
+                return Err(HeapError::Boundary { addr: acme });
+            }
+
+            self.cursor = chunk;
+            self.above_free = false;
+            Ok(Some(DebugChunk { base: chunk, acme, is_allocated: true }))
+        }
+    }
+}
+
+impl<'a> Iterator for ImageChunks<'a> {
+    type Item = Result<DebugChunk, HeapError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match self.step() {
+            Ok(Some(chunk)) => Some(Ok(chunk)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(error) => {
+                self.done = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::*;
+
+    fn capture(heap: Span) -> (Vec<u8>, usize) {
+        let (base, _) = heap.get_base_acme().unwrap();
+        (unsafe { core::slice::from_raw_parts(base, heap.size()) }.to_vec(), base as usize)
+    }
+
+    #[test]
+    fn heap_image_test() {
+        let mut first = [0u8; 10000];
+        let mut second = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        let first = unsafe { talc.claim(first.as_mut().into()).unwrap() };
+        let second = unsafe { talc.claim(second.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let mut allocs = Vec::new();
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            allocs.push(ptr);
+        }
+        for ptr in allocs.iter().step_by(3) {
+            unsafe { talc.free(*ptr, layout) };
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+
+        for heap in [first, second] {
+            let (bytes, base) = capture(heap);
+            let image = HeapImage::new(&bytes, base);
+
+            assert_eq!(image.verify(), Ok(unsafe { talc.region_report(heap) }));
+            let chunks = image.chunks().collect::<Result<Vec<_>, _>>().unwrap();
+            assert!(chunks.iter().copied().eq(unsafe { talc.debug_chunks(heap) }));
+        }
+
+        let (mut bytes, base) = capture(first);
+        let chunk = HeapImage::new(&bytes, base)
+            .chunks()
+            .flatten()
+            .find(|chunk| !chunk.is_allocated)
+            .unwrap();
+        let offset = chunk.acme - base - WORD_SIZE;
+
+        // a free chunk's sizes disagree
+        bytes[offset..offset + WORD_SIZE].copy_from_slice(&(MIN_CHUNK_SIZE * 2).to_ne_bytes());
+        assert!(matches!(HeapImage::new(&bytes, base).verify(), Err(HeapError::Size { .. })));
+
+        // a tag points out of the heap
+        bytes[offset..offset + WORD_SIZE].copy_from_slice(&(base - ALIGN + 1).to_ne_bytes());
+        assert_eq!(
+            HeapImage::new(&bytes, base).verify(),
+            Err(HeapError::Boundary { addr: chunk.acme })
+        );
+
+        // a truncated image
+        assert!(HeapImage::new(&bytes[..bytes.len() - 1], base).verify().is_err());
+
+        for ptr in allocs.iter().skip(1).step_by(3).chain(allocs.iter().skip(2).step_by(3)) {
+            unsafe { talc.free(*ptr, layout) };
+        }
+    }
+}
@@ -0,0 +1,125 @@
+//! Hold back memory for allocations made while handling a failure, such as panic formatting.
+
+use core::alloc::Layout;
+
+use super::Talc;
+use crate::{ptr_utils::ALIGN, OomHandler};
+
+impl<O: OomHandler> Talc<O> {
+    /// Set aside `size` bytes that ordinary allocations can't use, replacing any previous reserve.
+    ///
+    /// The reserve is released once an allocation fails while emergency mode is set by
+    /// [`Talc::set_emergency_mode`], e.g. from a panic handler, so that reporting the
+    /// failure doesn't fail for want of memory. Once released, it's gone until this is
+    /// called again. A `size` of zero releases the current reserve.
+    ///
+    /// The reserve is held as an allocation, so it counts as allocated memory in
+    /// [`Talc::report`] and the counters, and prevents truncating the memory it's in.
+    ///
+    /// Returns `Err` if `size` bytes can't be allocated, leaving no reserve.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// talc.reserve_emergency(1000).unwrap();
+    ///
+    /// // ... the heap is exhausted, and a panic handler kicks in ...
+    /// talc.set_emergency_mode(true);
+    /// ```
+    pub fn reserve_emergency(&mut self, size: usize) -> Result<(), ()> {
+        self.release_emergency_reserve();
+
+        if size != 0 {
+            let layout = Layout::from_size_align(size, ALIGN).map_err(|_| ())?;
+
+            // SAFETY: the size is nonzero
+            let ptr = unsafe { self.malloc(layout)? };
+            self.emergency_reserve = Some((ptr, size));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size of the reserve set aside by [`Talc::reserve_emergency`],
+    /// or zero if it has been released.
+    pub fn emergency_reserve(&self) -> usize {
+        self.emergency_reserve.map_or(0, |(_, size)| size)
+    }
+
+    /// Allow allocations to fall back on the reserve set aside by
+    /// [`Talc::reserve_emergency`], or stop allowing it.
+    ///
+    /// The reserve is only used once the OOM handler fails to make enough memory available.
+    pub fn set_emergency_mode(&mut self, enabled: bool) {
+        self.emergency_mode = enabled;
+    }
+
+    /// Returns whether emergency mode is set.
+    pub fn is_emergency_mode(&self) -> bool {
+        self.emergency_mode
+    }
+
+    /// Frees the emergency reserve, returning whether there was one.
+    pub(super) fn release_emergency_reserve(&mut self) -> bool {
+        match self.emergency_reserve.take() {
+            Some((ptr, size)) => {
+                // SAFETY: the reserve was allocated with this layout by `reserve_emergency`
+                unsafe { self.free(ptr, Layout::from_size_align_unchecked(size, ALIGN)) };
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn emergency_reserve_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        assert!(talc.reserve_emergency(20000).is_err());
+        talc.reserve_emergency(1000).unwrap();
+        assert_eq!(talc.emergency_reserve(), 1000);
+
+        // exhaust the heap
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let mut allocations = std::vec![];
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            allocations.push(ptr);
+        }
+
+        // the reserve is only released in emergency mode
+        let message = Layout::from_size_align(500, 8).unwrap();
+        assert!(unsafe { talc.malloc(message) }.is_err());
+        assert_eq!(talc.emergency_reserve(), 1000);
+
+        talc.set_emergency_mode(true);
+        assert!(talc.is_emergency_mode());
+        let ptr = unsafe { talc.malloc(message).unwrap() };
+        assert_eq!(talc.emergency_reserve(), 0);
+
+        // and only once
+        assert!(unsafe { talc.malloc(message) }.is_err());
+
+        unsafe { talc.free(ptr, message) };
+        for ptr in allocations {
+            unsafe { talc.free(ptr, layout) };
+        }
+
+        talc.set_emergency_mode(false);
+        talc.reserve_emergency(1000).unwrap();
+        talc.reserve_emergency(0).unwrap();
+        assert_eq!(talc.emergency_reserve(), 0);
+    }
+}
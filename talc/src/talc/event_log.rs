@@ -0,0 +1,167 @@
+//! Keep a circular log of recent heap operations for postmortem analysis.
+//!
+//! The log is stored inline in the `Talc` as plain `#[repr(C)]` data, so that it can be
+//! read from RAM by a debugger or a reset handler after a crash, as well as through
+//! [`Talc::events`] and [`Talc::dump_events`].
+
+use core::fmt;
+
+use super::Talc;
+use crate::OomHandler;
+
+/// The number of most recent events kept by the event log.
+pub const EVENT_LOG_CAPACITY: usize = 256;
+
+/// The kind of operation recorded by a [`HeapEvent`].
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeapOp {
+    /// [`Talc::malloc`], [`Talc::malloc_hinted`] or [`Talc::malloc_at`].
+    Malloc = 1,
+    /// [`Talc::free`].
+    Free = 2,
+    /// [`Talc::grow_in_place`], including as attempted by [`Talc::grow`].
+    GrowInPlace = 3,
+    /// [`Talc::shrink`].
+    Shrink = 4,
+}
+
+/// A heap operation recorded in the event log.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HeapEvent {
+    /// The operation performed.
+    pub op: HeapOp,
+    /// Whether the operation succeeded.
+    pub ok: bool,
+    /// The requested size, being the new size when reallocating.
+    pub size: usize,
+    /// The address of the allocation, or zero if allocating failed.
+    pub ptr: usize,
+}
+
+impl fmt::Display for HeapEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = if self.ok { "ok" } else { "failed" };
+        write!(f, "{:?} {} B at {:#x}: {}", self.op, self.size, self.ptr, result)
+    }
+}
+
+/// A ring buffer of the most recent [`HeapEvent`]s.
+#[repr(C)]
+#[derive(Debug)]
+pub(super) struct EventLog {
+    /// The total number of events recorded, wrapping.
+    count: usize,
+    events: [Option<HeapEvent>; EVENT_LOG_CAPACITY],
+}
+
+impl EventLog {
+    pub(super) const fn new() -> Self {
+        Self { count: 0, events: [None; EVENT_LOG_CAPACITY] }
+    }
+
+    #[inline]
+    pub(super) fn record(&mut self, op: HeapOp, ok: bool, size: usize, ptr: usize) {
+        self.events[self.count % EVENT_LOG_CAPACITY] = Some(HeapEvent { op, ok, size, ptr });
+        self.count = self.count.wrapping_add(1);
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the most recent heap operations, oldest first.
+    ///
+    /// Up to [`EVENT_LOG_CAPACITY`] events are kept, older ones being overwritten.
+    pub fn events(&self) -> impl Iterator<Item = HeapEvent> + '_ {
+        let log = &self.event_log;
+        let start = log.count % EVENT_LOG_CAPACITY;
+
+        log.events[start..].iter().chain(log.events[..start].iter()).filter_map(|event| *event)
+    }
+
+    /// Write the most recent heap operations to `w`, oldest first, one per line.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u64; 4]>();
+    /// unsafe {
+    ///     let ptr = talc.malloc(layout).unwrap();
+    ///     talc.free(ptr, layout);
+    /// }
+    ///
+    /// let mut dump = String::new();
+    /// talc.dump_events(&mut dump).unwrap();
+    /// assert!(dump.starts_with("Malloc 32 B at 0x"));
+    /// assert_eq!(dump.lines().count(), 2);
+    /// ```
+    pub fn dump_events(&self, w: &mut dyn fmt::Write) -> fmt::Result {
+        for event in self.events() {
+            writeln!(w, "{}", event)?;
+        }
+
+        Ok(())
+    }
+
+    /// Clear the event log.
+    pub fn clear_events(&mut self) {
+        self.event_log = EventLog::new();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn event_log_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        assert_eq!(talc.events().count(), 0);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            assert!(talc.malloc(Layout::from_size_align(1 << 20, 8).unwrap()).is_err());
+            let a = talc.grow(a, layout, 128).unwrap();
+            talc.shrink(a, Layout::from_size_align(128, 8).unwrap(), 32);
+            talc.free(a, Layout::from_size_align(32, 8).unwrap());
+
+            let events = std::vec::Vec::from_iter(talc.events());
+            let addr = a.as_ptr() as usize;
+            assert_eq!(events[0], HeapEvent { op: HeapOp::Malloc, ok: true, size: 64, ptr: addr });
+            assert_eq!(
+                events[1],
+                HeapEvent { op: HeapOp::Malloc, ok: false, size: 1 << 20, ptr: 0 }
+            );
+            assert_eq!(
+                events[2],
+                HeapEvent { op: HeapOp::GrowInPlace, ok: true, size: 128, ptr: addr }
+            );
+            assert_eq!(events[3], HeapEvent { op: HeapOp::Shrink, ok: true, size: 32, ptr: addr });
+            assert_eq!(events[4], HeapEvent { op: HeapOp::Free, ok: true, size: 32, ptr: addr });
+            assert_eq!(events.len(), 5);
+
+            // only the most recent events are kept
+            for _ in 0..EVENT_LOG_CAPACITY {
+                let ptr = talc.malloc(layout).unwrap();
+                talc.free(ptr, layout);
+            }
+            let events = std::vec::Vec::from_iter(talc.events());
+            assert_eq!(events.len(), EVENT_LOG_CAPACITY);
+            assert!(events.iter().step_by(2).all(|e| e.op == HeapOp::Malloc));
+            assert!(events.iter().skip(1).step_by(2).all(|e| e.op == HeapOp::Free));
+        }
+
+        talc.clear_events();
+        assert_eq!(talc.events().count(), 0);
+    }
+}
@@ -0,0 +1,288 @@
+//! A cache of recently freed small chunks, which are reused without being coalesced.
+
+use core::{alloc::Layout, ptr::null_mut};
+
+use super::{sizing::required_chunk_size, tag::Tag, Talc, HEADER_SIZE, MIN_CHUNK_SIZE, TAG_SIZE};
+use crate::{
+    ptr_utils::{align_up, is_aligned_to, ALIGN},
+    OomHandler,
+};
+
+/// The number of chunk sizes that are cached, counting up from the minimum chunk size.
+pub(super) const FASTBIN_COUNT: usize = 8;
+/// The number of chunks of each size that are cached before they're all freed.
+const FASTBIN_CAPACITY: usize = 8;
+
+/// A stack of cached chunks of a single size, linked through the first word of the allocation.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct FastBin {
+    /// The base of the most recently cached chunk, or null.
+    head: *mut u8,
+    len: usize,
+}
+
+impl FastBin {
+    pub(super) const EMPTY: Self = Self { head: null_mut(), len: 0 };
+}
+
+/// Returns the index of the fastbin that caches chunks of `chunk_size`, if any.
+#[inline]
+fn fastbin_of_size(chunk_size: usize) -> Option<usize> {
+    let index = (chunk_size - MIN_CHUNK_SIZE) / ALIGN;
+
+    if index < FASTBIN_COUNT { Some(index) } else { None }
+}
+
+/// Returns a pointer to where the link to the next cached chunk is stored.
+#[inline]
+unsafe fn link_ptr(chunk_base: *mut u8) -> *mut *mut u8 {
+    chunk_base.add(HEADER_SIZE).cast()
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Caches the allocated chunk instead of freeing it, returning whether it was cached.
+    ///
+    /// If the fastbin is full, its chunks are freed first.
+    #[inline]
+    pub(super) unsafe fn push_fastbin(&mut self, tag_ptr: *mut u8, tag: Tag) -> bool {
+        let chunk_base = tag.chunk_base();
+        let index = match fastbin_of_size(tag_ptr.add(TAG_SIZE) as usize - chunk_base as usize) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        if self.fastbins[index].len == FASTBIN_CAPACITY {
+            self.flush_fastbin(index);
+        }
+
+        #[cfg(feature = "generations")]
+        {
+            (*super::header_from_chunk_base(chunk_base)).generation = 0;
+        }
+
+        // the chunk stays tagged as allocated, so its neighbors won't coalesce with it
+        let fastbin = &mut self.fastbins[index];
+        link_ptr(chunk_base).write(fastbin.head);
+        fastbin.head = chunk_base;
+        fastbin.len += 1;
+
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
+
+        true
+    }
+
+    /// Takes a cached chunk that exactly fits `layout`, returning its base.
+    ///
+    /// The allocation is at the base of the chunk, after the header. Layouts aligned beyond
+    /// [`ALIGN`] are served only if the most recently cached chunk happens to suit them.
+    #[inline]
+    pub(super) unsafe fn pop_fastbin(&mut self, layout: Layout) -> Option<*mut u8> {
+        let size = layout.size();
+        let chunk_size = required_chunk_size(size);
+        let fastbin = &mut self.fastbins[fastbin_of_size(chunk_size)?];
+
+        if fastbin.head.is_null() || !is_aligned_to(fastbin.head.add(HEADER_SIZE), layout.align()) {
+            return None;
+        }
+
+        let chunk_base = fastbin.head;
+        fastbin.head = link_ptr(chunk_base).read();
+        fastbin.len -= 1;
+
+        // the chunk's size is the minimum required, so the tag is at or above the allocation's end
+        let tag_ptr = chunk_base.add(chunk_size - TAG_SIZE);
+        let post_alloc_ptr = align_up(chunk_base.add(HEADER_SIZE + size));
+
+        if post_alloc_ptr != tag_ptr {
+            post_alloc_ptr.cast::<*mut u8>().write(tag_ptr);
+        }
+
+        Some(chunk_base)
+    }
+
+    /// Frees all the chunks cached in the `index`th fastbin.
+    unsafe fn flush_fastbin(&mut self, index: usize) {
+        let chunk_size = MIN_CHUNK_SIZE + index * ALIGN;
+        let mut chunk_base = core::mem::replace(&mut self.fastbins[index], FastBin::EMPTY).head;
+
+        while !chunk_base.is_null() {
+            // read the link before the chunk is potentially merged and overwritten
+            let next = link_ptr(chunk_base).read();
+            let tag_ptr = chunk_base.add(chunk_size - TAG_SIZE);
+
+            self.free_chunk(tag_ptr, tag_ptr.cast::<Tag>().read());
+
+            chunk_base = next;
+        }
+    }
+
+    /// Frees up to `max_chunks` cached chunks, returning how many were freed.
+    pub(super) fn flush_fastbins_bounded(&mut self, max_chunks: usize) -> usize {
+        let mut flushed = 0;
+
+        for index in 0..FASTBIN_COUNT {
+            let chunk_size = MIN_CHUNK_SIZE + index * ALIGN;
+
+            while flushed < max_chunks && !self.fastbins[index].head.is_null() {
+                let chunk_base = self.fastbins[index].head;
+
+                // SAFETY: fastbins only contain chunks that were allocated and freed
+                unsafe {
+                    self.fastbins[index].head = link_ptr(chunk_base).read();
+                    self.fastbins[index].len -= 1;
+
+                    let tag_ptr = chunk_base.add(chunk_size - TAG_SIZE);
+                    self.free_chunk(tag_ptr, tag_ptr.cast::<Tag>().read());
+                }
+
+                flushed += 1;
+            }
+        }
+
+        flushed
+    }
+
+    /// Free all cached small chunks, returning whether there were any.
+    ///
+    /// With the `fastbins` feature, recently freed small chunks are cached for reuse by
+    /// allocations of the same size, without being coalesced with neighboring free memory.
+    /// Until flushed, cached chunks are considered allocated by functions such as
+    /// [`get_allocated_span`](Talc::get_allocated_span) and [`report`](Talc::report).
+    ///
+    /// This happens automatically before the OOM handler is called.
+    pub fn flush_fastbins(&mut self) -> bool {
+        let mut flushed = false;
+
+        for index in 0..FASTBIN_COUNT {
+            flushed |= !self.fastbins[index].head.is_null();
+
+            // SAFETY: fastbins only contain chunks that were allocated and freed
+            unsafe { self.flush_fastbin(index) };
+        }
+
+        flushed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn fastbin_reuse_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let initial = talc.report();
+        let layout = Layout::from_size_align(3 * ALIGN, ALIGN).unwrap();
+
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            let b = talc.malloc(layout).unwrap();
+            let c = talc.malloc(layout).unwrap();
+
+            talc.free(a, layout);
+            talc.free(b, layout);
+            let free_bytes = talc.report().free_bytes;
+
+            // reused last-in first-out, without being coalesced in between
+            assert_eq!(talc.malloc(layout).unwrap(), b);
+            let smaller = Layout::from_size_align(3 * ALIGN - 1, ALIGN / 2).unwrap();
+            assert_eq!(talc.malloc(smaller).unwrap(), a);
+            assert_eq!(talc.report().free_bytes, free_bytes);
+
+            talc.free(a, smaller);
+            talc.free(b, layout);
+            talc.free(c, layout);
+        }
+
+        assert!(talc.flush_fastbins());
+        assert!(!talc.flush_fastbins());
+        assert_eq!(talc.report(), initial);
+    }
+
+    #[test]
+    fn fastbin_overflow_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(2 * ALIGN, ALIGN).unwrap();
+        let mut ptrs = [NonNull::dangling(); FASTBIN_CAPACITY + 1];
+
+        unsafe {
+            for ptr in ptrs.iter_mut() {
+                *ptr = talc.malloc(layout).unwrap();
+            }
+
+            let free_chunk_count = talc.report().free_chunk_count;
+
+            // free every other chunk, such that flushing them doesn't coalesce them
+            for ptr in ptrs.iter().step_by(2) {
+                talc.free(*ptr, layout);
+            }
+            assert_eq!(talc.report().free_chunk_count, free_chunk_count);
+
+            for ptr in ptrs.iter().skip(1).step_by(2) {
+                talc.free(*ptr, layout);
+            }
+
+            // the cache overflowed and its chunks were freed, leaving the last cached
+            assert_eq!(talc.report().free_chunk_count, free_chunk_count + 1);
+        }
+    }
+
+    #[test]
+    fn fastbin_alignment_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(2 * ALIGN, ALIGN).unwrap();
+        let wide = Layout::from_size_align(2 * ALIGN, 2 * ALIGN).unwrap();
+
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            talc.free(a, layout);
+
+            // over-aligned allocations reuse the cached chunk if it happens to suit them
+            let b = talc.malloc(wide).unwrap();
+            assert!(is_aligned_to(b.as_ptr(), wide.align()));
+            assert_eq!(b == a, is_aligned_to(a.as_ptr(), wide.align()));
+            talc.free(b, wide);
+        }
+    }
+
+    #[test]
+    fn fastbin_oom_test() {
+        let mut arena = [0u8; 4000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let small = Layout::from_size_align(8, 8).unwrap();
+        let mut ptrs = [NonNull::dangling(); FASTBIN_CAPACITY];
+
+        unsafe {
+            let big = Layout::from_size_align(talc.report().largest_free_chunk / 2, 8).unwrap();
+            let a = talc.malloc(big).unwrap();
+
+            // fragment the remaining memory with cached chunks
+            for ptr in ptrs.iter_mut() {
+                *ptr = talc.malloc(small).unwrap();
+            }
+            talc.free(a, big);
+            for ptr in ptrs.iter() {
+                talc.free(*ptr, small);
+            }
+
+            // the cached chunks are flushed instead of failing
+            let b = talc.malloc(Layout::from_size_align(big.size() + 100, 8).unwrap()).unwrap();
+            talc.free(b, Layout::from_size_align(big.size() + 100, 8).unwrap());
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Generation numbers for detecting stale pointers to reused memory.
+
+use core::ptr::NonNull;
+
+use super::{header_from_chunk_base, Talc, HEADER_SIZE, MIN_CHUNK_SIZE};
+use crate::{ptr_utils::WORD_SIZE, OomHandler};
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the generation for a new allocation.
+    #[inline]
+    pub(super) fn take_generation(&mut self) -> usize {
+        let generation = self.next_generation;
+        // stays odd, to never match a freed chunk's zeroed generation
+        self.next_generation = self.next_generation.wrapping_add(2);
+        generation
+    }
+
+    /// Returns the chunk base of a pointer returned by this allocator,
+    /// or `None` if the word below it can't be the chunk base.
+    #[inline]
+    unsafe fn chunk_base_of(ptr: NonNull<u8>) -> Option<*mut u8> {
+        let chunk_base = ptr.as_ptr().cast::<*mut u8>().sub(1).read();
+        let offset = (ptr.as_ptr() as usize).wrapping_sub(chunk_base as usize);
+
+        // once the memory is reused, this word could be anything, so don't trust it
+        if (HEADER_SIZE..HEADER_SIZE + MIN_CHUNK_SIZE).contains(&offset)
+            && chunk_base as usize % WORD_SIZE == 0
+        {
+            Some(chunk_base)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the generation of an allocation.
+    ///
+    /// Store this alongside a pointer to later check whether it's stale using [`Talc::is_live`].
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated by this allocator, and not yet freed.
+    pub unsafe fn generation_of(&self, ptr: NonNull<u8>) -> usize {
+        debug_assert!(Self::chunk_base_of(ptr).is_some());
+
+        let chunk_base = ptr.as_ptr().cast::<*mut u8>().sub(1).read();
+        (*header_from_chunk_base(chunk_base)).generation
+    }
+
+    /// Returns whether `ptr` still refers to the allocation with the given `generation`.
+    ///
+    /// This is intended for catching dangling handles during development. A live allocation
+    /// is never reported as stale. A stale pointer is reported as such unless its memory has
+    /// since been reallocated and happens to contain a matching value, which is unlikely.
+    ///
+    /// Growing or shrinking in-place retains the generation, while a reallocation that
+    /// moves the memory acquires a new one.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously returned by this allocator,
+    /// and the heap it was allocated in must not have been truncated since.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u32; 8]>();
+    /// unsafe {
+    ///     let handle = talc.malloc(layout).unwrap();
+    ///     let generation = talc.generation_of(handle);
+    ///     assert!(talc.is_live(handle, generation));
+    ///
+    ///     talc.free(handle, layout);
+    ///     let reused = talc.malloc(layout).unwrap();
+    ///     assert_eq!(reused, handle);
+    ///     assert!(!talc.is_live(handle, generation));
+    /// }
+    /// ```
+    pub unsafe fn is_live(&self, ptr: NonNull<u8>, generation: usize) -> bool {
+        match Self::chunk_base_of(ptr) {
+            Some(chunk_base) => (*header_from_chunk_base(chunk_base)).generation == generation,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::{ptr_utils::ALIGN, *};
+
+    #[test]
+    fn generations_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        // chunks fit such allocations exactly on any word size, so they're reused in place
+        let small = Layout::from_size_align(8, ALIGN).unwrap();
+        let aligned = Layout::from_size_align(100, 128).unwrap();
+
+        unsafe {
+            let a = talc.malloc(small).unwrap();
+            let b = talc.malloc(aligned).unwrap();
+            let c = talc.malloc(small).unwrap();
+            let (gen_a, gen_b, gen_c) =
+                (talc.generation_of(a), talc.generation_of(b), talc.generation_of(c));
+
+            assert!(gen_a % 2 == 1 && gen_a != gen_b && gen_b != gen_c);
+            assert!(talc.is_live(a, gen_a) && talc.is_live(b, gen_b) && talc.is_live(c, gen_c));
+            assert!(!talc.is_live(a, gen_b));
+
+            // in-place reallocation keeps the generation
+            talc.shrink(b, aligned, 10);
+            assert!(talc.is_live(b, gen_b));
+            let b = talc.grow_in_place(b, Layout::from_size_align(10, 128).unwrap(), 100).unwrap();
+            assert!(talc.is_live(b, gen_b));
+
+            // b's header isn't necessarily overwritten upon being freed, but mustn't look live
+            talc.free(b, aligned);
+            assert!(!talc.is_live(b, gen_b));
+            assert!(talc.is_live(a, gen_a) && talc.is_live(c, gen_c));
+
+            talc.free(a, small);
+            assert!(!talc.is_live(a, gen_a));
+
+            // whether recycled from a fastbin or the free lists, reuse takes a new generation
+            let d = talc.malloc(small).unwrap();
+            assert_eq!(d, a);
+            assert!(!talc.is_live(a, gen_a));
+            let gen_d = talc.generation_of(d);
+            assert!(gen_d % 2 == 1 && gen_d != gen_a);
+            assert!(talc.is_live(d, gen_d));
+
+            talc.free(c, small);
+            talc.free(d, small);
+        }
+    }
+}
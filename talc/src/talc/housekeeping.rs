@@ -0,0 +1,107 @@
+//! Pay off the allocator's deferred work in one place, such as from an idle loop.
+
+use super::{HeapError, Talc};
+use crate::OomHandler;
+
+impl<O: OomHandler> Talc<O> {
+    /// Do up to `budget` units of deferred maintenance, and return whether none is left.
+    ///
+    /// This is intended to be called regularly from an idle loop or a timer, as the one
+    /// place to pay off the work the allocator puts off. In order:
+    /// - With the `fastbins` feature, cached chunks are freed and coalesced with their
+    ///   neighbors, a unit each, see [`Talc::flush_fastbins`].
+    /// - With the `counters` feature, the stats set up with `set_live_stats` are published.
+    /// - With the `incremental_scan` feature, free chunks are checked for corruption,
+    ///   a unit each, see [`Talc::scan_step`].
+    ///
+    /// Returns `Ok(true)` once no chunks are cached and, with `incremental_scan`, a pass
+    /// over all the free chunks has been completed, or `Err` if corruption is found.
+    ///
+    /// Trimming needs the heaps, which the caller keeps track of, so it's left to
+    /// [`Talc::trim`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// // e.g. in the idle loop
+    /// if let Err(error) = talc.housekeeping(16) {
+    ///     panic!("heap corruption: {}", error);
+    /// }
+    /// ```
+    pub fn housekeeping(&mut self, budget: usize) -> Result<bool, HeapError> {
+        #[cfg_attr(not(feature = "fastbins"), allow(unused_mut))]
+        let mut budget = budget;
+
+        // scanning is only completed with budget to spare, so no chunks are left cached
+        #[cfg(feature = "fastbins")]
+        {
+            budget -= self.flush_fastbins_bounded(budget);
+        }
+
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
+
+        if self.bins.is_null() {
+            return Ok(true);
+        }
+
+        #[cfg(feature = "incremental_scan")]
+        return self.scan_step(budget);
+
+        // flushing only leaves budget to spare once no chunks are left cached
+        #[cfg(not(feature = "incremental_scan"))]
+        Ok(!cfg!(feature = "fastbins") || budget != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn housekeeping_test() {
+        let mut talc = Talc::new(ErrOnOom);
+        assert_eq!(talc.housekeeping(0), Ok(true));
+
+        let mut arena = [0u8; 10000];
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(24, 8).unwrap();
+        let mut allocs = std::vec::Vec::new();
+        for _ in 0..20 {
+            allocs.push(unsafe { talc.malloc(layout).unwrap() });
+        }
+        for ptr in allocs.iter().step_by(2) {
+            unsafe { talc.free(*ptr, layout) };
+        }
+
+        // the work is spread over calls within the budget
+        #[cfg(any(feature = "fastbins", feature = "incremental_scan"))]
+        assert_eq!(talc.housekeeping(0), Ok(false));
+        #[cfg(feature = "incremental_scan")]
+        {
+            let mut calls = 1;
+            while !talc.housekeeping(3).unwrap() {
+                calls += 1;
+            }
+            assert!(calls > 2);
+        }
+        assert_eq!(talc.housekeeping(usize::MAX), Ok(true));
+
+        // cached chunks are freed as part of it
+        let report = talc.report();
+        #[cfg(feature = "fastbins")]
+        assert!(!talc.flush_fastbins());
+        assert_eq!(report.free_chunk_count, 11);
+
+        for ptr in allocs.iter().skip(1).step_by(2) {
+            unsafe { talc.free(*ptr, layout) };
+        }
+    }
+}
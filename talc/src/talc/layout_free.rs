@@ -0,0 +1,102 @@
+//! Free allocations and query their size without their layout, for C interop and handles.
+//!
+//! The allocation's size is found in its header with the `owners` feature, and the header
+//! is found through the word below the allocation with the `generations` feature.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{header_from_chunk_base, Header, Talc};
+use crate::{ptr_utils::align_up, OomHandler};
+
+/// Returns the header of an allocation, through the chunk base stored below it.
+#[inline]
+unsafe fn header_of(ptr: NonNull<u8>) -> *mut Header {
+    header_from_chunk_base(ptr.as_ptr().cast::<*mut u8>().sub(1).read())
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the size of the layout an allocation was last allocated or reallocated with.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated by this allocator, and not yet freed.
+    pub unsafe fn allocation_size(&self, ptr: NonNull<u8>) -> usize {
+        (*header_of(ptr)).size
+    }
+
+    /// Returns the number of bytes at `ptr` that can be used, like `malloc_usable_size`.
+    ///
+    /// This is at least the allocation's size, as it's rounded up to the machine word
+    /// size. The rest of the chunk may hold the allocator's metadata, so grow the
+    /// allocation in place to use more of it.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated by this allocator, and not yet freed.
+    pub unsafe fn usable_size(&self, ptr: NonNull<u8>) -> usize {
+        let size = self.allocation_size(ptr);
+        align_up(ptr.as_ptr().add(size)) as usize - ptr.as_ptr() as usize
+    }
+
+    /// Free previously allocated/reallocated memory, without its layout.
+    ///
+    /// This is equivalent to [`free`](Talc::free) given the allocation's layout, which is
+    /// recorded with the `owners` feature. Events of the `event_log` and `trace` features
+    /// record the alignment as one.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated by this allocator, and not yet freed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// unsafe {
+    ///     let ptr = talc.malloc(Layout::from_size_align(100, 16).unwrap()).unwrap();
+    ///     assert!(talc.usable_size(ptr) >= 100);
+    ///     talc.free_unsized(ptr);
+    /// }
+    /// ```
+    pub unsafe fn free_unsized(&mut self, ptr: NonNull<u8>) {
+        // the alignment isn't needed to free the allocation
+        let layout = Layout::from_size_align_unchecked(self.allocation_size(ptr), 1);
+        self.free(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn free_unsized_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let initial = talc.report();
+
+        let aligned = Layout::from_size_align(13, 256).unwrap();
+        unsafe {
+            let a = talc.malloc(aligned).unwrap();
+            let b = talc.malloc(Layout::new::<[u8; 100]>()).unwrap();
+            assert_eq!(talc.allocation_size(a), 13);
+            assert_eq!(talc.usable_size(a), 16);
+
+            // the recorded size follows reallocation
+            let b = talc.grow(b, Layout::new::<[u8; 100]>(), 1000).unwrap();
+            assert_eq!(talc.allocation_size(b), 1000);
+            b.as_ptr().write_bytes(0xAB, talc.usable_size(b));
+
+            talc.free_unsized(a);
+            talc.free_unsized(b);
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+    }
+}
@@ -0,0 +1,170 @@
+//! Track the bytes allocated by each owner, and optionally cap them.
+
+use super::Talc;
+use crate::OomHandler;
+
+/// The number of owners, from `0` up, whose allocations are tracked by [`Talc::owner_stats`].
+///
+/// Allocations of other owners are neither tracked nor capped.
+pub const TRACKED_OWNER_COUNT: usize = 16;
+
+/// Allocation statistics of an owner, see [`Talc::owner_stats`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct OwnerStats {
+    /// Sum of the sizes of the owner's live allocations.
+    pub allocated_bytes: usize,
+    /// The highest `allocated_bytes` has been since the peak was last reset.
+    pub peak_allocated_bytes: usize,
+    /// The limit on `allocated_bytes`, if any.
+    pub cap: Option<usize>,
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the allocation statistics of `owner`, or `None` if it isn't tracked.
+    ///
+    /// Owners are identified by the owner source set by [`Talc::set_owner_source`],
+    /// and those below [`TRACKED_OWNER_COUNT`] are tracked. This tells which owner,
+    /// e.g. which subsystem or task, is using the memory of a shared heap.
+    ///
+    /// Sizes are counted as requested, excluding the allocator's overhead.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// talc.set_owner_source(|| 3);
+    ///
+    /// let layout = Layout::new::<[u8; 100]>();
+    /// let ptr = unsafe { talc.malloc(layout).unwrap() };
+    /// assert_eq!(talc.owner_stats(3).unwrap().allocated_bytes, 100);
+    /// ```
+    pub fn owner_stats(&self, owner: usize) -> Option<OwnerStats> {
+        self.owner_stats.get(owner).copied()
+    }
+
+    /// Limit the sum of the sizes of `owner`'s live allocations to `cap` bytes, or lift the limit.
+    ///
+    /// Allocations and in-place growth that would exceed the cap fail without calling the
+    /// OOM handler. Moved reallocations are checked against the cap of the current owner.
+    /// Lowering the cap below the owner's allocated bytes doesn't affect existing allocations.
+    ///
+    /// Returns `Err` if `owner` isn't tracked.
+    pub fn set_owner_cap(&mut self, owner: usize, cap: Option<usize>) -> Result<(), ()> {
+        self.owner_stats.get_mut(owner).ok_or(())?.cap = cap;
+        Ok(())
+    }
+
+    /// Set the peak allocated bytes of every tracked owner to their currently allocated bytes.
+    pub fn reset_owner_peaks(&mut self) {
+        for stats in self.owner_stats.iter_mut() {
+            stats.peak_allocated_bytes = stats.allocated_bytes;
+        }
+    }
+
+    /// Returns whether `owner` can allocate `additional` bytes without exceeding its cap.
+    #[inline]
+    pub(super) fn fits_owner_cap(&self, owner: usize, additional: usize) -> bool {
+        match self.owner_stats.get(owner) {
+            Some(OwnerStats { allocated_bytes, cap: Some(cap), .. }) => {
+                match allocated_bytes.checked_add(additional) {
+                    Some(allocated_bytes) => allocated_bytes <= *cap,
+                    None => false,
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Accounts for an allocation of `owner` changing size from `old_size` to `new_size`,
+    /// where a size of zero is used for allocating and freeing.
+    #[inline]
+    pub(super) fn account_owner_realloc(&mut self, owner: usize, old_size: usize, new_size: usize) {
+        if let Some(stats) = self.owner_stats.get_mut(owner) {
+            stats.allocated_bytes = stats.allocated_bytes - old_size + new_size;
+            stats.peak_allocated_bytes = stats.peak_allocated_bytes.max(stats.allocated_bytes);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use super::*;
+    use crate::*;
+
+    static CURRENT_OWNER: AtomicUsize = AtomicUsize::new(0);
+
+    fn current_owner() -> usize {
+        CURRENT_OWNER.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn owner_stats_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        talc.set_owner_source(current_owner);
+
+        assert_eq!(talc.owner_stats(TRACKED_OWNER_COUNT), None);
+        assert!(talc.set_owner_cap(TRACKED_OWNER_COUNT, Some(0)).is_err());
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            CURRENT_OWNER.store(1, Ordering::Relaxed);
+            let a = talc.malloc(layout).unwrap();
+            let b = talc.malloc(layout).unwrap();
+
+            CURRENT_OWNER.store(2, Ordering::Relaxed);
+            talc.malloc(layout).unwrap();
+            // untracked owners are neither counted nor capped
+            CURRENT_OWNER.store(usize::MAX, Ordering::Relaxed);
+            let d = talc.malloc(layout).unwrap();
+
+            assert_eq!(
+                talc.owner_stats(1),
+                Some(OwnerStats { allocated_bytes: 200, peak_allocated_bytes: 200, cap: None })
+            );
+            assert_eq!(talc.owner_stats(2).unwrap().allocated_bytes, 100);
+
+            // reallocations are accounted to the allocation's owner, counting both while moving
+            let b = talc.grow(b, layout, 1000).unwrap();
+            talc.shrink(b, Layout::from_size_align(1000, 8).unwrap(), 50);
+            assert_eq!(talc.owner_stats(1).unwrap().allocated_bytes, 150);
+            assert_eq!(talc.owner_stats(1).unwrap().peak_allocated_bytes, 1200);
+            assert_eq!(talc.owner_stats(2).unwrap().allocated_bytes, 100);
+
+            talc.free(a, layout);
+            talc.reset_owner_peaks();
+            assert_eq!(talc.owner_stats(1).unwrap().peak_allocated_bytes, 50);
+
+            // caps limit allocation and in-place growth
+            talc.set_owner_cap(2, Some(250)).unwrap();
+            CURRENT_OWNER.store(2, Ordering::Relaxed);
+            let e = talc.malloc(layout).unwrap();
+            assert!(talc.malloc(layout).is_err());
+            assert!(talc.grow_in_place(e, layout, 200).is_err());
+            talc.grow_in_place(e, layout, 150).unwrap();
+            assert_eq!(talc.owner_stats(2).unwrap().allocated_bytes, 250);
+
+            // other owners are unaffected
+            CURRENT_OWNER.store(1, Ordering::Relaxed);
+            let f = talc.malloc(layout).unwrap();
+            talc.free(f, layout);
+
+            assert_eq!(talc.free_all_owned_by(heap, 2), 2);
+            assert_eq!(talc.owner_stats(2).unwrap().allocated_bytes, 0);
+
+            talc.free(b, Layout::from_size_align(50, 8).unwrap());
+            talc.free(d, layout);
+        }
+
+        assert_eq!(talc.owner_stats(1).unwrap().allocated_bytes, 0);
+    }
+}
@@ -0,0 +1,210 @@
+//! Track which task owns each allocation, allowing a task's allocations to be freed en masse.
+
+use core::ptr::NonNull;
+
+use super::{
+    gap_acme_to_base, header_from_alloc_ptr, header_from_chunk_base, is_gap_below, tag::Tag, Talc,
+    TAG_SIZE,
+};
+use crate::{OomHandler, Span};
+
+/// The default owner source, which attributes all allocations to owner `0`.
+pub(super) fn no_owner() -> usize {
+    0
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Set the function used to identify the owner of new allocations,
+    /// such as the current RTOS task or thread ID.
+    ///
+    /// It is called once per allocation, while the allocator is held, so it must not allocate.
+    /// Until this is set, all allocations are owned by `0`.
+    pub fn set_owner_source(&mut self, owner_source: fn() -> usize) {
+        self.owner_source = owner_source;
+    }
+
+    /// Returns the owner recorded for an allocation.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given a layout of `size`.
+    pub unsafe fn owner_of(&self, ptr: NonNull<u8>, size: usize) -> usize {
+        (*header_from_alloc_ptr(ptr.as_ptr(), size)).owner
+    }
+
+    /// Free every allocation in `heap` owned by `owner`, returning how many were freed.
+    ///
+    /// This is intended for reclaiming the memory of a task that was killed.
+    /// The heap is walked from top to bottom, so this takes time proportional to
+    /// the number of chunks in the heap. Call this for each heap the task may have
+    /// allocated in.
+    ///
+    /// # Safety
+    /// - `heap` must be the return value of a heap manipulation function.
+    /// - Pointers to any of the freed allocations must not be used afterwards.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::{alloc::Layout, sync::atomic::{AtomicUsize, Ordering}};
+    /// static CURRENT_TASK: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+    /// talc.set_owner_source(|| CURRENT_TASK.load(Ordering::Relaxed));
+    ///
+    /// let layout = Layout::new::<[u64; 4]>();
+    /// unsafe {
+    ///     CURRENT_TASK.store(7, Ordering::Relaxed);
+    ///     talc.malloc(layout).unwrap();
+    ///     talc.malloc(layout).unwrap();
+    ///
+    ///     // task 7 was killed, reclaim its memory
+    ///     assert_eq!(talc.free_all_owned_by(heap, 7), 2);
+    /// }
+    /// ```
+    pub unsafe fn free_all_owned_by(&mut self, heap: Span, owner: usize) -> usize {
+        self.scan_for_errors();
+
+        // cached and quarantined chunks look allocated, but mustn't be freed again
+        #[cfg(feature = "fastbins")]
+        self.flush_fastbins();
+        #[cfg(feature = "quarantine")]
+        self.flush_quarantine();
+
+        let (base, acme) = match heap.get_base_acme() {
+            Some(base_acme) => base_acme,
+            None => return 0,
+        };
+
+        let mut freed = 0;
+        let mut cursor = acme;
+
+        while cursor > base.add(TAG_SIZE) {
+            if is_gap_below(cursor) {
+                cursor = gap_acme_to_base(cursor);
+                continue;
+            }
+
+            let tag_ptr = cursor.sub(TAG_SIZE);
+            let tag = tag_ptr.cast::<Tag>().read();
+            let chunk_base = tag.chunk_base();
+
+            // the metadata chunk is the only chunk based at the heap's base
+            if chunk_base == base {
+                break;
+            }
+
+            // determine where to continue before the chunk is potentially merged
+            cursor =
+                if is_gap_below(chunk_base) { gap_acme_to_base(chunk_base) } else { chunk_base };
+
+            let header = header_from_chunk_base(chunk_base).read();
+            if header.owner == owner {
+                #[cfg(feature = "counters")]
+                self.counters.account_dealloc(header.size);
+                #[cfg(feature = "owner_stats")]
+                self.account_owner_realloc(owner, header.size, 0);
+
+                self.free_chunk(tag_ptr, tag);
+                freed += 1;
+            }
+        }
+
+        freed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        ptr::NonNull,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::*;
+
+    static CURRENT_TASK: AtomicUsize = AtomicUsize::new(0);
+
+    fn current_task() -> usize {
+        CURRENT_TASK.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn free_all_owned_by_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        talc.set_owner_source(current_task);
+
+        let initial = talc.report();
+
+        let small = Layout::from_size_align(24, 8).unwrap();
+        let aligned = Layout::from_size_align(100, 256).unwrap();
+
+        unsafe {
+            let mut kept: [(NonNull<u8>, Layout); 8] = [(NonNull::dangling(), small); 8];
+
+            for i in 0..16 {
+                CURRENT_TASK.store(1 + i % 2, Ordering::Relaxed);
+                let layout = if i % 3 == 0 { aligned } else { small };
+                let ptr = talc.malloc(layout).unwrap();
+                assert_eq!(talc.owner_of(ptr, layout.size()), current_task());
+
+                if current_task() == 2 {
+                    ptr.as_ptr().write_bytes(0xAB, layout.size());
+                    kept[i / 2] = (ptr, layout);
+                }
+            }
+
+            // moved reallocations keep their owner
+            CURRENT_TASK.store(1, Ordering::Relaxed);
+            let (ptr, layout) = kept[0];
+            let ptr = talc.grow(ptr, layout, 5000).unwrap();
+            let layout = Layout::from_size_align(5000, layout.align()).unwrap();
+            assert_eq!(talc.owner_of(ptr, layout.size()), 2);
+            talc.shrink(ptr, layout, 50);
+            kept[0] = (ptr, Layout::from_size_align(50, layout.align()).unwrap());
+
+            assert_eq!(talc.free_all_owned_by(heap, 3), 0);
+            assert_eq!(talc.free_all_owned_by(heap, 1), 8);
+
+            #[cfg(feature = "counters")]
+            assert_eq!(talc.get_counters().allocation_count, 8);
+
+            // the other task's allocations are untouched
+            for (ptr, layout) in kept.iter().skip(1) {
+                assert!((0..layout.size()).all(|i| *ptr.as_ptr().add(i) == 0xAB));
+            }
+
+            assert_eq!(talc.free_all_owned_by(heap, 2), 8);
+        }
+
+        assert_eq!(talc.report(), initial);
+    }
+
+    #[test]
+    fn owner_source_unwind_test() {
+        fn panicking_owner() -> usize {
+            panic!("no current task");
+        }
+
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        talc.set_owner_source(panicking_owner);
+
+        let initial = talc.report();
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe {
+            talc.malloc(layout)
+        }));
+        assert!(result.is_err());
+
+        // nothing was allocated
+        assert_eq!(talc.report(), initial);
+        #[cfg(feature = "counters")]
+        assert_eq!(talc.get_counters().allocation_count, 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! Pin allocations in place, such that reallocation never moves them.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{header_from_alloc_ptr, Talc};
+use crate::OomHandler;
+
+impl<O: OomHandler> Talc<O> {
+    /// Allocate memory like [`Talc::malloc`], pinned from the start, see [`Talc::set_pinned`].
+    ///
+    /// Zero-sized allocations aren't in the heap, so they aren't pinned.
+    ///
+    /// # Safety
+    /// As for [`Talc::malloc`].
+    pub unsafe fn malloc_pinned(&mut self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        let ptr = self.malloc(layout)?;
+        if layout.size() != 0 {
+            self.set_pinned(ptr, layout.size(), true);
+        }
+        Ok(ptr)
+    }
+
+    /// Pin or unpin an allocation.
+    ///
+    /// A pinned allocation is never moved: growing it, or shrinking it to a stricter
+    /// alignment, only succeeds in place, and fails otherwise. This applies to reallocation
+    /// through `Talc`, as well as [`Talck`](crate::Talck) and [`Talcell`](crate::Talcell),
+    /// for memory that must stay put, such as DMA descriptors or buffers held by foreign code.
+    ///
+    /// Allocations are unpinned by default.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given a layout of `size`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u8; 64]>();
+    /// unsafe {
+    ///     let descriptor = talc.malloc(layout).unwrap();
+    ///     let blocker = talc.malloc(layout).unwrap();
+    ///     talc.set_pinned(descriptor, layout.size(), true);
+    ///
+    ///     // the memory above is allocated, so this would need to move the allocation
+    ///     assert!(talc.grow(descriptor, layout, 128).is_err());
+    /// }
+    /// ```
+    pub unsafe fn set_pinned(&mut self, ptr: NonNull<u8>, size: usize, pinned: bool) {
+        (*header_from_alloc_ptr(ptr.as_ptr(), size)).pinned = pinned;
+    }
+
+    /// Returns whether an allocation is pinned, see [`Talc::set_pinned`].
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given a layout of `size`.
+    pub unsafe fn is_pinned(&self, ptr: NonNull<u8>, size: usize) -> bool {
+        (*header_from_alloc_ptr(ptr.as_ptr(), size)).pinned
+    }
+}
+
+#[cfg(all(test, feature = "lock_api"))]
+mod tests {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use crate::*;
+
+    #[test]
+    fn pinning_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        unsafe {
+            let pinned = talck.lock().malloc_pinned(layout).unwrap();
+            let movable = talck.lock().malloc(layout).unwrap();
+            let blocker = talck.alloc(layout);
+            assert!(talck.lock().is_pinned(pinned, layout.size()));
+            assert!(!talck.lock().is_pinned(movable, layout.size()));
+
+            // growing in place is fine
+            let grown = talck.realloc(blocker, layout, 128);
+            assert_eq!(grown, blocker);
+            let blocker = grown;
+
+            // moving isn't
+            assert!(talck.realloc(pinned.as_ptr(), layout, 1000).is_null());
+            assert!(talck.lock().grow(pinned, layout, 1000).is_err());
+            let moved = talck.realloc(movable.as_ptr(), layout, 1000);
+            assert!(!moved.is_null() && moved != movable.as_ptr());
+
+            // the pin is kept when grown in place, and can be lifted
+            talck.dealloc(moved, Layout::from_size_align(1000, 8).unwrap());
+            let grown = talck.lock().grow_in_place(pinned, layout, 128).unwrap();
+            let layout = Layout::from_size_align(128, 8).unwrap();
+            assert!(talck.lock().is_pinned(grown, layout.size()));
+
+            talck.lock().set_pinned(grown, layout.size(), false);
+            let moved = talck.realloc(grown.as_ptr(), layout, 2000);
+            assert!(!moved.is_null() && moved != grown.as_ptr());
+
+            talck.dealloc(moved, Layout::from_size_align(2000, 8).unwrap());
+            talck.dealloc(blocker, layout);
+        }
+    }
+}
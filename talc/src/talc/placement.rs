@@ -0,0 +1,186 @@
+//! Allocate at a fixed address, for reserving exact ranges from within the heap.
+//!
+//! The free chunk containing the allocation is split around it, as when allocating
+//! from the middle of a chunk with [`LifetimeHint::LongLived`](crate::LifetimeHint).
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{
+    bin_of_size, gap_node_to_base, gap_node_to_size, llist::LlistNode, Talc, HEADER_SIZE, TAG_SIZE,
+};
+use crate::{
+    ptr_utils::{align_up, is_aligned_to, ALIGN},
+    OomHandler,
+};
+
+impl<O: OomHandler> Talc<O> {
+    /// Allocate `layout` at `addr`, if the memory there is free.
+    ///
+    /// Returns `Err` if `addr` isn't aligned to `layout.align()` and the machine word size,
+    /// or if any of the memory there, or the allocation's metadata around it, is allocated
+    /// or outside the heaps. Cached and quarantined chunks are freed to make room if need be,
+    /// but the OOM handler isn't called. Free the allocation with [`free`](Talc::free)
+    /// as usual.
+    ///
+    /// Zero-sized allocations succeed at any address aligned to `layout.align()`,
+    /// without using the heap.
+    ///
+    /// This is for kernels and loaders that need to reserve exact physical or virtual
+    /// ranges, such as those of devices or loaded images, from within the heap.
+    ///
+    /// # Safety
+    /// As for [`Talc::malloc`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+    ///
+    /// let layout = Layout::new::<[u32; 64]>();
+    /// let addr = heap.get_base_acme().unwrap().1.wrapping_sub(2048);
+    /// unsafe {
+    ///     let ptr = talc.malloc_at(addr, layout).unwrap();
+    ///     assert_eq!(ptr.as_ptr(), addr);
+    ///
+    ///     // the memory is taken
+    ///     assert!(talc.malloc_at(addr, layout).is_err());
+    ///     talc.free(ptr, layout);
+    /// }
+    /// ```
+    pub unsafe fn malloc_at(&mut self, addr: *mut u8, layout: Layout) -> Result<NonNull<u8>, ()> {
+        if layout.size() == 0 {
+            return match NonNull::new(addr) {
+                Some(ptr) if is_aligned_to(addr, layout.align()) => Ok(ptr),
+                _ => Err(()),
+            };
+        }
+
+        if !is_aligned_to(addr, layout.align().max(ALIGN)) {
+            return Err(());
+        }
+
+        // reject allocations that would overflow the address space before doing any pointer arithmetic
+        if (addr as usize) < HEADER_SIZE
+            || layout.size() > usize::MAX - (ALIGN - 1) - TAG_SIZE - addr as usize
+        {
+            return Err(());
+        }
+
+        self.scan_for_errors();
+        let header = self.admit(layout)?;
+
+        // the allocated chunk spans from the header to the tag, at the least
+        let span_base = addr.sub(HEADER_SIZE);
+        let span_acme = align_up(addr.add(layout.size())).add(TAG_SIZE);
+
+        #[cfg_attr(not(any(feature = "fastbins", feature = "quarantine")), allow(unused_mut))]
+        let mut gap = self.take_gap_containing(span_base, span_acme);
+
+        // the memory might be held by cached chunks
+        #[cfg(feature = "quarantine")]
+        if gap.is_none() && self.flush_quarantine() {
+            gap = self.take_gap_containing(span_base, span_acme);
+        }
+        #[cfg(feature = "fastbins")]
+        if gap.is_none() && self.flush_fastbins() {
+            gap = self.take_gap_containing(span_base, span_acme);
+        }
+
+        let result = match gap {
+            Some((free_base, free_acme)) => {
+                Ok(self.carve(free_base, free_acme, addr, header, layout.size()))
+            }
+            None => {
+                self.alloc_failed(layout, "address unavailable");
+                Err(())
+            }
+        };
+
+        #[cfg(feature = "event_log")]
+        self.event_log.record(
+            super::event_log::HeapOp::Malloc,
+            result.is_ok(),
+            layout.size(),
+            addr as usize,
+        );
+
+        result
+    }
+
+    /// Deregister the free chunk containing `[base, acme)`, if any, returning its bounds.
+    unsafe fn take_gap_containing(
+        &mut self,
+        base: *mut u8,
+        acme: *mut u8,
+    ) -> Option<(*mut u8, *mut u8)> {
+        // only chunks at least as large as the span can contain it
+        let mut bin = self.next_available_bin(bin_of_size(acme as usize - base as usize))?;
+
+        loop {
+            for node in LlistNode::iter_mut(*self.get_bin_ptr(bin)) {
+                let gap_base = gap_node_to_base(node);
+                let gap_acme = gap_base.add(gap_node_to_size(node).read());
+
+                if gap_base <= base && acme <= gap_acme {
+                    self.deregister_gap(gap_base, bin);
+                    return Some((gap_base, gap_acme));
+                }
+            }
+
+            bin = self.next_available_bin(bin + 1)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn malloc_at_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let initial = talc.report();
+
+        let layout = Layout::new::<[u32; 50]>();
+        let (base, acme) = heap.get_base_acme().unwrap();
+        let addr = base.wrapping_add(4800);
+
+        unsafe {
+            let a = talc.malloc_at(addr, layout).unwrap();
+            assert_eq!(a.as_ptr(), addr);
+            a.as_ptr().write_bytes(0xAB, layout.size());
+
+            // the chunk is split around it, so the memory on either side remains allocatable
+            let below = talc.malloc_at(addr.sub(1000), layout).unwrap();
+            let above = talc.malloc_at(addr.add(1000), layout).unwrap();
+            assert_eq!(talc.report().free_chunk_count, 4);
+            assert!((0..layout.size()).all(|i| *a.as_ptr().add(i) == 0xAB));
+
+            // taken, misaligned, and out-of-heap addresses are rejected
+            assert!(talc.malloc_at(addr.add(64), layout).is_err());
+            assert!(talc.malloc_at(addr.add(501), layout).is_err());
+            assert!(talc.malloc_at(acme.sub(64), layout).is_err());
+
+            talc.free(below, layout);
+            talc.free(above, layout);
+
+            // freed memory can be placed into again, once it's released from any caches
+            talc.free(a, layout);
+            let a = talc.malloc_at(addr, layout).unwrap();
+            talc.free(a, layout);
+        }
+
+        #[cfg(feature = "quarantine")]
+        talc.flush_quarantine();
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+    }
+}
@@ -0,0 +1,99 @@
+//! Fill free memory with a recognizable pattern, and check that it's intact upon reuse.
+//!
+//! All free memory that isn't used to keep track of its chunk holds the pattern.
+//! Memory is poisoned as it's freed or made available, and checked as it's allocated
+//! out of a free chunk, catching late writes through dangling pointers. Chunks cached
+//! by the `fastbins` feature are only poisoned once flushed.
+
+use super::{GAP_HIGH_SIZE_OFFSET, GAP_LOW_SIZE_OFFSET};
+use crate::ptr_utils::WORD_SIZE;
+
+/// The byte that free memory is filled with.
+pub const POISON_BYTE: u8 = 0xDD;
+
+/// Fill `[base, acme)` with [`POISON_BYTE`], doing nothing if it's empty.
+#[inline]
+pub(super) unsafe fn poison(base: *mut u8, acme: *mut u8) {
+    if base < acme {
+        base.write_bytes(POISON_BYTE, acme as usize - base as usize);
+    }
+}
+
+/// Check that the `size` bytes at `alloc_base`, allocated out of the free chunk
+/// `[free_base, free_acme)`, still hold the pattern, skipping the chunk's metadata.
+///
+/// # Panics
+/// Panics with the offset of the first modified byte from `alloc_base`.
+pub(super) unsafe fn verify(
+    free_base: *mut u8,
+    free_acme: *mut u8,
+    alloc_base: *mut u8,
+    size: usize,
+) {
+    let interior_base = free_base.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE);
+    let interior_acme = free_acme.sub(GAP_HIGH_SIZE_OFFSET);
+
+    let base = alloc_base.max(interior_base);
+    let acme = alloc_base.add(size).min(interior_acme);
+
+    let mut ptr = base;
+    while ptr < acme {
+        if ptr.read() != POISON_BYTE {
+            let offset = ptr as usize - alloc_base as usize;
+            panic!("heap used after free at offset {} of {:p}", offset, alloc_base);
+        }
+        ptr = ptr.add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn poison_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(200, 8).unwrap();
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            let b = talc.malloc(layout).unwrap();
+            a.as_ptr().write_bytes(1, layout.size());
+            b.as_ptr().write_bytes(1, layout.size());
+
+            talc.free(a, layout);
+            assert_eq!(a.as_ptr().add(100).read(), POISON_BYTE);
+
+            // shrinking poisons the freed tail too
+            talc.shrink(b, layout, 8);
+            assert_eq!(b.as_ptr().add(100).read(), POISON_BYTE);
+            talc.free(b, Layout::from_size_align(8, 8).unwrap());
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "heap used after free at offset 100")]
+    fn use_after_free_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(200, 8).unwrap();
+        unsafe {
+            let stale = talc.malloc(layout).unwrap();
+            talc.free(stale, layout);
+            #[cfg(feature = "fastbins")]
+            talc.flush_fastbins();
+
+            // a late write through a dangling pointer
+            stale.as_ptr().add(100).write(0);
+
+            let reused = talc.malloc(layout).unwrap();
+            assert_eq!(reused, stale);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Touch the free memory of Talc's heaps ahead of time, to avoid demand paging later.
+
+use super::{
+    gap_node_to_base, gap_node_to_size, llist::LlistNode, Talc, BIN_COUNT, GAP_HIGH_SIZE_OFFSET,
+    GAP_LOW_SIZE_OFFSET,
+};
+use crate::{ptr_utils::WORD_SIZE, OomHandler};
+
+/// The stride at which memory is touched.
+///
+/// This is the smallest page size of common hosted targets. Where pages
+/// are larger, some pages are touched more than once, which is harmless.
+const PAGE_SIZE: usize = 4096;
+
+/// The byte written to free memory, which must keep the poison pattern intact.
+#[cfg(not(feature = "poison"))]
+const FILL_BYTE: u8 = 0;
+#[cfg(feature = "poison")]
+const FILL_BYTE: u8 = super::POISON_BYTE;
+
+impl<O: OomHandler> Talc<O> {
+    /// Write to every page of free memory in the established heaps, so that the
+    /// operating system backs them with physical memory now rather than upon first use.
+    ///
+    /// On hosted targets, memory obtained from the operating system is typically only
+    /// mapped in when first written to, which can dominate the latency of allocations
+    /// that first use it. Call this before a benchmark or latency-critical phase.
+    ///
+    /// Only free memory is written to, so this is safe to call while allocations are in use.
+    /// Memory claimed later is not affected. This takes time proportional to the size of
+    /// the free memory, and has no benefit where memory is always mapped in, e.g. on
+    /// embedded targets.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 100000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// talc.prefault();
+    /// ```
+    pub fn prefault(&mut self) {
+        self.for_each_free_interior(|base, acme| {
+            let mut ptr = base;
+
+            while ptr < acme {
+                // SAFETY: the memory is free and unused by the allocator's metadata
+                unsafe { ptr.write_volatile(FILL_BYTE) };
+
+                ptr = ptr.wrapping_add(PAGE_SIZE - (ptr as usize & (PAGE_SIZE - 1)));
+            }
+        });
+    }
+
+    /// Like [`Talc::prefault`], but zero all free memory instead of a byte per page.
+    ///
+    /// Free memory isn't kept zeroed, so this doesn't make future allocations zeroed.
+    /// This is useful where freshly mapped pages may not already be zeroed, or to
+    /// clear stale data out of free memory.
+    ///
+    /// With the `poison` feature, free memory is filled with the pattern instead.
+    pub fn prefault_zeroed(&mut self) {
+        self.for_each_free_interior(|base, acme| {
+            // SAFETY: the memory is free and unused by the allocator's metadata
+            unsafe { base.write_bytes(FILL_BYTE, acme as usize - base as usize) };
+        });
+    }
+
+    /// Calls `f` with the base and acme of the memory in each free chunk
+    /// that isn't used to keep track of the chunk.
+    fn for_each_free_interior(&mut self, mut f: impl FnMut(*mut u8, *mut u8)) {
+        if self.bins.is_null() {
+            return;
+        }
+
+        for b in 0..BIN_COUNT {
+            // SAFETY: bins are established, and all registered gaps are valid free chunks
+            unsafe {
+                for node in LlistNode::iter_mut(*self.get_bin_ptr(b)) {
+                    let base = gap_node_to_base(node);
+                    let size = gap_node_to_size(node).read();
+
+                    let interior_base = base.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE);
+                    let interior_acme = base.add(size - GAP_HIGH_SIZE_OFFSET);
+
+                    if interior_base < interior_acme {
+                        #[cfg(feature = "sanitize")]
+                        super::sanitize::unpoison(interior_base, interior_acme);
+
+                        f(interior_base, interior_acme);
+
+                        #[cfg(feature = "sanitize")]
+                        super::sanitize::poison_gap(base, base.add(size));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::*;
+
+    #[test]
+    fn prefault_test() {
+        let mut arena = [0xFFu8; 100000];
+        let arena_ptr = arena.as_mut_ptr();
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(Span::from_base_size(arena_ptr, arena.len())).unwrap() };
+
+        let report = talc.report();
+        talc.prefault();
+        assert_eq!(talc.report(), report);
+
+        talc.prefault_zeroed();
+        assert_eq!(talc.report(), report);
+
+        // all but the free chunk's metadata is zeroed
+        // (the free memory is poisoned under the sanitize feature, so don't peek at it)
+        #[cfg(not(feature = "sanitize"))]
+        {
+            let zeroed =
+                (0..100000).filter(|&i| unsafe { *arena_ptr.add(i) } == super::FILL_BYTE).count();
+            assert!(zeroed >= report.free_bytes - 4 * core::mem::size_of::<usize>());
+        }
+    }
+}
@@ -0,0 +1,248 @@
+//! Hold freed chunks back from reuse for a while, to catch and contain use-after-free.
+//!
+//! Quarantined chunks stay tagged as allocated, so they aren't coalesced or allocated
+//! out of. They're queued first-in first-out, linked through the first word of the
+//! allocation, and freed for real once enough further frees have been made or too many
+//! bytes are held. With the `poison` feature, the rest of a quarantined chunk is poisoned
+//! as it's queued, and checked as it's released.
+
+use core::ptr::null_mut;
+
+use super::{tag::Tag, Talc, HEADER_SIZE, TAG_SIZE};
+#[cfg(any(feature = "poison", feature = "sanitize"))]
+use crate::ptr_utils::WORD_SIZE;
+use crate::OomHandler;
+
+/// A queue of freed chunks, identified by the pointers to their tags.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct Quarantine {
+    /// The tag pointer of the least recently quarantined chunk, or null.
+    oldest: *mut u8,
+    /// The tag pointer of the most recently quarantined chunk, or null.
+    newest: *mut u8,
+    len: usize,
+    bytes: usize,
+    max_len: usize,
+    max_bytes: usize,
+}
+
+impl Quarantine {
+    pub(super) const OFF: Self =
+        Self { oldest: null_mut(), newest: null_mut(), len: 0, bytes: 0, max_len: 0, max_bytes: 0 };
+}
+
+/// Returns a pointer to where the link to the next newer quarantined chunk is stored.
+#[inline]
+unsafe fn link_ptr(tag_ptr: *mut u8) -> *mut *mut u8 {
+    tag_ptr.cast::<Tag>().read().chunk_base().add(HEADER_SIZE).cast()
+}
+
+/// Returns the size of the chunk with the tag at `tag_ptr`.
+#[inline]
+unsafe fn chunk_size(tag_ptr: *mut u8) -> usize {
+    tag_ptr.add(TAG_SIZE) as usize - tag_ptr.cast::<Tag>().read().chunk_base() as usize
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Quarantines the allocated chunk instead of freeing it, returning whether it was.
+    ///
+    /// The oldest chunks are freed as necessary to stay within the limits.
+    #[inline]
+    pub(super) unsafe fn push_quarantine(&mut self, tag_ptr: *mut u8, tag: Tag) -> bool {
+        let size = tag_ptr.add(TAG_SIZE) as usize - tag.chunk_base() as usize;
+        if self.quarantine.max_len == 0 || size > self.quarantine.max_bytes {
+            return false;
+        }
+
+        #[cfg(feature = "generations")]
+        {
+            (*super::header_from_chunk_base(tag.chunk_base())).generation = 0;
+        }
+
+        let link = link_ptr(tag_ptr);
+
+        #[cfg(feature = "poison")]
+        super::poison::poison(link.cast::<u8>().add(WORD_SIZE), tag_ptr);
+        #[cfg(feature = "sanitize")]
+        super::sanitize::poison(link.cast::<u8>().add(WORD_SIZE), tag_ptr);
+
+        link.write(null_mut());
+        if self.quarantine.newest.is_null() {
+            self.quarantine.oldest = tag_ptr;
+        } else {
+            link_ptr(self.quarantine.newest).write(tag_ptr);
+        }
+        self.quarantine.newest = tag_ptr;
+        self.quarantine.len += 1;
+        self.quarantine.bytes += size;
+
+        while self.quarantine.len > self.quarantine.max_len
+            || self.quarantine.bytes > self.quarantine.max_bytes
+        {
+            self.release_oldest();
+        }
+
+        #[cfg(feature = "counters")]
+        self.publish_live_stats();
+
+        true
+    }
+
+    /// Frees the least recently quarantined chunk, returning whether there was one.
+    fn release_oldest(&mut self) -> bool {
+        let tag_ptr = self.quarantine.oldest;
+        if tag_ptr.is_null() {
+            return false;
+        }
+
+        // SAFETY: the quarantine only contains chunks that were allocated and freed
+        unsafe {
+            let link = link_ptr(tag_ptr);
+            let size = chunk_size(tag_ptr);
+
+            #[cfg(feature = "sanitize")]
+            super::sanitize::unpoison(link.cast(), tag_ptr);
+            #[cfg(feature = "poison")]
+            verify(link.cast(), tag_ptr);
+
+            self.quarantine.oldest = link.read();
+            if self.quarantine.oldest.is_null() {
+                self.quarantine.newest = null_mut();
+            }
+            self.quarantine.len -= 1;
+            self.quarantine.bytes -= size;
+
+            self.free_chunk(tag_ptr, tag_ptr.cast::<Tag>().read());
+        }
+
+        true
+    }
+
+    /// Hold freed chunks back from reuse until `max_frees` further frees have been made,
+    /// keeping at most `max_bytes` of chunks held, or turn the quarantine off if either is zero.
+    ///
+    /// With the `quarantine` feature, the quarantine is off by default. While on, memory
+    /// isn't handed out again immediately after being freed, so dangling pointers are less
+    /// likely to alias new allocations, and the `poison` feature has longer to catch writes
+    /// through them. Chunks larger than `max_bytes` are freed immediately.
+    ///
+    /// Quarantined chunks are considered allocated by functions such as
+    /// [`get_allocated_span`](Talc::get_allocated_span) and [`report`](Talc::report),
+    /// until released. Chunks beyond the new limits are released immediately.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// talc.set_quarantine(4, 4096);
+    ///
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// unsafe {
+    ///     let a = talc.malloc(layout).unwrap();
+    ///     talc.free(a, layout);
+    ///
+    ///     // the freed memory isn't reused straight away
+    ///     let b = talc.malloc(layout).unwrap();
+    ///     assert_ne!(a, b);
+    ///     talc.free(b, layout);
+    /// }
+    /// ```
+    pub fn set_quarantine(&mut self, max_frees: usize, max_bytes: usize) {
+        if max_frees == 0 || max_bytes == 0 {
+            self.flush_quarantine();
+            self.quarantine.max_len = 0;
+            self.quarantine.max_bytes = 0;
+            return;
+        }
+
+        self.quarantine.max_len = max_frees;
+        self.quarantine.max_bytes = max_bytes;
+
+        while self.quarantine.len > self.quarantine.max_len
+            || self.quarantine.bytes > self.quarantine.max_bytes
+        {
+            self.release_oldest();
+        }
+    }
+
+    /// Free all quarantined chunks, returning whether there were any.
+    ///
+    /// This happens automatically before the OOM handler is called.
+    pub fn flush_quarantine(&mut self) -> bool {
+        let mut flushed = false;
+
+        while self.release_oldest() {
+            flushed = true;
+        }
+
+        flushed
+    }
+}
+
+/// Check that the quarantined chunk from `alloc_base` to `tag_ptr` still holds the
+/// pattern, past the link.
+///
+/// # Panics
+/// Panics with the offset of the first modified byte from `alloc_base`.
+#[cfg(feature = "poison")]
+unsafe fn verify(alloc_base: *mut u8, tag_ptr: *mut u8) {
+    let mut ptr = alloc_base.add(WORD_SIZE);
+    while ptr < tag_ptr {
+        if ptr.read() != super::poison::POISON_BYTE {
+            let offset = ptr as usize - alloc_base as usize;
+            panic!("heap used after free at offset {} of {:p}", offset, alloc_base);
+        }
+        ptr = ptr.add(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::NonNull};
+
+    use crate::*;
+
+    #[test]
+    fn quarantine_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let initial = talc.report();
+
+        talc.set_quarantine(2, 1000);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let mut ptrs = [NonNull::dangling(); 4];
+
+        unsafe {
+            for ptr in ptrs.iter_mut() {
+                *ptr = talc.malloc(layout).unwrap();
+            }
+            let free_bytes = talc.report().free_bytes;
+
+            // held back until two further frees have been made
+            talc.free(ptrs[0], layout);
+            talc.free(ptrs[1], layout);
+            assert_eq!(talc.report().free_bytes, free_bytes);
+            talc.free(ptrs[2], layout);
+            assert!(talc.report().free_bytes > free_bytes);
+
+            // chunks beyond the byte limit aren't held
+            let big = Layout::from_size_align(2000, 8).unwrap();
+            let b = talc.malloc(big).unwrap();
+            let before = talc.report().free_bytes;
+            talc.free(b, big);
+            assert!(talc.report().free_bytes > before);
+
+            talc.free(ptrs[3], layout);
+        }
+
+        assert!(talc.flush_quarantine());
+        assert!(!talc.flush_quarantine());
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+    }
+}
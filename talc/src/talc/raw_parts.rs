@@ -0,0 +1,202 @@
+//! Rebuild a Talc over heaps that outlived it, such as across a warm reboot.
+
+use super::{gap_acme_to_base, is_gap_below, tag::Tag, Bin, Talc, BIN_COUNT, TAG_SIZE};
+use crate::{OomHandler, Span};
+
+impl<O: OomHandler> Talc<O> {
+    /// Decompose the allocator into the pointer to its metadata, and its OOM handler.
+    ///
+    /// The rest of the allocator's bookkeeping is kept within its heaps, so it can be
+    /// rebuilt from these with [`Talc::from_raw_parts`]. The pointer is null if no heap
+    /// has been established.
+    pub fn into_raw_parts(self) -> (*mut u8, O) {
+        (self.bins.cast(), self.oom_handler)
+    }
+
+    /// Rebuild an allocator over `heaps`, which are intact along with their allocations,
+    /// given the pointer to its metadata as returned by [`Talc::into_raw_parts`] or
+    /// [`Talc::arena_base`].
+    ///
+    /// This lets a warm boot keep using a heap that survived in RAM, instead of throwing
+    /// it away. The metadata pointer can be kept in memory that isn't initialized on boot,
+    /// or recomputed, as it's the same each time a given first heap is claimed.
+    ///
+    /// Options such as the bin scan limit take their defaults, as with [`Talc::new`].
+    /// The `counters` are rebuilt from the heaps. Without the `owners` feature, the sizes
+    /// of the allocations aren't known, so those of their chunks are counted instead,
+    /// overestimating the allocated bytes. Chunks cached by the `fastbins` feature or
+    /// quarantined by the `quarantine` feature, and the `emergency_reserve`, aren't known
+    /// to be free, and stay allocated.
+    ///
+    /// # Safety
+    /// - `metadata` must be null, or the allocator's metadata pointer of the allocator
+    ///   that established `heaps`.
+    /// - `heaps` must be all of the allocator's heaps, as returned by the last
+    ///   heap-manipulation function called on each, and they mustn't have been modified
+    ///   other than through the allocations since.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+    ///
+    /// let layout = Layout::new::<[u64; 4]>();
+    /// let ptr = unsafe { talc.malloc(layout).unwrap() };
+    ///
+    /// // e.g. the metadata pointer and heap are stored in memory that survives a reset
+    /// let (metadata, oom_handler) = talc.into_raw_parts();
+    ///
+    /// let mut talc = unsafe { Talc::from_raw_parts(metadata, &[heap], oom_handler) };
+    /// unsafe { talc.free(ptr, layout); }
+    /// ```
+    pub unsafe fn from_raw_parts(metadata: *mut u8, heaps: &[Span], oom_handler: O) -> Self {
+        let mut talc = Self::new(oom_handler);
+
+        if metadata.is_null() {
+            return talc;
+        }
+
+        talc.bins = metadata.cast::<Bin>();
+
+        for b in 0..BIN_COUNT {
+            if (*talc.get_bin_ptr(b)).is_some() {
+                talc.set_avails(b);
+            }
+        }
+
+        for &heap in heaps {
+            talc.restore_heap(heap);
+        }
+
+        talc.scan_for_errors();
+
+        talc
+    }
+
+    /// Rebuilds the state kept outside of `heap` by walking its chunks, top-down.
+    unsafe fn restore_heap(&mut self, heap: Span) {
+        let (base, acme) = match heap.get_base_acme() {
+            Some(base_acme) => base_acme,
+            None => return,
+        };
+
+        #[cfg(feature = "counters")]
+        self.counters.account_claim(heap.size());
+
+        let mut cursor = acme;
+        while cursor > base.add(TAG_SIZE) {
+            if is_gap_below(cursor) {
+                let chunk_base = gap_acme_to_base(cursor);
+                #[cfg(feature = "counters")]
+                self.counters.account_register_gap(cursor as usize - chunk_base as usize);
+                cursor = chunk_base;
+                continue;
+            }
+
+            let chunk_base = cursor.sub(TAG_SIZE).cast::<Tag>().read().chunk_base();
+            // the bins' chunk is the only chunk based at the heap's base
+            if chunk_base == base {
+                break;
+            }
+
+            self.restore_allocation(chunk_base, cursor);
+            cursor = chunk_base;
+        }
+    }
+
+    /// Accounts for the allocation in the chunk from `chunk_base` to `chunk_acme`.
+    unsafe fn restore_allocation(&mut self, chunk_base: *mut u8, chunk_acme: *mut u8) {
+        #[cfg(any(feature = "owners", feature = "generations"))]
+        let header = super::header_from_chunk_base(chunk_base).read();
+
+        #[cfg(feature = "owners")]
+        let size = header.size;
+        #[cfg(not(feature = "owners"))]
+        let size = chunk_acme as usize - chunk_base as usize - super::HEADER_SIZE - TAG_SIZE;
+
+        #[cfg(feature = "counters")]
+        self.counters.account_alloc(size);
+        #[cfg(feature = "owner_stats")]
+        self.account_owner_realloc(header.owner, 0, size);
+
+        // keep new generations from repeating those of live allocations
+        #[cfg(feature = "generations")]
+        if header.generation.wrapping_sub(self.next_generation) < usize::MAX / 2 {
+            self.next_generation = header.generation.wrapping_add(2);
+        }
+
+        #[cfg(not(feature = "counters"))]
+        let _ = size;
+        #[cfg(feature = "owners")]
+        let _ = chunk_acme;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn raw_parts_test() {
+        let mut first = [0u8; 10000];
+        let mut second = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        let first = unsafe { talc.claim(first.as_mut().into()).unwrap() };
+        let second = unsafe { talc.claim(second.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(200, 8).unwrap();
+        let mut allocs = std::vec::Vec::new();
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            unsafe { ptr.as_ptr().write_bytes(0xab, layout.size()) };
+            allocs.push(ptr);
+        }
+        for ptr in allocs.iter().step_by(2) {
+            unsafe { talc.free(*ptr, layout) };
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        let report = talc.report();
+        #[cfg(feature = "counters")]
+        let counters = *talc.get_counters();
+
+        let (metadata, oom_handler) = talc.into_raw_parts();
+        let mut talc = unsafe { Talc::from_raw_parts(metadata, &[first, second], oom_handler) };
+        assert_eq!(talc.validate(), Ok(()));
+
+        #[cfg_attr(not(feature = "counters"), allow(unused_mut))]
+        let mut restored = talc.report();
+        #[cfg(feature = "counters")]
+        {
+            // without owners, the allocations' sizes are approximated by their chunks'
+            assert!(restored.allocated_bytes >= report.allocated_bytes);
+            #[cfg(feature = "owners")]
+            assert_eq!(restored.allocated_bytes, report.allocated_bytes);
+            restored.allocated_bytes = report.allocated_bytes;
+
+            assert_eq!(talc.get_counters().available_bytes, counters.available_bytes);
+            assert_eq!(talc.get_counters().fragment_count, counters.fragment_count);
+        }
+        assert_eq!(restored, report);
+
+        // the allocations are intact and can be freed, after which the heaps are whole again
+        for ptr in allocs.iter().skip(1).step_by(2) {
+            assert_eq!(unsafe { *ptr.as_ptr() }, 0xab);
+            unsafe { talc.free(*ptr, layout) };
+        }
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report().free_chunk_count, 2);
+
+        let empty = Talc::new(ErrOnOom);
+        let (metadata, oom_handler) = empty.into_raw_parts();
+        assert!(metadata.is_null());
+        let empty = unsafe { Talc::from_raw_parts(metadata, &[], oom_handler) };
+        assert_eq!(empty.arena_base(), None);
+    }
+}
@@ -0,0 +1,187 @@
+//! Move an allocation within its chunk to satisfy a stricter alignment.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use super::{tag_from_alloc_ptr, Talc, HEADER_SIZE};
+use crate::{
+    ptr_utils::{align_up, align_up_by},
+    OomHandler,
+};
+
+impl<O: OomHandler> Talc<O> {
+    /// Attempt to realign an allocation to `align` without reallocating it.
+    ///
+    /// The contents are moved within the allocation's chunk, into slack left below or
+    /// above the allocation, else the chunk is grown in place into the free memory above
+    /// to make room. On success, returns the new pointer, which must be used with a layout
+    /// of the same size and an alignment of `align` from then on. If `ptr` is already
+    /// aligned, it's returned as-is.
+    ///
+    /// Returns `Err` if there isn't enough room, if `align` isn't a power of two,
+    /// or if the allocation is pinned. The allocation is untouched in that case.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given `layout`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::from_size_align(100, 1).unwrap();
+    /// unsafe {
+    ///     let buffer = talc.malloc(layout).unwrap();
+    ///     buffer.as_ptr().write_bytes(7, layout.size());
+    ///
+    ///     // having parsed the buffer, it turns out it needs 64-byte alignment
+    ///     let buffer = talc.realign_in_place(buffer, layout, 64).unwrap();
+    ///     assert_eq!(buffer.as_ptr() as usize % 64, 0);
+    ///     assert_eq!(buffer.as_ptr().add(99).read(), 7);
+    ///
+    ///     talc.free(buffer, Layout::from_size_align(100, 64).unwrap());
+    /// }
+    /// ```
+    pub unsafe fn realign_in_place(
+        &mut self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        align: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        let result = self.realign_untraced(ptr, layout, align);
+
+        #[cfg(feature = "trace")]
+        self.trace(super::trace::TraceEntry::Realign {
+            ptr: ptr.as_ptr() as usize,
+            layout,
+            align,
+            new_ptr: result.map_or(0, |ptr| ptr.as_ptr() as usize),
+        });
+
+        result
+    }
+
+    #[inline]
+    unsafe fn realign_untraced(
+        &mut self,
+        ptr: NonNull<u8>,
+        layout: Layout,
+        align: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        if !align.is_power_of_two() {
+            return Err(());
+        }
+
+        let align_mask = align - 1;
+        if ptr.as_ptr() as usize & align_mask == 0 {
+            return Ok(ptr);
+        }
+
+        if !self.is_movable(ptr, layout.size()) {
+            return Err(());
+        }
+
+        self.scan_for_errors();
+
+        let size = layout.size();
+        let (tag_ptr, tag) = tag_from_alloc_ptr(ptr.as_ptr(), size);
+        let chunk_base = tag.chunk_base();
+
+        let below = ptr.as_ptr().wrapping_sub(ptr.as_ptr() as usize & align_mask);
+        let above = align_up_by(ptr.as_ptr(), align_mask);
+        let shift = above as usize - ptr.as_ptr() as usize;
+
+        let new_ptr = if below >= chunk_base.add(HEADER_SIZE) {
+            below
+        } else if align_up(above.add(size)) <= tag_ptr {
+            above
+        } else {
+            // the allocation ends where the grown allocation does, so the tag needn't change
+            self.grow_in_place_unlogged(ptr, layout, size.checked_add(shift).ok_or(())?)?;
+            ptr.as_ptr().copy_to(above, size);
+            self.account_shrink(chunk_base, size + shift, size);
+
+            #[cfg(feature = "generations")]
+            above.cast::<*mut u8>().sub(1).write(chunk_base);
+
+            return Ok(NonNull::new_unchecked(above));
+        };
+
+        ptr.as_ptr().copy_to(new_ptr, size);
+
+        // point the word after the allocation to the tag, as it's moved
+        let post_alloc_ptr = align_up(new_ptr.add(size));
+        if post_alloc_ptr != tag_ptr {
+            post_alloc_ptr.cast::<*mut u8>().write(tag_ptr);
+        }
+
+        #[cfg(feature = "generations")]
+        new_ptr.cast::<*mut u8>().sub(1).write(chunk_base);
+
+        Ok(NonNull::new_unchecked(new_ptr))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, mem::size_of};
+
+    use crate::*;
+
+    #[test]
+    fn realign_in_place_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            a.as_ptr().write_bytes(0xAB, layout.size());
+
+            assert!(talc.realign_in_place(a, layout, 3).is_err());
+            assert_eq!(talc.realign_in_place(a, layout, 8), Ok(a));
+
+            // grows into the free memory above
+            let a = talc.realign_in_place(a, layout, 256).unwrap();
+            assert_eq!(a.as_ptr() as usize % 256, 0);
+            assert!((0..100).all(|i| a.as_ptr().add(i).read() == 0xAB));
+            let layout = Layout::from_size_align(100, 256).unwrap();
+
+            // the memory above is allocated, and the slack below is too small
+            let blocker = talc.malloc(Layout::new::<usize>()).unwrap();
+            let align = 1 << ((a.as_ptr() as usize).trailing_zeros() + 1);
+            assert!(talc.realign_in_place(a, layout, align).is_err());
+            assert!((0..100).all(|i| a.as_ptr().add(i).read() == 0xAB));
+
+            // find an allocation that's misaligned by a word
+            let word = size_of::<usize>();
+            let b_layout = Layout::from_size_align(10 * word, word).unwrap();
+            let b = loop {
+                let b = talc.malloc(b_layout).unwrap();
+                if b.as_ptr() as usize % (2 * word) == word {
+                    break b;
+                }
+                talc.malloc(Layout::new::<u8>()).unwrap();
+            };
+            talc.malloc(Layout::new::<usize>()).unwrap();
+            b.as_ptr().write_bytes(0xCD, b_layout.size());
+
+            // leave slack above it, which is used
+            talc.shrink(b, b_layout, 9 * word);
+            let b_layout = Layout::from_size_align(9 * word, word).unwrap();
+            let b = talc.realign_in_place(b, b_layout, 2 * word).unwrap();
+            assert!((0..9 * word).all(|i| b.as_ptr().add(i).read() == 0xCD));
+
+            // the chunks are intact
+            let a = talc.grow(a, layout, 200).unwrap();
+            assert!((0..100).all(|i| a.as_ptr().add(i).read() == 0xAB));
+            talc.free(a, Layout::from_size_align(200, 256).unwrap());
+            talc.free(b, Layout::from_size_align(9 * word, 2 * word).unwrap());
+            talc.free(blocker, Layout::new::<usize>());
+        }
+    }
+}
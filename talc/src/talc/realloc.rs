@@ -0,0 +1,153 @@
+//! Reallocate to an arbitrary layout, handling size and alignment changes together.
+
+use core::{alloc::Layout, cmp::Ordering, ptr::NonNull};
+
+use super::Talc;
+use crate::{
+    ptr_utils::{dangling_for, is_aligned_to},
+    OomHandler,
+};
+
+impl<O: OomHandler> Talc<O> {
+    /// Reallocate a previously allocated/reallocated region of memory to `new_layout`,
+    /// growing, shrinking, or realigning it as necessary.
+    ///
+    /// The allocation is kept in place if its chunk permits: a stricter alignment is
+    /// first satisfied with [`realign_in_place`](Talc::realign_in_place), then the allocation
+    /// is grown in place or shrunk. Otherwise, the contents are moved to a new allocation,
+    /// up to the smaller of the two sizes.
+    ///
+    /// Returns `Err` if the allocation needed to move and couldn't, in which case it's
+    /// left as it was, though possibly realigned, such that it's still valid given `old_layout`.
+    ///
+    /// Reallocating to or from a zero-sized layout frees or allocates anew, as zero-sized
+    /// allocations don't use the heap, see [`malloc`](Talc::malloc).
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given `old_layout`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let old_layout = Layout::from_size_align(100, 1).unwrap();
+    /// let new_layout = Layout::from_size_align(200, 64).unwrap();
+    /// unsafe {
+    ///     let ptr = talc.malloc(old_layout).unwrap();
+    ///     ptr.as_ptr().write_bytes(7, old_layout.size());
+    ///
+    ///     let ptr = talc.realloc(ptr, old_layout, new_layout).unwrap();
+    ///     assert_eq!(ptr.as_ptr() as usize % 64, 0);
+    ///     assert_eq!(ptr.as_ptr().add(99).read(), 7);
+    ///
+    ///     talc.free(ptr, new_layout);
+    /// }
+    /// ```
+    pub unsafe fn realloc(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, ()> {
+        if old_layout.size() == 0 {
+            return self.malloc(new_layout);
+        } else if new_layout.size() == 0 {
+            self.free(ptr, old_layout);
+            return Ok(dangling_for(new_layout));
+        }
+
+        let mut ptr = ptr;
+        let mut layout = old_layout;
+
+        if !is_aligned_to(ptr.as_ptr(), new_layout.align()) {
+            match self.realign_in_place(ptr, layout, new_layout.align()) {
+                Ok(realigned) => {
+                    ptr = realigned;
+                    layout = Layout::from_size_align_unchecked(layout.size(), new_layout.align());
+                }
+                Err(()) => return self.realloc_by_moving(ptr, layout, new_layout),
+            }
+        }
+
+        match new_layout.size().cmp(&layout.size()) {
+            Ordering::Greater => match self.grow_in_place(ptr, layout, new_layout.size()) {
+                Ok(ptr) => Ok(ptr),
+                Err(()) => self.realloc_by_moving(ptr, layout, new_layout),
+            },
+            Ordering::Less => {
+                self.shrink(ptr, layout, new_layout.size());
+                Ok(ptr)
+            }
+            Ordering::Equal => Ok(ptr),
+        }
+    }
+
+    /// Move the allocation to a new one of `new_layout`, unless it's pinned.
+    unsafe fn realloc_by_moving(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<u8>, ()> {
+        if !self.is_movable(ptr, old_layout.size()) {
+            return Err(());
+        }
+
+        let allocation = self.malloc(new_layout)?;
+        let size = old_layout.size().min(new_layout.size());
+        allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), size);
+        self.inherit_owner(ptr, old_layout.size(), allocation, new_layout.size());
+        self.free(ptr, old_layout);
+        self.account_moved_realloc(old_layout.size(), new_layout.size());
+
+        Ok(allocation)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn realloc_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let initial = talc.report();
+
+        let small = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let a = talc.malloc(small).unwrap();
+            a.as_ptr().write_bytes(0xAB, small.size());
+            let b = talc.malloc(small).unwrap();
+
+            // growing in place isn't possible with b above, so a moves
+            let large = Layout::from_size_align(1000, 8).unwrap();
+            let a2 = talc.realloc(a, small, large).unwrap();
+            assert_ne!(a2, a);
+            assert!((0..small.size()).all(|i| *a2.as_ptr().add(i) == 0xAB));
+
+            // shrinking and realigning moves the contents within the chunk where possible
+            let aligned = Layout::from_size_align(50, 256).unwrap();
+            let a3 = talc.realloc(a2, large, aligned).unwrap();
+            assert_eq!(a3.as_ptr() as usize % 256, 0);
+            assert!((0..aligned.size()).all(|i| *a3.as_ptr().add(i) == 0xAB));
+
+            // unchanged layouts are a no-op
+            assert_eq!(talc.realloc(b, small, small).unwrap(), b);
+
+            talc.free(a3, aligned);
+            talc.free(b, small);
+        }
+
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        assert_eq!(talc.report(), initial);
+    }
+}
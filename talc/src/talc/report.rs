@@ -0,0 +1,456 @@
+//! Summarize the state of Talc's heaps for logging and debugging.
+
+use super::{gap_node_to_base, gap_node_to_size, llist::LlistNode, Bin, Talc, BIN_COUNT};
+use crate::{OomHandler, Span};
+
+/// Returns the percentage of `free_bytes` not in the largest free chunk, or zero if
+/// there are no free bytes.
+fn fragmentation_percent(free_bytes: usize, largest_free_chunk: usize) -> usize {
+    match free_bytes {
+        0 => 0,
+        // widened, as multiplying by 100 overflows sizes above 1% of the address space
+        free => ((free - largest_free_chunk) as u128 * 100 / free as u128) as usize,
+    }
+}
+
+/// A compact summary of the allocator's state, as returned by [`Talc::report`].
+///
+/// The [`Display`](core::fmt::Display) implementation formats this as a single line.
+///
+/// Arena and allocation totals are only tracked when the `counters` feature is enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct HeapReport {
+    /// Sum of bytes actively claimed.
+    #[cfg(feature = "counters")]
+    pub claimed_bytes: usize,
+    /// Number of active established heaps.
+    #[cfg(feature = "counters")]
+    pub heap_count: usize,
+    /// Sum of active allocations' layouts' size.
+    #[cfg(feature = "counters")]
+    pub allocated_bytes: usize,
+    /// Number of active allocations.
+    #[cfg(feature = "counters")]
+    pub allocation_count: usize,
+
+    /// Bytes reserved for the allocator's bins. Zero if no heap has been established.
+    pub metadata_bytes: usize,
+    /// Sum of the sizes of all free chunks.
+    pub free_bytes: usize,
+    /// Number of free chunks.
+    pub free_chunk_count: usize,
+    /// Size of the largest free chunk, or zero if there are none.
+    pub largest_free_chunk: usize,
+    /// Number of bins containing at least one free chunk.
+    pub occupied_bins: usize,
+    /// Total number of bins.
+    pub bin_count: usize,
+}
+
+impl HeapReport {
+    /// Returns the percentage of free memory that's not in the largest free chunk,
+    /// being zero if the free memory is contiguous, or if there is none.
+    ///
+    /// This is a cheap indicator of the heaps' health: the higher it is, the larger the
+    /// share of free memory that can't satisfy large allocations.
+    pub fn fragmentation_percent(&self) -> usize {
+        fragmentation_percent(self.free_bytes, self.largest_free_chunk)
+    }
+}
+
+impl core::fmt::Display for HeapReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        #[cfg(feature = "counters")]
+        write!(
+            f,
+            "claimed {} B in {} heaps, allocated {} B in {} allocs, ",
+            self.claimed_bytes, self.heap_count, self.allocated_bytes, self.allocation_count
+        )?;
+
+        write!(
+            f,
+            "metadata {} B, free {} B in {} chunks (largest {} B, {}% fragmented), {}/{} bins occupied",
+            self.metadata_bytes,
+            self.free_bytes,
+            self.free_chunk_count,
+            self.largest_free_chunk,
+            self.fragmentation_percent(),
+            self.occupied_bins,
+            self.bin_count
+        )
+    }
+}
+
+/// The free chunks in a single bin, as returned by [`Talc::bin_histogram`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct BinOccupancy {
+    /// Number of free chunks in the bin.
+    pub free_chunk_count: usize,
+    /// Sum of the sizes of the free chunks in the bin.
+    pub free_bytes: usize,
+    /// Size of the largest free chunk in the bin, or zero if there are none.
+    pub largest_free_chunk: usize,
+}
+
+/// A summary of a single heap, as returned by [`Talc::region_report`].
+///
+/// The [`Display`](core::fmt::Display) implementation formats this as a single line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct RegionReport {
+    /// The heap summarized.
+    pub heap: Span,
+    /// Bytes of the heap not in free chunks, including allocations, their overhead,
+    /// and the allocator's metadata if it's in this heap.
+    pub used_bytes: usize,
+    /// Sum of the sizes of the free chunks in the heap.
+    pub free_bytes: usize,
+    /// Number of free chunks in the heap.
+    pub free_chunk_count: usize,
+    /// Size of the largest free chunk in the heap, or zero if there are none.
+    pub largest_free_chunk: usize,
+}
+
+impl RegionReport {
+    /// Returns the percentage of free memory that's not in the largest free chunk,
+    /// being zero if the free memory is contiguous, or if there is none.
+    pub fn fragmentation_percent(&self) -> usize {
+        fragmentation_percent(self.free_bytes, self.largest_free_chunk)
+    }
+}
+
+impl core::fmt::Display for RegionReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{}: used {} B, free {} B in {} chunks (largest {} B, {}% fragmented)",
+            self.heap,
+            self.used_bytes,
+            self.free_bytes,
+            self.free_chunk_count,
+            self.largest_free_chunk,
+            self.fragmentation_percent()
+        )
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Summarize the allocator's free memory, metadata and bin occupancy.
+    ///
+    /// This walks every free chunk, so it takes time proportional to the fragmentation
+    /// of the heaps. Allocation and arena totals are included if the `counters` feature
+    /// is enabled.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let report = talc.report();
+    /// assert_eq!(report.free_chunk_count, 1);
+    /// println!("heap: {}", report);
+    /// ```
+    pub fn report(&self) -> HeapReport {
+        let mut report = HeapReport { bin_count: BIN_COUNT, ..Default::default() };
+
+        #[cfg(feature = "counters")]
+        {
+            report.claimed_bytes = self.counters.claimed_bytes;
+            report.heap_count = self.counters.heap_count;
+            report.allocated_bytes = self.counters.allocated_bytes;
+            report.allocation_count = self.counters.allocation_count;
+        }
+
+        if self.bins.is_null() {
+            return report;
+        }
+
+        report.metadata_bytes = core::mem::size_of::<Bin>() * BIN_COUNT;
+
+        for b in 0..BIN_COUNT {
+            let mut occupied = false;
+
+            // SAFETY: bins are established, and all registered gaps are valid free chunks
+            unsafe {
+                for node in LlistNode::iter_mut(*self.get_bin_ptr(b)) {
+                    let size = gap_node_to_size(node).read();
+
+                    occupied = true;
+                    report.free_bytes += size;
+                    report.free_chunk_count += 1;
+                    report.largest_free_chunk = report.largest_free_chunk.max(size);
+                }
+            }
+
+            report.occupied_bins += occupied as usize;
+        }
+
+        report
+    }
+
+    /// Returns the percentage of free memory that's not in the largest free chunk,
+    /// as given by [`HeapReport::fragmentation_percent`].
+    ///
+    /// This doesn't allocate, but walks every free chunk, like [`Talc::report`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    /// assert_eq!(talc.fragmentation_percent(), 0);
+    ///
+    /// // a free chunk is left below an allocation
+    /// let layout = Layout::new::<[u8; 1000]>();
+    /// let a = unsafe { talc.malloc(layout).unwrap() };
+    /// let b = unsafe { talc.malloc(layout).unwrap() };
+    /// unsafe { talc.free(a, layout) };
+    /// assert!(talc.fragmentation_percent() > 0);
+    /// ```
+    pub fn fragmentation_percent(&self) -> usize {
+        self.report().fragmentation_percent()
+    }
+
+    /// Fill `histogram` with the free chunks in each bin, indexed by bin.
+    ///
+    /// Chunks are binned by size, use [`bin_of_size`](crate::bin_of_size) to find which
+    /// bin a chunk size belongs to. This shows how the free memory is distributed,
+    /// e.g. to tell why a large allocation fails despite plenty of free memory in total.
+    /// This walks every free chunk, like [`Talc::report`]. Chunks cached by the `fastbins`
+    /// feature aren't counted.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let mut histogram = [BinOccupancy::default(); BIN_COUNT];
+    /// talc.bin_histogram(&mut histogram);
+    ///
+    /// let occupied = histogram.iter().filter(|bin| bin.free_chunk_count != 0).count();
+    /// assert_eq!(occupied, talc.report().occupied_bins);
+    /// ```
+    pub fn bin_histogram(&self, histogram: &mut [BinOccupancy; BIN_COUNT]) {
+        *histogram = [BinOccupancy::default(); BIN_COUNT];
+
+        if self.bins.is_null() {
+            return;
+        }
+
+        for (b, occupancy) in histogram.iter_mut().enumerate() {
+            // SAFETY: bins are established, and all registered gaps are valid free chunks
+            unsafe {
+                for node in LlistNode::iter_mut(*self.get_bin_ptr(b)) {
+                    let size = gap_node_to_size(node).read();
+
+                    occupancy.free_chunk_count += 1;
+                    occupancy.free_bytes += size;
+                    occupancy.largest_free_chunk = occupancy.largest_free_chunk.max(size);
+                }
+            }
+        }
+    }
+
+    /// Summarize the used and free memory of a single heap, such as one of several
+    /// memory regions claimed, to tell whether that region specifically is exhausted.
+    ///
+    /// This walks every free chunk, like [`Talc::report`]. Chunks cached by the `fastbins`
+    /// feature count as used.
+    ///
+    /// # Safety
+    /// `heap` must be the return value of a heap-manipulation function
+    /// of this allocator instance.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut sram = [0u8; 10000];
+    /// let mut sdram = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let sram = unsafe { talc.claim(sram.as_mut().into()).unwrap() };
+    /// let sdram = unsafe { talc.claim(sdram.as_mut().into()).unwrap() };
+    ///
+    /// let report = unsafe { talc.region_report(sdram) };
+    /// assert_eq!(report.free_chunk_count, 1);
+    /// println!("sdram: {}", report);
+    /// ```
+    pub unsafe fn region_report(&self, heap: Span) -> RegionReport {
+        let mut report = RegionReport { heap, ..Default::default() };
+
+        if !self.bins.is_null() {
+            for b in 0..BIN_COUNT {
+                for node in LlistNode::iter_mut(*self.get_bin_ptr(b)) {
+                    if !heap.contains(gap_node_to_base(node)) {
+                        continue;
+                    }
+
+                    let size = gap_node_to_size(node).read();
+                    report.free_bytes += size;
+                    report.free_chunk_count += 1;
+                    report.largest_free_chunk = report.largest_free_chunk.max(size);
+                }
+            }
+        }
+
+        report.used_bytes = heap.size() - report.free_bytes;
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn report_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+
+        let empty = talc.report();
+        assert_eq!(empty.metadata_bytes, 0);
+        assert_eq!(empty.free_chunk_count, 0);
+        assert_eq!(empty.occupied_bins, 0);
+
+        let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let initial = talc.report();
+        assert!(initial.metadata_bytes > 0);
+        assert_eq!(initial.free_chunk_count, 1);
+        assert_eq!(initial.occupied_bins, 1);
+        assert_eq!(initial.largest_free_chunk, initial.free_bytes);
+        assert!(initial.free_bytes + initial.metadata_bytes <= heap.size());
+
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        let a = unsafe { talc.malloc(layout).unwrap() };
+        let b = unsafe { talc.malloc(layout).unwrap() };
+        unsafe { talc.free(a, layout) };
+
+        let fragmented = talc.report();
+        assert_eq!(fragmented.free_chunk_count, 2);
+        assert!(fragmented.largest_free_chunk < fragmented.free_bytes);
+        assert!(fragmented.free_bytes < initial.free_bytes);
+
+        #[cfg(feature = "counters")]
+        {
+            assert_eq!(fragmented.claimed_bytes, heap.size());
+            assert_eq!(fragmented.allocation_count, 1);
+            assert_eq!(fragmented.allocated_bytes, layout.size());
+        }
+
+        assert_eq!(initial.fragmentation_percent(), 0);
+        assert!(fragmented.fragmentation_percent() > 0);
+        assert_eq!(talc.fragmentation_percent(), fragmented.fragmentation_percent());
+
+        let line = std::format!("{}", fragmented);
+        assert!(!line.contains('\n'));
+        assert!(line.contains("2 chunks"));
+
+        unsafe { talc.free(b, layout) };
+        assert_eq!(talc.report(), initial);
+    }
+    #[test]
+    fn fragmentation_percent_test() {
+        let report = HeapReport {
+            free_bytes: usize::MAX,
+            largest_free_chunk: usize::MAX / 4,
+            ..Default::default()
+        };
+        assert_eq!(report.fragmentation_percent(), 75);
+        assert_eq!(HeapReport::default().fragmentation_percent(), 0);
+    }
+
+    #[test]
+    fn bin_histogram_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        let mut histogram = [BinOccupancy::default(); BIN_COUNT];
+
+        talc.bin_histogram(&mut histogram);
+        assert!(histogram.iter().all(|bin| *bin == BinOccupancy::default()));
+
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        // free every other allocation, leaving small chunks behind
+        let small = Layout::from_size_align(100, 8).unwrap();
+        let allocs: std::vec::Vec<_> =
+            (0..20).map(|_| unsafe { talc.malloc(small).unwrap() }).collect();
+        for ptr in allocs.iter().step_by(2) {
+            unsafe { talc.free(*ptr, small) };
+        }
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+
+        talc.bin_histogram(&mut histogram);
+        let report = talc.report();
+        assert_eq!(
+            histogram.iter().map(|bin| bin.free_chunk_count).sum::<usize>(),
+            report.free_chunk_count
+        );
+        assert_eq!(histogram.iter().map(|bin| bin.free_bytes).sum::<usize>(), report.free_bytes);
+        assert_eq!(
+            histogram.iter().map(|bin| bin.largest_free_chunk).max(),
+            Some(report.largest_free_chunk)
+        );
+
+        // the freed chunks share a bin
+        let small_bin = bin_of_size(required_chunk_size(small.size()));
+        assert_eq!(histogram[small_bin].free_chunk_count, 10);
+
+        for ptr in allocs.iter().skip(1).step_by(2) {
+            unsafe { talc.free(*ptr, small) };
+        }
+    }
+
+    #[test]
+    fn region_report_test() {
+        let mut arena = [0u8; 20000];
+        let arena = Span::from(arena.as_mut());
+        let mut talc = Talc::new(ErrOnOom);
+
+        let first = unsafe { talc.claim(arena.truncate(0, 10000)).unwrap() };
+        let second = unsafe { talc.claim(arena.truncate(10000, 0)).unwrap() };
+
+        // the first holds the metadata
+        let (a, b) = unsafe { (talc.region_report(first), talc.region_report(second)) };
+        assert!(a.used_bytes > b.used_bytes);
+        assert_eq!(a.used_bytes + a.free_bytes, first.size());
+        assert_eq!((a.free_chunk_count, b.free_chunk_count), (1, 1));
+        assert_eq!(b.fragmentation_percent(), 0);
+
+        // fragment the first, and free up the second
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let mut allocs = std::vec::Vec::new();
+        while let Ok(ptr) = unsafe { talc.malloc(layout) } {
+            allocs.push(ptr);
+        }
+        for (i, &ptr) in allocs.iter().enumerate() {
+            if second.contains(ptr.as_ptr()) || i % 2 == 0 {
+                unsafe { talc.free(ptr, layout) };
+            }
+        }
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+
+        // the free chunks are split up by allocations, but the last may have merged with the
+        // remainder of the heap, which is too small for another allocation
+        let chunk_size = required_chunk_size(layout.size());
+        let (a, b) = unsafe { (talc.region_report(first), talc.region_report(second)) };
+        assert!(a.free_chunk_count > 10);
+        assert!(a.largest_free_chunk < 2 * chunk_size);
+        assert!(a.fragmentation_percent() > 80);
+        assert_eq!(b.free_bytes, second.size() - b.used_bytes);
+        assert_eq!(b.free_chunk_count, 1);
+
+        let line = std::format!("{}", b);
+        assert!(!line.contains('\n'));
+        assert!(line.contains("0% fragmented"));
+    }
+}
@@ -0,0 +1,53 @@
+//! Report allocation failures and heap usage over RTT, to be shown live by debug probe tooling.
+//!
+//! Events are printed to the `rtt-target` print channel, so the application must set it up
+//! with `rtt_init_print!` (or `set_print_channel`), and provide a `critical-section`
+//! implementation. Until then, events are silently dropped.
+
+use core::alloc::Layout;
+
+use super::Talc;
+use crate::OomHandler;
+
+impl<O: OomHandler> Talc<O> {
+    /// Print an event when the allocated bytes rise above `threshold`, or stop doing so.
+    ///
+    /// The event is printed once per crossing: the allocated bytes must fall back
+    /// to `threshold` or below before it's printed again.
+    #[cfg(feature = "counters")]
+    pub fn set_rtt_threshold(&mut self, threshold: Option<usize>) {
+        self.rtt_threshold = threshold;
+        self.rtt_threshold_exceeded = false;
+    }
+
+    /// Prints an event if the allocated bytes have risen above the threshold.
+    #[cfg(feature = "counters")]
+    #[inline]
+    pub(super) fn check_rtt_threshold(&mut self) {
+        if let Some(threshold) = self.rtt_threshold {
+            let exceeded = self.counters.allocated_bytes > threshold;
+
+            if exceeded && !self.rtt_threshold_exceeded {
+                rtt_target::rprintln!(
+                    "talc: {} B allocated, above the threshold of {} B ({} B available)",
+                    self.counters.allocated_bytes,
+                    threshold,
+                    self.counters.available_bytes,
+                );
+            }
+
+            self.rtt_threshold_exceeded = exceeded;
+        }
+    }
+
+    /// Prints an event for an allocation that failed for `reason`.
+    #[cold]
+    pub(super) fn report_alloc_failure(&self, layout: Layout, reason: &str) {
+        rtt_target::rprintln!(
+            "talc: failed to allocate {} B aligned to {}: {}",
+            layout.size(),
+            layout.align(),
+            reason,
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! Tell AddressSanitizer which of the heaps' memory is free, so it reports accesses to it.
+//!
+//! The interior of each free chunk, all but its list links and sizes, is poisoned as the
+//! chunk is registered, and the whole chunk is unpoisoned as it's deregistered, before
+//! it's split, merged or allocated. Chunks cached by the `fastbins` feature, the
+//! allocator's metadata, and the tags of allocated chunks stay unpoisoned, as the
+//! allocator accesses them freely.
+//!
+//! Memory handed to the allocator is unpoisoned, as it may have been left poisoned
+//! by its previous owner.
+//!
+//! The hooks are provided by building with `-Zsanitizer=address`. Otherwise, such as
+//! for `cargo test --features sanitize`, they do nothing.
+
+use super::{GAP_HIGH_SIZE_OFFSET, GAP_LOW_SIZE_OFFSET};
+use crate::{ptr_utils::WORD_SIZE, Span};
+
+#[cfg(sanitize = "address")]
+extern "C" {
+    fn __asan_poison_memory_region(addr: *const u8, size: usize);
+    fn __asan_unpoison_memory_region(addr: *const u8, size: usize);
+}
+
+#[cfg(not(sanitize = "address"))]
+unsafe fn __asan_poison_memory_region(_addr: *const u8, _size: usize) {}
+
+#[cfg(not(sanitize = "address"))]
+unsafe fn __asan_unpoison_memory_region(_addr: *const u8, _size: usize) {}
+
+/// Poison the interior of the free chunk `[base, acme)`, leaving its metadata accessible.
+#[inline]
+pub(super) unsafe fn poison_gap(base: *mut u8, acme: *mut u8) {
+    let interior_base = base.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE);
+    let interior_acme = acme.sub(GAP_HIGH_SIZE_OFFSET);
+
+    if interior_base < interior_acme {
+        __asan_poison_memory_region(interior_base, interior_acme as usize - interior_base as usize);
+    }
+}
+
+/// Poison `[base, acme)`, doing nothing if it's empty.
+#[cfg(feature = "quarantine")]
+#[inline]
+pub(super) unsafe fn poison(base: *mut u8, acme: *mut u8) {
+    if base < acme {
+        __asan_poison_memory_region(base, acme as usize - base as usize);
+    }
+}
+
+/// Unpoison `[base, acme)`, such as a free chunk that's being deregistered.
+#[inline]
+pub(super) unsafe fn unpoison(base: *mut u8, acme: *mut u8) {
+    __asan_unpoison_memory_region(base, acme as usize - base as usize);
+}
+
+/// Unpoison memory that's being handed to the allocator, which may have been
+/// left poisoned by its previous owner, such as another allocator.
+#[inline]
+pub(super) unsafe fn unpoison_span(span: Span) {
+    if let Some((base, acme)) = span.get_base_acme() {
+        unpoison(base, acme);
+    }
+}
@@ -0,0 +1,313 @@
+//! Check the free chunks and bins for corruption, a few at a time.
+
+use core::ptr::NonNull;
+
+use super::{
+    bin_of_size, gap_acme_to_size, gap_node_to_base, gap_node_to_size, llist::LlistNode, tag::Tag,
+    Talc, BIN_COUNT, MIN_CHUNK_SIZE, TAG_SIZE,
+};
+use crate::{
+    ptr_utils::{ALIGN, WORD_BITS},
+    OomHandler,
+};
+
+/// Corruption found by [`Talc::scan_step`] or [`Talc::validate`].
+///
+/// Addresses are those of the affected free chunk's base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeapError {
+    /// The availability flag of a bin doesn't match whether it's empty.
+    Availability { bin: usize },
+    /// A free chunk's list links don't point back at it.
+    Link { chunk: usize },
+    /// A free chunk's size is invalid, or differs between its base and acme.
+    Size { chunk: usize },
+    /// A free chunk is in a bin that doesn't match its size.
+    Bin { chunk: usize, bin: usize },
+    /// The tag below a free chunk doesn't record that it's free.
+    Tag { chunk: usize },
+    /// The word below the chunk boundary at `addr` is neither a valid free chunk size
+    /// nor a valid tag, so the heap can't be walked further. Only reported when walking
+    /// a heap, such as by `debug::HeapImage`.
+    Boundary { addr: usize },
+}
+
+impl core::fmt::Display for HeapError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match *self {
+            HeapError::Availability { bin } => {
+                write!(f, "availability flag of bin {} is wrong", bin)
+            }
+            HeapError::Link { chunk } => write!(f, "free chunk at {:#x} is mislinked", chunk),
+            HeapError::Size { chunk } => {
+                write!(f, "free chunk at {:#x} has an invalid size", chunk)
+            }
+            HeapError::Bin { chunk, bin } => {
+                write!(f, "free chunk at {:#x} is in the wrong bin ({})", chunk, bin)
+            }
+            HeapError::Tag { chunk } => {
+                write!(f, "free chunk at {:#x} isn't marked free by its tag", chunk)
+            }
+            HeapError::Boundary { addr } => {
+                write!(f, "chunk boundary at {:#x} is invalid", addr)
+            }
+        }
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Check up to `max_chunks` free chunks for corruption, continuing where the last call
+    /// left off, and returns whether a pass over all the free chunks was just completed.
+    ///
+    /// This is intended to be called regularly, e.g. from an idle loop, to detect heap
+    /// corruption on deployed devices without the cost of checking the whole heap at once.
+    /// Each free chunk's sizes, list links, bin, and the tag below it are checked, as
+    /// well as the availability flags of the bins.
+    ///
+    /// The position is kept as an index into a bin, so chunks may be skipped or checked
+    /// twice in a pass if the heap changes in between calls. Resuming walks the bin's
+    /// list up to the position, which is cheap compared to the checks.
+    ///
+    /// Corruption that leaves pointers wild may cause a fault instead of an `Err`.
+    ///
+    /// Requires the `incremental_scan` feature, which adds the position to the `Talc`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// // e.g. in the idle loop
+    /// if let Err(error) = talc.scan_step(4) {
+    ///     panic!("heap corruption: {}", error);
+    /// }
+    /// ```
+    #[cfg(feature = "incremental_scan")]
+    pub fn scan_step(&mut self, max_chunks: usize) -> Result<bool, HeapError> {
+        if self.bins.is_null() {
+            return Ok(false);
+        }
+
+        let (mut bin, mut index) = self.scan_position;
+        let mut checked = 0;
+
+        let result = loop {
+            if bin == BIN_COUNT {
+                bin = 0;
+                index = 0;
+                break Ok(true);
+            }
+
+            if checked == max_chunks {
+                break Ok(false);
+            }
+
+            // SAFETY: bins are established
+            let bin_ptr = unsafe { self.get_bin_ptr(bin) };
+
+            if index == 0 && self.is_available(bin) != unsafe { *bin_ptr }.is_some() {
+                break Err(HeapError::Availability { bin });
+            }
+
+            // SAFETY: the links are checked as they're followed
+            let node = unsafe { LlistNode::iter_mut(*bin_ptr).nth(index) };
+
+            match node {
+                Some(node) => {
+                    let check = unsafe { Self::check_free_chunk(node, bin, bin_ptr, index) };
+                    if let Err(error) = check {
+                        break Err(error);
+                    }
+
+                    index += 1;
+                    checked += 1;
+                }
+                None => {
+                    bin += 1;
+                    index = 0;
+                }
+            }
+        };
+
+        self.scan_position = (bin, index);
+
+        #[cfg(all(feature = "semihosting", target_arch = "arm"))]
+        if let Err(error) = result {
+            super::semihosting::report_heap_error(error);
+        }
+
+        result
+    }
+
+    /// Check all the free chunks for corruption at once, returning the first error found.
+    ///
+    /// This performs the same checks as [`Talc::scan_step`] in a single pass, without
+    /// affecting its position, and is available in release builds. It takes time
+    /// proportional to the number of free chunks, so prefer `scan_step` where latency
+    /// matters.
+    ///
+    /// Corruption that leaves pointers wild may cause a fault instead of an `Err`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// assert_eq!(talc.validate(), Ok(()));
+    /// ```
+    pub fn validate(&self) -> Result<(), HeapError> {
+        if self.bins.is_null() {
+            return Ok(());
+        }
+
+        let result = (0..BIN_COUNT).try_for_each(|bin| {
+            // SAFETY: bins are established
+            let bin_ptr = unsafe { self.get_bin_ptr(bin) };
+
+            if self.is_available(bin) != unsafe { *bin_ptr }.is_some() {
+                return Err(HeapError::Availability { bin });
+            }
+
+            // SAFETY: the links are checked as they're followed
+            for (index, node) in unsafe { LlistNode::iter_mut(*bin_ptr) }.enumerate() {
+                unsafe { Self::check_free_chunk(node, bin, bin_ptr, index)? };
+            }
+
+            Ok(())
+        });
+
+        #[cfg(all(feature = "semihosting", target_arch = "arm"))]
+        if let Err(error) = result {
+            super::semihosting::report_heap_error(error);
+        }
+
+        result
+    }
+
+    /// Returns whether the availability flag of `bin` is set.
+    fn is_available(&self, bin: usize) -> bool {
+        if bin < WORD_BITS {
+            self.availability_low & 1 << bin != 0
+        } else {
+            self.availability_high & 1 << (bin - WORD_BITS) != 0
+        }
+    }
+
+    /// Checks the free chunk of `node`, which is at `index` in the list of `bin`.
+    ///
+    /// # Safety
+    /// `node` must be readable.
+    unsafe fn check_free_chunk(
+        node: NonNull<LlistNode>,
+        bin: usize,
+        bin_ptr: *mut Option<NonNull<LlistNode>>,
+        index: usize,
+    ) -> Result<(), HeapError> {
+        let base = gap_node_to_base(node);
+        let chunk = base as usize;
+
+        // the first node is linked from the bin, the rest from the node before
+        let next_of_prev = (*node.as_ptr()).next_of_prev;
+        let linked = if index == 0 {
+            next_of_prev == bin_ptr
+        } else {
+            !next_of_prev.is_null() && *next_of_prev == Some(node)
+        };
+        if !linked {
+            return Err(HeapError::Link { chunk });
+        }
+
+        let size = gap_node_to_size(node).read();
+        if size < MIN_CHUNK_SIZE || size % ALIGN != 0 || chunk.checked_add(size).is_none() {
+            return Err(HeapError::Size { chunk });
+        }
+        if gap_acme_to_size(base.add(size)).read() != size {
+            return Err(HeapError::Size { chunk });
+        }
+
+        if bin_of_size(size) != bin {
+            return Err(HeapError::Bin { chunk, bin });
+        }
+
+        let lower_tag = base.sub(TAG_SIZE).cast::<Tag>().read();
+        if !lower_tag.is_allocated() || !lower_tag.is_above_free() {
+            return Err(HeapError::Tag { chunk });
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(all(test, feature = "incremental_scan"))]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn scan_step_test() {
+        let mut arena = [0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        assert_eq!(talc.scan_step(100), Ok(false));
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        // fragment the heap
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let mut allocations = std::vec![];
+        for i in 0..100 {
+            let ptr = unsafe { talc.malloc(Layout::from_size_align(8 + i * 16, 8).unwrap()) };
+            allocations.push(ptr.unwrap());
+        }
+        for i in (0..100).step_by(2) {
+            unsafe { talc.free(allocations[i], Layout::from_size_align(8 + i * 16, 8).unwrap()) };
+        }
+        let free_chunk_count = talc.report().free_chunk_count;
+        assert!(free_chunk_count > 10);
+
+        // a pass takes several steps, and is repeated
+        let mut steps = 0;
+        while !talc.scan_step(3).unwrap() {
+            steps += 1;
+        }
+        assert!(steps >= free_chunk_count / 3);
+        assert_eq!(talc.scan_step(usize::MAX), Ok(true));
+
+        // allocating in between steps is fine
+        assert_eq!(talc.scan_step(3), Ok(false));
+        let ptr = unsafe { talc.malloc(layout).unwrap() };
+        assert_eq!(talc.scan_step(usize::MAX), Ok(true));
+        unsafe { talc.free(ptr, layout) };
+
+        // corrupt a free chunk's high size
+        let node = unsafe { (*talc.get_bin_ptr(talc.next_available_bin(0).unwrap())).unwrap() };
+        let base = unsafe { gap_node_to_base(node) };
+        let size = unsafe { gap_node_to_size(node).read() };
+        unsafe { gap_acme_to_size(base.add(size)).write(size + ALIGN) };
+        assert_eq!(talc.scan_step(usize::MAX), Err(HeapError::Size { chunk: base as usize }));
+        assert_eq!(talc.validate(), Err(HeapError::Size { chunk: base as usize }));
+        unsafe { gap_acme_to_size(base.add(size)).write(size) };
+        assert_eq!(talc.validate(), Ok(()));
+
+        // corrupt the availability flag of the last bin
+        let flip_last_flag = |talc: &mut Talc<ErrOnOom>| {
+            let flag = 1 << ((BIN_COUNT - 1) % WORD_BITS);
+            if BIN_COUNT > WORD_BITS {
+                talc.availability_high ^= flag;
+            } else {
+                talc.availability_low ^= flag;
+            }
+        };
+        flip_last_flag(&mut talc);
+        talc.scan_position = (0, 0);
+        assert_eq!(talc.scan_step(usize::MAX), Err(HeapError::Availability { bin: BIN_COUNT - 1 }));
+        assert_eq!(talc.validate(), Err(HeapError::Availability { bin: BIN_COUNT - 1 }));
+        flip_last_flag(&mut talc);
+        talc.scan_position = (0, 0);
+        assert_eq!(talc.scan_step(usize::MAX), Ok(true));
+    }
+}
@@ -0,0 +1,65 @@
+//! Report allocation failures and heap corruption over Arm semihosting, for board bring-up.
+//!
+//! Messages are printed to the debugger's console using the `SYS_WRITE0` operation, so no
+//! UART or RTT setup is needed. Semihosting halts the core on a breakpoint until the debugger
+//! services it, which is slow, and faults if no debugger is attached. Only enable this
+//! while a debugger is attached.
+
+use core::{alloc::Layout, fmt::Write};
+
+use super::HeapError;
+
+/// The maximum length of a message, longer messages are truncated.
+const MESSAGE_CAPACITY: usize = 128;
+
+/// Formats a message into a NUL-terminated buffer, truncating it if too long.
+struct Message {
+    buf: [u8; MESSAGE_CAPACITY],
+    len: usize,
+}
+
+impl Write for Message {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        // leave space for the NUL terminator
+        let n = s.len().min(MESSAGE_CAPACITY - 1 - self.len);
+        self.buf[self.len..self.len + n].copy_from_slice(&s.as_bytes()[..n]);
+        self.len += n;
+        Ok(())
+    }
+}
+
+/// Print a line to the debugger's console.
+fn print(args: core::fmt::Arguments) {
+    const SYS_WRITE0: usize = 0x04;
+
+    let mut message = Message { buf: [0; MESSAGE_CAPACITY], len: 0 };
+    let _ = message.write_fmt(args);
+    let _ = message.write_str("\n");
+
+    // SAFETY: SYS_WRITE0 only reads the NUL-terminated string, which the buffer always holds
+    unsafe {
+        core::arch::asm!(
+            "bkpt #0xAB",
+            inout("r0") SYS_WRITE0 => _,
+            in("r1") message.buf.as_ptr(),
+            options(nostack, preserves_flags, readonly)
+        );
+    }
+}
+
+/// Prints an allocation that failed for `reason`.
+#[cold]
+pub(super) fn report_alloc_failure(layout: Layout, reason: &str) {
+    print(format_args!(
+        "talc: failed to allocate {} B aligned to {}: {}",
+        layout.size(),
+        layout.align(),
+        reason
+    ));
+}
+
+/// Prints heap corruption found by [`Talc::scan_step`](super::Talc::scan_step).
+#[cold]
+pub(super) fn report_heap_error(error: HeapError) {
+    print(format_args!("talc: heap corruption: {}", error));
+}
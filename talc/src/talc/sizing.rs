@@ -0,0 +1,256 @@
+//! Functions for predicting how much memory Talc reserves for allocations.
+//!
+//! These allow containers to choose capacities that fill the memory
+//! Talc would reserve anyway, rather than leave it as unusable slack.
+
+use core::alloc::Layout;
+
+use super::{Talc, BIN_ARRAY_SIZE, HEADER_SIZE, MIN_CHUNK_SIZE, TAG_SIZE};
+use crate::{ptr_utils::ALIGN, OomHandler};
+
+/// Returns the size of the chunk reserved for an allocation of `size` bytes
+/// with an alignment no greater than that of `usize`.
+///
+/// This includes the allocator's per-allocation metadata.
+/// Sizes too large to be allocated saturate instead of overflowing.
+#[inline]
+pub const fn required_chunk_size(size: usize) -> usize {
+    if size.saturating_add(HEADER_SIZE) <= MIN_CHUNK_SIZE - TAG_SIZE {
+        MIN_CHUNK_SIZE
+    } else {
+        size.saturating_add(HEADER_SIZE + TAG_SIZE + (ALIGN - 1)) & !(ALIGN - 1)
+    }
+}
+
+/// Returns the index of the bin that free chunks of `chunk_size` bytes are sorted into.
+///
+/// Bins are ordered by size, and chunks smaller than the minimum chunk size
+/// belong in the first bin. See [`HeapReport::bin_count`](crate::HeapReport::bin_count)
+/// for the number of bins.
+#[inline]
+pub fn bin_of_size(chunk_size: usize) -> usize {
+    // SAFETY: the size is at least the minimum chunk size
+    unsafe { super::bin_of_size(chunk_size.max(MIN_CHUNK_SIZE)) }
+}
+
+/// Returns the largest size an allocation of `size` bytes aligned to `align` is
+/// guaranteed to be able to grow to in-place, as the memory is reserved regardless.
+///
+/// Allocating this size instead of `size` never costs additional memory.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// let elem_size = core::mem::size_of::<u16>();
+/// let capacity = round_up_to_allocation_size(5 * elem_size, 2) / elem_size;
+/// assert!(capacity >= 5);
+/// ```
+#[inline]
+pub const fn round_up_to_allocation_size(size: usize, align: usize) -> usize {
+    let rounded = if align <= ALIGN {
+        // the allocation immediately follows the header at the chunk's base
+        required_chunk_size(size) - HEADER_SIZE - TAG_SIZE
+    } else {
+        // the chunk base isn't predictable, but the tag is at least word-aligned
+        size.saturating_add(ALIGN - 1) & !(ALIGN - 1)
+    };
+
+    // avoid shrinking sizes that saturated
+    if rounded < size { size } else { rounded }
+}
+
+/// Returns the smallest arena that, when claimed as the allocator's first heap,
+/// can serve an allocation of `capacity` bytes with an alignment no greater than that of `usize`.
+///
+/// The first heap also holds the allocator's metadata. If `capacity` is zero,
+/// this is the smallest arena that can be claimed at all.
+///
+/// The arena's base need not be aligned. See [`assert_arena_size`] for checking
+/// the size of a static arena at compile time.
+#[inline]
+pub const fn min_arena_size(capacity: usize) -> usize {
+    // the metadata chunk can take up the rest of the heap, so no free chunk is needed
+    let chunk_size = if capacity == 0 { 0 } else { required_chunk_size(capacity) };
+
+    // the base tag and the metadata chunk
+    min_heap_size(TAG_SIZE + BIN_ARRAY_SIZE + TAG_SIZE, chunk_size)
+}
+
+/// Returns the smallest arena that, when claimed as a heap other than the allocator's first,
+/// can serve an allocation of `capacity` bytes with an alignment no greater than that of `usize`.
+///
+/// If `capacity` is zero, this is the smallest arena that can be claimed at all.
+#[inline]
+pub const fn min_additional_arena_size(capacity: usize) -> usize {
+    // the base tag
+    min_heap_size(TAG_SIZE, required_chunk_size(capacity))
+}
+
+#[inline]
+const fn min_heap_size(overhead: usize, chunk_size: usize) -> usize {
+    // an unaligned base is rounded up, so at most a word less one byte is lost
+    overhead.saturating_add(chunk_size).saturating_add(ALIGN - 1)
+}
+
+/// Fails compilation if an arena of `size` bytes isn't large enough to be claimed
+/// as the allocator's first heap and then serve an allocation of `capacity` bytes.
+///
+/// This is intended for static arenas, where an undersized arena is otherwise
+/// only discovered at runtime, when claiming it fails or allocations run out of memory.
+/// See [`min_arena_size`].
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// const ARENA_SIZE: usize = 10000;
+/// static mut ARENA: [u8; ARENA_SIZE] = [0; ARENA_SIZE];
+///
+/// // the largest buffer we allocate is 4 KiB
+/// assert_arena_size!(ARENA_SIZE, 4096);
+/// ```
+///
+/// ```rust,compile_fail
+/// # use talc::*;
+/// assert_arena_size!(1000, 4096);
+/// ```
+#[macro_export]
+macro_rules! assert_arena_size {
+    ($size:expr, $capacity:expr) => {
+        const _: () = ::core::assert!(
+            $size >= $crate::min_arena_size($capacity),
+            "the arena is too small for the allocator's metadata and the required capacity"
+        );
+    };
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Returns the number of elements of `elem_layout` an array can hold,
+    /// if it's allocated to hold at least `desired_len` elements.
+    ///
+    /// Use this to pick the capacity of collections, such as with `Vec::with_capacity`,
+    /// to make use of memory the allocator would otherwise reserve as slack.
+    ///
+    /// Returns `desired_len` if the elements are zero-sized or the array is too large.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let talc = Talc::new(ErrOnOom);
+    /// let capacity = talc.recommend_capacity(Layout::new::<u8>(), 13);
+    /// assert!(capacity >= 13);
+    ///
+    /// let vec = Vec::<u8>::with_capacity(capacity);
+    /// ```
+    pub fn recommend_capacity(&self, elem_layout: Layout, desired_len: usize) -> usize {
+        let elem_size = elem_layout.pad_to_align().size();
+
+        if elem_size == 0 || desired_len == 0 {
+            return desired_len;
+        }
+
+        match elem_size.checked_mul(desired_len) {
+            Some(size) => round_up_to_allocation_size(size, elem_layout.align()) / elem_size,
+            None => desired_len,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn round_up_to_allocation_size_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        for align in [1, 2, ALIGN, 64] {
+            for size in 1..200 {
+                let rounded = round_up_to_allocation_size(size, align);
+                assert!(rounded >= size);
+
+                let layout = Layout::from_size_align(size, align).unwrap();
+                let ptr = unsafe { talc.malloc(layout).unwrap() };
+                let free_bytes = talc.report().free_bytes;
+
+                // growing to the rounded size doesn't take any more memory
+                let grown = unsafe { talc.grow_in_place(ptr, layout, rounded).unwrap() };
+                assert_eq!(talc.report().free_bytes, free_bytes);
+
+                unsafe { talc.free(grown, Layout::from_size_align(rounded, align).unwrap()) };
+            }
+        }
+
+        assert_eq!(round_up_to_allocation_size(usize::MAX - 1, 1), usize::MAX - 1);
+        assert_eq!(round_up_to_allocation_size(usize::MAX, 4096), usize::MAX);
+    }
+
+    #[test]
+    fn public_sizing_test() {
+        assert_eq!(bin_of_size(0), 0);
+        assert!(bin_of_size(required_chunk_size(1)) < bin_of_size(required_chunk_size(1000)));
+        assert!(bin_of_size(usize::MAX) < crate::talc::BIN_COUNT);
+        assert!(required_chunk_size(100) >= 100);
+        assert_eq!(required_chunk_size(usize::MAX) % ALIGN, 0);
+    }
+
+    #[test]
+    fn min_arena_size_test() {
+        for capacity in [0, 1, 100, 1000] {
+            for offset in 0..ALIGN {
+                let mut arena = [0u8; 6000];
+                let mut talc = Talc::new(ErrOnOom);
+
+                let size = min_arena_size(capacity);
+                let first = Span::from(&mut arena[offset..offset + size]);
+                unsafe { talc.claim(first).unwrap() };
+
+                let size = min_additional_arena_size(capacity);
+                let additional = Span::from(&mut arena[3000 + offset..3000 + offset + size]);
+                unsafe { talc.claim(additional).unwrap() };
+
+                // both heaps can serve the capacity
+                if capacity != 0 {
+                    let layout = Layout::from_size_align(capacity, ALIGN).unwrap();
+                    unsafe {
+                        talc.malloc(layout).unwrap();
+                        talc.malloc(layout).unwrap();
+                    }
+                }
+            }
+        }
+
+        assert!(min_arena_size(100) > min_additional_arena_size(100));
+        assert_eq!(min_arena_size(usize::MAX), usize::MAX);
+    }
+
+    crate::assert_arena_size!(10000, 4096);
+
+    #[test]
+    fn recommend_capacity_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let elem_layout = Layout::new::<[u8; 3]>();
+        for len in 1..50 {
+            let capacity = talc.recommend_capacity(elem_layout, len);
+            assert!(capacity >= len);
+
+            // the recommended capacity fits in the memory reserved for the desired length
+            let layout = Layout::array::<[u8; 3]>(len).unwrap();
+            let ptr = unsafe { talc.malloc(layout).unwrap() };
+            let ptr = unsafe { talc.grow_in_place(ptr, layout, capacity * 3).unwrap() };
+            unsafe { talc.free(ptr, Layout::array::<[u8; 3]>(capacity).unwrap()) };
+        }
+
+        assert_eq!(talc.recommend_capacity(Layout::new::<()>(), 7), 7);
+        assert_eq!(talc.recommend_capacity(Layout::new::<u64>(), 0), 0);
+        assert_eq!(talc.recommend_capacity(Layout::new::<u64>(), usize::MAX), usize::MAX);
+    }
+}
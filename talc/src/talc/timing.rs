@@ -0,0 +1,166 @@
+//! Measure how many cycles `malloc` and `free` take, e.g. for worst-case execution time analysis.
+
+use super::Talc;
+use crate::OomHandler;
+
+/// Cycle statistics of an operation, see [`Counters`](super::counters::Counters).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct OpTiming {
+    /// Number of timed operations.
+    pub count: u64,
+    /// The fewest cycles an operation took, or zero if none were timed.
+    pub min_cycles: u32,
+    /// The most cycles an operation took.
+    pub max_cycles: u32,
+    /// Sum of the cycles all operations took.
+    pub total_cycles: u64,
+}
+
+impl OpTiming {
+    pub const fn new() -> Self {
+        Self { count: 0, min_cycles: 0, max_cycles: 0, total_cycles: 0 }
+    }
+
+    /// Returns the average cycles an operation took, rounded down, or zero if none were timed.
+    pub const fn average_cycles(&self) -> u64 {
+        match self.total_cycles.checked_div(self.count) {
+            Some(average) => average,
+            None => 0,
+        }
+    }
+
+    pub(crate) fn record(&mut self, cycles: u32) {
+        if self.count == 0 || cycles < self.min_cycles {
+            self.min_cycles = cycles;
+        }
+        if cycles > self.max_cycles {
+            self.max_cycles = cycles;
+        }
+
+        self.count += 1;
+        self.total_cycles += cycles as u64;
+    }
+}
+
+/// Reads the cycle counter of the Cortex-M Data Watchpoint and Trace unit, `DWT.CYCCNT`.
+///
+/// Pass this to [`Talc::set_timestamp_source`] to time allocations in CPU cycles.
+/// The counter must be enabled beforehand, e.g. using the `cortex-m` crate's
+/// `DCB::enable_trace` and `DWT::enable_cycle_counter`. Not all Cortex-M cores have one,
+/// notably ARMv6-M (Cortex-M0/M0+) cores don't.
+///
+/// Only call this on Cortex-M targets, as it reads the memory-mapped register directly.
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+#[inline]
+pub fn dwt_cycle_count() -> u32 {
+    const DWT_CYCCNT: *const u32 = 0xE000_1004 as *const u32;
+
+    // SAFETY: the register is always readable on Cortex-M, and reading it has no side effects
+    unsafe { DWT_CYCCNT.read_volatile() }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Time `malloc` and `free` using `source`, or stop timing them.
+    ///
+    /// `source` returns a timestamp in cycles (or any other unit) that wraps around
+    /// on overflow, such as [`dwt_cycle_count`](crate::dwt_cycle_count) on Cortex-M.
+    /// It's called at the start and end of each allocation and free, and the cycles
+    /// in between are recorded in [`Counters::malloc_timing`] and [`Counters::free_timing`].
+    /// Time spent in the OOM handler is included, but failed allocations aren't recorded.
+    ///
+    /// [`Counters::malloc_timing`]: super::counters::Counters::malloc_timing
+    /// [`Counters::free_timing`]: super::counters::Counters::free_timing
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// # use core::sync::atomic::{AtomicU32, Ordering};
+    /// static NOW: AtomicU32 = AtomicU32::new(0);
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// // e.g. `talc.set_timestamp_source(Some(dwt_cycle_count))` on Cortex-M
+    /// talc.set_timestamp_source(Some(|| NOW.fetch_add(50, Ordering::Relaxed)));
+    ///
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// unsafe { talc.malloc(layout).unwrap(); }
+    /// assert_eq!(talc.get_counters().malloc_timing.max_cycles, 50);
+    /// ```
+    pub fn set_timestamp_source(&mut self, source: Option<fn() -> u32>) {
+        self.timestamp_source = source;
+    }
+
+    /// Returns the current timestamp, if operations are being timed.
+    #[inline]
+    pub(super) fn timestamp(&self) -> Option<u32> {
+        self.timestamp_source.map(|source| source())
+    }
+
+    /// Returns the cycles elapsed since `start`, if operations are being timed.
+    #[inline]
+    pub(super) fn cycles_since(&self, start: Option<u32>) -> Option<u32> {
+        Some(self.timestamp()?.wrapping_sub(start?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::Layout,
+        sync::atomic::{AtomicU32, Ordering},
+    };
+
+    use crate::*;
+
+    static NOW: AtomicU32 = AtomicU32::new(u32::MAX - 100);
+
+    /// Advances by 10 cycles each call, wrapping around early on.
+    fn now() -> u32 {
+        NOW.fetch_add(10, Ordering::Relaxed)
+    }
+
+    #[test]
+    fn timing_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        // untimed by default
+        unsafe {
+            let ptr = talc.malloc(layout).unwrap();
+            talc.free(ptr, layout);
+        }
+        assert_eq!(talc.get_counters().malloc_timing, OpTiming::new());
+
+        talc.set_timestamp_source(Some(now));
+        let mut allocations = std::vec![];
+        for _ in 0..20 {
+            allocations.push(unsafe { talc.malloc(layout).unwrap() });
+        }
+        // failed allocations aren't timed
+        assert!(unsafe { talc.malloc(Layout::from_size_align(100000, 8).unwrap()) }.is_err());
+        for ptr in allocations {
+            unsafe { talc.free(ptr, layout) };
+        }
+
+        let malloc_timing = talc.get_counters().malloc_timing;
+        assert_eq!(malloc_timing.count, 20);
+        assert_eq!(malloc_timing.min_cycles, 10);
+        assert_eq!(malloc_timing.max_cycles, 10);
+        assert_eq!(malloc_timing.total_cycles, 200);
+        assert_eq!(malloc_timing.average_cycles(), 10);
+        assert_eq!(talc.get_counters().free_timing.count, 20);
+
+        talc.set_timestamp_source(None);
+        unsafe {
+            let ptr = talc.malloc(layout).unwrap();
+            talc.free(ptr, layout);
+        }
+        assert_eq!(talc.get_counters().malloc_timing, malloc_timing);
+    }
+}
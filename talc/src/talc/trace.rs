@@ -0,0 +1,351 @@
+//! Record a trace of allocation calls, and replay it against another allocator.
+//!
+//! Recording is `no_std`, handing each [`TraceEntry`] to a sink function, such that a
+//! workload can be captured on the target. Replaying requires the `std` feature, and
+//! is intended for comparing allocator configurations against real workloads on a host.
+
+use core::alloc::Layout;
+
+use super::{LifetimeHint, Talc};
+use crate::OomHandler;
+
+/// A call to the allocator, as recorded by a trace sink, see [`Talc::set_trace_sink`].
+///
+/// Pointers are recorded as addresses, which are zero if the call failed.
+/// The contents of allocations aren't recorded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TraceEntry {
+    /// [`Talc::malloc`] or [`Talc::malloc_hinted`].
+    Malloc { layout: Layout, hint: LifetimeHint, ptr: usize },
+    /// [`Talc::free`].
+    Free { ptr: usize, layout: Layout },
+    /// [`Talc::grow`].
+    Grow { ptr: usize, layout: Layout, new_size: usize, new_ptr: usize },
+    /// [`Talc::grow_in_place`].
+    GrowInPlace { ptr: usize, layout: Layout, new_size: usize, ok: bool },
+    /// [`Talc::shrink`].
+    Shrink { ptr: usize, layout: Layout, new_size: usize },
+    /// [`Talc::realign_in_place`].
+    Realign { ptr: usize, layout: Layout, align: usize, new_ptr: usize },
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Set a function to be called with a [`TraceEntry`] for each call to the allocator,
+    /// or stop tracing.
+    ///
+    /// Calls made by the allocator on its own behalf, such as when [`Talc::grow`] falls
+    /// back on reallocating, aren't recorded separately. The sink must not call into
+    /// the allocator.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// # use core::sync::atomic::{AtomicUsize, Ordering};
+    /// static CALLS: AtomicUsize = AtomicUsize::new(0);
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// // e.g. write the entries to a buffer or a debug channel
+    /// talc.set_trace_sink(Some(|_entry| { CALLS.fetch_add(1, Ordering::Relaxed); }));
+    ///
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// unsafe {
+    ///     let ptr = talc.malloc(layout).unwrap();
+    ///     talc.free(ptr, layout);
+    /// }
+    /// assert_eq!(CALLS.load(Ordering::Relaxed), 2);
+    /// ```
+    pub fn set_trace_sink(&mut self, sink: Option<fn(TraceEntry)>) {
+        self.trace_sink = sink;
+    }
+
+    /// Hands `entry` to the trace sink, if any.
+    #[inline]
+    pub(super) fn trace(&self, entry: TraceEntry) {
+        if let Some(sink) = self.trace_sink {
+            sink(entry);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub use replay::{ReplayReport, Replayer};
+
+#[cfg(feature = "std")]
+mod replay {
+    use core::{alloc::Layout, ptr::NonNull};
+    use std::collections::HashMap;
+
+    use super::TraceEntry;
+    use crate::{OomHandler, Talc};
+
+    /// Statistics of a replayed trace, as returned by [`Replayer::report`].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+    pub struct ReplayReport {
+        /// Number of entries replayed.
+        pub replayed: usize,
+        /// Number of entries that failed when replayed, despite having succeeded when recorded.
+        pub failed: usize,
+        /// Number of entries skipped, as they failed when recorded, or concern an allocation
+        /// that wasn't made by the replay.
+        pub skipped: usize,
+        /// The most bytes allocated at once by the replay.
+        pub peak_allocated_bytes: usize,
+    }
+
+    /// Drives a [`Talc`] with a recorded sequence of [`TraceEntry`]s.
+    ///
+    /// Recorded addresses are mapped to the replay's allocations as it goes. Where the replay
+    /// diverges from the recording, such as an allocation failing, later entries concerning
+    /// the affected allocation are adapted or skipped, keeping the replay sound.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let layout = Layout::new::<[u64; 8]>();
+    /// let trace = [
+    ///     TraceEntry::Malloc { layout, hint: LifetimeHint::ShortLived, ptr: 0x1000 },
+    ///     TraceEntry::Grow { ptr: 0x1000, layout, new_size: 128, new_ptr: 0x2000 },
+    ///     TraceEntry::Free { ptr: 0x2000, layout: Layout::from_size_align(128, 8).unwrap() },
+    /// ];
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let mut replayer = Replayer::new();
+    /// unsafe { replayer.replay_all(&mut talc, trace); }
+    /// assert_eq!(replayer.report().replayed, 3);
+    /// assert_eq!(replayer.report().peak_allocated_bytes, 128);
+    /// ```
+    #[derive(Debug, Default)]
+    pub struct Replayer {
+        /// The replay's allocations and their layouts, by recorded address.
+        live: HashMap<usize, (NonNull<u8>, Layout)>,
+        allocated_bytes: usize,
+        report: ReplayReport,
+    }
+
+    impl Replayer {
+        pub fn new() -> Self {
+            Self::default()
+        }
+
+        /// Returns the statistics of the entries replayed so far.
+        pub fn report(&self) -> ReplayReport {
+            self.report
+        }
+
+        /// Replay each of `entries` in order, see [`Replayer::replay`].
+        ///
+        /// # Safety
+        /// See [`Replayer::replay`].
+        pub unsafe fn replay_all<O: OomHandler>(
+            &mut self,
+            talc: &mut Talc<O>,
+            entries: impl IntoIterator<Item = TraceEntry>,
+        ) {
+            for entry in entries {
+                self.replay(talc, entry);
+            }
+        }
+
+        /// Perform the call recorded by `entry` on `talc`.
+        ///
+        /// # Safety
+        /// `talc` must be the same allocator for every call on this `Replayer`.
+        pub unsafe fn replay<O: OomHandler>(&mut self, talc: &mut Talc<O>, entry: TraceEntry) {
+            match self.replay_entry(talc, entry) {
+                Some(ok) => {
+                    self.report.replayed += 1;
+                    self.report.failed += !ok as usize;
+                }
+                None => self.report.skipped += 1,
+            }
+        }
+
+        /// Returns whether the call succeeded, or `None` if it was skipped.
+        unsafe fn replay_entry<O: OomHandler>(
+            &mut self,
+            talc: &mut Talc<O>,
+            entry: TraceEntry,
+        ) -> Option<bool> {
+            match entry {
+                TraceEntry::Malloc { layout, hint, ptr } if ptr != 0 => {
+                    let allocation = talc.malloc_hinted(layout, hint).ok();
+                    if let Some(allocation) = allocation {
+                        self.insert(ptr, allocation, layout);
+                    }
+
+                    Some(allocation.is_some())
+                }
+                TraceEntry::Free { ptr, .. } => {
+                    let (allocation, layout) = self.live.remove(&ptr)?;
+                    talc.free(allocation, layout);
+                    self.allocated_bytes -= layout.size();
+
+                    Some(true)
+                }
+                TraceEntry::Grow { ptr, new_size, new_ptr, .. } if new_ptr != 0 => {
+                    let (allocation, layout) = self.live.get(&ptr).copied()?;
+                    if new_size < layout.size() {
+                        return None;
+                    }
+
+                    // later entries refer to the allocation by its new address regardless
+                    match talc.grow(allocation, layout, new_size) {
+                        Ok(grown) => {
+                            self.resize(ptr, new_ptr, grown, layout, new_size);
+                            Some(true)
+                        }
+                        Err(_) => {
+                            self.resize(ptr, new_ptr, allocation, layout, layout.size());
+                            Some(false)
+                        }
+                    }
+                }
+                TraceEntry::GrowInPlace { ptr, new_size, ok: true, .. } => {
+                    let (allocation, layout) = self.live.get(&ptr).copied()?;
+                    if new_size < layout.size() {
+                        return None;
+                    }
+
+                    let grown = talc.grow_in_place(allocation, layout, new_size).is_ok();
+                    if grown {
+                        self.resize(ptr, ptr, allocation, layout, new_size);
+                    }
+
+                    Some(grown)
+                }
+                TraceEntry::Shrink { ptr, new_size, .. } => {
+                    let (allocation, layout) = self.live.get(&ptr).copied()?;
+                    if new_size > layout.size() {
+                        return None;
+                    }
+
+                    talc.shrink(allocation, layout, new_size);
+                    self.resize(ptr, ptr, allocation, layout, new_size);
+
+                    Some(true)
+                }
+                TraceEntry::Realign { ptr, align, new_ptr, .. } if new_ptr != 0 => {
+                    let (allocation, layout) = self.live.get(&ptr).copied()?;
+                    self.live.remove(&ptr);
+
+                    // later entries refer to the allocation by its new address regardless
+                    match talc.realign_in_place(allocation, layout, align) {
+                        Ok(realigned) => {
+                            let layout = Layout::from_size_align_unchecked(layout.size(), align);
+                            self.live.insert(new_ptr, (realigned, layout));
+                            Some(true)
+                        }
+                        Err(_) => {
+                            self.live.insert(new_ptr, (allocation, layout));
+                            Some(false)
+                        }
+                    }
+                }
+                // the call failed when recorded
+                _ => None,
+            }
+        }
+
+        /// Free all allocations made by the replay that remain.
+        ///
+        /// # Safety
+        /// See [`Replayer::replay`].
+        pub unsafe fn release<O: OomHandler>(&mut self, talc: &mut Talc<O>) {
+            for (_, (allocation, layout)) in self.live.drain() {
+                talc.free(allocation, layout);
+            }
+
+            self.allocated_bytes = 0;
+        }
+
+        fn insert(&mut self, ptr: usize, allocation: NonNull<u8>, layout: Layout) {
+            self.live.insert(ptr, (allocation, layout));
+            self.allocated_bytes += layout.size();
+            self.report.peak_allocated_bytes =
+                self.report.peak_allocated_bytes.max(self.allocated_bytes);
+        }
+
+        unsafe fn resize(
+            &mut self,
+            ptr: usize,
+            new_ptr: usize,
+            allocation: NonNull<u8>,
+            layout: Layout,
+            new_size: usize,
+        ) {
+            self.live.remove(&ptr);
+            self.allocated_bytes -= layout.size();
+            self.insert(
+                new_ptr,
+                allocation,
+                Layout::from_size_align_unchecked(new_size, layout.align()),
+            );
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use core::alloc::Layout;
+    use std::{sync::Mutex, vec::Vec};
+
+    use crate::*;
+
+    static TRACE: Mutex<Vec<TraceEntry>> = Mutex::new(Vec::new());
+
+    #[test]
+    fn trace_replay_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        talc.set_trace_sink(Some(|entry| TRACE.lock().unwrap().push(entry)));
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        unsafe {
+            let a = talc.malloc(layout).unwrap();
+            let b = talc.malloc_hinted(layout, LifetimeHint::LongLived).unwrap();
+            assert!(talc.malloc(Layout::from_size_align(1 << 20, 8).unwrap()).is_err());
+            let a = talc.grow(a, layout, 128).unwrap();
+            talc.shrink(b, layout, 16);
+            talc.free(b, Layout::from_size_align(16, 8).unwrap());
+
+            // growing by reallocating is recorded as a single entry
+            let blocker = talc.malloc(layout).unwrap();
+            let a = talc.grow(a, Layout::from_size_align(128, 8).unwrap(), 4096).unwrap();
+            talc.free(blocker, layout);
+            talc.free(a, Layout::from_size_align(4096, 8).unwrap());
+        }
+
+        talc.set_trace_sink(None);
+        let trace = core::mem::take(&mut *TRACE.lock().unwrap());
+        assert_eq!(trace.len(), 10);
+        assert!(matches!(trace[2], TraceEntry::Malloc { ptr: 0, .. }));
+        assert!(matches!(trace[7], TraceEntry::Grow { new_size: 4096, .. }));
+
+        // replay against a smaller arena, in which the last growth fails
+        let mut replay_arena = [0u8; 3000];
+        let mut replay_talc = Talc::new(ErrOnOom);
+        unsafe { replay_talc.claim(replay_arena.as_mut().into()).unwrap() };
+
+        let mut replayer = Replayer::new();
+        unsafe { replayer.replay_all(&mut replay_talc, trace) };
+        assert_eq!(
+            replayer.report(),
+            ReplayReport { replayed: 9, failed: 1, skipped: 1, peak_allocated_bytes: 192 }
+        );
+
+        // the allocation that failed to grow was still freed, with its actual layout
+        unsafe { replayer.release(&mut replay_talc) };
+        #[cfg(feature = "fastbins")]
+        replay_talc.flush_fastbins();
+        assert_eq!(replay_talc.report().free_chunk_count, 1);
+    }
+}
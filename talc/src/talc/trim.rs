@@ -0,0 +1,120 @@
+//! Release free memory at the ends of a heap, up to a budget.
+
+use super::{Talc, MIN_CHUNK_SIZE, TAG_SIZE};
+use crate::{ptr_utils::WORD_SIZE, OomHandler, Span};
+
+impl<O: OomHandler> Talc<O> {
+    /// Release up to `max_bytes` of free memory from the ends of `heap`, returning the
+    /// resulting heap.
+    ///
+    /// The top of the heap is trimmed first, then the bottom. The released memory is
+    /// the difference between `heap` and the returned heap, which is never more than
+    /// `max_bytes`, and can be handed back to a page allocator or another subsystem.
+    /// Use the returned heap in place of `heap` from then on.
+    ///
+    /// If no memory is allocated within `heap` and `max_bytes` covers all of it, the heap
+    /// is released entirely, and the returned span is empty, see [`Talc::truncate`].
+    ///
+    /// # Safety
+    /// `heap` must be the return value of a heap-manipulation function
+    /// of this allocator instance.
+    ///
+    /// # Panics
+    /// Panics if the heap metadata is not yet allocated, see [`Talc::claim`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// let heap = unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+    ///
+    /// // under memory pressure, give back some of the free memory
+    /// let trimmed = unsafe { talc.trim(heap, 4000) };
+    /// let released = heap.size() - trimmed.size();
+    /// assert!(released > 0 && released <= 4000);
+    /// ```
+    pub unsafe fn trim(&mut self, heap: Span, max_bytes: usize) -> Span {
+        // cached and quarantined chunks can't be released
+        #[cfg(feature = "fastbins")]
+        self.flush_fastbins();
+        #[cfg(feature = "quarantine")]
+        self.flush_quarantine();
+
+        let (base, acme) = heap.get_base_acme().unwrap();
+        let max_bytes = max_bytes & !(WORD_SIZE - 1);
+
+        let (alloc_base, alloc_acme) = match self.get_allocated_span(heap).get_base_acme() {
+            Some(base_acme) => base_acme,
+            None => {
+                if max_bytes >= heap.size() {
+                    return self.truncate(heap, Span::empty());
+                }
+
+                // only the base tag remains
+                (base, base.add(TAG_SIZE))
+            }
+        };
+
+        // the free chunks at either end may shrink, but not below the minimum chunk size
+        let trimmed_size = |free_size: usize, budget: usize| {
+            let trimmed = free_size.min(budget);
+            match free_size - trimmed {
+                0 => trimmed,
+                rest if rest >= MIN_CHUNK_SIZE => trimmed,
+                _ => free_size.saturating_sub(MIN_CHUNK_SIZE),
+            }
+        };
+
+        let top = trimmed_size(acme as usize - alloc_acme as usize, max_bytes);
+        let bottom = trimmed_size(alloc_base as usize - base as usize, max_bytes - top);
+
+        if top == 0 && bottom == 0 {
+            return heap;
+        }
+
+        self.truncate(heap, Span::new(base.add(bottom), acme.sub(top)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn trim_test() {
+        let mut arena = [0u8; 20000];
+        let mut talc = Talc::new(ErrOnOom);
+        let arena_span = Span::from(arena.as_mut());
+        let layout = Layout::from_size_align(100, 8).unwrap();
+
+        unsafe {
+            // the first heap holds the metadata, so fill it up, leaving the second to work with
+            talc.claim(arena_span.truncate(0, 10000)).unwrap();
+            while talc.malloc(Layout::new::<usize>()).is_ok() {}
+            let second = talc.claim(arena_span.truncate(10000, 0)).unwrap();
+
+            // nothing to release
+            assert_eq!(talc.trim(second, 0), second);
+
+            // trim the top
+            let a = talc.malloc(layout).unwrap();
+            let heap = talc.trim(second, 1000);
+            assert_eq!(heap, second.truncate(0, 1000));
+
+            // then the bottom, but not past the allocations
+            let b = talc.malloc_hinted(layout, LifetimeHint::LongLived).unwrap();
+            talc.free(a, layout);
+            let heap = talc.trim(heap, 1000000);
+            assert!(heap.contains(b.as_ptr()));
+            assert!(heap.size() < 1000);
+
+            // release the rest once it's free
+            talc.free(b, layout);
+            assert!(talc.trim(heap, heap.size()).is_empty());
+        }
+    }
+}
@@ -0,0 +1,279 @@
+//! Keep track of zeroed memory that hasn't been allocated since it was claimed,
+//! such that zeroed allocations and growth into it needn't be cleared.
+//!
+//! The untouched memory is the top of a free chunk, and is only written to by the
+//! allocator where the metadata of the free chunk containing it is, at either end.
+//! Allocating or growing a chunk into it moves its base above the chunk.
+//!
+//! The tracking needs the `claim_zeroed` feature. Without it, zeroed allocations
+//! are always cleared.
+
+use core::{
+    alloc::Layout,
+    ptr::{null_mut, NonNull},
+};
+
+#[cfg(all(feature = "claim_zeroed", not(feature = "poison")))]
+use super::{BIN_ARRAY_SIZE, TAG_SIZE};
+use super::{Talc, GAP_HIGH_SIZE_OFFSET, GAP_LOW_SIZE_OFFSET};
+#[cfg(feature = "claim_zeroed")]
+use crate::Span;
+use crate::{ptr_utils::WORD_SIZE, OomHandler};
+
+impl<O: OomHandler> Talc<O> {
+    /// Like [`claim`](Talc::claim), for memory that's known to be zeroed, such as `.bss`
+    /// or RAM that's cleared on boot.
+    ///
+    /// Allocations made out of this memory with [`malloc_zeroed`](Talc::malloc_zeroed)
+    /// aren't cleared until it's been allocated once. Only the memory of the latest call
+    /// is tracked. With the `poison` feature, claimed memory is poisoned, so this is
+    /// equivalent to `claim`.
+    ///
+    /// # Safety
+    /// As for [`claim`](Talc::claim), and the memory within `memory` must be zeroed.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim_zeroed(arena.as_mut().into()).unwrap(); }
+    ///
+    /// // this doesn't clear the memory, as it's never been allocated
+    /// let layout = Layout::new::<[u64; 1000]>();
+    /// let ptr = unsafe { talc.malloc_zeroed(layout).unwrap() };
+    /// assert!(unsafe { (*ptr.cast::<[u64; 1000]>().as_ptr()).iter().all(|&x| x == 0) });
+    /// ```
+    #[cfg(feature = "claim_zeroed")]
+    pub unsafe fn claim_zeroed(&mut self, memory: Span) -> Result<Span, ()> {
+        let establishes_metadata = self.bins.is_null();
+        let heap = self.claim(memory)?;
+
+        #[cfg(not(feature = "poison"))]
+        {
+            let (base, acme) = heap.get_base_acme().unwrap();
+
+            // the free chunk is above the heap's base tag, and the metadata if it's allocated here
+            let mut free_base = base.add(TAG_SIZE);
+            if establishes_metadata {
+                free_base = free_base.add(BIN_ARRAY_SIZE + TAG_SIZE);
+            }
+
+            self.untouched = (free_base.min(acme), acme);
+        }
+        #[cfg(feature = "poison")]
+        let _ = establishes_metadata;
+
+        Ok(heap)
+    }
+
+    /// Allocate zeroed memory.
+    ///
+    /// With the `claim_zeroed` feature, only memory that's been allocated before is
+    /// cleared, see `Talc::claim_zeroed`.
+    ///
+    /// # Safety
+    /// As for [`malloc`](Talc::malloc).
+    pub unsafe fn malloc_zeroed(&mut self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        self.malloc_zeroed_from(layout, 0)
+    }
+
+    /// Like [`malloc_zeroed`](Talc::malloc_zeroed), but only zeroes the allocation from `offset`.
+    pub(crate) unsafe fn malloc_zeroed_from(
+        &mut self,
+        layout: Layout,
+        offset: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        let untouched = self.untouched();
+        let ptr = self.malloc(layout)?;
+
+        zero_unless_untouched(untouched, ptr.as_ptr().add(offset), ptr.as_ptr().add(layout.size()));
+        Ok(ptr)
+    }
+
+    /// Grow a previously allocated/reallocated region of memory to `new_size`, zeroing
+    /// the memory above the old size.
+    ///
+    /// This is like [`grow`](Talc::grow), followed by zeroing the new memory, but the
+    /// contents of a moved allocation aren't zeroed first. With the `claim_zeroed` feature,
+    /// memory that hasn't been allocated since it was claimed with `Talc::claim_zeroed`
+    /// isn't cleared either.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given `layout`.
+    /// `new_size` must be larger or equal to `layout.size()`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u8; 100]>();
+    /// unsafe {
+    ///     let ptr = talc.malloc(layout).unwrap();
+    ///     ptr.as_ptr().write_bytes(7, layout.size());
+    ///
+    ///     let ptr = talc.grow_zeroed(ptr, layout, 1000).unwrap();
+    ///     assert_eq!(ptr.as_ptr().add(99).read(), 7);
+    ///     assert!((100..1000).all(|i| ptr.as_ptr().add(i).read() == 0));
+    /// }
+    /// ```
+    pub unsafe fn grow_zeroed(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        let new_layout = Layout::from_size_align(new_size, old_layout.align()).map_err(|_| ())?;
+        if old_layout.size() == 0 {
+            return self.malloc_zeroed(new_layout);
+        }
+
+        if let Ok(ptr) = self.grow_in_place_zeroed(ptr, old_layout, new_size) {
+            return Ok(ptr);
+        }
+
+        if !self.is_movable(ptr, old_layout.size()) {
+            return Err(());
+        }
+
+        let allocation = self.malloc_zeroed_from(new_layout, old_layout.size())?;
+        allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), old_layout.size());
+        self.inherit_owner(ptr, old_layout.size(), allocation, new_size);
+        self.free(ptr, old_layout);
+        self.account_moved_realloc(old_layout.size(), new_size);
+
+        Ok(allocation)
+    }
+
+    /// Like [`grow_in_place`](Talc::grow_in_place), zeroing the memory above the old size.
+    pub(crate) unsafe fn grow_in_place_zeroed(
+        &mut self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_size: usize,
+    ) -> Result<NonNull<u8>, ()> {
+        let untouched = self.untouched();
+        let ptr = self.grow_in_place(ptr, old_layout, new_size)?;
+
+        zero_unless_untouched(
+            untouched,
+            ptr.as_ptr().add(old_layout.size()),
+            ptr.as_ptr().add(new_size),
+        );
+        Ok(ptr)
+    }
+
+    /// Returns the untouched memory, which is empty without the `claim_zeroed` feature.
+    #[inline]
+    fn untouched(&self) -> (*mut u8, *mut u8) {
+        #[cfg(feature = "claim_zeroed")]
+        return self.untouched;
+
+        #[cfg(not(feature = "claim_zeroed"))]
+        (null_mut(), null_mut())
+    }
+
+    /// Account for the chunk `[chunk_base, chunk_acme)` being allocated, or grown.
+    #[cfg(feature = "claim_zeroed")]
+    #[inline]
+    pub(super) fn touch(&mut self, chunk_base: *mut u8, chunk_acme: *mut u8) {
+        let (untouched_base, untouched_acme) = self.untouched;
+
+        if chunk_base < untouched_acme && chunk_acme > untouched_base {
+            self.untouched.0 = chunk_acme.min(untouched_acme);
+        }
+    }
+
+    /// Forget about the untouched memory if it's within `heap`, which is being truncated.
+    #[cfg(feature = "claim_zeroed")]
+    pub(super) fn forget_untouched_in(&mut self, heap: Span) {
+        if heap.contains(self.untouched.0) {
+            self.untouched = (null_mut(), null_mut());
+        }
+    }
+}
+
+/// Zero `[base, acme)`, newly allocated memory, besides what was in `untouched` before
+/// it was allocated.
+unsafe fn zero_unless_untouched(untouched: (*mut u8, *mut u8), base: *mut u8, acme: *mut u8) {
+    let (untouched_base, untouched_acme) = untouched;
+
+    // the free chunk's metadata might be left at either end of the untouched memory
+    let mut zeroed_base = acme;
+    let mut zeroed_acme = acme;
+    if untouched_base < untouched_acme {
+        zeroed_base = untouched_base.add(GAP_LOW_SIZE_OFFSET + WORD_SIZE).max(base);
+        zeroed_acme = untouched_acme.sub(GAP_HIGH_SIZE_OFFSET).min(acme);
+    }
+
+    if zeroed_base < zeroed_acme {
+        base.write_bytes(0, zeroed_base as usize - base as usize);
+        zeroed_acme.write_bytes(0, acme as usize - zeroed_acme as usize);
+    } else {
+        base.write_bytes(0, acme as usize - base as usize);
+    }
+}
+
+#[cfg(all(test, feature = "claim_zeroed"))]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn malloc_zeroed_test() {
+        let mut metadata_arena = [0u8; 1500];
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(metadata_arena.as_mut().into()).unwrap() };
+        let heap = unsafe { talc.claim_zeroed(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        let is_zeroed = |ptr: core::ptr::NonNull<u8>| unsafe {
+            (0..layout.size()).all(|i| *ptr.as_ptr().add(i) == 0)
+        };
+
+        unsafe {
+            let a = talc.malloc_zeroed(layout).unwrap();
+            assert!(is_zeroed(a));
+            a.as_ptr().write_bytes(0xAB, layout.size());
+            let b = talc.malloc_zeroed(layout).unwrap();
+            assert!(is_zeroed(b));
+
+            // memory that's been allocated is cleared
+            talc.free(a, layout);
+            let c = talc.malloc_zeroed(layout).unwrap();
+            assert!(is_zeroed(c));
+
+            // b's memory is cleared, as is the free chunk's metadata that was above it
+            b.as_ptr().write_bytes(0xAB, layout.size());
+            talc.free(b, layout);
+            let d = talc.malloc_zeroed(Layout::from_size_align(3000, 8).unwrap()).unwrap();
+            assert!((0..3000).all(|i| *d.as_ptr().add(i) == 0));
+
+            // growing clears the memory above the old size, whether in place or moved
+            let e = talc.grow_zeroed(c, layout, 1500).unwrap();
+            assert!((0..1500).all(|i| *e.as_ptr().add(i) == 0));
+            d.as_ptr().write_bytes(0xAB, 3000);
+            let d = talc.grow_zeroed(d, Layout::from_size_align(3000, 8).unwrap(), 4000).unwrap();
+            assert!((3000..4000).all(|i| *d.as_ptr().add(i) == 0));
+
+            talc.free(e, Layout::from_size_align(1500, 8).unwrap());
+            talc.free(d, Layout::from_size_align(4000, 8).unwrap());
+        }
+
+        // the untouched memory is forgotten once the heap is released
+        unsafe {
+            talc.truncate(heap, Span::empty());
+            heap.get_base_acme().unwrap().0.write_bytes(0xFF, heap.size());
+            talc.claim(heap).unwrap();
+        }
+        let f = unsafe { talc.malloc_zeroed(layout).unwrap() };
+        assert!(is_zeroed(f));
+    }
+}
@@ -0,0 +1,92 @@
+//! Store a word of the caller's choosing alongside each allocation.
+
+use core::ptr::NonNull;
+
+use super::{header_from_alloc_ptr, Talc};
+use crate::OomHandler;
+
+impl<O: OomHandler> Talc<O> {
+    /// Set the user data word of an allocation.
+    ///
+    /// The word is stored in the allocation's header, and can hold anything, such as a type ID,
+    /// a reference count, or a handle, saving the need for a header of your own or a side table.
+    /// It's zero for new allocations, and kept when an allocation is reallocated.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given a layout of `size`.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// # use core::alloc::Layout;
+    /// const MESSAGE_TYPE_ID: usize = 7;
+    ///
+    /// let mut arena = [0u8; 10000];
+    /// let mut talc = Talc::new(ErrOnOom);
+    /// unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+    ///
+    /// let layout = Layout::new::<[u8; 64]>();
+    /// unsafe {
+    ///     let message = talc.malloc(layout).unwrap();
+    ///     assert_eq!(talc.user_data(message, layout.size()), 0);
+    ///
+    ///     talc.set_user_data(message, layout.size(), MESSAGE_TYPE_ID);
+    ///     assert_eq!(talc.user_data(message, layout.size()), MESSAGE_TYPE_ID);
+    /// }
+    /// ```
+    pub unsafe fn set_user_data(&mut self, ptr: NonNull<u8>, size: usize, data: usize) {
+        (*header_from_alloc_ptr(ptr.as_ptr(), size)).user_data = data;
+    }
+
+    /// Returns the user data word of an allocation, see [`Talc::set_user_data`].
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated or reallocated given a layout of `size`.
+    pub unsafe fn user_data(&self, ptr: NonNull<u8>, size: usize) -> usize {
+        (*header_from_alloc_ptr(ptr.as_ptr(), size)).user_data
+    }
+}
+
+#[cfg(all(test, feature = "lock_api"))]
+mod tests {
+    use core::{
+        alloc::{GlobalAlloc, Layout},
+        ptr::NonNull,
+    };
+
+    use crate::*;
+
+    #[test]
+    fn user_data_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        unsafe {
+            let a = talck.lock().malloc(layout).unwrap();
+            let b = talck.lock().malloc(layout).unwrap();
+            talck.lock().set_user_data(a, layout.size(), 1);
+            talck.lock().set_user_data(b, layout.size(), usize::MAX);
+            assert_eq!(talck.lock().user_data(a, layout.size()), 1);
+
+            // writing to the allocation doesn't disturb it
+            a.as_ptr().write_bytes(0xFF, layout.size());
+            assert_eq!(talck.lock().user_data(a, layout.size()), 1);
+
+            // kept when moved by reallocation
+            let a = talck.realloc(a.as_ptr(), layout, 1000);
+            assert_eq!(talck.lock().user_data(NonNull::new(a).unwrap(), 1000), 1);
+            assert_eq!(talck.lock().user_data(b, layout.size()), usize::MAX);
+
+            // and when reallocated in place
+            talck.lock().shrink(b, layout, 8);
+            assert_eq!(talck.lock().user_data(b, 8), usize::MAX);
+
+            talck.dealloc(a, Layout::from_size_align(1000, 8).unwrap());
+            let c = talck.lock().malloc(layout).unwrap();
+            assert_eq!(talck.lock().user_data(c, layout.size()), 0);
+        }
+    }
+}
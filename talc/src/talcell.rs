@@ -0,0 +1,216 @@
+//! Home of Talcell, a `RefCell`-based wrapper of Talc for single-threaded environments.
+
+use crate::{
+    access::{self, TalcAccess},
+    talc::Talc,
+    OomHandler,
+};
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::{RefCell, RefMut},
+    ptr::{null_mut, NonNull},
+};
+
+#[cfg(feature = "allocator")]
+use core::alloc::{AllocError, Allocator};
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator")))]
+use allocator_api2::alloc::{AllocError, Allocator};
+
+/// Talc cell, contains a [`RefCell`]-wrapped [`Talc`].
+///
+/// Unlike [`Talck`](crate::Talck), this doesn't need a lock, nor does it require
+/// asserting that the environment is single-threaded. In turn, `Talcell` isn't `Sync`,
+/// except on WebAssembly without the `atomics` target feature, where it's suitable
+/// as a `#[global_allocator]`.
+///
+/// Re-entrant allocation (e.g. allocating with the global allocator from
+/// within an [`OomHandler`] while `Talcell` is the global allocator) fails
+/// instead of causing undefined behavior. Re-entrant deallocation leaks the memory.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::{GlobalAlloc, Layout};
+/// let mut arena = [0u8; 10000];
+/// let talcell = Talc::new(ErrOnOom).cell();
+/// unsafe { talcell.borrow_mut().claim(arena.as_mut().into()).unwrap(); }
+///
+/// let layout = Layout::new::<[u64; 8]>();
+/// let ptr = unsafe { talcell.alloc(layout) };
+/// assert!(!ptr.is_null());
+/// unsafe { talcell.dealloc(ptr, layout); }
+/// ```
+#[derive(Debug)]
+pub struct Talcell<O: OomHandler> {
+    cell: RefCell<Talc<O>>,
+}
+
+// SAFETY: WebAssembly without atomics can't share memory between threads.
+#[cfg(all(target_family = "wasm", not(target_feature = "atomics")))]
+unsafe impl<O: OomHandler> Sync for Talcell<O> {}
+
+impl<O: OomHandler> Talcell<O> {
+    /// Create a new `Talcell`.
+    pub const fn new(talc: Talc<O>) -> Self {
+        Self { cell: RefCell::new(talc) }
+    }
+
+    /// Mutably borrow the inner `Talc`.
+    ///
+    /// # Panics
+    /// Panics if the inner `Talc` is currently borrowed.
+    pub fn borrow_mut(&self) -> RefMut<'_, Talc<O>> {
+        self.cell.borrow_mut()
+    }
+
+    /// Try to mutably borrow the inner `Talc`.
+    pub fn try_borrow_mut(&self) -> Option<RefMut<'_, Talc<O>>> {
+        self.cell.try_borrow_mut().ok()
+    }
+
+    /// Retrieve the inner `Talc`.
+    pub fn into_inner(self) -> Talc<O> {
+        self.cell.into_inner()
+    }
+}
+
+impl<O: OomHandler> TalcAccess for Talcell<O> {
+    type Oom = O;
+    type Guard<'a>
+        = RefMut<'a, Talc<O>>
+    where
+        Self: 'a;
+
+    // nothing else can use the Talc in the meantime
+    const RELEASE_TO_COPY_ABOVE: usize = usize::MAX;
+
+    fn access(&self) -> Option<Self::Guard<'_>> {
+        self.try_borrow_mut()
+    }
+
+    unsafe fn free(&self, ptr: NonNull<u8>, layout: Layout) {
+        // the memory is leaked if the Talc is in use, e.g. by the OOM handler
+        if let Some(mut talc) = self.try_borrow_mut() {
+            talc.free(ptr, layout);
+        }
+    }
+}
+
+unsafe impl<O: OomHandler> GlobalAlloc for Talcell<O> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.try_borrow_mut() {
+            Some(mut talc) => talc.malloc(layout).map_or(null_mut(), |nn| nn.as_ptr()),
+            None => null_mut(),
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match self.try_borrow_mut() {
+            Some(mut talc) => talc.malloc_zeroed(layout).map_or(null_mut(), |nn| nn.as_ptr()),
+            None => null_mut(),
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        TalcAccess::free(self, NonNull::new_unchecked(ptr), layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> *mut u8 {
+        access::realloc(self, ptr, old_layout, new_size)
+    }
+}
+
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+unsafe impl<O: OomHandler> Allocator for Talcell<O> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        access::allocate(self, layout, false)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        access::allocate(self, layout, true)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        if layout.size() != 0 {
+            TalcAccess::free(self, ptr, layout);
+        }
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        access::grow(self, ptr, old_layout, new_layout, false)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        access::grow(self, ptr, old_layout, new_layout, true)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        access::shrink(self, ptr, old_layout, new_layout)
+    }
+}
+
+impl<O: OomHandler> Talc<O> {
+    /// Wrap in `Talcell`, a [`RefCell`]-based wrapper struct for single-threaded use.
+    ///
+    /// This implements the [`GlobalAlloc`](core::alloc::GlobalAlloc) trait and provides
+    /// access to the [`Allocator`](core::alloc::Allocator) API.
+    pub const fn cell(self) -> Talcell<O> {
+        Talcell::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ErrOnOom, Span};
+
+    #[test]
+    fn talcell_reentrancy_test() {
+        let mut arena = [0u8; 2000];
+        let talcell = Talc::new(ErrOnOom).cell();
+        unsafe { talcell.borrow_mut().claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100, 8).unwrap();
+
+        unsafe {
+            let ptr = talcell.alloc(layout);
+            assert!(!ptr.is_null());
+            ptr.write_bytes(0xAB, layout.size());
+
+            let ptr = talcell.realloc(ptr, layout, 500);
+            assert!(!ptr.is_null());
+            assert!(core::slice::from_raw_parts(ptr, layout.size()).iter().all(|&b| b == 0xAB));
+
+            // allocation fails while the Talc is borrowed, instead of aliasing it,
+            // and deallocation leaks the memory instead of panicking
+            let leaked = talcell.alloc(layout);
+            let guard = talcell.borrow_mut();
+            assert!(talcell.alloc(layout).is_null());
+            assert!(talcell.realloc(ptr, Layout::from_size_align(500, 8).unwrap(), 600).is_null());
+            talcell.dealloc(leaked, layout);
+            drop(guard);
+
+            talcell.dealloc(ptr, Layout::from_size_align(500, 8).unwrap());
+        }
+
+        let talc = talcell.into_inner();
+        assert!(unsafe { talc.get_allocated_span(Span::from(arena.as_mut())) }.size() < 2000);
+    }
+}
@@ -1,10 +1,26 @@
 //! Home of Talck, a mutex-locked wrapper of Talc.
 
-use crate::{talc::Talc, OomHandler};
+mod child_heap;
+#[cfg(feature = "deferred_frees")]
+mod deferred;
+mod extend_guard;
+#[cfg(feature = "std")]
+mod panic_hook;
+#[cfg(feature = "reentrancy_check")]
+mod reentrancy;
+
+use crate::{
+    access::{self, TalcAccess},
+    talc::Talc,
+    OomHandler, Span,
+};
+pub use child_heap::ChildHeap;
+pub use extend_guard::ExtendGuard;
+#[cfg(feature = "deferred_frees")]
+use deferred::DeferredFrees;
 
 use core::{
     alloc::{GlobalAlloc, Layout},
-    cmp::Ordering,
     ptr::{null_mut, NonNull},
 };
 
@@ -14,15 +30,35 @@ use core::alloc::{AllocError, Allocator};
 #[cfg(all(feature = "allocator-api2", not(feature = "allocator")))]
 use allocator_api2::alloc::{AllocError, Allocator};
 
-#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
-pub(crate) fn is_aligned_to(ptr: *mut u8, align: usize) -> bool {
-    (ptr as usize).trailing_zeros() >= align.trailing_zeros()
-}
+#[cfg(feature = "counters")]
+use crate::talc::counters::Stats;
 
 const RELEASE_LOCK_ON_REALLOC_LIMIT: usize = 0x10000;
 
+/// The guard of a lock taken by the allocator's own calls.
+#[cfg(not(feature = "reentrancy_check"))]
+type CallGuard<'a, R, O> = lock_api::MutexGuard<'a, R, Talc<O>>;
+#[cfg(feature = "reentrancy_check")]
+type CallGuard<'a, R, O> = reentrancy::Held<'a, lock_api::MutexGuard<'a, R, Talc<O>>>;
+
 /// Talc lock, contains a mutex-locked [`Talc`].
 ///
+/// With the `deferred_frees` feature, frees can be deferred with
+/// [`defer_free`](Talck::defer_free), which pushes them onto a lock-free stack instead of
+/// waiting for the lock. This is also done whenever the lock is contended when memory
+/// is deallocated.
+/// Deferred frees are performed the next time the lock is acquired, so until then,
+/// the memory isn't available, and counters, reports, and whether heaps are empty
+/// lag behind. Allocations smaller than two `usize`s can't be deferred, nor can any
+/// allocations on targets without pointer-sized atomic compare-and-swap.
+///
+/// With the `reentrancy_check` and `std` features, locking from a thread that holds
+/// the lock for an allocator call aborts with a message, rather than deadlocking.
+/// This happens if the OOM handler allocates through the global allocator, for example.
+/// Locks taken with [`lock`](Talck::lock) and [`try_lock`](Talck::try_lock) aren't
+/// recorded, as their guards are handed out. Without `std`, threads can't be told apart,
+/// so the lock is taken as usual.
+///
 /// # Example
 /// ```rust
 /// # use talc::*;
@@ -32,103 +68,234 @@ const RELEASE_LOCK_ON_REALLOC_LIMIT: usize = 0x10000;
 #[derive(Debug)]
 pub struct Talck<R: lock_api::RawMutex, O: OomHandler> {
     mutex: lock_api::Mutex<R, Talc<O>>,
+    #[cfg(feature = "deferred_frees")]
+    deferred: DeferredFrees,
+    #[cfg(feature = "reentrancy_check")]
+    reentrancy: reentrancy::ReentrancyCheck,
 }
 
 impl<R: lock_api::RawMutex, O: OomHandler> Talck<R, O> {
     /// Create a new `Talck`.
     pub const fn new(talc: Talc<O>) -> Self {
-        Self { mutex: lock_api::Mutex::new(talc) }
+        Self {
+            mutex: lock_api::Mutex::new(talc),
+            #[cfg(feature = "deferred_frees")]
+            deferred: DeferredFrees::new(),
+            #[cfg(feature = "reentrancy_check")]
+            reentrancy: reentrancy::ReentrancyCheck::new(),
+        }
     }
 
     /// Lock the mutex and access the inner `Talc`.
+    ///
+    /// Any deferred frees are performed first, with the `deferred_frees` feature.
+    ///
+    /// # Aborts
+    /// With the `reentrancy_check` and `std` features, aborts if the current thread holds
+    /// the lock for an allocator call.
     pub fn lock(&self) -> lock_api::MutexGuard<R, Talc<O>> {
-        self.mutex.lock()
+        #[cfg(not(feature = "reentrancy_check"))]
+        #[cfg_attr(not(feature = "deferred_frees"), allow(unused_mut))]
+        let mut talc = self.mutex.lock();
+        #[cfg(feature = "reentrancy_check")]
+        #[cfg_attr(not(feature = "deferred_frees"), allow(unused_mut))]
+        let mut talc = self.reentrancy.lock(|| self.mutex.try_lock(), || self.mutex.lock());
+
+        #[cfg(feature = "deferred_frees")]
+        self.deferred.drain(&mut talc);
+        talc
     }
 
     /// Try to lock the mutex and access the inner `Talc`.
+    ///
+    /// Any deferred frees are performed first, with the `deferred_frees` feature.
     pub fn try_lock(&self) -> Option<lock_api::MutexGuard<R, Talc<O>>> {
-        self.mutex.try_lock()
+        #[cfg_attr(not(feature = "deferred_frees"), allow(unused_mut))]
+        let mut talc = self.mutex.try_lock()?;
+        #[cfg(feature = "deferred_frees")]
+        self.deferred.drain(&mut talc);
+        Some(talc)
     }
 
-    /// Retrieve the inner `Talc`.
-    pub fn into_inner(self) -> Talc<O> {
-        self.mutex.into_inner()
+    /// Lock the mutex for an allocator call, recording the current thread as the holder
+    /// until the guard is dropped, with the `reentrancy_check` feature.
+    #[inline]
+    fn lock_for_call(&self) -> CallGuard<'_, R, O> {
+        #[cfg(feature = "reentrancy_check")]
+        return self.reentrancy.held(self.lock());
+        #[cfg(not(feature = "reentrancy_check"))]
+        self.lock()
     }
-}
 
-unsafe impl<R: lock_api::RawMutex, O: OomHandler> GlobalAlloc for Talck<R, O> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.lock().malloc(layout).map_or(null_mut(), |nn| nn.as_ptr())
+    /// As [`lock_for_call`](Talck::lock_for_call), but without waiting on the lock.
+    #[cfg(feature = "deferred_frees")]
+    #[inline]
+    fn try_lock_for_call(&self) -> Option<CallGuard<'_, R, O>> {
+        #[cfg(feature = "reentrancy_check")]
+        return self.try_lock().map(|talc| self.reentrancy.held(talc));
+        #[cfg(not(feature = "reentrancy_check"))]
+        self.try_lock()
     }
 
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.lock().free(NonNull::new_unchecked(ptr), layout)
+    /// Claim `memory` as the allocator's first heap, unless a heap has already been
+    /// established, in which case `Err` is returned and `memory` is left untouched.
+    ///
+    /// This is intended for initializing a global allocator that may be reachable from
+    /// several boot paths. Checking and claiming happen under the lock, so only one
+    /// caller can succeed, and claiming the same memory twice, which would corrupt
+    /// every allocation made in between, is prevented.
+    ///
+    /// # Safety
+    /// See [`Talc::claim`].
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// static mut ARENA: [u8; 10000] = [0; 10000];
+    /// static TALCK: Talck<spin::Mutex<()>, ErrOnOom> = Talc::new(ErrOnOom).lock();
+    ///
+    /// let arena = Span::from_array(unsafe { core::ptr::addr_of_mut!(ARENA) });
+    /// assert!(unsafe { TALCK.claim_once(arena) }.is_ok());
+    /// assert!(unsafe { TALCK.claim_once(arena) }.is_err());
+    /// ```
+    pub unsafe fn claim_once(&self, memory: Span) -> Result<Span, ()> {
+        let mut talc = self.lock();
+
+        if talc.arena_base().is_some() {
+            return Err(());
+        }
+
+        talc.claim(memory)
     }
 
-    unsafe fn realloc(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> *mut u8 {
-        let nn_ptr = NonNull::new_unchecked(ptr);
+    /// Retrieve the inner `Talc`.
+    ///
+    /// Any deferred frees are performed first, with the `deferred_frees` feature.
+    pub fn into_inner(self) -> Talc<O> {
+        #[cfg_attr(not(feature = "deferred_frees"), allow(unused_mut))]
+        let mut talc = self.mutex.into_inner();
+        #[cfg(feature = "deferred_frees")]
+        self.deferred.drain(&mut talc);
+        talc
+    }
 
-        match new_size.cmp(&old_layout.size()) {
-            Ordering::Greater => {
-                // first try to grow in-place before manually re-allocating
+    /// Attempt to allocate without waiting on the lock.
+    ///
+    /// Returns `Err` immediately if the lock is held, as well as if allocation fails.
+    /// This makes it suitable for signal handlers and interrupt service routines,
+    /// provided the OOM handler doesn't block either.
+    ///
+    /// # Safety
+    /// As for [`Talc::malloc`].
+    pub unsafe fn try_alloc(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        self.try_lock().ok_or(())?.malloc(layout)
+    }
 
-                if let Ok(nn) = self.lock().grow_in_place(nn_ptr, old_layout, new_size) {
-                    return nn.as_ptr();
+    /// Queue `ptr` to be freed the next time the lock is acquired, without
+    /// acquiring or waiting on the lock. This is suitable for use in interrupt
+    /// handlers, which must not block on the heap lock.
+    ///
+    /// Returns `Err` if the free cannot be deferred, in which case `ptr` is untouched
+    /// and ownership of the memory remains with the caller. This is the case if
+    /// `layout.size()` is smaller than two `usize`s, or the target doesn't support
+    /// pointer-sized atomic compare-and-swap.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated by this allocator given `layout`.
+    #[cfg(feature = "deferred_frees")]
+    pub unsafe fn defer_free(&self, ptr: NonNull<u8>, layout: Layout) -> Result<(), ()> {
+        if self.deferred.push(ptr, layout) { Ok(()) } else { Err(()) }
+    }
+
+    /// Free `ptr`, unless the lock is contended, in which case defer it if possible
+    /// with the `deferred_frees` feature.
+    ///
+    /// # Safety
+    /// `ptr` must have been previously allocated given `layout`.
+    unsafe fn free_or_defer(&self, ptr: NonNull<u8>, layout: Layout) {
+        #[cfg(not(feature = "deferred_frees"))]
+        self.lock_for_call().free(ptr, layout);
+
+        #[cfg(feature = "deferred_frees")]
+        match self.try_lock_for_call() {
+            Some(mut talc) => talc.free(ptr, layout),
+            None => {
+                if !self.deferred.push(ptr, layout) {
+                    self.lock_for_call().free(ptr, layout);
                 }
+            }
+        }
+    }
+}
 
-                // grow in-place failed, reallocate manually
+impl<R: lock_api::RawMutex, O: OomHandler> TalcAccess for Talck<R, O> {
+    type Oom = O;
+    type Guard<'a>
+        = CallGuard<'a, R, O>
+    where
+        Self: 'a;
 
-                let new_layout = Layout::from_size_align_unchecked(new_size, old_layout.align());
+    const RELEASE_TO_COPY_ABOVE: usize = RELEASE_LOCK_ON_REALLOC_LIMIT;
 
-                let mut lock = self.lock();
-                let allocation = match lock.malloc(new_layout) {
-                    Ok(ptr) => ptr,
-                    Err(_) => return null_mut(),
-                };
+    fn access(&self) -> Option<Self::Guard<'_>> {
+        Some(self.lock_for_call())
+    }
 
-                if old_layout.size() > RELEASE_LOCK_ON_REALLOC_LIMIT {
-                    drop(lock);
-                    allocation.as_ptr().copy_from_nonoverlapping(ptr, old_layout.size());
-                    lock = self.lock();
-                } else {
-                    allocation.as_ptr().copy_from_nonoverlapping(ptr, old_layout.size());
-                }
+    unsafe fn free(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.free_or_defer(ptr, layout)
+    }
+}
 
-                lock.free(nn_ptr, old_layout);
-                allocation.as_ptr()
-            }
+unsafe impl<R: lock_api::RawMutex, O: OomHandler> GlobalAlloc for Talck<R, O> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.lock_for_call().malloc(layout).map_or(null_mut(), |nn| nn.as_ptr())
+    }
 
-            Ordering::Less => {
-                self.lock().shrink(NonNull::new_unchecked(ptr), old_layout, new_size);
-                ptr
-            }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.lock_for_call().malloc_zeroed(layout).map_or(null_mut(), |nn| nn.as_ptr())
+    }
 
-            Ordering::Equal => ptr,
-        }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.free_or_defer(NonNull::new_unchecked(ptr), layout)
     }
-}
 
-/// Convert a nonnull and length to a nonnull slice.
-#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
-fn nonnull_slice_from_raw_parts(ptr: NonNull<u8>, len: usize) -> NonNull<[u8]> {
-    unsafe { NonNull::new_unchecked(core::ptr::slice_from_raw_parts_mut(ptr.as_ptr(), len)) }
+    unsafe fn realloc(&self, ptr: *mut u8, old_layout: Layout, new_size: usize) -> *mut u8 {
+        access::realloc(self, ptr, old_layout, new_size)
+    }
 }
 
+/// As `Allocator` is also implemented for references to allocators, `&Talck` can be
+/// used as a copyable allocator handle, without requiring the `Talck` to be `static`.
+///
+/// # Example
+/// ```rust
+/// # #![cfg_attr(feature = "allocator", feature(allocator_api))]
+/// # #[cfg(feature = "allocator")]
+/// # {
+/// # use talc::*;
+/// let mut arena = [0u8; 10000];
+/// let mut talc = Talc::new(ErrOnOom);
+/// unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+/// let talck = talc.lock::<spin::Mutex<()>>();
+///
+/// let mut a = Vec::new_in(&talck);
+/// let b = Vec::<u32, _>::with_capacity_in(10, *a.allocator());
+/// a.push(b);
+/// # }
+/// ```
 #[cfg(any(feature = "allocator", feature = "allocator-api2"))]
 unsafe impl<R: lock_api::RawMutex, O: OomHandler> Allocator for Talck<R, O> {
     fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
-        if layout.size() == 0 {
-            return Ok(nonnull_slice_from_raw_parts(NonNull::dangling(), 0));
-        }
+        access::allocate(self, layout, false)
+    }
 
-        unsafe { self.lock().malloc(layout) }
-            .map(|nn| nonnull_slice_from_raw_parts(nn, layout.size()))
-            .map_err(|_| AllocError)
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        access::allocate(self, layout, true)
     }
 
     unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
         if layout.size() != 0 {
-            self.lock().free(ptr, layout);
+            self.free_or_defer(ptr, layout);
         }
     }
 
@@ -138,33 +305,7 @@ unsafe impl<R: lock_api::RawMutex, O: OomHandler> Allocator for Talck<R, O> {
         old_layout: Layout,
         new_layout: Layout,
     ) -> Result<NonNull<[u8]>, AllocError> {
-        debug_assert!(new_layout.size() >= old_layout.size());
-
-        if old_layout.size() == 0 {
-            return self.allocate(new_layout);
-        } else if is_aligned_to(ptr.as_ptr(), new_layout.align()) {
-            // alignment is fine, try to allocate in-place
-            if let Ok(nn) = self.lock().grow_in_place(ptr, old_layout, new_layout.size()) {
-                return Ok(nonnull_slice_from_raw_parts(nn, new_layout.size()));
-            }
-        }
-
-        // can't grow in place, reallocate manually
-
-        let mut lock = self.lock();
-        let allocation = lock.malloc(new_layout).map_err(|_| AllocError)?;
-
-        if old_layout.size() > RELEASE_LOCK_ON_REALLOC_LIMIT {
-            drop(lock);
-            allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), old_layout.size());
-            lock = self.lock();
-        } else {
-            allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), old_layout.size());
-        }
-
-        lock.free(ptr, old_layout);
-
-        Ok(nonnull_slice_from_raw_parts(allocation, new_layout.size()))
+        access::grow(self, ptr, old_layout, new_layout, false)
     }
 
     unsafe fn grow_zeroed(
@@ -173,17 +314,7 @@ unsafe impl<R: lock_api::RawMutex, O: OomHandler> Allocator for Talck<R, O> {
         old_layout: Layout,
         new_layout: Layout,
     ) -> Result<NonNull<[u8]>, AllocError> {
-        let res = self.grow(ptr, old_layout, new_layout);
-
-        if let Ok(allocation) = res {
-            allocation
-                .as_ptr()
-                .cast::<u8>()
-                .add(old_layout.size())
-                .write_bytes(0, new_layout.size() - old_layout.size());
-        }
-
-        res
+        access::grow(self, ptr, old_layout, new_layout, true)
     }
 
     unsafe fn shrink(
@@ -192,35 +323,64 @@ unsafe impl<R: lock_api::RawMutex, O: OomHandler> Allocator for Talck<R, O> {
         old_layout: Layout,
         new_layout: Layout,
     ) -> Result<NonNull<[u8]>, AllocError> {
-        debug_assert!(new_layout.size() <= old_layout.size());
-
-        if new_layout.size() == 0 {
-            if old_layout.size() > 0 {
-                self.lock().free(ptr, old_layout);
-            }
+        access::shrink(self, ptr, old_layout, new_layout)
+    }
+}
 
-            return Ok(nonnull_slice_from_raw_parts(NonNull::dangling(), 0));
-        }
+/// Measures the allocation statistics of a [`Talck`] over a span of execution,
+/// mirroring the `stats_alloc` crate's `Region`.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # let mut arena = [0u8; 10000];
+/// # let mut talc = Talc::new(ErrOnOom);
+/// # unsafe { talc.claim(arena.as_mut().into()).unwrap(); }
+/// # let talck = talc.lock::<spin::Mutex<()>>();
+/// let region = Region::new(&talck);
+///
+/// let layout = core::alloc::Layout::new::<[u64; 4]>();
+/// unsafe { core::alloc::GlobalAlloc::alloc(&talck, layout) };
+///
+/// assert_eq!(region.change().allocations, 1);
+/// assert_eq!(region.change().bytes_allocated, 32);
+/// ```
+#[cfg(feature = "counters")]
+#[derive(Debug)]
+pub struct Region<'a, R: lock_api::RawMutex, O: OomHandler> {
+    talck: &'a Talck<R, O>,
+    initial: Stats,
+}
 
-        if !is_aligned_to(ptr.as_ptr(), new_layout.align()) {
-            let mut lock = self.lock();
-            let allocation = lock.malloc(new_layout).map_err(|_| AllocError)?;
+#[cfg(feature = "counters")]
+impl<'a, R: lock_api::RawMutex, O: OomHandler> Region<'a, R, O> {
+    /// Begin measuring from the current state of `talck`.
+    pub fn new(talck: &'a Talck<R, O>) -> Self {
+        Self { talck, initial: talck.lock().get_counters().stats() }
+    }
 
-            if new_layout.size() > RELEASE_LOCK_ON_REALLOC_LIMIT {
-                drop(lock);
-                allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), new_layout.size());
-                lock = self.lock();
-            } else {
-                allocation.as_ptr().copy_from_nonoverlapping(ptr.as_ptr(), new_layout.size());
-            }
+    /// Returns the statistics at the start of the region.
+    pub fn initial(&self) -> Stats {
+        self.initial
+    }
 
-            lock.free(ptr, old_layout);
-            return Ok(nonnull_slice_from_raw_parts(allocation, new_layout.size()));
-        }
+    /// Returns the difference between the current and initial statistics.
+    pub fn change(&self) -> Stats {
+        self.talck.lock().get_counters().stats() - self.initial
+    }
 
-        self.lock().shrink(ptr, old_layout, new_layout.size());
+    /// Returns the difference between the current and initial statistics,
+    /// then restarts the region from the current state.
+    pub fn change_and_reset(&mut self) -> Stats {
+        let current = self.talck.lock().get_counters().stats();
+        let change = current - self.initial;
+        self.initial = current;
+        change
+    }
 
-        Ok(nonnull_slice_from_raw_parts(ptr, new_layout.size()))
+    /// Restarts the region from the current state.
+    pub fn reset(&mut self) {
+        self.initial = self.talck.lock().get_counters().stats();
     }
 }
 
@@ -260,5 +420,151 @@ impl TalckWasm {
     }
 }
 
+/// A [`Talck`] for single-threaded WASM, which grows WASM memory as needed
+/// with a [`WasmHandler`](crate::WasmHandler).
+///
+/// # Example
+/// ```rust,ignore
+/// #[global_allocator]
+/// static ALLOCATOR: talc::TalckWasm = unsafe { talc::TalckWasm::new_global() };
+/// ```
 #[cfg(all(target_family = "wasm"))]
 pub type TalckWasm = Talck<crate::locking::AssumeUnlockable, crate::WasmHandler>;
+
+#[cfg(all(target_family = "wasm", target_feature = "atomics"))]
+impl TalckWasmShared {
+    /// Create a [`Talck`] instance that takes control of WASM memory management,
+    /// for use with shared memory and multiple threads, e.g. web workers.
+    ///
+    /// # Safety
+    /// No other code may allocate memory using memory.grow,
+    /// unless it tolerates the allocator claiming it as well.
+    pub const unsafe fn new_global() -> Self {
+        Talc::new(crate::WasmHandler::new()).lock()
+    }
+}
+
+/// A [`Talck`] for multithreaded WASM, locked with a [`TicketMutex`](crate::locking::TicketMutex).
+#[cfg(all(target_family = "wasm", target_feature = "atomics"))]
+pub type TalckWasmShared = Talck<crate::locking::TicketMutex, crate::WasmHandler>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn try_alloc_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        unsafe {
+            let guard = talck.lock();
+            assert!(talck.try_alloc(layout).is_err());
+            drop(guard);
+
+            let ptr = talck.try_alloc(layout).unwrap();
+            talck.dealloc(ptr.as_ptr(), layout);
+
+            assert!(talck.try_alloc(Layout::from_size_align(100000, 8).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn claim_once_test() {
+        let mut first = [0u8; 10000];
+        let mut second = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+
+        let heap = unsafe { talck.claim_once(first.as_mut().into()).unwrap() };
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let ptr = unsafe { talck.alloc(layout) };
+        assert!(heap.contains(ptr));
+
+        // a second initialization is refused, whether over the same memory or not
+        assert!(unsafe { talck.claim_once(heap) }.is_err());
+        assert!(unsafe { talck.claim_once(second.as_mut().into()) }.is_err());
+        assert_eq!(talck.lock().report().free_chunk_count, 1);
+
+        unsafe { talck.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn realloc_in_place_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let small = Layout::from_size_align(100, 8).unwrap();
+        let big = Layout::from_size_align(1000, 8).unwrap();
+
+        unsafe {
+            // grow into the free memory above
+            let a = talck.alloc(small);
+            assert_eq!(talck.realloc(a, small, big.size()), a);
+
+            // shrink without moving, freeing the excess
+            assert_eq!(talck.realloc(a, big, small.size()), a);
+            let b = talck.alloc(small);
+            assert!(a < b && b < a.add(big.size()));
+
+            // the memory above is now occupied, so growing moves the allocation
+            a.write_bytes(0xAB, small.size());
+            let c = talck.realloc(a, small, big.size());
+            assert_ne!(c, a);
+            assert!((0..small.size()).all(|i| *c.add(i) == 0xAB));
+
+            talck.dealloc(b, small);
+            talck.dealloc(c, big);
+        }
+    }
+
+    /// Claims the spans it holds one at a time, counting how often it was called.
+    struct ClaimEach {
+        spans: [Span; 2],
+        calls: usize,
+    }
+
+    impl OomHandler for ClaimEach {
+        fn handle_oom(talc: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+            talc.oom_handler.calls += 1;
+            let index = talc.oom_handler.spans.iter().position(|span| !span.is_empty()).ok_or(())?;
+            let span = core::mem::take(&mut talc.oom_handler.spans[index]);
+            unsafe { talc.claim(span).map(|_| ()) }
+        }
+    }
+
+    static mut ARENA_A: [u8; 5000] = [0; 5000];
+    static mut ARENA_B: [u8; 5000] = [0; 5000];
+
+    // globals with stateful OOM handlers are built in const contexts, without lazy initialization
+    static CLAIM_ON_OOM: Talck<spin::Mutex<()>, ClaimOnOom> =
+        Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA_A))) })
+            .lock();
+    static CLAIM_EACH: Talck<spin::Mutex<()>, ClaimEach> = Talc::new(ClaimEach {
+        spans: [Span::empty(), Span::from_array(core::ptr::addr_of_mut!(ARENA_B))],
+        calls: 0,
+    })
+    .lock();
+
+    #[test]
+    fn const_global_test() {
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+
+        unsafe {
+            let a = CLAIM_ON_OOM.alloc(layout);
+            assert!(!a.is_null());
+            CLAIM_ON_OOM.dealloc(a, layout);
+
+            let b = CLAIM_EACH.alloc(layout);
+            assert!(!b.is_null());
+            assert_eq!(CLAIM_EACH.lock().oom_handler.calls, 1);
+            CLAIM_EACH.dealloc(b, layout);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! Home of ChildHeap, which returns a child allocator's region to its parent when dropped.
+
+use core::{mem::ManuallyDrop, ops::Deref};
+
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+use core::{alloc::Layout, ptr::NonNull};
+
+#[cfg(feature = "allocator")]
+use core::alloc::{AllocError, Allocator};
+
+#[cfg(all(feature = "allocator-api2", not(feature = "allocator")))]
+use allocator_api2::alloc::{AllocError, Allocator};
+
+use super::Talck;
+use crate::{OomHandler, Span};
+
+/// A child allocator carved out of a parent [`Talck`] by [`Talck::carve_child`].
+///
+/// This dereferences to the child's own `Talck`. When dropped, the child's region is
+/// returned to the parent as a single free chunk if the child has no outstanding
+/// allocations, else it's leaked, as the allocations might still be in use.
+/// Use [`release`](ChildHeap::release) to check whether this succeeds, or
+/// [`release_forcibly`](ChildHeap::release_forcibly) to free the region regardless.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::{GlobalAlloc, Layout};
+/// let mut arena = [0u8; 10000];
+/// let parent = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+/// unsafe { parent.lock().claim(arena.as_mut().into()).unwrap(); }
+///
+/// let child = parent.carve_child(4000, ErrOnOom).unwrap();
+/// let layout = Layout::new::<[u32; 8]>();
+/// unsafe {
+///     let ptr = child.alloc(layout);
+///     child.dealloc(ptr, layout);
+/// }
+///
+/// // the child is empty, so its region is returned to the parent
+/// assert!(child.release().is_ok());
+/// ```
+#[derive(Debug)]
+pub struct ChildHeap<'a, R: lock_api::RawMutex, O: OomHandler, C: OomHandler> {
+    parent: &'a Talck<R, O>,
+    child: Talck<R, C>,
+    region: Span,
+    /// The allocated span of the child's heap while it has no allocations.
+    empty_span: Span,
+}
+
+impl<R: lock_api::RawMutex, O: OomHandler> Talck<R, O> {
+    /// Carve a child allocator out of this one, see [`Talc::carve_child`](crate::Talc::carve_child).
+    ///
+    /// The child's region is returned when the [`ChildHeap`] is dropped, if the
+    /// child has no outstanding allocations.
+    pub fn carve_child<C: OomHandler>(
+        &self,
+        size: usize,
+        oom_handler: C,
+    ) -> Result<ChildHeap<'_, R, O, C>, ()> {
+        let (child, region) = self.lock().carve_child(size, oom_handler)?;
+
+        // SAFETY: the child claimed the whole region
+        let empty_span = unsafe { child.get_allocated_span(region) };
+
+        Ok(ChildHeap { parent: self, child: child.lock(), region, empty_span })
+    }
+}
+
+impl<'a, R: lock_api::RawMutex, O: OomHandler, C: OomHandler> ChildHeap<'a, R, O, C> {
+    /// Returns the region of the parent's heap that the child manages.
+    pub fn region(&self) -> Span {
+        self.region
+    }
+
+    /// Returns whether the child has no outstanding allocations.
+    pub fn is_empty(&self) -> bool {
+        // cached and quarantined chunks look allocated
+        #[cfg(feature = "fastbins")]
+        self.child.lock().flush_fastbins();
+        #[cfg(feature = "quarantine")]
+        self.child.lock().flush_quarantine();
+
+        // SAFETY: the child claimed the whole region
+        unsafe { self.child.lock().get_allocated_span(self.region) == self.empty_span }
+    }
+
+    /// Return the child's region to the parent, if the child has no outstanding
+    /// allocations, else return the child back as `Err`.
+    #[allow(clippy::result_large_err)] // handing the child back is the point
+    pub fn release(self) -> Result<(), Self> {
+        if self.is_empty() {
+            // the region is freed on drop
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+
+    /// Return the child's region to the parent, along with any outstanding allocations.
+    ///
+    /// # Safety
+    /// The child's outstanding allocations must no longer be used.
+    pub unsafe fn release_forcibly(self) {
+        let mut this = ManuallyDrop::new(self);
+        // skip the emptiness check, but not the child's drop glue, such as its OOM handler's
+        core::ptr::drop_in_place(&mut this.child);
+        this.parent.lock().free_child(this.region);
+    }
+}
+
+impl<'a, R: lock_api::RawMutex, O: OomHandler, C: OomHandler> Deref for ChildHeap<'a, R, O, C> {
+    type Target = Talck<R, C>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.child
+    }
+}
+
+/// Allocates from the child, such that `&ChildHeap` can be used as an allocator handle
+/// like `&Talck`, e.g. `Vec::new_in(&child)`.
+#[cfg(any(feature = "allocator", feature = "allocator-api2"))]
+unsafe impl<'a, R: lock_api::RawMutex, O: OomHandler, C: OomHandler> Allocator
+    for ChildHeap<'a, R, O, C>
+{
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.child.allocate(layout)
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.child.allocate_zeroed(layout)
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.child.deallocate(ptr, layout)
+    }
+
+    unsafe fn grow(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.child.grow(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.child.grow_zeroed(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn shrink(
+        &self,
+        ptr: NonNull<u8>,
+        old_layout: Layout,
+        new_layout: Layout,
+    ) -> Result<NonNull<[u8]>, AllocError> {
+        self.child.shrink(ptr, old_layout, new_layout)
+    }
+}
+
+impl<'a, R: lock_api::RawMutex, O: OomHandler, C: OomHandler> Drop for ChildHeap<'a, R, O, C> {
+    fn drop(&mut self) {
+        if self.is_empty() {
+            // SAFETY: the region was carved from the parent and the child has no allocations
+            unsafe { self.parent.lock().free_child(self.region) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        alloc::{GlobalAlloc, Layout},
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use crate::*;
+
+    /// Counts how many times it's dropped.
+    struct DropCounter<'a>(&'a AtomicUsize);
+
+    impl OomHandler for DropCounter<'_> {
+        fn handle_oom(_: &mut Talc<Self>, _: Layout) -> Result<(), ()> {
+            Err(())
+        }
+    }
+
+    impl Drop for DropCounter<'_> {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn child_heap_test() {
+        let mut arena = [0u8; 20000];
+        let parent = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { parent.lock().claim(arena.as_mut().into()).unwrap() };
+        let initial_free = parent.lock().report().free_bytes;
+
+        let layout = Layout::from_size_align(1000, 8).unwrap();
+        let child = parent.carve_child(5000, ErrOnOom).unwrap();
+        assert!(child.is_empty());
+
+        // a child with allocations isn't released
+        let ptr = unsafe { child.alloc(layout) };
+        assert!(child.region().contains(ptr));
+        assert!(!child.is_empty());
+        let child = child.release().unwrap_err();
+
+        // until the allocations are freed
+        unsafe { child.dealloc(ptr, layout) };
+        assert!(child.is_empty());
+        assert!(child.release().is_ok());
+        assert_eq!(parent.lock().report().free_bytes, initial_free);
+
+        // dropping a child with allocations leaks its region
+        let child = parent.carve_child(5000, ErrOnOom).unwrap();
+        let region = child.region();
+        unsafe { child.alloc(layout) };
+        drop(child);
+        assert!(parent.lock().report().free_bytes < initial_free - region.size());
+
+        // unless it's released forcibly, which still drops the child's OOM handler
+        let drops = AtomicUsize::new(0);
+        let child = parent.carve_child(5000, DropCounter(&drops)).unwrap();
+        let before = parent.lock().report().free_bytes;
+        unsafe {
+            child.alloc(layout);
+            child.release_forcibly();
+        }
+        assert!(parent.lock().report().free_bytes > before);
+        assert_eq!(drops.load(Ordering::Relaxed), 1);
+    }
+
+    #[cfg(feature = "allocator")]
+    #[test]
+    fn allocator_handle_test() {
+        let mut arena = [0u8; 20000];
+        let parent = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { parent.lock().claim(arena.as_mut().into()).unwrap() };
+        let child = parent.carve_child(5000, ErrOnOom).unwrap();
+
+        // references to both are allocator handles
+        let mut from_parent = std::vec::Vec::new_in(&parent);
+        let mut from_child = std::vec::Vec::new_in(&child);
+        from_parent.extend_from_slice(&[1u64; 100]);
+        from_child.extend_from_slice(&[2u64; 100]);
+        assert!(child.region().contains(from_child.as_mut_ptr().cast()));
+        assert!(!child.region().contains(from_parent.as_mut_ptr().cast()));
+
+        drop(from_child);
+        assert!(child.release().is_ok());
+    }
+}
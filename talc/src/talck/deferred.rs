@@ -0,0 +1,201 @@
+//! A lock-free stack of frees to be performed the next time the lock is acquired.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use crate::{talc::Talc, OomHandler};
+
+#[cfg(target_has_atomic = "ptr")]
+use crate::ptr_utils::WORD_SIZE;
+#[cfg(target_has_atomic = "ptr")]
+use core::{
+    ptr::null_mut,
+    sync::atomic::{AtomicPtr, Ordering},
+};
+
+/// Written into the first two words of a freed allocation while it awaits being freed.
+#[cfg(target_has_atomic = "ptr")]
+#[repr(C)]
+struct DeferredNode {
+    next: *mut DeferredNode,
+    size: usize,
+}
+
+/// Allocations need to be able to hold a node to be deferred.
+///
+/// Allocations are always at least word-aligned, so alignment needn't be checked.
+#[cfg(target_has_atomic = "ptr")]
+const MIN_DEFERRABLE_SIZE: usize = core::mem::size_of::<DeferredNode>();
+
+/// A Treiber stack of allocations that could not be freed immediately.
+///
+/// Pushing is lock-free. Draining takes the entire stack at once, so nodes
+/// are never popped individually and the ABA problem doesn't arise.
+///
+/// On targets without pointer-sized compare-and-swap, nothing can be deferred.
+#[derive(Debug)]
+pub(crate) struct DeferredFrees {
+    #[cfg(target_has_atomic = "ptr")]
+    head: AtomicPtr<DeferredNode>,
+}
+
+impl DeferredFrees {
+    pub(crate) const fn new() -> Self {
+        Self {
+            #[cfg(target_has_atomic = "ptr")]
+            head: AtomicPtr::new(null_mut()),
+        }
+    }
+
+    /// Attempts to defer freeing `ptr`, returning whether it was deferred.
+    ///
+    /// Fails if `layout` is too small to hold the bookkeeping or atomics aren't available.
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by the allocator that drains this stack, given `layout`.
+    #[inline]
+    pub(crate) unsafe fn push(&self, ptr: NonNull<u8>, layout: Layout) -> bool {
+        #[cfg(target_has_atomic = "ptr")]
+        if layout.size() >= MIN_DEFERRABLE_SIZE {
+            debug_assert!(ptr.as_ptr() as usize % WORD_SIZE == 0);
+
+            let node = ptr.as_ptr().cast::<DeferredNode>();
+            let mut head = self.head.load(Ordering::Relaxed);
+
+            loop {
+                node.write(DeferredNode { next: head, size: layout.size() });
+
+                match self.head.compare_exchange_weak(
+                    head,
+                    node,
+                    Ordering::Release,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => return true,
+                    Err(h) => head = h,
+                }
+            }
+        }
+
+        #[cfg(not(target_has_atomic = "ptr"))]
+        let _ = (ptr, layout);
+
+        false
+    }
+
+    /// Frees everything that has been deferred.
+    #[inline]
+    pub(crate) fn drain<O: OomHandler>(&self, talc: &mut Talc<O>) {
+        #[cfg(target_has_atomic = "ptr")]
+        {
+            // avoid contending on the cache line in the common case
+            if self.head.load(Ordering::Relaxed).is_null() {
+                return;
+            }
+
+            let mut node = self.head.swap(null_mut(), Ordering::Acquire);
+
+            while !node.is_null() {
+                unsafe {
+                    // read everything out before the memory is handed back
+                    let DeferredNode { next, size } = node.read();
+
+                    // only the size is used to free an allocation, so the alignment is moot
+                    talc.free(
+                        NonNull::new_unchecked(node.cast()),
+                        Layout::from_size_align_unchecked(size, 1),
+                    );
+
+                    node = next;
+                }
+            }
+        }
+
+        #[cfg(not(target_has_atomic = "ptr"))]
+        let _ = talc;
+    }
+}
+
+#[cfg(all(test, target_has_atomic = "ptr"))]
+mod tests {
+    use core::alloc::GlobalAlloc;
+
+    use super::*;
+    use crate::*;
+
+    #[test]
+    fn deferred_free_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let big = Layout::from_size_align(100, 8).unwrap();
+        let small = Layout::from_size_align(MIN_DEFERRABLE_SIZE - 1, 1).unwrap();
+
+        let initial_free = talck.lock().report().free_bytes;
+
+        unsafe {
+            let a = talck.alloc(big);
+            let b = talck.alloc(big);
+            let c = talck.alloc(small);
+
+            let guard = talck.mutex.lock();
+
+            // the lock is held, so this is deferred
+            talck.dealloc(a, big);
+            talck.dealloc(b, big);
+            assert!(!talck.deferred.head.load(Ordering::Relaxed).is_null());
+
+            // too small to be deferred
+            assert!(!talck.deferred.push(NonNull::new_unchecked(c), small));
+
+            drop(guard);
+            talck.dealloc(c, small);
+        }
+
+        assert!(talck.deferred.head.load(Ordering::Relaxed).is_null());
+        #[cfg(feature = "fastbins")]
+        talck.lock().flush_fastbins();
+
+        assert_eq!(talck.lock().report().free_bytes, initial_free);
+    }
+
+    #[test]
+    fn defer_free_test() {
+        let mut arena = [0u8; 10000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut().into()).unwrap() };
+        let talck = talc.lock::<spin::Mutex<()>>();
+
+        let layout = Layout::from_size_align(64, 8).unwrap();
+        let small = Layout::from_size_align(MIN_DEFERRABLE_SIZE - 1, 1).unwrap();
+
+        let initial_free = talck.lock().report().free_bytes;
+
+        unsafe {
+            let a = NonNull::new(talck.alloc(layout)).unwrap();
+            let b = NonNull::new(talck.alloc(layout)).unwrap();
+            let c = NonNull::new(talck.alloc(small)).unwrap();
+
+            // simulate an interrupt arriving while the lock is held
+            let guard = talck.mutex.lock();
+            assert!(talck.defer_free(a, layout).is_ok());
+            assert!(talck.defer_free(c, small).is_err());
+            drop(guard);
+
+            // deferral doesn't need the lock to be held either
+            assert!(talck.defer_free(b, layout).is_ok());
+            assert!(talck.mutex.lock().report().free_bytes < initial_free);
+
+            // the next allocation drains the queue
+            let d = talck.alloc(layout);
+            talck.dealloc(d, layout);
+            talck.dealloc(c.as_ptr(), small);
+        }
+
+        #[cfg(feature = "fastbins")]
+        talck.lock().flush_fastbins();
+
+        assert_eq!(talck.lock().report().free_bytes, initial_free);
+    }
+}
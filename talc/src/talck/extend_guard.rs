@@ -0,0 +1,182 @@
+//! Home of ExtendGuard, which truncates a heap back when dropped.
+
+use core::mem::ManuallyDrop;
+
+use super::Talck;
+use crate::{OomHandler, Span};
+
+/// A heap temporarily extended over lent memory by [`Talck::extend_scoped`].
+///
+/// When dropped, the heap is truncated back to its extent before it was extended,
+/// returning the lent memory. If any of the lent memory is still allocated, this panics,
+/// as the lender's memory would otherwise be in use by both the lender and the allocations.
+/// Use [`release`](ExtendGuard::release) to check whether this succeeds instead.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// # use core::alloc::{GlobalAlloc, Layout};
+/// let mut memory = [0u8; 20000];
+/// let (arena, frame_buffer) = memory.split_at_mut(10000);
+///
+/// let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+/// let heap = unsafe { talck.lock().claim(arena.into()).unwrap() };
+///
+/// // lend the frame buffer to the heap during initialization
+/// let guard = unsafe { talck.extend_scoped(heap, frame_buffer.into()) };
+/// let layout = Layout::new::<[u8; 15000]>();
+/// unsafe {
+///     let scratch = talck.alloc(layout);
+///     assert!(!scratch.is_null());
+///     talck.dealloc(scratch, layout);
+/// }
+///
+/// // the frame buffer is returned
+/// assert_eq!(guard.release().ok(), Some(heap));
+/// ```
+#[derive(Debug)]
+pub struct ExtendGuard<'a, R: lock_api::RawMutex, O: OomHandler> {
+    talck: &'a Talck<R, O>,
+    /// The heap before it was extended.
+    heap: Span,
+    /// The heap while extended.
+    extended: Span,
+}
+
+impl<R: lock_api::RawMutex, O: OomHandler> Talck<R, O> {
+    /// Extend `heap` over the adjacent memory in `extra` until the returned
+    /// [`ExtendGuard`] is dropped, at which point the heap is truncated back to `heap`.
+    ///
+    /// This makes it impossible to forget to take back memory that's lent to the
+    /// heap for a while, such as a frame buffer during initialization.
+    ///
+    /// # Safety
+    /// - `heap` must be the return value of a heap-manipulation function of this
+    ///   allocator, and mustn't be changed by other means while the guard exists.
+    /// - `extra` must be readable and writable, and not otherwise in use, until the guard is
+    ///   dropped.
+    ///
+    /// # Panics
+    /// Panics if `extra` neither overlaps with `heap` nor borders it, as well as for
+    /// the reasons [`Talc::extend`](crate::Talc::extend) panics.
+    pub unsafe fn extend_scoped(&self, heap: Span, extra: Span) -> ExtendGuard<'_, R, O> {
+        let req_heap = heap.fit_over(extra);
+        assert!(
+            req_heap.size() <= heap.size() + extra.size(),
+            "the extra memory must border the heap"
+        );
+
+        let extended = self.lock().extend(heap, req_heap);
+
+        ExtendGuard { talck: self, heap, extended }
+    }
+}
+
+impl<'a, R: lock_api::RawMutex, O: OomHandler> ExtendGuard<'a, R, O> {
+    /// Returns the heap while extended.
+    pub fn extended(&self) -> Span {
+        self.extended
+    }
+
+    /// Truncate the heap back to its extent before it was extended and return it,
+    /// if none of the lent memory is allocated, else return the guard back as `Err`.
+    pub fn release(self) -> Result<Span, Self> {
+        let mut this = ManuallyDrop::new(self);
+
+        match this.truncate() {
+            Ok(heap) => Ok(heap),
+            Err(()) => Err(ManuallyDrop::into_inner(this)),
+        }
+    }
+
+    fn truncate(&mut self) -> Result<Span, ()> {
+        let mut talc = self.talck.lock();
+
+        // cached and quarantined chunks look allocated
+        #[cfg(feature = "fastbins")]
+        talc.flush_fastbins();
+        #[cfg(feature = "quarantine")]
+        talc.flush_quarantine();
+
+        // SAFETY: the extended heap hasn't been changed by other means, per `extend_scoped`
+        unsafe {
+            if !self.heap.contains_span(talc.get_allocated_span(self.extended)) {
+                return Err(());
+            }
+
+            Ok(talc.truncate(self.extended, self.heap))
+        }
+    }
+}
+
+impl<'a, R: lock_api::RawMutex, O: OomHandler> Drop for ExtendGuard<'a, R, O> {
+    fn drop(&mut self) {
+        if self.truncate().is_err() {
+            panic!("memory lent to the heap with extend_scoped is still allocated");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::{GlobalAlloc, Layout};
+
+    use crate::*;
+
+    #[test]
+    fn extend_guard_test() {
+        let mut memory = [0u8; 20000];
+        let (low, high) = memory.split_at_mut(10000);
+        let (lent, arena) = low.split_at_mut(4000);
+
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        let heap = unsafe { talck.lock().claim(arena.into()).unwrap() };
+        let initial = talck.lock().report();
+
+        // memory below and above can be lent
+        for extra in [Span::from(lent), Span::from(&mut high[..])] {
+            let guard = unsafe { talck.extend_scoped(heap, extra) };
+            assert!(guard.extended().contains_span(heap));
+
+            // fill the heap, such that some allocations are in the lent memory
+            let layout = Layout::from_size_align(500, 8).unwrap();
+            let mut allocs = std::vec::Vec::new();
+            loop {
+                let ptr = unsafe { talck.alloc(layout) };
+                if ptr.is_null() {
+                    break;
+                }
+                allocs.push(ptr);
+            }
+            assert!(allocs.iter().any(|&ptr| !heap.contains(ptr)));
+
+            // the lent memory is in use, so it can't be returned
+            let guard = guard.release().err().unwrap();
+
+            for ptr in allocs {
+                unsafe { talck.dealloc(ptr, layout) };
+            }
+            assert_eq!(guard.release().ok(), Some(heap));
+            assert_eq!(talck.lock().report(), initial);
+        }
+
+        // dropping the guard returns the memory too
+        drop(unsafe { talck.extend_scoped(heap, high.into()) });
+        assert_eq!(talck.lock().report(), initial);
+    }
+
+    #[test]
+    #[should_panic = "still allocated"]
+    fn extend_guard_leak_test() {
+        let mut memory = [0u8; 20000];
+        let (arena, lent) = memory.split_at_mut(10000);
+
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        let heap = unsafe { talck.lock().claim(arena.into()).unwrap() };
+
+        let guard = unsafe { talck.extend_scoped(heap, lent.into()) };
+        let layout = Layout::from_size_align(heap.size(), 8).unwrap();
+        unsafe { talck.alloc(layout) };
+        drop(guard);
+    }
+}
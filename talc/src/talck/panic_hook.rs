@@ -0,0 +1,104 @@
+//! Print a summary of the heap on panic, giving crashes context on the state of the heap.
+
+use std::{boxed::Box, io::Write};
+
+use super::Talck;
+use crate::OomHandler;
+
+impl<R: lock_api::RawMutex, O: OomHandler> Talck<R, O> {
+    /// Write a summary of the heap to `w`: the [`report`](crate::Talc::report), as well as
+    /// the counters and the layout of the last failed allocation if the `counters` feature
+    /// is enabled.
+    ///
+    /// If the lock is held, e.g. because the panic occurred while allocating, this says so
+    /// rather than deadlocking.
+    pub fn write_heap_summary(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let talc = match self.try_lock() {
+            Some(talc) => talc,
+            None => return writeln!(w, "talc: heap summary unavailable, the allocator is locked"),
+        };
+
+        writeln!(w, "talc: {}", talc.report())?;
+
+        #[cfg(feature = "counters")]
+        {
+            let counters = talc.get_counters();
+            match counters.last_failed_layout {
+                Some(layout) => writeln!(
+                    w,
+                    "talc: last failed allocation: {} B aligned to {}",
+                    layout.size(),
+                    layout.align()
+                )?,
+                None => writeln!(w, "talc: no allocation has failed")?,
+            }
+            writeln!(w, "{}", counters)?;
+        }
+
+        Ok(())
+    }
+
+    /// Print a summary of the heap to standard error, see [`Talck::write_heap_summary`].
+    ///
+    /// This doesn't allocate, so it can be called from a custom panic hook.
+    pub fn print_heap_summary(&self) {
+        // there's nowhere left to report a failure to write to stderr
+        let _ = self.write_heap_summary(&mut std::io::stderr().lock());
+    }
+
+    /// Install a panic hook that prints a summary of the heap, see
+    /// [`Talck::print_heap_summary`], after calling the previous hook.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use talc::*;
+    /// static mut ARENA: [u8; 10000] = [0; 10000];
+    ///
+    /// static ALLOCATOR: Talck<spin::Mutex<()>, ClaimOnOom> =
+    ///     Talc::new(unsafe { ClaimOnOom::new(Span::from_array(core::ptr::addr_of_mut!(ARENA))) })
+    ///         .lock();
+    ///
+    /// ALLOCATOR.install_panic_hook();
+    /// ```
+    pub fn install_panic_hook(&'static self)
+    where
+        Self: Sync,
+    {
+        let previous = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            previous(info);
+            self.print_heap_summary();
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::alloc::Layout;
+
+    use crate::*;
+
+    #[test]
+    fn heap_summary_test() {
+        let mut arena = [0u8; 10000];
+        let talck = Talc::new(ErrOnOom).lock::<spin::Mutex<()>>();
+        unsafe { talck.lock().claim(arena.as_mut().into()).unwrap() };
+
+        let layout = Layout::from_size_align(100000, 8).unwrap();
+        assert!(unsafe { talck.lock().malloc(layout) }.is_err());
+
+        let mut summary = std::vec::Vec::new();
+        talck.write_heap_summary(&mut summary).unwrap();
+        let summary = std::string::String::from_utf8(summary).unwrap();
+
+        assert!(summary.contains("free"));
+        #[cfg(feature = "counters")]
+        assert!(summary.contains("last failed allocation: 100000 B aligned to 8"));
+
+        let guard = talck.lock();
+        let mut summary = std::vec::Vec::new();
+        talck.write_heap_summary(&mut summary).unwrap();
+        assert!(std::string::String::from_utf8(summary).unwrap().contains("locked"));
+        drop(guard);
+    }
+}
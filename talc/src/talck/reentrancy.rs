@@ -0,0 +1,187 @@
+//! Detect the allocator being locked again by the context that holds it, which would deadlock.
+//!
+//! This typically happens when an OOM handler or hook allocates through the global allocator,
+//! e.g. by formatting a message, while the `Talck` is locked for the allocation that called it.
+
+use core::{
+    ops::{Deref, DerefMut},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+#[cfg(any(feature = "std", test))]
+std::thread_local! {
+    /// Its address identifies the thread.
+    static CONTEXT: u8 = const { 0 };
+}
+
+/// Returns a nonzero value identifying the current thread, if it can be identified.
+///
+/// This requires the `std` feature. Without it, contexts can't be told apart, and
+/// contention from other cores or threads would be mistaken for re-entrancy.
+#[inline]
+fn current_context() -> Option<usize> {
+    #[cfg(any(feature = "std", test))]
+    if let Ok(context) = CONTEXT.try_with(|context| context as *const u8 as usize) {
+        return Some(context);
+    }
+
+    None
+}
+
+/// Records which context holds the lock, if it's held by a [`Held`] guard.
+///
+/// Only a context can record itself as the holder, and it forgets itself before releasing
+/// the lock, so finding the current context recorded means that it holds the lock.
+#[derive(Debug)]
+pub(crate) struct ReentrancyCheck {
+    holder: AtomicUsize,
+}
+
+impl ReentrancyCheck {
+    pub(crate) const fn new() -> Self {
+        Self { holder: AtomicUsize::new(0) }
+    }
+
+    /// Record the current context as the holder of the lock that `guard` holds,
+    /// until the returned guard is dropped.
+    #[inline]
+    pub(crate) fn held<G>(&self, guard: G) -> Held<'_, G> {
+        self.holder.store(current_context().unwrap_or(0), Ordering::Relaxed);
+        Held { guard, holder: &self.holder }
+    }
+
+    /// Acquire the lock with `try_lock`, or with `lock` if it's held by another context.
+    ///
+    /// If the current context can't be identified, this just calls `lock`.
+    ///
+    /// # Aborts
+    /// Aborts the process if the lock is held by the current context. Panicking isn't
+    /// an option, as unwinding out of a global allocator is undefined behavior.
+    pub(crate) fn lock<G>(
+        &self,
+        try_lock: impl FnMut() -> Option<G>,
+        lock: impl FnOnce() -> G,
+    ) -> G {
+        #[cfg(any(feature = "std", test))]
+        if let Some(context) = current_context() {
+            return match self.lock_unless_held(context, try_lock, lock) {
+                Some(guard) => guard,
+                None => reentered(),
+            };
+        }
+
+        #[cfg(not(any(feature = "std", test)))]
+        let _ = try_lock;
+
+        lock()
+    }
+
+    /// Acquire the lock, unless it's held by `context`, in which case `None` is returned.
+    #[cfg(any(feature = "std", test))]
+    fn lock_unless_held<G>(
+        &self,
+        context: usize,
+        mut try_lock: impl FnMut() -> Option<G>,
+        lock: impl FnOnce() -> G,
+    ) -> Option<G> {
+        if let Some(guard) = try_lock() {
+            return Some(guard);
+        }
+
+        // only `context` itself records `context`, so this isn't stale
+        if self.holder.load(Ordering::Relaxed) == context { None } else { Some(lock()) }
+    }
+}
+
+/// Wraps the guard of a lock, forgetting the holder before the lock is released.
+#[derive(Debug)]
+pub(crate) struct Held<'a, G> {
+    // dropped after `Held::drop` runs, releasing the lock
+    guard: G,
+    holder: &'a AtomicUsize,
+}
+
+impl<G> Drop for Held<'_, G> {
+    #[inline]
+    fn drop(&mut self) {
+        self.holder.store(0, Ordering::Relaxed);
+    }
+}
+
+impl<G: Deref> Deref for Held<'_, G> {
+    type Target = G::Target;
+
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl<G: DerefMut> DerefMut for Held<'_, G> {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+#[cfg(any(feature = "std", test))]
+#[cold]
+fn reentered() -> ! {
+    std::eprintln!(
+        "talc: the allocator was locked again by the thread holding it, \
+        e.g. by allocating in an OOM handler, which would deadlock"
+    );
+    std::process::abort()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reentrancy_check_test() {
+        let check = ReentrancyCheck::new();
+        let mutex = spin::Mutex::new(());
+        let context = current_context().unwrap();
+
+        let guard = check.lock_unless_held(context, || mutex.try_lock(), || mutex.lock()).unwrap();
+        let guard = check.held(guard);
+
+        // locking again from the holding thread is detected instead of deadlocking
+        assert!(check.lock_unless_held(context, || mutex.try_lock(), || mutex.lock()).is_none());
+
+        // other threads wait as usual
+        std::thread::scope(|scope| {
+            let handle = scope.spawn(|| {
+                let context = current_context().unwrap();
+                let guard = check.lock_unless_held(context, || mutex.try_lock(), || mutex.lock());
+                assert!(guard.is_some());
+            });
+
+            std::thread::sleep(std::time::Duration::from_millis(10));
+            drop(guard);
+            handle.join().unwrap();
+        });
+
+        // the lock can be taken again once released
+        let guard = check.lock_unless_held(context, || mutex.try_lock(), || mutex.lock()).unwrap();
+        drop(check.held(guard));
+
+        // a previous holder waits on another holder that hasn't recorded itself yet
+        std::thread::scope(|scope| {
+            let (sender, receiver) = std::sync::mpsc::channel();
+            let mutex = &mutex;
+            scope.spawn(move || {
+                let guard = mutex.lock();
+                sender.send(()).unwrap();
+                std::thread::sleep(std::time::Duration::from_millis(10));
+                drop(guard);
+            });
+
+            receiver.recv().unwrap();
+            assert!(
+                check.lock_unless_held(context, || mutex.try_lock(), || mutex.lock()).is_some()
+            );
+        });
+    }
+}
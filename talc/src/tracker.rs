@@ -0,0 +1,388 @@
+//! Home of BacktraceTracker, a leak-tracking [`GlobalAlloc`] wrapper for hosted environments.
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    cell::Cell,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use std::{
+    backtrace::Backtrace,
+    collections::BTreeMap,
+    string::String,
+    sync::{Mutex, MutexGuard},
+    vec::Vec,
+};
+
+std::thread_local! {
+    /// Set while the tracker is doing its own bookkeeping on this thread,
+    /// during which any (de)allocations it causes are passed straight through.
+    static BOOKKEEPING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Symbol prefixes of frames that belong to the tracker, the backtrace machinery,
+/// or the allocation plumbing between the callsite and the tracker.
+const SKIPPED_FRAME_PREFIXES: &[&str] = &[
+    "std::backtrace",
+    "talc::tracker::BacktraceTracker",
+    "__rustc::",
+    "__rust_",
+    "__rg_",
+    "alloc::",
+    "core::",
+];
+
+#[derive(Debug)]
+struct Records {
+    /// Maps outstanding allocations' addresses to their size and callsite index.
+    live: BTreeMap<usize, (usize, usize)>,
+    /// Callsites by index.
+    callsites: Vec<String>,
+    /// Maps callsites to their index.
+    callsite_indices: BTreeMap<String, usize>,
+}
+
+/// Wraps a [`GlobalAlloc`], such as a [`Talck`](crate::Talck), and records a
+/// truncated backtrace for every allocation until it is freed.
+///
+/// Use [`leak_report`](BacktraceTracker::leak_report) to get the outstanding
+/// allocations grouped by callsite.
+///
+/// Capturing backtraces is slow, and both the bookkeeping and symbolization
+/// (which needs several megabytes) are allocated from the wrapped allocator,
+/// so this is intended for hosted debugging and testing.
+///
+/// # Example
+/// ```rust
+/// # use talc::*;
+/// static mut ARENA: [u8; 1 << 26] = [0; 1 << 26];
+///
+/// #[global_allocator]
+/// static ALLOCATOR: BacktraceTracker<Talck<spin::Mutex<()>, ClaimOnOom>> = BacktraceTracker::new(
+///     Talc::new(unsafe { ClaimOnOom::new(Span::from_const_array(core::ptr::addr_of!(ARENA))) })
+///         .lock(),
+///     8,
+/// );
+///
+/// fn main() {
+///     let _leaked = Box::leak(Box::new([0u8; 100]));
+///     print!("{}", ALLOCATOR.leak_report());
+/// }
+/// ```
+#[derive(Debug)]
+pub struct BacktraceTracker<A: GlobalAlloc> {
+    inner: A,
+    enabled: AtomicBool,
+    depth: usize,
+    records: Mutex<Records>,
+}
+
+impl<A: GlobalAlloc> BacktraceTracker<A> {
+    /// Wrap `inner`, keeping up to `depth` frames of each allocation's backtrace.
+    ///
+    /// Recording is enabled to begin with.
+    pub const fn new(inner: A, depth: usize) -> Self {
+        Self {
+            inner,
+            enabled: AtomicBool::new(true),
+            depth,
+            records: Mutex::new(Records {
+                live: BTreeMap::new(),
+                callsites: Vec::new(),
+                callsite_indices: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// Start or stop recording new allocations.
+    ///
+    /// Frees of recorded allocations are always accounted for.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    /// Returns whether new allocations are being recorded.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Access the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Group all outstanding recorded allocations by callsite, largest total first.
+    pub fn leak_report(&self) -> LeakReport {
+        Self::bookkeeping(|| {
+            let records = self.records();
+
+            let mut totals = std::vec![(0, 0); records.callsites.len()];
+            for &(size, callsite) in records.live.values() {
+                totals[callsite].0 += 1;
+                totals[callsite].1 += size;
+            }
+
+            let mut callsites = totals
+                .into_iter()
+                .zip(records.callsites.iter())
+                .filter(|((count, _), _)| *count != 0)
+                .map(|((count, bytes), backtrace)| CallsiteLeaks {
+                    backtrace: backtrace.clone(),
+                    count,
+                    bytes,
+                })
+                .collect::<Vec<_>>();
+
+            callsites.sort_by(|a, b| b.bytes.cmp(&a.bytes).then(b.count.cmp(&a.count)));
+
+            LeakReport { callsites }
+        })
+        .unwrap_or_default()
+    }
+
+    /// Forget all outstanding recorded allocations.
+    pub fn clear(&self) {
+        Self::bookkeeping(|| {
+            let mut records = self.records();
+            records.live.clear();
+            records.callsites.clear();
+            records.callsite_indices.clear();
+        });
+    }
+
+    fn records(&self) -> MutexGuard<'_, Records> {
+        // the records are always left in a consistent state, so poisoning is irrelevant
+        self.records.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Runs `f` with bookkeeping flagged on this thread, unless it already is.
+    fn bookkeeping<R>(f: impl FnOnce() -> R) -> Option<R> {
+        // this fails during thread teardown, in which case tracking is skipped
+        let entered = BOOKKEEPING.try_with(|b| !b.replace(true)).unwrap_or(false);
+
+        if entered {
+            let r = f();
+            BOOKKEEPING.with(|b| b.set(false));
+            Some(r)
+        } else {
+            None
+        }
+    }
+
+    fn capture_callsite(&self) -> String {
+        let backtrace = std::format!("{}", Backtrace::force_capture());
+
+        let mut callsite = String::new();
+        let mut frames = 0;
+        let mut skipping = true;
+        let mut keeping = false;
+
+        for line in backtrace.lines() {
+            let line = line.trim();
+
+            // frame lines look like "12: symbol", followed by "at file:line:col" lines
+            match line.split_once(": ") {
+                Some((index, symbol)) if index.bytes().all(|b| b.is_ascii_digit()) => {
+                    let symbol = symbol.trim_start_matches('<');
+                    skipping &= SKIPPED_FRAME_PREFIXES.iter().any(|p| symbol.starts_with(p));
+                    keeping = !skipping && frames < self.depth;
+                    frames += keeping as usize;
+                }
+                _ if keeping => callsite.push_str("  "),
+                _ => (),
+            }
+
+            if keeping {
+                callsite.push_str(line);
+                callsite.push('\n');
+            }
+        }
+
+        callsite
+    }
+
+    fn record(&self, ptr: *mut u8, size: usize) {
+        if ptr.is_null() || !self.is_enabled() {
+            return;
+        }
+
+        Self::bookkeeping(|| {
+            let callsite = self.capture_callsite();
+            let mut records = self.records();
+
+            let index = match records.callsite_indices.get(&callsite) {
+                Some(&index) => index,
+                None => {
+                    let index = records.callsites.len();
+                    records.callsites.push(callsite.clone());
+                    records.callsite_indices.insert(callsite, index);
+                    index
+                }
+            };
+
+            records.live.insert(ptr as usize, (size, index));
+        });
+    }
+
+    fn forget(&self, ptr: *mut u8) -> Option<(usize, usize)> {
+        Self::bookkeeping(|| self.records().live.remove(&(ptr as usize))).flatten()
+    }
+}
+
+unsafe impl<A: GlobalAlloc> GlobalAlloc for BacktraceTracker<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        self.record(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        self.record(ptr, layout.size());
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.forget(ptr);
+        self.inner.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+
+        if !new_ptr.is_null() {
+            match self.forget(ptr) {
+                // keep attributing the memory to where it was first allocated
+                Some((_, callsite)) => {
+                    Self::bookkeeping(|| {
+                        self.records().live.insert(new_ptr as usize, (new_size, callsite))
+                    });
+                }
+                None => self.record(new_ptr, new_size),
+            }
+        }
+
+        new_ptr
+    }
+}
+
+/// Outstanding allocations grouped by callsite, as returned by
+/// [`BacktraceTracker::leak_report`].
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct LeakReport {
+    /// Callsites with outstanding allocations, largest total first.
+    pub callsites: Vec<CallsiteLeaks>,
+}
+
+impl LeakReport {
+    /// Total number of outstanding allocations.
+    pub fn count(&self) -> usize {
+        self.callsites.iter().map(|c| c.count).sum()
+    }
+
+    /// Total size of outstanding allocations.
+    pub fn bytes(&self) -> usize {
+        self.callsites.iter().map(|c| c.bytes).sum()
+    }
+}
+
+impl core::fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        writeln!(
+            f,
+            "{} B outstanding in {} allocations from {} callsites",
+            self.bytes(),
+            self.count(),
+            self.callsites.len()
+        )?;
+
+        for callsite in &self.callsites {
+            writeln!(f, "\n{} B in {} allocations at:", callsite.bytes, callsite.count)?;
+
+            for line in callsite.backtrace.lines() {
+                writeln!(f, "    {}", line)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Outstanding allocations from a single callsite.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CallsiteLeaks {
+    /// The truncated backtrace identifying the callsite, one line per frame or location.
+    pub backtrace: String,
+    /// Number of outstanding allocations.
+    pub count: usize,
+    /// Sum of outstanding allocations' sizes.
+    pub bytes: usize,
+}
+
+#[cfg(all(test, feature = "lock_api"))]
+mod tests {
+    use super::*;
+    use crate::*;
+
+    type Tracker = BacktraceTracker<Talck<spin::Mutex<()>, ErrOnOom>>;
+
+    #[inline(never)]
+    fn leak_site_a(tracker: &Tracker, size: usize) -> *mut u8 {
+        unsafe { tracker.alloc(Layout::from_size_align(size, 8).unwrap()) }
+    }
+
+    #[inline(never)]
+    fn leak_site_b(tracker: &Tracker, size: usize) -> *mut u8 {
+        unsafe { tracker.alloc(Layout::from_size_align(size, 8).unwrap()) }
+    }
+
+    #[test]
+    fn backtrace_tracker_test() {
+        let mut arena = std::vec![0u8; 100000];
+        let mut talc = Talc::new(ErrOnOom);
+        unsafe { talc.claim(arena.as_mut_slice().into()).unwrap() };
+        let tracker = BacktraceTracker::new(talc.lock::<spin::Mutex<()>>(), 4);
+
+        // allocate from the same line so that the whole truncated backtraces match
+        let [a1, a2] = [(); 2].map(|_| leak_site_a(&tracker, 100));
+        let b = leak_site_b(&tracker, 50);
+
+        let report = tracker.leak_report();
+        assert_eq!(report.count(), 3);
+        assert_eq!(report.bytes(), 250);
+        assert!(report.callsites.iter().all(|c| c.backtrace.lines().count() <= 4 * 2));
+
+        // only expect the callsites to be told apart if symbols can be resolved
+        if report.callsites.iter().any(|c| c.backtrace.contains("leak_site_a")) {
+            assert_eq!(report.callsites.len(), 2);
+            assert!(report.callsites[0].backtrace.contains("leak_site_a"));
+            assert_eq!((report.callsites[0].count, report.callsites[0].bytes), (2, 200));
+            assert!(report.callsites[1].backtrace.contains("leak_site_b"));
+            assert_eq!((report.callsites[1].count, report.callsites[1].bytes), (1, 50));
+        }
+
+        unsafe {
+            let a1 = tracker.realloc(a1, Layout::from_size_align(100, 8).unwrap(), 1000);
+            assert_eq!(tracker.leak_report().bytes(), 1150);
+
+            tracker.dealloc(a1, Layout::from_size_align(1000, 8).unwrap());
+            tracker.dealloc(b, Layout::from_size_align(50, 8).unwrap());
+        }
+
+        let report = tracker.leak_report();
+        assert_eq!((report.count(), report.bytes()), (1, 100));
+
+        tracker.set_enabled(false);
+        let untracked = leak_site_b(&tracker, 10);
+        assert_eq!(tracker.leak_report().count(), 1);
+        tracker.set_enabled(true);
+
+        unsafe {
+            tracker.dealloc(untracked, Layout::from_size_align(10, 8).unwrap());
+            tracker.dealloc(a2, Layout::from_size_align(100, 8).unwrap());
+        }
+
+        assert_eq!(tracker.leak_report(), LeakReport::default());
+        assert!(std::format!("{}", tracker.leak_report()).starts_with("0 B outstanding"));
+    }
+}
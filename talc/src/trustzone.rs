@@ -0,0 +1,275 @@
+//! Home of DualTalck, which keeps separate heaps for the Secure and Non-secure worlds of Armv8-M.
+
+use core::{alloc::Layout, ptr::NonNull};
+
+use crate::{talc::Talc, OomHandler, Span, Talck};
+
+/// A TrustZone security world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum World {
+    /// Only accessible from the Secure state.
+    Secure,
+    /// Accessible from both the Secure and Non-secure states.
+    NonSecure,
+}
+
+/// Returns the security world of the memory from `base` up to `base + size`,
+/// or `None` if it's not all in the same world, according to the SAU and IDAU.
+///
+/// This uses the `TT` instruction, so it's only available on Armv8-M targets with
+/// the Security Extension, and must be called from the Secure state.
+///
+/// If both ends of the memory are in the same SAU and IDAU regions, those are taken
+/// to cover it entirely. Otherwise, each 32-byte block is checked individually, as this
+/// is the granularity of the SAU.
+#[cfg(all(target_arch = "arm", target_os = "none"))]
+pub fn sau_world_of(base: *const u8, size: usize) -> Option<World> {
+    // the fields of the TT instruction's result
+    const SREGION: u32 = 0xFF << 8;
+    const SRVALID: u32 = 1 << 17;
+    const S: u32 = 1 << 22;
+    const IRVALID: u32 = 1 << 23;
+    const IREGION: u32 = 0xFF << 24;
+    const SAU_GRANULE: usize = 32;
+
+    #[inline]
+    fn test_target(addr: usize) -> u32 {
+        let result: u32;
+        // SAFETY: TT only reads the security attribution and MPU configuration of the address
+        unsafe {
+            core::arch::asm!(
+                "tt {}, {}",
+                out(reg) result,
+                in(reg) addr,
+                options(pure, nomem, nostack, preserves_flags)
+            );
+        }
+        result
+    }
+
+    let world = |result: u32| if result & S != 0 { World::Secure } else { World::NonSecure };
+
+    let first = base as usize;
+    let last = first.checked_add(size.max(1) - 1)?;
+
+    let first_result = test_target(first);
+    let last_result = test_target(last);
+
+    let region_mask = SREGION | SRVALID | S | IRVALID | IREGION;
+    if first_result & region_mask != last_result & region_mask {
+        return None;
+    }
+    if first_result & (SRVALID | IRVALID) == SRVALID | IRVALID {
+        return Some(world(first_result));
+    }
+
+    let mut block = first & !(SAU_GRANULE - 1);
+    while block < last {
+        block += SAU_GRANULE;
+        if test_target(block) & S != first_result & S {
+            return None;
+        }
+    }
+
+    Some(world(first_result))
+}
+
+/// A pair of [`Talck`]s for Armv8-M TrustZone firmware, one managing Secure memory, the
+/// other Non-secure memory, used from the Secure world.
+///
+/// Memory is checked against the security attribution (i.e. the SAU and IDAU configuration)
+/// when it's claimed, such that Secure allocations never end up in memory the Non-secure
+/// world can access, and buffers shared with the Non-secure world are always accessible to it.
+/// Pointers received from the Non-secure world can be validated with
+/// [`is_non_secure`](DualTalck::is_non_secure) before use.
+///
+/// The attribution is looked up using the function given to [`DualTalck::new`], which
+/// is usually [`sau_world_of`] on target. It returns the world of all the memory in the
+/// given range, or `None` if it spans both.
+///
+/// # Example
+/// ```rust
+/// # use talc::{*, trustzone::*};
+/// # use core::alloc::Layout;
+/// static mut SECURE_ARENA: [u8; 10000] = [0; 10000];
+/// static mut NON_SECURE_ARENA: [u8; 10000] = [0; 10000];
+///
+/// // on target, use `sau_world_of` instead
+/// fn world_of(base: *const u8, size: usize) -> Option<World> {
+///     let non_secure = Span::from_array(core::ptr::addr_of_mut!(NON_SECURE_ARENA));
+///     let span = Span::from_base_size(base as *mut u8, size);
+///     if non_secure.contains_span(span) {
+///         Some(World::NonSecure)
+///     } else if !non_secure.overlaps(span) {
+///         Some(World::Secure)
+///     } else {
+///         None
+///     }
+/// }
+///
+/// let heaps = DualTalck::<spin::Mutex<()>, _, _>::new(
+///     Talc::new(ErrOnOom),
+///     Talc::new(ErrOnOom),
+///     world_of,
+/// );
+///
+/// unsafe {
+///     heaps.claim(World::Secure, Span::from_array(core::ptr::addr_of_mut!(SECURE_ARENA))).unwrap();
+///     heaps.claim(World::NonSecure, Span::from_array(core::ptr::addr_of_mut!(NON_SECURE_ARENA))).unwrap();
+/// }
+///
+/// // e.g. a buffer for the Non-secure world to fill
+/// let layout = Layout::new::<[u8; 256]>();
+/// let buffer = heaps.alloc_non_secure(layout).unwrap();
+/// assert!(heaps.is_non_secure(buffer.as_ptr(), 256));
+/// unsafe { heaps.free_non_secure(buffer, layout) };
+/// ```
+#[derive(Debug)]
+pub struct DualTalck<R: lock_api::RawMutex, S: OomHandler, N: OomHandler> {
+    secure: Talck<R, S>,
+    non_secure: Talck<R, N>,
+    world_of: fn(*const u8, usize) -> Option<World>,
+}
+
+impl<R: lock_api::RawMutex, S: OomHandler, N: OomHandler> DualTalck<R, S, N> {
+    /// Create a new `DualTalck` from the Secure and Non-secure heaps' allocators,
+    /// and a function returning the security world of a range of memory.
+    ///
+    /// The allocators mustn't have claimed any memory yet, as it wouldn't be checked.
+    pub const fn new(
+        secure: Talc<S>,
+        non_secure: Talc<N>,
+        world_of: fn(*const u8, usize) -> Option<World>,
+    ) -> Self {
+        Self { secure: secure.lock(), non_secure: non_secure.lock(), world_of }
+    }
+
+    /// Access the `Talck` managing Secure memory.
+    ///
+    /// It can be used as the Secure world's global allocator.
+    pub fn secure(&self) -> &Talck<R, S> {
+        &self.secure
+    }
+
+    /// Access the `Talck` managing Non-secure memory.
+    pub fn non_secure(&self) -> &Talck<R, N> {
+        &self.non_secure
+    }
+
+    /// Returns the security world of the memory from `base` up to `base + size`,
+    /// or `None` if it spans both.
+    pub fn world_of(&self, base: *const u8, size: usize) -> Option<World> {
+        (self.world_of)(base, size)
+    }
+
+    /// Returns whether the memory from `ptr` up to `ptr + size` is Non-secure.
+    ///
+    /// Use this to validate pointers received from the Non-secure world, such that it
+    /// can't get the Secure world to read or write Secure memory on its behalf.
+    pub fn is_non_secure(&self, ptr: *const u8, size: usize) -> bool {
+        self.world_of(ptr, size) == Some(World::NonSecure)
+    }
+
+    /// Claim `memory` for the heap of `world`.
+    ///
+    /// Returns `Err` if `memory` isn't entirely in `world`, or if claiming fails.
+    ///
+    /// # Safety
+    /// See [`Talc::claim`].
+    pub unsafe fn claim(&self, world: World, memory: Span) -> Result<Span, ()> {
+        let (base, acme) = memory.get_base_acme().ok_or(())?;
+        if self.world_of(base, acme as usize - base as usize) != Some(world) {
+            return Err(());
+        }
+
+        match world {
+            World::Secure => self.secure.lock().claim(memory),
+            World::NonSecure => self.non_secure.lock().claim(memory),
+        }
+    }
+
+    /// Allocate a buffer that the Non-secure world can access, e.g. to pass to it.
+    ///
+    /// Returns `Err` if the allocation fails, or if `layout.size()` is zero.
+    pub fn alloc_non_secure(&self, layout: Layout) -> Result<NonNull<u8>, ()> {
+        if layout.size() == 0 {
+            return Err(());
+        }
+
+        unsafe { self.non_secure.lock().malloc(layout) }
+    }
+
+    /// Free a buffer allocated by [`alloc_non_secure`](DualTalck::alloc_non_secure).
+    ///
+    /// # Safety
+    /// `ptr` must have been allocated by `alloc_non_secure` given `layout`. Note that the
+    /// Non-secure world can corrupt the heap's metadata, so only free buffers into it while
+    /// the Non-secure world can't run or is trusted not to touch memory it wasn't given.
+    pub unsafe fn free_non_secure(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.non_secure.lock().free(ptr, layout);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{alloc::Layout, ptr::addr_of_mut};
+
+    use super::*;
+    use crate::*;
+
+    const ARENA_SIZE: usize = 10000;
+    static mut NON_SECURE_ARENA: [u8; ARENA_SIZE] = [0; ARENA_SIZE];
+
+    /// Treats `NON_SECURE_ARENA` as the only Non-secure memory.
+    fn world_of(base: *const u8, size: usize) -> Option<World> {
+        let non_secure = addr_of_mut!(NON_SECURE_ARENA).cast::<u8>() as usize;
+        let (first, acme) = (base as usize, base as usize + size);
+
+        if first >= non_secure && acme <= non_secure + ARENA_SIZE {
+            Some(World::NonSecure)
+        } else if acme <= non_secure || first >= non_secure + ARENA_SIZE {
+            Some(World::Secure)
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn dual_talck_test() {
+        let mut secure_arena = std::vec![0u8; ARENA_SIZE];
+        let non_secure_arena = Span::from_array(addr_of_mut!(NON_SECURE_ARENA));
+
+        let heaps = DualTalck::<spin::Mutex<()>, _, _>::new(
+            Talc::new(ErrOnOom),
+            Talc::new(ErrOnOom),
+            world_of,
+        );
+
+        // memory is only claimed by the heap of its world
+        unsafe {
+            assert!(heaps.claim(World::NonSecure, secure_arena.as_mut_slice().into()).is_err());
+            assert!(heaps.claim(World::Secure, non_secure_arena).is_err());
+            assert!(heaps.claim(World::Secure, non_secure_arena.extend(0, 100)).is_err());
+
+            heaps.claim(World::Secure, secure_arena.as_mut_slice().into()).unwrap();
+            heaps.claim(World::NonSecure, non_secure_arena).unwrap();
+        }
+
+        let layout = Layout::from_size_align(256, 8).unwrap();
+        let buffer = heaps.alloc_non_secure(layout).unwrap();
+        assert!(heaps.is_non_secure(buffer.as_ptr(), layout.size()));
+        assert!(heaps.alloc_non_secure(Layout::new::<()>()).is_err());
+
+        let secret = unsafe { heaps.secure().lock().malloc(layout).unwrap() };
+        assert!(!heaps.is_non_secure(secret.as_ptr(), layout.size()));
+
+        // e.g. a Non-secure caller passing a range straddling Secure memory
+        let (_, non_secure_acme) = non_secure_arena.get_base_acme().unwrap();
+        assert!(!heaps.is_non_secure(non_secure_acme.wrapping_sub(8), 16));
+
+        unsafe {
+            heaps.free_non_secure(buffer, layout);
+            heaps.secure().lock().free(secret, layout);
+        }
+    }
+}